@@ -46,7 +46,11 @@ void main() {
   } else {
     color = texture(glyph_tex, o_tex);
     if (o_has_color == 0.0) {
-      color.rgb = o_fg_color.rgb;
+      // Subpixel glyphs hold a coverage per channel. Pick the color that
+      // alpha blending over the background turns into a per channel mix
+      // of fg and bg; for grayscale glyphs that is simply fg.
+      vec3 coverage = color.a > 0.0 ? color.rgb / color.a : vec3(1.0);
+      color.rgb = mix(o_bg_color.rgb, o_fg_color.rgb, coverage);
     }
   }
 }
\ No newline at end of file
@@ -1,11 +1,19 @@
 use crate::core::hyperlink;
+use crate::pty::CommandBuilder;
 use crate::term;
 use crate::term::color::RgbColor;
+use crate::term::keyassignment::KeyBinding;
+use crate::term::{
+    AllowWindowOps, BackspaceSends, CopyWrappedLines, CtrlSpaceSends, DeleteSends,
+    ImagePasteBehavior,
+};
+use anyhow::Context;
 use regex::Regex;
 use serde_derive::*;
 use serde_json::Value;
 use std;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 #[derive(Default, Debug, Deserialize, Clone)]
 pub struct Theme {
@@ -13,6 +21,49 @@ pub struct Theme {
     pub color: RgbColor,
 }
 
+/// How glyph edges are smoothed when rasterized.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub enum FontAntiAliasing {
+    None,
+    Grayscale,
+    /// Uses the LCD's subpixels; assumes RGB ordering
+    Subpixel,
+}
+
+impl Default for FontAntiAliasing {
+    fn default() -> Self {
+        FontAntiAliasing::Grayscale
+    }
+}
+
+/// Whether the cursor jumps or glides to its new position.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub enum CursorAnimation {
+    None,
+    SmoothMove,
+}
+
+impl Default for CursorAnimation {
+    fn default() -> Self {
+        CursorAnimation::None
+    }
+}
+
+/// How the window contents are drawn.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub enum Renderer {
+    /// OpenGL, via glium
+    Gpu,
+    /// Composited in system memory; slow, but needs no OpenGL
+    Cpu,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer::Gpu
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     #[serde(default = "default_font_size")]
@@ -21,15 +72,130 @@ pub struct Config {
     pub dpi: f64,
     #[serde(default)]
     pub font: TextStyle,
+    #[serde(default)]
+    pub font_antialias: FontAntiAliasing,
+    /// Draws box drawing and block element characters to fill the cell
+    /// instead of taking them from the font.
+    #[serde(default = "default_true")]
+    pub custom_block_glyphs: bool,
     #[serde(default = "default_hyperlink_rules")]
     pub hyperlink_rules: Vec<hyperlink::Rule>,
+    /// Applied after `hyperlink_rules`, so that custom rules don't have
+    /// to repeat the defaults. `$0` in the format is the whole match and
+    /// `$1`.. are its capture groups.
+    #[serde(default)]
+    pub extra_hyperlink_rules: Vec<hyperlink::Rule>,
     pub ratelimit_output_bytes_per_second: Option<u32>,
     #[serde(default)]
     pub font_rules: Vec<StyleRule>,
     pub colors: Option<Palette>,
     pub scrollback_lines: Option<usize>,
     #[serde(default)]
+    pub scrollback_in_alt_screen: bool,
+    #[serde(default)]
     pub send_composed_key_when_alt_is_pressed: bool,
+    #[serde(default)]
+    pub allow_resize_escape: bool,
+    #[serde(default)]
+    pub allow_window_ops: AllowWindowOps,
+    /// Sent in reply to ENQ (0x05); empty disables the reply.
+    #[serde(default)]
+    pub answerback: String,
+    #[serde(default)]
+    pub predictive_echo: bool,
+    #[serde(default)]
+    pub read_only: bool,
+    /// Makes Ctrl-S and Ctrl-Q pause and resume output, like XOFF/XON,
+    /// instead of sending them to the application.
+    #[serde(default)]
+    pub xon_xoff: bool,
+    /// Extra key bindings, applied over the defaults.
+    #[serde(default)]
+    pub keys: Vec<KeyBinding>,
+    #[serde(default)]
+    pub paste_filter: bool,
+    /// Asks before pasting text with line breaks into an application
+    /// that hasn't enabled bracketed paste.
+    #[serde(default)]
+    pub confirm_multiline_paste: bool,
+    #[serde(default)]
+    pub copy_wrapped_lines_as: CopyWrappedLines,
+    /// Copies the gaps left by horizontal tabs as tabs instead of spaces.
+    #[serde(default)]
+    pub copy_tabs_as_tabs: bool,
+    #[serde(default)]
+    pub image_paste_behavior: ImagePasteBehavior,
+    /// Whether erased cells take the current background color (`bce`).
+    #[serde(default = "default_true")]
+    pub background_color_erase: bool,
+    #[serde(default = "default_max_line_length")]
+    pub max_line_length: usize,
+    /// Packs lines as they scroll into the scrollback, trading some work
+    /// when they are read back for less memory.
+    #[serde(default)]
+    pub compress_scrollback: bool,
+    #[serde(default)]
+    pub enable_notifications: bool,
+    /// Seconds without input or output after which the header stops
+    /// animating; never when unset.
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+    /// The WCAG contrast ratio, from 1 to 21, that text is brightened or
+    /// darkened to reach against its background; colors are left alone
+    /// when unset.
+    #[serde(default)]
+    pub minimum_contrast_ratio: Option<f64>,
+    /// Fills runs of plain spaces without shaping them or working out the
+    /// colors of each cell; turning this off paints every cell the slow
+    /// way, to rule the shortcut out when something looks wrong.
+    #[serde(default = "default_true")]
+    pub blank_cell_fast_path: bool,
+    /// Bells closer together than this are dropped.
+    #[serde(default = "default_bell_rate_limit_ms")]
+    pub bell_rate_limit_ms: u64,
+    #[serde(default)]
+    pub backspace_sends: BackspaceSends,
+    #[serde(default)]
+    pub delete_sends: DeleteSends,
+    #[serde(default)]
+    pub ctrl_space_sends: CtrlSpaceSends,
+    #[serde(default = "default_true")]
+    pub close_on_exit: bool,
+    #[serde(default = "default_glyph_cache_size")]
+    pub glyph_cache_size: usize,
+    #[serde(default = "default_max_fps")]
+    pub max_fps: u32,
+    #[serde(default)]
+    pub renderer: Renderer,
+    /// Only the GPU renderer animates the cursor.
+    #[serde(default)]
+    pub cursor_animation: CursorAnimation,
+    /// Lines scrolled per notch of the mouse wheel; fractional values
+    /// accumulate across events.
+    #[serde(default = "default_scroll_lines_per_wheel")]
+    pub scroll_lines_per_wheel: f64,
+    /// OpenType features applied on top of the shaper defaults, in
+    /// harfbuzz syntax, e.g. `calt=0` or `ss01`.
+    #[serde(default)]
+    pub harfbuzz_features: Vec<String>,
+    #[serde(default)]
+    pub disable_ligatures: bool,
+    pub default_cwd: Option<PathBuf>,
+    /// The X11 `WM_CLASS`, for window manager rules.
+    #[serde(default = "default_window_name")]
+    pub window_class: String,
+    /// The title shown until the application sets one.
+    #[serde(default = "default_window_name")]
+    pub window_title: String,
+    /// A title that applications are not allowed to change.
+    #[serde(default)]
+    pub fixed_title: Option<String>,
+    /// The `TERM` set in the child's environment.
+    #[serde(default = "default_term_value")]
+    pub term_value: String,
+    #[serde(default)]
+    pub set_environment_variables: HashMap<String, String>,
+    #[serde(default)]
     pub theme: Theme,
 }
 
@@ -50,18 +216,91 @@ fn default_dpi() -> f64 {
     96.0
 }
 
+fn default_true() -> bool {
+    true
+}
+
+fn default_glyph_cache_size() -> usize {
+    1024
+}
+
+fn default_max_line_length() -> usize {
+    term::DEFAULT_MAX_LINE_LENGTH
+}
+
+fn default_max_fps() -> u32 {
+    crate::window::DEFAULT_FPS
+}
+
+fn default_window_name() -> String {
+    "miro".to_string()
+}
+
+fn default_term_value() -> String {
+    "xterm-256color".to_string()
+}
+
+fn default_bell_rate_limit_ms() -> u64 {
+    500
+}
+
+fn default_scroll_lines_per_wheel() -> f64 {
+    1.0
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             font_size: default_font_size(),
             dpi: default_dpi(),
             font: TextStyle::default(),
+            font_antialias: FontAntiAliasing::default(),
+            custom_block_glyphs: true,
             ratelimit_output_bytes_per_second: None,
             font_rules: Vec::new(),
             colors: None,
             hyperlink_rules: default_hyperlink_rules(),
+            extra_hyperlink_rules: Vec::new(),
             scrollback_lines: None,
+            scrollback_in_alt_screen: false,
             send_composed_key_when_alt_is_pressed: false,
+            allow_resize_escape: false,
+            allow_window_ops: AllowWindowOps::default(),
+            answerback: String::new(),
+            predictive_echo: false,
+            read_only: false,
+            xon_xoff: false,
+            keys: Vec::new(),
+            paste_filter: false,
+            confirm_multiline_paste: false,
+            copy_wrapped_lines_as: CopyWrappedLines::default(),
+            copy_tabs_as_tabs: false,
+            image_paste_behavior: ImagePasteBehavior::default(),
+            background_color_erase: true,
+            max_line_length: default_max_line_length(),
+            compress_scrollback: false,
+            enable_notifications: false,
+            idle_timeout_secs: None,
+            minimum_contrast_ratio: None,
+            blank_cell_fast_path: true,
+            bell_rate_limit_ms: default_bell_rate_limit_ms(),
+            backspace_sends: BackspaceSends::default(),
+            delete_sends: DeleteSends::default(),
+            ctrl_space_sends: CtrlSpaceSends::default(),
+            close_on_exit: true,
+            glyph_cache_size: default_glyph_cache_size(),
+            max_fps: default_max_fps(),
+            renderer: Renderer::default(),
+            cursor_animation: CursorAnimation::default(),
+            scroll_lines_per_wheel: default_scroll_lines_per_wheel(),
+            harfbuzz_features: Vec::new(),
+            disable_ligatures: false,
+            default_cwd: None,
+            window_class: default_window_name(),
+            window_title: default_window_name(),
+            fixed_title: None,
+            term_value: default_term_value(),
+            set_environment_variables: HashMap::new(),
             theme: Theme::default(),
         }
     }
@@ -171,8 +410,62 @@ pub struct StyleRule {
 }
 
 impl Config {
-    pub fn default_config(theme: Theme) -> Self {
-        Self::default().compute_extra_defaults(theme)
+    /// Loads the config from `path`, or from `miro.toml` in the user's
+    /// config directory when no path is given. Fields missing from the
+    /// file, or the whole file if none is found, fall back to defaults.
+    pub fn load(path: Option<&Path>, theme: Theme) -> anyhow::Result<Self> {
+        let cfg = match path {
+            Some(path) => Self::load_file(path)?,
+            None => match config_file_path().filter(|path| path.exists()) {
+                Some(path) => Self::load_file(&path)?,
+                None => Self::default(),
+            },
+        };
+        Ok(cfg.compute_extra_defaults(theme))
+    }
+
+    fn load_file(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("failed to parse config {}", path.display()))
+    }
+
+    /// The font features to shape with; `disable_ligatures` comes last so
+    /// that it wins over anything in `harfbuzz_features`.
+    pub fn font_features(&self) -> Vec<String> {
+        let mut features = self.harfbuzz_features.clone();
+        if self.disable_ligatures {
+            features.extend(["calt=0", "clig=0", "liga=0"].iter().map(|f| f.to_string()));
+        }
+        features
+    }
+
+    /// The rules used to find implicit hyperlinks in the terminal output.
+    pub fn all_hyperlink_rules(&self) -> Vec<hyperlink::Rule> {
+        self.hyperlink_rules.iter().chain(self.extra_hyperlink_rules.iter()).cloned().collect()
+    }
+
+    /// Builds the command for `prog`, or the user's shell when that is
+    /// None, with the configured directory and environment.
+    pub fn build_prog(&self, prog: Option<&[String]>) -> anyhow::Result<CommandBuilder> {
+        let mut cmd = match prog {
+            Some([program, args @ ..]) => {
+                let mut cmd = CommandBuilder::new(program);
+                for arg in args {
+                    cmd.arg(arg);
+                }
+                cmd
+            }
+            _ => CommandBuilder::new(crate::pty::get_shell()?),
+        };
+        if let Some(cwd) = &self.default_cwd {
+            cmd.cwd(cwd);
+        }
+        cmd.env("TERM", &self.term_value);
+        for (key, value) in &self.set_environment_variables {
+            cmd.env(key, value);
+        }
+        Ok(cmd)
     }
 
     fn compute_extra_defaults(&self, theme: Theme) -> Self {
@@ -207,6 +500,14 @@ impl Config {
     }
 }
 
+fn config_file_path() -> Option<PathBuf> {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("miro").join("miro.toml"))
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Palette {
     pub foreground: Option<RgbColor>,
@@ -217,6 +518,17 @@ pub struct Palette {
     pub selection_bg: Option<RgbColor>,
     pub ansi: Option<[RgbColor; 8]>,
     pub brights: Option<[RgbColor; 8]>,
+    #[serde(default)]
+    pub indexed: HashMap<u8, RgbColor>,
+}
+
+impl Palette {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read color scheme {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("failed to parse color scheme {}", path.display()))
+    }
 }
 
 impl From<Palette> for term::color::ColorPalette {
@@ -231,7 +543,9 @@ impl From<Palette> for term::color::ColorPalette {
         }
         apply_color!(foreground);
         apply_color!(background);
-        apply_color!(cursor_fg);
+        if let Some(cursor_fg) = cfg.cursor_fg {
+            p.cursor_fg = Some(cursor_fg);
+        }
         apply_color!(cursor_bg);
         apply_color!(selection_fg);
         apply_color!(selection_bg);
@@ -246,6 +560,9 @@ impl From<Palette> for term::color::ColorPalette {
                 p.colors.0[idx + 8] = *col;
             }
         }
+        for (idx, col) in cfg.indexed {
+            p.colors.0[idx as usize] = col;
+        }
         p
     }
 }
@@ -314,3 +631,146 @@ fn get_mainname(filename: &str) -> String {
     re.captures(filename)
         .map_or_else(|| filename.to_string(), |caps| caps.get(1).unwrap().as_str().to_string())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::term::color::{ColorAttribute, ColorPalette};
+    use std::ffi::OsStr;
+
+    #[test]
+    fn load_palette() {
+        let path = std::env::temp_dir().join(format!("miro-palette-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r##"{
+                "foreground": "#fdf6e3",
+                "ansi": ["#073642", "#dc322f", "#859900", "#b58900",
+                         "#268bd2", "#d33682", "#2aa198", "#eee8d5"],
+                "indexed": {"16": "orange"}
+            }"##,
+        )
+        .unwrap();
+        let palette: ColorPalette = Palette::load(&path).unwrap().into();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(
+            palette.resolve_fg(ColorAttribute::PaletteIndex(1)),
+            RgbColor::new(0xdc, 0x32, 0x2f)
+        );
+        assert_eq!(palette.resolve_fg(ColorAttribute::Default), RgbColor::new(0xfd, 0xf6, 0xe3));
+        assert_eq!(palette.colors.0[16], RgbColor::new(0xff, 0xa5, 0x00));
+    }
+
+    #[test]
+    fn parse_config_file() {
+        let config: Config = toml::from_str(
+            r##"
+            font_size = 12.5
+            scrollback_lines = 5000
+            allow_resize_escape = true
+            window_class = "scratchpad"
+
+            [font]
+            font = [{ family = "Fira Code" }]
+
+            [colors]
+            background = "#002b36"
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(config.font_size, 12.5);
+        assert_eq!(config.scrollback_lines, Some(5000));
+        assert!(config.allow_resize_escape);
+        assert_eq!(config.font.font[0].family, "Fira Code");
+        assert_eq!(config.colors.unwrap().background, Some(RgbColor::new(0x00, 0x2b, 0x36)));
+        assert_eq!(config.dpi, default_dpi());
+        assert_eq!(config.glyph_cache_size, default_glyph_cache_size());
+        assert_eq!(config.hyperlink_rules.len(), 2);
+        assert_eq!(config.window_class, "scratchpad");
+        assert_eq!(config.window_title, "miro");
+    }
+
+    #[test]
+    fn extra_hyperlink_rules_expand_their_template() {
+        let config: Config = toml::from_str(
+            r##"
+            [[extra_hyperlink_rules]]
+            regex = "\\b([A-Z]+)-(\\d+)\\b"
+            format = "https://tracker.example.com/$1/issue/$2"
+            "##,
+        )
+        .unwrap();
+
+        let rules = config.all_hyperlink_rules();
+        assert_eq!(rules.len(), 3);
+        let matches =
+            hyperlink::Rule::match_hyperlinks("see JIRA-123 or mail a@example.com", &rules);
+        let uris: Vec<_> = matches.iter().map(|m| (m.range.clone(), m.link.uri())).collect();
+        assert_eq!(
+            uris,
+            vec![
+                (21..34, "mailto:a@example.com"),
+                (4..12, "https://tracker.example.com/JIRA/issue/123"),
+            ]
+        );
+    }
+
+    #[test]
+    fn disable_ligatures_overrides_features() {
+        let config: Config = toml::from_str(
+            r##"
+            harfbuzz_features = ["liga=1", "ss01"]
+            disable_ligatures = true
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(config.font_features(), vec!["liga=1", "ss01", "calt=0", "clig=0", "liga=0"]);
+        assert!(Config::default().font_features().is_empty());
+    }
+
+    #[test]
+    fn build_prog_sets_term() {
+        let has_term = |config: &Config, value: &str| {
+            let cmd = config.build_prog(None).unwrap().as_command();
+            cmd.get_envs().any(|(k, v)| k == OsStr::new("TERM") && v == Some(OsStr::new(value)))
+        };
+
+        let mut config = Config::default();
+        assert!(has_term(&config, "xterm-256color"));
+
+        config.term_value = "miro".to_string();
+        assert!(has_term(&config, "miro"));
+
+        config.set_environment_variables.insert("TERM".to_string(), "vt100".to_string());
+        assert!(has_term(&config, "vt100"));
+    }
+
+    #[test]
+    fn build_prog_runs_the_given_command() {
+        let mut config = Config::default();
+        config.set_environment_variables.insert("FOO".to_string(), "bar".to_string());
+        let prog = vec!["htop".to_string(), "-d".to_string(), "10".to_string()];
+        let cmd = config.build_prog(Some(&prog)).unwrap().as_command();
+
+        assert_eq!(cmd.get_program(), OsStr::new("htop"));
+        assert_eq!(cmd.get_args().collect::<Vec<_>>(), vec![OsStr::new("-d"), OsStr::new("10")]);
+        assert!(cmd
+            .get_envs()
+            .any(|(k, v)| k == OsStr::new("FOO") && v == Some(OsStr::new("bar"))));
+    }
+
+    #[test]
+    fn config_parse_error_has_location() {
+        let err = toml::from_str::<Config>("font_size = \"big\"\n").unwrap_err();
+        assert!(err.to_string().contains("line 1"));
+    }
+
+    #[test]
+    fn invalid_palette_color() {
+        let err = serde_json::from_str::<Palette>(r##"{"foreground": "#12345"}"##).unwrap_err();
+        assert!(err.to_string().contains("unknown color name: #12345"));
+    }
+}
@@ -76,6 +76,7 @@ pub enum Underline {
     None = 0,
     Single = 1,
     Double = 2,
+    Curly = 3,
 }
 
 impl Into<bool> for Underline {
@@ -84,6 +85,16 @@ impl Into<bool> for Underline {
     }
 }
 
+/// Marks blank cells that a horizontal tab moved the cursor over, so
+/// that copying can give the tab back.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[repr(u16)]
+pub enum TabGap {
+    None = 0,
+    Start = 1,
+    Continued = 2,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[repr(u16)]
 pub enum Blink {
@@ -107,6 +118,10 @@ impl CellAttributes {
     bitfield!(strikethrough, set_strikethrough, 8);
     bitfield!(invisible, set_invisible, 9);
     bitfield!(wrapped, set_wrapped, 10);
+    bitfield!(predicted, set_predicted, 11);
+    /// Set by DECSCA; selective erases leave protected cells alone.
+    bitfield!(protected, set_protected, 12);
+    bitfield!(tab_gap, set_tab_gap, TabGap, 0b11, 13);
 
     pub fn set_foreground<C: Into<ColorAttribute>>(&mut self, foreground: C) -> &mut Self {
         self.foreground = foreground.into();
@@ -123,13 +138,21 @@ impl CellAttributes {
         self
     }
 
+    /// A copy without the hyperlink and the bits that describe a cell
+    /// rather than the pen that drew it.
     pub fn clone_sgr_only(&self) -> Self {
-        Self {
+        let mut attrs = Self {
             attributes: self.attributes,
             foreground: self.foreground,
             background: self.background,
             hyperlink: None,
-        }
+        };
+        attrs
+            .set_wrapped(false)
+            .set_predicted(false)
+            .set_protected(false)
+            .set_tab_gap(TabGap::None);
+        attrs
     }
 }
 
@@ -197,6 +220,10 @@ impl Cell {
     pub fn attrs(&self) -> &CellAttributes {
         &self.attrs
     }
+
+    pub fn attrs_mut(&mut self) -> &mut CellAttributes {
+        &mut self.attrs
+    }
 }
 
 pub fn unicode_column_width(s: &str) -> usize {
@@ -227,3 +254,25 @@ pub enum AttributeChange {
     Background(ColorAttribute),
     Hyperlink(Option<Arc<Hyperlink>>),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clone_sgr_only_keeps_just_the_pen() {
+        let mut attrs = CellAttributes::default();
+        attrs
+            .set_intensity(Intensity::Bold)
+            .set_italic(true)
+            .set_wrapped(true)
+            .set_predicted(true)
+            .set_protected(true)
+            .set_tab_gap(TabGap::Continued)
+            .set_hyperlink(Some(Arc::new(Hyperlink::new_implicit("http://x"))));
+
+        let mut expected = CellAttributes::default();
+        expected.set_intensity(Intensity::Bold).set_italic(true);
+        assert_eq!(attrs.clone_sgr_only(), expected);
+    }
+}
@@ -61,8 +61,39 @@ impl RgbColor {
         format!("#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
     }
 
+    /// The `rgb:RRRR/GGGG/BBBB` form that xterm uses in OSC query replies
+    pub fn to_x11_16bit_rgb_string(self) -> String {
+        format!(
+            "rgb:{:02x}{:02x}/{:02x}{:02x}/{:02x}{:02x}",
+            self.red, self.red, self.green, self.green, self.blue, self.blue
+        )
+    }
+
+    /// Parses the X11 `rgb:R/G/B` form, where each component has one to
+    /// four hex digits.
+    pub fn from_x11_rgb_str(s: &str) -> Option<RgbColor> {
+        if !s.starts_with("rgb:") {
+            return None;
+        }
+        let mut components = s[4..].split('/').map(|digits| {
+            if digits.is_empty() || digits.len() > 4 {
+                return None;
+            }
+            let value = u32::from_str_radix(digits, 16).ok()?;
+            let max = (1u32 << (4 * digits.len())) - 1;
+            Some((value * 0xff / max) as u8)
+        });
+        let red = components.next()??;
+        let green = components.next()??;
+        let blue = components.next()??;
+        if components.next().is_some() {
+            return None;
+        }
+        Some(Self::new(red, green, blue))
+    }
+
     pub fn from_rgb_str(s: &str) -> Option<RgbColor> {
-        if s.as_bytes()[0] == b'#' && s.len() == 7 {
+        if s.len() == 7 && s.starts_with('#') {
             let mut chars = s.chars().skip(1);
 
             macro_rules! digit {
@@ -84,8 +115,81 @@ impl RgbColor {
         }
     }
 
+    /// The WCAG relative luminance, from 0 for black to 1 for white.
+    pub fn relative_luminance(self) -> f64 {
+        let linear = |c: u8| {
+            let c = f64::from(c) / 255.;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * linear(self.red) + 0.7152 * linear(self.green) + 0.0722 * linear(self.blue)
+    }
+
+    /// The WCAG contrast ratio, from 1 for identical luminance to 21 for
+    /// black against white.
+    pub fn contrast_ratio(self, other: RgbColor) -> f64 {
+        let (a, b) = (self.relative_luminance(), other.relative_luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// Moves `self` towards white or black, as little as needed, so that it
+    /// has at least `ratio` contrast against `bg`. It goes away from `bg`
+    /// when that can reach the ratio, and to whichever extreme contrasts
+    /// best when neither can.
+    pub fn with_minimum_contrast(self, bg: RgbColor, ratio: f64) -> RgbColor {
+        if self.contrast_ratio(bg) >= ratio {
+            return self;
+        }
+        let white = RgbColor::new(0xff, 0xff, 0xff);
+        let black = RgbColor::new(0, 0, 0);
+        let towards = |target: RgbColor| {
+            if target.contrast_ratio(bg) < ratio {
+                return None;
+            }
+            let (mut lo, mut hi) = (0., 1.);
+            for _ in 0..16 {
+                let mid = (lo + hi) / 2.;
+                if self.mix(target, mid).contrast_ratio(bg) >= ratio {
+                    hi = mid;
+                } else {
+                    lo = mid;
+                }
+            }
+            Some(self.mix(target, hi))
+        };
+
+        let (away, back) = if self.relative_luminance() >= bg.relative_luminance() {
+            (white, black)
+        } else {
+            (black, white)
+        };
+        towards(away).or_else(|| towards(back)).unwrap_or_else(|| {
+            if white.contrast_ratio(bg) >= black.contrast_ratio(bg) {
+                white
+            } else {
+                black
+            }
+        })
+    }
+
+    /// Linearly interpolates each channel `amount` of the way to `other`.
+    fn mix(self, other: RgbColor, amount: f64) -> RgbColor {
+        let channel =
+            |a: u8, b: u8| (f64::from(a) + (f64::from(b) - f64::from(a)) * amount).round() as u8;
+        RgbColor::new(
+            channel(self.red, other.red),
+            channel(self.green, other.green),
+            channel(self.blue, other.blue),
+        )
+    }
+
     pub fn from_named_or_rgb_string(s: &str) -> Option<Self> {
-        RgbColor::from_rgb_str(&s).or_else(|| RgbColor::from_named(&s))
+        RgbColor::from_rgb_str(&s)
+            .or_else(|| RgbColor::from_x11_rgb_str(&s))
+            .or_else(|| RgbColor::from_named(&s))
     }
 }
 
@@ -138,6 +242,17 @@ impl From<RgbColor> for ColorSpec {
     }
 }
 
+impl From<ColorAttribute> for ColorSpec {
+    fn from(attr: ColorAttribute) -> Self {
+        match attr {
+            ColorAttribute::TrueColorWithPaletteFallback(color, _)
+            | ColorAttribute::TrueColorWithDefaultFallback(color) => ColorSpec::TrueColor(color),
+            ColorAttribute::PaletteIndex(idx) => ColorSpec::PaletteIndex(idx),
+            ColorAttribute::Default => ColorSpec::Default,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum ColorAttribute {
     TrueColorWithPaletteFallback(RgbColor, PaletteIndex),
@@ -167,3 +282,28 @@ impl From<ColorSpec> for ColorAttribute {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn minimum_contrast_only_adjusts_low_contrast_pairs() {
+        let black = RgbColor::new(0, 0, 0);
+        let dark_gray = RgbColor::new(0x30, 0x30, 0x30);
+        assert!(dark_gray.contrast_ratio(black) < 4.5);
+
+        let adjusted = dark_gray.with_minimum_contrast(black, 4.5);
+        assert!(adjusted.contrast_ratio(black) >= 4.5);
+        assert!(adjusted.relative_luminance() > dark_gray.relative_luminance());
+        // Only as far as needed, rather than all the way to white
+        assert!(adjusted.contrast_ratio(black) < 5.);
+
+        let light_gray = RgbColor::new(0xc0, 0xc0, 0xc0);
+        assert_eq!(light_gray.with_minimum_contrast(black, 4.5), light_gray);
+
+        let white = RgbColor::new(0xff, 0xff, 0xff);
+        let pale = RgbColor::new(0xee, 0xee, 0xee);
+        assert!(pale.with_minimum_contrast(white, 4.5).relative_luminance() < 0.2);
+    }
+}
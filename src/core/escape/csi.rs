@@ -155,6 +155,8 @@ pub enum Device {
     SoftReset,
     RequestPrimaryDeviceAttributes,
     RequestSecondaryDeviceAttributes,
+    /// XTVERSION
+    RequestTerminalNameAndVersion,
     StatusReport,
 }
 
@@ -172,6 +174,7 @@ impl Display for Device {
             Device::SoftReset => write!(f, "!p")?,
             Device::RequestPrimaryDeviceAttributes => write!(f, "c")?,
             Device::RequestSecondaryDeviceAttributes => write!(f, ">c")?,
+            Device::RequestTerminalNameAndVersion => write!(f, ">q")?,
             Device::StatusReport => write!(f, "5n")?,
         };
         Ok(())
@@ -252,6 +255,11 @@ pub enum Window {
     PopIconAndWindowTitle,
     PopIconTitle,
     PopWindowTitle,
+    /// xterm's `CSI > Ps t`: 0 sets titles from hex, 1 reports them in
+    /// hex, 2 and 3 do the same with UTF-8.
+    SetTitleModes(Vec<i64>),
+    /// `CSI > Ps T`; no modes resets all of them.
+    ResetTitleModes(Vec<i64>),
 
     ChecksumRectangularArea {
         request_id: i64,
@@ -277,13 +285,13 @@ impl Display for Window {
             Window::Iconify => write!(f, "2t"),
             Window::MoveWindow { x, y } => write!(f, "3;{};{}t", x, y),
             Window::ResizeWindowPixels { width, height } => {
-                write!(f, "4;{};{}t", numstr_or_empty(width), numstr_or_empty(height))
+                write!(f, "4;{};{}t", numstr_or_empty(height), numstr_or_empty(width))
             }
             Window::RaiseWindow => write!(f, "5t"),
             Window::LowerWindow => write!(f, "6t"),
             Window::RefreshWindow => write!(f, "7t"),
             Window::ResizeWindowCells { width, height } => {
-                write!(f, "8;{};{}t", numstr_or_empty(width), numstr_or_empty(height))
+                write!(f, "8;{};{}t", numstr_or_empty(height), numstr_or_empty(width))
             }
             Window::RestoreMaximizedWindow => write!(f, "9;0t"),
             Window::MaximizeWindow => write!(f, "9;1t"),
@@ -309,6 +317,17 @@ impl Display for Window {
             Window::PopIconAndWindowTitle => write!(f, "23;0t"),
             Window::PopIconTitle => write!(f, "23;1t"),
             Window::PopWindowTitle => write!(f, "23;2t"),
+            Window::SetTitleModes(modes) | Window::ResetTitleModes(modes) => {
+                write!(f, ">")?;
+                for (idx, mode) in modes.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ";")?;
+                    }
+                    write!(f, "{}", mode)?;
+                }
+                let end = if let Window::SetTitleModes(_) = self { 't' } else { 'T' };
+                write!(f, "{}", end)
+            }
             Window::ChecksumRectangularArea {
                 request_id,
                 page_number,
@@ -421,6 +440,7 @@ pub enum DecPrivateMode {
 #[derive(Debug, Clone, PartialEq, Eq, FromPrimitive, ToPrimitive)]
 pub enum DecPrivateModeCode {
     ApplicationCursorKeys = 1,
+    AutoWrap = 7,
     StartBlinkingCursor = 12,
     ShowCursor = 25,
 
@@ -432,10 +452,19 @@ pub enum DecPrivateModeCode {
 
     AnyEventMouse = 1003,
 
+    /// Reports focus changes as `CSI I` and `CSI O`
+    FocusTracking = 1004,
+
     SGRMouse = 1006,
+    /// Like `SGRMouse`, but reports pixel rather than cell coordinates
+    SGRPixelsMouse = 1016,
     ClearAndEnableAlternateScreen = 1049,
     EnableAlternateScreen = 47,
+    /// Like `EnableAlternateScreen`, but clears the alternate screen when
+    /// leaving it
+    EnableAlternateScreenClearOnExit = 1047,
     BracketedPaste = 2004,
+    SynchronizedOutput = 2026,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -527,6 +556,33 @@ pub enum Edit {
     EraseInDisplay(EraseInDisplay),
 
     Repeat(u32),
+
+    /// DECSED: like `EraseInDisplay`, but leaves protected cells alone
+    SelectiveEraseInDisplay(EraseInDisplay),
+
+    /// DECSEL: like `EraseInLine`, but leaves protected cells alone
+    SelectiveEraseInLine(EraseInLine),
+
+    /// DECSCA: whether subsequently printed cells are protected
+    CharacterProtection(bool),
+
+    /// DECERA: erases the rectangle, edges included
+    EraseRectangularArea {
+        top: OneBased,
+        left: OneBased,
+        bottom: OneBased,
+        right: OneBased,
+    },
+
+    /// DECFRA: fills the rectangle, edges included, with `ch` in the
+    /// current pen
+    FillRectangularArea {
+        ch: char,
+        top: OneBased,
+        left: OneBased,
+        bottom: OneBased,
+        right: OneBased,
+    },
 }
 
 trait EncodeCSIParam {
@@ -577,6 +633,23 @@ impl Display for Edit {
             Edit::ScrollUp(n) => n.write_csi(f, "S")?,
             Edit::EraseInDisplay(n) => n.write_csi(f, "J")?,
             Edit::Repeat(n) => n.write_csi(f, "b")?,
+            Edit::SelectiveEraseInDisplay(n) => {
+                write!(f, "?")?;
+                n.write_csi(f, "J")?
+            }
+            Edit::SelectiveEraseInLine(n) => {
+                write!(f, "?")?;
+                n.write_csi(f, "K")?
+            }
+            Edit::CharacterProtection(protected) => {
+                write!(f, "{}\"q", if *protected { 1 } else { 0 })?
+            }
+            Edit::EraseRectangularArea { top, left, bottom, right } => {
+                write!(f, "{};{};{};{}$z", top, left, bottom, right)?
+            }
+            Edit::FillRectangularArea { ch, top, left, bottom, right } => {
+                write!(f, "{};{};{};{};{}$x", *ch as u32, top, left, bottom, right)?
+            }
         }
         Ok(())
     }
@@ -784,6 +857,7 @@ impl Display for Sgr {
             Sgr::Intensity(Intensity::Normal) => code!(NormalIntensity),
             Sgr::Underline(Underline::Single) => code!(UnderlineOn),
             Sgr::Underline(Underline::Double) => code!(UnderlineDouble),
+            Sgr::Underline(Underline::Curly) => write!(f, "{}:3m", SgrCode::UnderlineOn as i64)?,
             Sgr::Underline(Underline::None) => code!(UnderlineOff),
             Sgr::Blink(Blink::Slow) => code!(BlinkOn),
             Sgr::Blink(Blink::Rapid) => code!(RapidBlinkOn),
@@ -883,16 +957,30 @@ struct CSIParser<'a> {
     control: char,
 
     params: Option<&'a [i64]>,
+    /// `subparams[i]` is true if the i-th parameter followed a `:`
+    subparams: &'a [bool],
+    /// Index into the original parameters at which `params` begins
+    offset: usize,
 }
 
 impl CSI {
+    /// `subparams` flags the parameters that were introduced by `:`
+    /// rather than `;`; it may be shorter than `params`, or empty.
     pub fn parse<'a>(
         params: &'a [i64],
+        subparams: &'a [bool],
         intermediates: &'a [u8],
         ignored_extra_intermediates: bool,
         control: char,
     ) -> impl Iterator<Item = CSI> + 'a {
-        CSIParser { intermediates, ignored_extra_intermediates, control, params: Some(params) }
+        CSIParser {
+            intermediates,
+            ignored_extra_intermediates,
+            control,
+            params: Some(params),
+            subparams,
+            offset: 0,
+        }
     }
 }
 
@@ -936,6 +1024,21 @@ macro_rules! parse {
     }};
 }
 
+/// The top, left, bottom and right of a DEC rectangular area; a missing or
+/// zero bottom or right extends it to the edge of the screen.
+fn rectangle(params: &[i64]) -> Result<(OneBased, OneBased, OneBased, OneBased), ()> {
+    let edge = |idx: usize| match params.get(idx) {
+        None | Some(0) => Ok(OneBased::new(u32::max_value())),
+        Some(&param) => OneBased::from_esc_param(param),
+    };
+    Ok((
+        OneBased::from_optional_esc_param(params.first())?,
+        OneBased::from_optional_esc_param(params.get(1))?,
+        edge(2)?,
+        edge(3)?,
+    ))
+}
+
 impl<'a> CSIParser<'a> {
     fn parse_next(&mut self, params: &'a [i64]) -> Result<CSI, ()> {
         match (self.control, self.intermediates) {
@@ -977,10 +1080,15 @@ impl<'a> CSIParser<'a> {
             ('m', &[]) => self.sgr(params).map(CSI::Sgr),
             ('n', &[]) => self.dsr(params),
             ('q', &[b' ']) => self.cursor_style(params),
+            ('q', &[b'"']) => self.decsca(params),
             ('r', &[]) => self.decstbm(params),
             ('s', &[]) => noparams!(Cursor, SaveCursor, params),
             ('t', &[]) => self.window(params).map(CSI::Window),
             ('u', &[]) => noparams!(Cursor, RestoreCursor, params),
+            ('t', &[b'>']) if !params.is_empty() => {
+                Ok(CSI::Window(Window::SetTitleModes(params.to_vec())))
+            }
+            ('T', &[b'>']) => Ok(CSI::Window(Window::ResetTitleModes(params.to_vec()))),
             ('y', &[b'*']) => {
                 fn p(params: &[i64], idx: usize) -> Result<i64, ()> {
                     params.get(idx).cloned().ok_or(())
@@ -1003,6 +1111,23 @@ impl<'a> CSIParser<'a> {
 
             ('p', &[b'!']) => Ok(CSI::Device(Box::new(Device::SoftReset))),
 
+            ('z', &[b'$']) => {
+                let (top, left, bottom, right) = rectangle(params)?;
+                Ok(CSI::Edit(Edit::EraseRectangularArea { top, left, bottom, right }))
+            }
+            ('x', &[b'$']) => {
+                // Only the printable characters of the DEC multinational set
+                let ch = match params.first() {
+                    Some(&ch @ 32..=126) | Some(&ch @ 160..=255) => char::from(ch as u8),
+                    _ => return Err(()),
+                };
+                let (top, left, bottom, right) = rectangle(&params[1..])?;
+                Ok(CSI::Edit(Edit::FillRectangularArea { ch, top, left, bottom, right }))
+            }
+
+            ('J', &[b'?']) => parse!(Edit, SelectiveEraseInDisplay, params),
+            ('K', &[b'?']) => parse!(Edit, SelectiveEraseInLine, params),
+
             ('h', &[b'?']) => self.dec(params).map(|mode| CSI::Mode(Mode::SetDecPrivateMode(mode))),
             ('l', &[b'?']) => {
                 self.dec(params).map(|mode| CSI::Mode(Mode::ResetDecPrivateMode(mode)))
@@ -1022,6 +1147,9 @@ impl<'a> CSIParser<'a> {
             ('c', &[b'>']) => {
                 self.req_secondary_device_attributes(params).map(|dev| CSI::Device(Box::new(dev)))
             }
+            ('q', &[b'>']) => {
+                self.req_terminal_name_and_version(params).map(|dev| CSI::Device(Box::new(dev)))
+            }
             ('c', &[b'?']) => {
                 self.secondary_device_attributes(params).map(|dev| CSI::Device(Box::new(dev)))
             }
@@ -1035,9 +1163,26 @@ impl<'a> CSIParser<'a> {
         if !next.is_empty() {
             self.params = Some(next);
         }
+        self.offset += n;
         result
     }
 
+    /// The number of parameters after `params[0]` joined to it by `:`
+    fn count_subparams(&self, params: &[i64]) -> usize {
+        (1..params.len())
+            .take_while(|&i| self.subparams.get(self.offset + i).cloned().unwrap_or(false))
+            .count()
+    }
+
+    fn decsca(&mut self, params: &'a [i64]) -> Result<CSI, ()> {
+        let protected = match params {
+            [] | [0] | [2] => false,
+            [1] => true,
+            _ => return Err(()),
+        };
+        Ok(CSI::Edit(Edit::CharacterProtection(protected)))
+    }
+
     fn cursor_style(&mut self, params: &'a [i64]) -> Result<CSI, ()> {
         if params.len() != 1 {
             Err(())
@@ -1091,6 +1236,16 @@ impl<'a> CSIParser<'a> {
         }
     }
 
+    fn req_terminal_name_and_version(&mut self, params: &'a [i64]) -> Result<Device, ()> {
+        if params.is_empty() {
+            Ok(Device::RequestTerminalNameAndVersion)
+        } else if params == [0] {
+            Ok(self.advance_by(1, params, Device::RequestTerminalNameAndVersion))
+        } else {
+            Err(())
+        }
+    }
+
     fn req_secondary_device_attributes(&mut self, params: &'a [i64]) -> Result<Device, ()> {
         if params.is_empty() {
             Ok(Device::RequestSecondaryDeviceAttributes)
@@ -1205,8 +1360,19 @@ impl<'a> CSIParser<'a> {
         }
     }
 
-    fn parse_sgr_color(&mut self, params: &'a [i64]) -> Result<ColorSpec, ()> {
-        if params.len() >= 5 && params[1] == 2 {
+    fn parse_sgr_color(&mut self, params: &'a [i64], num_sub: usize) -> Result<ColorSpec, ()> {
+        if num_sub > 0 {
+            // T.416 places a color space id before the components; it is
+            // commonly left out altogether.
+            let color = match params[1..=num_sub] {
+                [2, _, r, g, b] | [2, r, g, b] => {
+                    RgbColor::new(to_u8(r)?, to_u8(g)?, to_u8(b)?).into()
+                }
+                [5, idx] => ColorSpec::PaletteIndex(to_u8(idx)?),
+                _ => return Err(()),
+            };
+            Ok(self.advance_by(1 + num_sub, params, color))
+        } else if params.len() >= 5 && params[1] == 2 {
             let red = to_u8(params[2])?;
             let green = to_u8(params[3])?;
             let blue = to_u8(params[4])?;
@@ -1230,11 +1396,11 @@ impl<'a> CSIParser<'a> {
                 1 => Ok(Window::DeIconify),
                 2 => Ok(Window::Iconify),
                 3 => Ok(Window::MoveWindow { x: arg1.unwrap_or(0), y: arg2.unwrap_or(0) }),
-                4 => Ok(Window::ResizeWindowPixels { width: arg1, height: arg2 }),
+                4 => Ok(Window::ResizeWindowPixels { height: arg1, width: arg2 }),
                 5 => Ok(Window::RaiseWindow),
                 6 => Ok(Window::LowerWindow),
                 7 => Ok(Window::RefreshWindow),
-                8 => Ok(Window::ResizeWindowCells { width: arg1, height: arg2 }),
+                8 => Ok(Window::ResizeWindowCells { height: arg1, width: arg2 }),
                 9 => match arg1 {
                     Some(0) => Ok(Window::RestoreMaximizedWindow),
                     Some(1) => Ok(Window::MaximizeWindow),
@@ -1286,9 +1452,10 @@ impl<'a> CSIParser<'a> {
         if params.is_empty() {
             Ok(Sgr::Reset)
         } else {
+            let num_sub = self.count_subparams(params);
             macro_rules! one {
                 ($t:expr) => {
-                    Ok(self.advance_by(1, params, $t))
+                    Ok(self.advance_by(1 + num_sub, params, $t))
                 };
             }
 
@@ -1299,7 +1466,18 @@ impl<'a> CSIParser<'a> {
                     SgrCode::IntensityBold => one!(Sgr::Intensity(Intensity::Bold)),
                     SgrCode::IntensityDim => one!(Sgr::Intensity(Intensity::Half)),
                     SgrCode::NormalIntensity => one!(Sgr::Intensity(Intensity::Normal)),
-                    SgrCode::UnderlineOn => one!(Sgr::Underline(Underline::Single)),
+                    SgrCode::UnderlineOn => {
+                        let underline = match params.get(1) {
+                            _ if num_sub == 0 => Underline::Single,
+                            Some(0) => Underline::None,
+                            // Dotted and dashed are drawn as a single underline
+                            Some(1) | Some(4) | Some(5) => Underline::Single,
+                            Some(2) => Underline::Double,
+                            Some(3) => Underline::Curly,
+                            _ => return Err(()),
+                        };
+                        one!(Sgr::Underline(underline))
+                    }
                     SgrCode::UnderlineDouble => one!(Sgr::Underline(Underline::Double)),
                     SgrCode::UnderlineOff => one!(Sgr::Underline(Underline::None)),
                     SgrCode::BlinkOn => one!(Sgr::Blink(Blink::Slow)),
@@ -1307,7 +1485,9 @@ impl<'a> CSIParser<'a> {
                     SgrCode::BlinkOff => one!(Sgr::Blink(Blink::None)),
                     SgrCode::ItalicOn => one!(Sgr::Italic(true)),
                     SgrCode::ItalicOff => one!(Sgr::Italic(false)),
-                    SgrCode::ForegroundColor => self.parse_sgr_color(params).map(Sgr::Foreground),
+                    SgrCode::ForegroundColor => {
+                        self.parse_sgr_color(params, num_sub).map(Sgr::Foreground)
+                    }
                     SgrCode::ForegroundBlack => one!(Sgr::Foreground(AnsiColor::Black.into())),
                     SgrCode::ForegroundRed => one!(Sgr::Foreground(AnsiColor::Maroon.into())),
                     SgrCode::ForegroundGreen => one!(Sgr::Foreground(AnsiColor::Green.into())),
@@ -1332,7 +1512,9 @@ impl<'a> CSIParser<'a> {
                         one!(Sgr::Foreground(AnsiColor::White.into()))
                     }
 
-                    SgrCode::BackgroundColor => self.parse_sgr_color(params).map(Sgr::Background),
+                    SgrCode::BackgroundColor => {
+                        self.parse_sgr_color(params, num_sub).map(Sgr::Background)
+                    }
                     SgrCode::BackgroundBlack => one!(Sgr::Background(AnsiColor::Black.into())),
                     SgrCode::BackgroundRed => one!(Sgr::Background(AnsiColor::Maroon.into())),
                     SgrCode::BackgroundGreen => one!(Sgr::Background(AnsiColor::Green.into())),
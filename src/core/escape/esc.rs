@@ -43,6 +43,10 @@ pub enum EscCode {
 
     ReturnTerminalId = esc!('Z'),
 
+    DecBackIndex = esc!('6'),
+
+    DecForwardIndex = esc!('9'),
+
     StringTerminator = esc!('\\'),
 
     PrivacyMessage = esc!('^'),
@@ -57,6 +61,11 @@ pub enum EscCode {
 
     DecNormalKeyPad = esc!('>'),
 
+    DecDoubleHeightTopHalfLine = esc!('#', '3'),
+    DecDoubleHeightBottomHalfLine = esc!('#', '4'),
+    DecSingleWidthLine = esc!('#', '5'),
+    DecDoubleWidthLine = esc!('#', '6'),
+
     DecLineDrawing = esc!('(', '0'),
 
     AsciiCharacterSet = esc!('(', 'B'),
@@ -140,5 +149,9 @@ mod test {
     fn test() {
         assert_eq!(parse("(0"), Esc::Code(EscCode::DecLineDrawing));
         assert_eq!(parse("(B"), Esc::Code(EscCode::AsciiCharacterSet));
+        assert_eq!(parse("#3"), Esc::Code(EscCode::DecDoubleHeightTopHalfLine));
+        assert_eq!(parse("#4"), Esc::Code(EscCode::DecDoubleHeightBottomHalfLine));
+        assert_eq!(parse("#5"), Esc::Code(EscCode::DecSingleWidthLine));
+        assert_eq!(parse("#6"), Esc::Code(EscCode::DecDoubleWidthLine));
     }
 }
@@ -41,7 +41,13 @@ impl Display for Action {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DeviceControlMode {
-    Enter { params: Vec<i64>, intermediates: Vec<u8>, ignored_extra_intermediates: bool },
+    Enter {
+        params: Vec<i64>,
+        intermediates: Vec<u8>,
+        ignored_extra_intermediates: bool,
+        /// The final byte that selects the device control function
+        byte: u8,
+    },
 
     Exit,
 
@@ -86,6 +92,7 @@ pub enum ControlCode {
 
     BPH = 0x82,
     NBH = 0x83,
+    IND = 0x84,
     NEL = 0x85,
     SSA = 0x86,
     ESA = 0x87,
@@ -18,7 +18,7 @@ impl Display for ColorOrQuery {
     fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
         match self {
             ColorOrQuery::Query => write!(f, "?"),
-            ColorOrQuery::Color(c) => write!(f, "{}", c.to_rgb_string()),
+            ColorOrQuery::Color(c) => write!(f, "{}", c.to_x11_16bit_rgb_string()),
         }
     }
 }
@@ -32,12 +32,37 @@ pub enum OperatingSystemCommand {
     ClearSelection(Selection),
     QuerySelection(Selection),
     SetSelection(Selection, String),
-    SystemNotification(String),
+    SystemNotification { title: Option<String>, body: String },
     ChangeColorNumber(Vec<ChangeColorPair>),
     ChangeDynamicColors(DynamicColorNumber, Vec<ColorOrQuery>),
+    ResetDynamicColor(DynamicColorNumber),
+    SemanticPrompt(SemanticPrompt),
+    ITermProprietary(ITermProprietary),
     Unspecified(Vec<Vec<u8>>),
 }
 
+/// iTerm2's `OSC 1337` extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ITermProprietary {
+    /// `SetUserVar=name=base64value`; `value` is decoded
+    SetUserVar { name: String, value: String },
+    /// Any other subcommand, such as inline images, kept verbatim
+    Unsupported(String),
+}
+
+/// FinalTerm's `OSC 133` markers, emitted by shell integration scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SemanticPrompt {
+    /// `A`: move to a fresh line and start the prompt
+    PromptStart,
+    /// `B`: the prompt ends and the command being typed starts
+    CommandStart,
+    /// `C`: the command was entered and its output starts
+    OutputStart,
+    /// `D ; status`: the command finished, with its exit status if known
+    CommandFinished(Option<i32>),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
 #[repr(u8)]
 pub enum DynamicColorNumber {
@@ -160,6 +185,51 @@ impl OperatingSystemCommand {
         }
     }
 
+    /// iTerm2's `OSC 9 ; body`; the body may itself contain semicolons.
+    fn parse_system_notification(osc: &[&[u8]]) -> anyhow::Result<Self> {
+        anyhow::ensure!(osc.len() >= 2, "wrong param count");
+        let body = String::from_utf8(osc[1..].join(&b';'))?;
+        Ok(OperatingSystemCommand::SystemNotification { title: None, body })
+    }
+
+    /// rxvt's `OSC 777 ; notify ; title ; body`
+    fn parse_rxvt_proprietary(osc: &[&[u8]]) -> anyhow::Result<Self> {
+        anyhow::ensure!(osc.len() >= 4 && osc[1] == b"notify", "unhandled OSC 777");
+        let title = String::from_utf8(osc[2].to_vec())?;
+        let body = String::from_utf8(osc[3..].join(&b';'))?;
+        Ok(OperatingSystemCommand::SystemNotification { title: Some(title), body })
+    }
+
+    /// `OSC 1337 ; payload`; payloads such as `File=` may contain semicolons.
+    fn parse_iterm_proprietary(osc: &[&[u8]]) -> anyhow::Result<Self> {
+        anyhow::ensure!(osc.len() >= 2, "wrong param count");
+        let payload = String::from_utf8(osc[1..].join(&b';'))?;
+        let command = if payload.starts_with("SetUserVar=") {
+            let mut parts = payload["SetUserVar=".len()..].splitn(2, '=');
+            let name = parts.next().unwrap_or_default().to_string();
+            let value = parts.next().ok_or_else(|| anyhow::anyhow!("SetUserVar without value"))?;
+            let value = String::from_utf8(base64::decode(value)?)?;
+            ITermProprietary::SetUserVar { name, value }
+        } else {
+            ITermProprietary::Unsupported(payload)
+        };
+        Ok(OperatingSystemCommand::ITermProprietary(command))
+    }
+
+    fn parse_semantic_prompt(osc: &[&[u8]]) -> anyhow::Result<Self> {
+        anyhow::ensure!(osc.len() >= 2, "wrong param count");
+        let marker = match osc[1] {
+            b"A" => SemanticPrompt::PromptStart,
+            b"B" => SemanticPrompt::CommandStart,
+            b"C" => SemanticPrompt::OutputStart,
+            b"D" => SemanticPrompt::CommandFinished(
+                osc.get(2).and_then(|status| str::from_utf8(status).ok()?.parse().ok()),
+            ),
+            _ => bail!("unhandled OSC 133: {:?}", osc),
+        };
+        Ok(OperatingSystemCommand::SemanticPrompt(marker))
+    }
+
     fn parse_change_color_number(osc: &[&[u8]]) -> anyhow::Result<Self> {
         let mut pairs = vec![];
         let mut iter = osc.iter();
@@ -202,8 +272,22 @@ impl OperatingSystemCommand {
         Ok(OperatingSystemCommand::ChangeDynamicColors(which_color, colors))
     }
 
+    fn parse_reset_dynamic_color_number(idx: u8) -> anyhow::Result<Self> {
+        let which_color: DynamicColorNumber = num::FromPrimitive::from_u8(idx)
+            .ok_or_else(|| anyhow::anyhow!("osc code is not a valid DynamicColorNumber!?"))?;
+
+        Ok(OperatingSystemCommand::ResetDynamicColor(which_color))
+    }
+
     fn internal_parse(osc: &[&[u8]]) -> anyhow::Result<Self> {
         anyhow::ensure!(!osc.is_empty(), "no params");
+        // dtterm's `OSC l title` and `OSC L label` have no separator after
+        // the code; they are also what `CSI 21 t` and `CSI 20 t` report
+        match osc[0].first() {
+            Some(b'l') => return Ok(OperatingSystemCommand::SetWindowTitle(title(osc, 1))),
+            Some(b'L') => return Ok(OperatingSystemCommand::SetIconName(title(osc, 1))),
+            _ => {}
+        }
         let p1str = String::from_utf8_lossy(osc[0]);
         let code: i64 = p1str.parse()?;
         let osc_code: OperatingSystemCommandCode =
@@ -211,12 +295,10 @@ impl OperatingSystemCommand {
 
         macro_rules! single_string {
             ($variant:ident) => {{
-                if osc.len() != 2 {
+                if osc.len() < 2 {
                     bail!("wrong param count");
                 }
-                let s = String::from_utf8(osc[1].to_vec())?;
-
-                Ok(OperatingSystemCommand::$variant(s))
+                Ok(OperatingSystemCommand::$variant(title(&osc[1..], 0)))
             }};
         }
 
@@ -227,8 +309,11 @@ impl OperatingSystemCommand {
             SetIconName => single_string!(SetIconName),
             SetHyperlink => Ok(OperatingSystemCommand::SetHyperlink(Hyperlink::parse(osc)?)),
             ManipulateSelectionData => Self::parse_selection(osc),
-            SystemNotification => single_string!(SystemNotification),
+            SystemNotification => Self::parse_system_notification(osc),
+            RxvtProprietary => Self::parse_rxvt_proprietary(osc),
             ChangeColorNumber => Self::parse_change_color_number(osc),
+            FinalTermSemanticPrompt => Self::parse_semantic_prompt(osc),
+            ITermProprietary => Self::parse_iterm_proprietary(osc),
             SetTextForegroundColor
             | SetTextBackgroundColor
             | SetTextCursorColor
@@ -241,12 +326,31 @@ impl OperatingSystemCommand {
             | SetHighlightForegroundColor => {
                 Self::parse_change_dynamic_color_number(osc_code as u8, osc)
             }
+            ResetTextForegroundColor
+            | ResetTextBackgroundColor
+            | ResetTextCursorColor
+            | ResetMouseForegroundColor
+            | ResetMouseBackgroundColor
+            | ResetTektronixForegroundColor
+            | ResetTektronixBackgroundColor
+            | ResetHighlightBackgroundColor
+            | ResetTektronixCursorColor
+            | ResetHighlightForegroundColor => {
+                Self::parse_reset_dynamic_color_number(osc_code as u8 - 100)
+            }
 
             _ => bail!("not impl"),
         }
     }
 }
 
+/// Joins the params that make up a title, skipping the first `skip` bytes,
+/// since the title may itself contain `;`. Invalid UTF-8 is replaced
+/// rather than losing the whole title.
+fn title(osc: &[&[u8]], skip: usize) -> String {
+    String::from_utf8_lossy(&osc.join(&b';')[skip..]).into_owned()
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, FromPrimitive)]
 pub enum OperatingSystemCommandCode {
     SetIconNameAndWindowTitle = 0,
@@ -275,7 +379,19 @@ pub enum OperatingSystemCommandCode {
     SetFont = 50,
     EmacsShell = 51,
     ManipulateSelectionData = 52,
+    ResetTextForegroundColor = 110,
+    ResetTextBackgroundColor = 111,
+    ResetTextCursorColor = 112,
+    ResetMouseForegroundColor = 113,
+    ResetMouseBackgroundColor = 114,
+    ResetTektronixForegroundColor = 115,
+    ResetTektronixBackgroundColor = 116,
+    ResetHighlightBackgroundColor = 117,
+    ResetTektronixCursorColor = 118,
+    ResetHighlightForegroundColor = 119,
+    FinalTermSemanticPrompt = 133,
     RxvtProprietary = 777,
+    ITermProprietary = 1337,
 }
 
 impl Display for OperatingSystemCommand {
@@ -306,7 +422,10 @@ impl Display for OperatingSystemCommand {
             ClearSelection(s) => write!(f, "52;{}", s)?,
             QuerySelection(s) => write!(f, "52;{};?", s)?,
             SetSelection(s, val) => write!(f, "52;{};{}", s, base64::encode(val))?,
-            SystemNotification(s) => write!(f, "9;{}", s)?,
+            SystemNotification { title: None, body } => write!(f, "9;{}", body)?,
+            SystemNotification { title: Some(title), body } => {
+                write!(f, "777;notify;{};{}", title, body)?
+            }
             ChangeColorNumber(specs) => {
                 write!(f, "4;")?;
                 for pair in specs {
@@ -319,8 +438,151 @@ impl Display for OperatingSystemCommand {
                     write!(f, ";{}", color)?
                 }
             }
+            ResetDynamicColor(color) => write!(f, "{}", 100 + *color as u8)?,
+            SemanticPrompt(marker) => match marker {
+                self::SemanticPrompt::PromptStart => write!(f, "133;A")?,
+                self::SemanticPrompt::CommandStart => write!(f, "133;B")?,
+                self::SemanticPrompt::OutputStart => write!(f, "133;C")?,
+                self::SemanticPrompt::CommandFinished(Some(status)) => {
+                    write!(f, "133;D;{}", status)?
+                }
+                self::SemanticPrompt::CommandFinished(None) => write!(f, "133;D")?,
+            },
+            ITermProprietary(self::ITermProprietary::SetUserVar { name, value }) => {
+                write!(f, "1337;SetUserVar={}={}", name, base64::encode(value))?
+            }
+            ITermProprietary(self::ITermProprietary::Unsupported(payload)) => {
+                write!(f, "1337;{}", payload)?
+            }
         };
         write!(f, "\x07")?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(osc: &[&str], expected: &str) -> OperatingSystemCommand {
+        let params: Vec<&[u8]> = osc.iter().map(|s| s.as_bytes()).collect();
+        let result = OperatingSystemCommand::parse(&params);
+        assert_eq!(format!("{}", result), expected);
+        result
+    }
+
+    #[test]
+    fn titles() {
+        let parse_bytes = |params: &[&[u8]]| OperatingSystemCommand::parse(params);
+        assert_eq!(
+            parse_bytes(&[b"2", "h\u{e9}llo \u{2713}".as_bytes()]),
+            OperatingSystemCommand::SetWindowTitle("h\u{e9}llo \u{2713}".to_string())
+        );
+        assert_eq!(
+            parse_bytes(&[b"0", b"bad\xffbyte"]),
+            OperatingSystemCommand::SetIconNameAndWindowTitle("bad\u{fffd}byte".to_string())
+        );
+        assert_eq!(
+            parse_bytes(&[b"2", b"a", b"b"]),
+            OperatingSystemCommand::SetWindowTitle("a;b".to_string())
+        );
+        assert_eq!(
+            parse_bytes(&[b"lvim", b"\xe2\x9c"]),
+            OperatingSystemCommand::SetWindowTitle("vim;\u{fffd}".to_string())
+        );
+        assert_eq!(
+            parse_bytes(&[b"Licon"]),
+            OperatingSystemCommand::SetIconName("icon".to_string())
+        );
+    }
+
+    #[test]
+    fn system_notification() {
+        assert_eq!(
+            parse(&["9", "build done; 0 errors"], "\x1b]9;build done; 0 errors\x07"),
+            OperatingSystemCommand::SystemNotification {
+                title: None,
+                body: "build done; 0 errors".to_string()
+            }
+        );
+        assert_eq!(
+            parse(&["777", "notify", "make", "done"], "\x1b]777;notify;make;done\x07"),
+            OperatingSystemCommand::SystemNotification {
+                title: Some("make".to_string()),
+                body: "done".to_string()
+            }
+        );
+        assert_eq!(
+            parse(&["777", "preexec"], "\x1b]777;preexec\x07"),
+            OperatingSystemCommand::Unspecified(vec![b"777".to_vec(), b"preexec".to_vec()])
+        );
+    }
+
+    #[test]
+    fn semantic_prompt() {
+        assert_eq!(
+            parse(&["133", "A"], "\x1b]133;A\x07"),
+            OperatingSystemCommand::SemanticPrompt(SemanticPrompt::PromptStart)
+        );
+        assert_eq!(
+            parse(&["133", "C"], "\x1b]133;C\x07"),
+            OperatingSystemCommand::SemanticPrompt(SemanticPrompt::OutputStart)
+        );
+        assert_eq!(
+            parse(&["133", "D", "127"], "\x1b]133;D;127\x07"),
+            OperatingSystemCommand::SemanticPrompt(SemanticPrompt::CommandFinished(Some(127)))
+        );
+        assert_eq!(
+            parse(&["133", "D"], "\x1b]133;D\x07"),
+            OperatingSystemCommand::SemanticPrompt(SemanticPrompt::CommandFinished(None))
+        );
+    }
+
+    #[test]
+    fn iterm_proprietary() {
+        assert_eq!(
+            parse(
+                &["1337", "SetUserVar=host=ZXhhbXBsZS5jb20="],
+                "\x1b]1337;SetUserVar=host=ZXhhbXBsZS5jb20=\x07"
+            ),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::SetUserVar {
+                name: "host".to_string(),
+                value: "example.com".to_string()
+            })
+        );
+        assert_eq!(
+            parse(
+                &["1337", "File=name=eA==", "size=1:eA=="],
+                "\x1b]1337;File=name=eA==;size=1:eA==\x07"
+            ),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::Unsupported(
+                "File=name=eA==;size=1:eA==".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn dynamic_colors() {
+        assert_eq!(
+            parse(&["10", "?"], "\x1b]10;?\x07"),
+            OperatingSystemCommand::ChangeDynamicColors(
+                DynamicColorNumber::TextForegroundColor,
+                vec![ColorOrQuery::Query]
+            )
+        );
+        assert_eq!(
+            parse(&["11", "#102030"], "\x1b]11;rgb:1010/2020/3030\x07"),
+            OperatingSystemCommand::ChangeDynamicColors(
+                DynamicColorNumber::TextBackgroundColor,
+                vec![ColorOrQuery::Color(RgbColor::new(0x10, 0x20, 0x30))]
+            )
+        );
+        assert_eq!(
+            parse(&["12", "rgb:ffff/8/0"], "\x1b]12;rgb:ffff/8888/0000\x07"),
+            OperatingSystemCommand::ChangeDynamicColors(
+                DynamicColorNumber::TextCursorColor,
+                vec![ColorOrQuery::Color(RgbColor::new(0xff, 0x88, 0))]
+            )
+        );
+    }
+}
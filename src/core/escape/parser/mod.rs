@@ -1,6 +1,7 @@
 use crate::core::escape::{Action, DeviceControlMode, Esc, OperatingSystemCommand, CSI};
 use num;
-use vtparse::{VTActor, VTParser};
+use smallvec::SmallVec;
+use vtparse::{CsiParam, VTActor, VTParser};
 
 pub struct Parser {
     state_machine: VTParser,
@@ -41,6 +42,7 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
 
     fn dcs_hook(
         &mut self,
+        byte: u8,
         params: &[i64],
         intermediates: &[u8],
         ignored_extra_intermediates: bool,
@@ -49,6 +51,7 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
             params: params.to_vec(),
             intermediates: intermediates.to_vec(),
             ignored_extra_intermediates,
+            byte,
         })));
     }
 
@@ -65,16 +68,43 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
         (self.callback)(Action::OperatingSystemCommand(Box::new(osc)));
     }
 
-    fn csi_dispatch(
-        &mut self,
-        params: &[i64],
-        intermediates: &[u8],
-        ignored_extra_intermediates: bool,
-        control: u8,
-    ) {
-        for action in
-            CSI::parse(params, intermediates, ignored_extra_intermediates, control as char)
-        {
+    fn csi_dispatch(&mut self, raw: &[CsiParam], ignored_extra_intermediates: bool, control: u8) {
+        // vtparse hands over the separators and any private marker or
+        // intermediates inline with the numbers; split them back out,
+        // remembering which parameters were introduced by a `:`
+        let mut params = SmallVec::<[i64; 16]>::new();
+        let mut subparams = SmallVec::<[bool; 16]>::new();
+        let mut intermediates = SmallVec::<[u8; 2]>::new();
+        let mut current = None;
+        let mut is_subparam = false;
+        let mut any_params = false;
+        for param in raw {
+            match param {
+                CsiParam::Integer(value) => {
+                    current = Some(*value);
+                    any_params = true;
+                }
+                CsiParam::P(sep @ b';') | CsiParam::P(sep @ b':') => {
+                    params.push(current.take().unwrap_or(0));
+                    subparams.push(is_subparam);
+                    is_subparam = *sep == b':';
+                    any_params = true;
+                }
+                CsiParam::P(byte) => intermediates.push(*byte),
+            }
+        }
+        if any_params {
+            params.push(current.unwrap_or(0));
+            subparams.push(is_subparam);
+        }
+
+        for action in CSI::parse(
+            &params,
+            &subparams,
+            &intermediates,
+            ignored_extra_intermediates,
+            control as char,
+        ) {
             (self.callback)(Action::CSI(action));
         }
     }
@@ -91,4 +121,66 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
             control,
         )));
     }
+
+    fn apc_dispatch(&mut self, _data: Vec<u8>) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::cell::{Intensity, Underline};
+    use crate::core::color::{ColorSpec, RgbColor};
+    use crate::core::escape::csi::{DecPrivateMode, DecPrivateModeCode, Mode, Sgr};
+
+    fn parse_sgr(chunks: &[&str]) -> Vec<Sgr> {
+        let mut parser = Parser::new();
+        let mut sgr = vec![];
+        for chunk in chunks {
+            parser.parse(chunk.as_bytes(), |action| match action {
+                Action::CSI(CSI::Sgr(s)) => sgr.push(s),
+                other => panic!("unexpected {:?}", other),
+            });
+        }
+        sgr
+    }
+
+    #[test]
+    fn colon_underline_style() {
+        assert_eq!(parse_sgr(&["\x1b[4:3m"]), vec![Sgr::Underline(Underline::Curly)]);
+        assert_eq!(parse_sgr(&["\x1b[4:0m"]), vec![Sgr::Underline(Underline::None)]);
+        assert_eq!(
+            parse_sgr(&["\x1b[4;3m"]),
+            vec![Sgr::Underline(Underline::Single), Sgr::Italic(true)]
+        );
+    }
+
+    #[test]
+    fn colon_true_color() {
+        let red = ColorSpec::TrueColor(RgbColor::new(255, 0, 0));
+        assert_eq!(parse_sgr(&["\x1b[38:2::255:0:0m"]), vec![Sgr::Foreground(red)]);
+        assert_eq!(
+            parse_sgr(&["\x1b[1;48:2:255:0:0;", "38:5:2m"]),
+            vec![
+                Sgr::Intensity(Intensity::Bold),
+                Sgr::Background(red),
+                Sgr::Foreground(ColorSpec::PaletteIndex(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn colon_forms_after_8bit_csi() {
+        let mut parser = Parser::new();
+        let mut actions = vec![];
+        parser.parse(b"\x9b4:3m\x9b?25l", |action| actions.push(action));
+        assert_eq!(
+            actions,
+            vec![
+                Action::CSI(CSI::Sgr(Sgr::Underline(Underline::Curly))),
+                Action::CSI(CSI::Mode(Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                    DecPrivateModeCode::ShowCursor
+                )))),
+            ]
+        );
+    }
 }
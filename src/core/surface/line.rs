@@ -1,4 +1,4 @@
-use crate::core::cell::{Cell, CellAttributes};
+use crate::core::cell::{Cell, CellAttributes, TabGap};
 use crate::core::cellcluster::CellCluster;
 use crate::core::hyperlink::Rule;
 use bitflags::bitflags;
@@ -15,9 +15,22 @@ bitflags! {
         const HAS_HYPERLINK = 1<<1;
         const SCANNED_IMPLICIT_HYPERLINKS = 1<<2;
         const HAS_IMPLICIT_HYPERLINKS = 1<<3;
+        const DOUBLE_WIDTH = 1<<4;
+        const DOUBLE_HEIGHT_TOP = 1<<5;
+        const DOUBLE_HEIGHT_BOTTOM = 1<<6;
+        const PROMPT = 1<<7;
     }
 }
 
+/// The DEC line attribute selected by `ESC # 3` through `ESC # 6`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineSize {
+    Single,
+    DoubleWidth,
+    DoubleHeightTop,
+    DoubleHeightBottom,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Line {
     bits: LineBits,
@@ -79,6 +92,40 @@ impl Line {
         self.bits &= !LineBits::DIRTY;
     }
 
+    pub fn line_size(&self) -> LineSize {
+        if self.bits.contains(LineBits::DOUBLE_HEIGHT_TOP) {
+            LineSize::DoubleHeightTop
+        } else if self.bits.contains(LineBits::DOUBLE_HEIGHT_BOTTOM) {
+            LineSize::DoubleHeightBottom
+        } else if self.bits.contains(LineBits::DOUBLE_WIDTH) {
+            LineSize::DoubleWidth
+        } else {
+            LineSize::Single
+        }
+    }
+
+    pub fn set_line_size(&mut self, size: LineSize) {
+        self.bits &= !(LineBits::DOUBLE_WIDTH
+            | LineBits::DOUBLE_HEIGHT_TOP
+            | LineBits::DOUBLE_HEIGHT_BOTTOM);
+        self.bits |= match size {
+            LineSize::Single => LineBits::NONE,
+            LineSize::DoubleWidth => LineBits::DOUBLE_WIDTH,
+            LineSize::DoubleHeightTop => LineBits::DOUBLE_HEIGHT_TOP,
+            LineSize::DoubleHeightBottom => LineBits::DOUBLE_HEIGHT_BOTTOM,
+        };
+        self.bits |= LineBits::DIRTY;
+    }
+
+    /// Whether a shell prompt starts on this line, per `OSC 133 ; A`.
+    pub fn is_prompt(&self) -> bool {
+        self.bits.contains(LineBits::PROMPT)
+    }
+
+    pub fn set_prompt(&mut self, prompt: bool) {
+        self.bits.set(LineBits::PROMPT, prompt);
+    }
+
     pub fn invalidate_implicit_hyperlinks(&mut self) {
         if (self.bits & (LineBits::SCANNED_IMPLICIT_HYPERLINKS | LineBits::HAS_IMPLICIT_HYPERLINKS))
             == LineBits::NONE
@@ -197,6 +244,49 @@ impl Line {
         s
     }
 
+    /// Like `columns_as_str`, but gives back a `\t` for each tab gap
+    /// that is still intact.
+    pub fn columns_as_str_with_tabs(&self, range: Range<usize>) -> String {
+        let mut s = String::new();
+        let mut in_gap = false;
+        for (n, c) in self.visible_cells() {
+            if n < range.start {
+                continue;
+            }
+            if n >= range.end {
+                break;
+            }
+            in_gap = match c.attrs().tab_gap() {
+                TabGap::Start => {
+                    s.push('\t');
+                    true
+                }
+                TabGap::Continued if in_gap => true,
+                _ => {
+                    s.push_str(c.str());
+                    false
+                }
+            };
+        }
+        s
+    }
+
+    /// Records that a tab moved the cursor over `cols`, provided they
+    /// are all blank.
+    pub fn mark_tab_gap(&mut self, cols: Range<usize>) {
+        let blank = match self.cells.get(cols.clone()) {
+            Some(cells) => cells.iter().all(|cell| cell.str() == " "),
+            None => false,
+        };
+        if !blank {
+            return;
+        }
+        for x in cols.clone() {
+            let gap = if x == cols.start { TabGap::Start } else { TabGap::Continued };
+            self.cells[x].attrs_mut().set_tab_gap(gap);
+        }
+    }
+
     pub fn set_cell(&mut self, idx: usize, cell: Cell) -> &Cell {
         let width = cell.width();
 
@@ -281,6 +371,75 @@ impl Line {
     pub fn cells(&self) -> &[Cell] {
         &self.cells
     }
+
+    pub fn last_cell_was_wrapped(&self) -> bool {
+        self.cells.last().map(|cell| cell.attrs().wrapped()).unwrap_or(false)
+    }
+
+    pub fn set_last_cell_was_wrapped(&mut self, wrapped: bool) {
+        if let Some(cell) = self.cells.last_mut() {
+            cell.attrs_mut().set_wrapped(wrapped);
+        }
+    }
+}
+
+/// A `Line` packed for storage: the text of its cells as one string,
+/// plus runs of cells that share attributes. Mostly default text then
+/// costs little more than its UTF-8.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressedLine {
+    bits: LineBits,
+    text: String,
+    /// (number of cells, attributes) for each run of equal attributes
+    attrs: Vec<(usize, CellAttributes)>,
+}
+
+impl CompressedLine {
+    pub fn expand(&self) -> Line {
+        let mut runs =
+            self.attrs.iter().flat_map(|(len, attrs)| std::iter::repeat(attrs).take(*len));
+        let cells = self
+            .text
+            .graphemes(true)
+            .zip(&mut runs)
+            .map(|(text, attrs)| Cell::new_grapheme(text, attrs.clone()))
+            .collect();
+        Line { bits: self.bits, cells }
+    }
+
+    pub fn set_dirty(&mut self) {
+        self.bits |= LineBits::DIRTY;
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.bits &= !LineBits::DIRTY;
+    }
+
+    pub fn has_hyperlink(&self) -> bool {
+        (self.bits & (LineBits::HAS_HYPERLINK | LineBits::HAS_IMPLICIT_HYPERLINKS))
+            != LineBits::NONE
+    }
+}
+
+impl Line {
+    /// Packs the line, or returns None when its cells don't split back
+    /// apart from the joined text, as happens for a cell holding only a
+    /// combining mark; such lines have to be kept as they are.
+    pub fn compress(&self) -> Option<CompressedLine> {
+        let text: String = self.cells.iter().map(Cell::str).collect();
+        if text.graphemes(true).ne(self.cells.iter().map(Cell::str)) {
+            return None;
+        }
+
+        let mut attrs: Vec<(usize, CellAttributes)> = vec![];
+        for cell in &self.cells {
+            match attrs.last_mut() {
+                Some((len, run)) if run == cell.attrs() => *len += 1,
+                _ => attrs.push((1, cell.attrs().clone())),
+            }
+        }
+        Some(CompressedLine { bits: self.bits, text, attrs })
+    }
 }
 
 impl<'a> From<&'a str> for Line {
@@ -288,3 +447,43 @@ impl<'a> From<&'a str> for Line {
         Line::from_text(s, &CellAttributes::default())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::cell::Intensity;
+    use crate::core::color::ColorAttribute;
+    use crate::core::hyperlink::Hyperlink;
+
+    #[test]
+    fn compressed_lines_expand_to_the_original() {
+        let mut line = Line::with_width(12);
+        let mut bold = CellAttributes::default();
+        bold.set_intensity(Intensity::Bold);
+        let mut linked = CellAttributes::default();
+        linked.set_hyperlink(Some(Arc::new(Hyperlink::new_implicit("http://x"))));
+        linked.set_background(ColorAttribute::PaletteIndex(4));
+        for (idx, c) in "abc".chars().enumerate() {
+            line.set_cell(idx, Cell::new(c, bold.clone()));
+        }
+        line.set_cell(4, Cell::new('\u{4e2d}', linked.clone()));
+        line.set_cell(6, Cell::new_grapheme("e\u{301}", linked));
+        line.set_last_cell_was_wrapped(true);
+
+        let compressed = line.compress().unwrap();
+        assert_eq!(compressed.text, "abc \u{4e2d} e\u{301}     ");
+        assert_eq!(
+            compressed.attrs.iter().map(|(len, _)| *len).collect::<Vec<_>>(),
+            [3, 1, 3, 4, 1]
+        );
+        assert_eq!(compressed.expand(), line);
+    }
+
+    #[test]
+    fn lone_combining_marks_are_not_compressed() {
+        let mut line = Line::with_width(2);
+        line.set_cell(0, Cell::new('e', CellAttributes::default()));
+        line.set_cell(1, Cell::new('\u{301}', CellAttributes::default()));
+        assert_eq!(line.compress(), None);
+    }
+}
@@ -0,0 +1,43 @@
+use thiserror::Error;
+
+/// Failures from font loading, pty creation, config parsing and shader
+/// compilation; everything else still reports through `anyhow`.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to load fonts: {0:#}")]
+    FontLoad(anyhow::Error),
+    #[error("failed to open pty: {0:#}")]
+    PtyOpen(anyhow::Error),
+    #[error("failed to parse {what}: {reason}")]
+    Parse { what: &'static str, reason: String },
+    #[error("render error: {0}")]
+    Render(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            Error::FontLoad(anyhow!("no match for monospace")).to_string(),
+            "failed to load fonts: no match for monospace"
+        );
+        assert_eq!(
+            Error::PtyOpen(anyhow!("out of ptys").context("openpty")).to_string(),
+            "failed to open pty: openpty: out of ptys"
+        );
+        assert_eq!(
+            Error::Parse { what: "FontLocatorSelection", reason: "bogus".into() }.to_string(),
+            "failed to parse FontLocatorSelection: bogus"
+        );
+        assert_eq!(
+            Error::Render("Failed to compile shaders".into()).to_string(),
+            "render error: Failed to compile shaders"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::config::FontAntiAliasing;
 use crate::font::locator::FontDataHandle;
 use anyhow::{anyhow, bail, Context};
 pub use freetype::freetype::*;
@@ -17,10 +18,42 @@ fn ft_result<T>(err: FT_Error, t: T) -> anyhow::Result<T> {
     }
 }
 
+pub fn render_mode_for(antialias: FontAntiAliasing) -> FT_Render_Mode {
+    match antialias {
+        FontAntiAliasing::None => FT_Render_Mode::FT_RENDER_MODE_MONO,
+        FontAntiAliasing::Grayscale => FT_Render_Mode::FT_RENDER_MODE_LIGHT,
+        FontAntiAliasing::Subpixel => FT_Render_Mode::FT_RENDER_MODE_LCD,
+    }
+}
+
 pub fn compute_load_flags_for_mode(render_mode: FT_Render_Mode) -> i32 {
     FT_LOAD_COLOR as i32 | (render_mode as i32) << 16
 }
 
+/// Picks the smallest fixed strike that is at least `pixel_size` tall, or
+/// the largest strike if none are; scaling a bitmap down looks better than
+/// scaling it up.
+pub fn closest_fixed_size(heights: &[i16], pixel_size: f64) -> usize {
+    let mut best: Option<(usize, i16)> = None;
+    for (idx, &height) in heights.iter().enumerate() {
+        best = match best {
+            None => Some((idx, height)),
+            Some((_, best_height)) => {
+                let fits = f64::from(height) >= pixel_size;
+                let best_fits = f64::from(best_height) >= pixel_size;
+                if (fits && (!best_fits || height < best_height))
+                    || (!fits && !best_fits && height > best_height)
+                {
+                    Some((idx, height))
+                } else {
+                    best
+                }
+            }
+        };
+    }
+    best.map(|(idx, _)| idx).unwrap_or(0)
+}
+
 pub struct Face {
     pub face: FT_Face,
     _bytes: Vec<u8>,
@@ -36,6 +69,7 @@ impl Drop for Face {
 
 impl Face {
     pub fn set_font_size(&mut self, size: f64, dpi: u32) -> anyhow::Result<(f64, f64)> {
+        let pixel_size = size * f64::from(dpi) / 72.0;
         let size = (size * 64.0) as FT_F26Dot6;
 
         let (cell_width, cell_height) = match self.set_char_size(size, size, dpi, dpi) {
@@ -49,22 +83,10 @@ impl Face {
                     return Err(err);
                 }
 
-                let mut best = 0;
-                let mut best_size = 0;
-                let mut cell_width = 0;
-                let mut cell_height = 0;
-
-                for (idx, info) in sizes.iter().enumerate() {
-                    let size = best_size.max(info.height);
-                    if size > best_size {
-                        best = idx;
-                        best_size = size;
-                        cell_width = info.width;
-                        cell_height = info.height;
-                    }
-                }
+                let heights: Vec<i16> = sizes.iter().map(|info| info.height).collect();
+                let best = closest_fixed_size(&heights, pixel_size);
                 self.select_size(best)?;
-                (f64::from(cell_width), f64::from(cell_height))
+                (f64::from(sizes[best].width), f64::from(sizes[best].height))
             }
         };
 
@@ -1,14 +1,12 @@
 use crate::config::FontAttributes;
+use crate::error::Result;
 use crate::font::locator::{FontDataHandle, FontLocator};
 use font_loader::system_fonts;
 
 pub struct FontLoaderFontLocator {}
 
 impl FontLocator for FontLoaderFontLocator {
-    fn load_fonts(
-        &self,
-        fonts_selection: &[FontAttributes],
-    ) -> anyhow::Result<Vec<FontDataHandle>> {
+    fn load_fonts(&self, fonts_selection: &[FontAttributes]) -> Result<Vec<FontDataHandle>> {
         let mut fonts = Vec::new();
         for font_attr in fonts_selection {
             let mut font_props =
@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 use crate::config::FontAttributes;
-use anyhow::{anyhow, Error};
+use crate::error::{Error, Result};
 use serde_derive::*;
 use std::path::PathBuf;
 use std::sync::Mutex;
@@ -16,8 +16,7 @@ pub enum FontDataHandle {
 }
 
 pub trait FontLocator {
-    fn load_fonts(&self, fonts_selection: &[FontAttributes])
-        -> anyhow::Result<Vec<FontDataHandle>>;
+    fn load_fonts(&self, fonts_selection: &[FontAttributes]) -> Result<Vec<FontDataHandle>>;
 }
 
 #[derive(Debug, Deserialize, Clone, Copy)]
@@ -74,11 +73,14 @@ impl std::str::FromStr for FontLocatorSelection {
         match s.to_lowercase().as_ref() {
             "fontconfig" => Ok(Self::FontConfig),
             "fontloader" => Ok(Self::FontLoader),
-            _ => Err(anyhow!(
-                "{} is not a valid FontLocatorSelection variant, possible values are {:?}",
-                s,
-                Self::variants()
-            )),
+            _ => Err(Error::Parse {
+                what: "FontLocatorSelection",
+                reason: format!(
+                    "{} is not a valid variant, possible values are {:?}",
+                    s,
+                    Self::variants()
+                ),
+            }),
         }
     }
 }
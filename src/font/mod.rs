@@ -22,6 +22,21 @@ use crate::font::shaper::{FontShaper, FontShaperSelection};
 use super::config::{Config, TextStyle};
 use crate::term::CellAttributes;
 
+/// How much larger than at the 96 dpi baseline glyphs must be drawn on a
+/// display of `dpi`.
+pub fn dpi_scale(dpi: usize) -> f64 {
+    dpi as f64 / 96.
+}
+
+const MIN_FONT_SCALE: f64 = 0.25;
+const MAX_FONT_SCALE: f64 = 4.0;
+
+/// Keeps zooming from shrinking cells to nothing or growing a single
+/// cell past the window.
+pub fn clamp_font_scale(font_scale: f64) -> f64 {
+    font_scale.max(MIN_FONT_SCALE).min(MAX_FONT_SCALE)
+}
+
 pub struct LoadedFont {
     rasterizers: Vec<Box<dyn FontRasterizer>>,
     shaper: Box<dyn FontShaper>,
@@ -85,12 +100,16 @@ impl FontConfiguration {
         let handles = self.locator.load_fonts(&attributes)?;
         let mut rasterizers = vec![];
         for handle in &handles {
-            rasterizers.push(FontRasterizerSelection::get_default().new_rasterizer(&handle)?);
+            rasterizers.push(
+                FontRasterizerSelection::get_default()
+                    .new_rasterizer(&handle, self.config.font_antialias)?,
+            );
         }
-        let shaper = FontShaperSelection::get_default().new_shaper(&handles)?;
+        let shaper = FontShaperSelection::get_default()
+            .new_shaper(&handles, &self.config.font_features())?;
 
         let font_size = self.config.font_size * *self.font_scale.borrow();
-        let dpi = *self.dpi_scale.borrow() as u32 * self.config.dpi as u32;
+        let dpi = (*self.dpi_scale.borrow() * self.config.dpi) as u32;
         let metrics = shaper.metrics(font_size, dpi)?;
 
         let loaded = Rc::new(LoadedFont { rasterizers, shaper, metrics, font_size, dpi });
@@ -111,6 +130,10 @@ impl FontConfiguration {
         self.resolve_font(&self.config.font)
     }
 
+    pub fn custom_block_glyphs(&self) -> bool {
+        self.config.custom_block_glyphs
+    }
+
     pub fn get_font_scale(&self) -> f64 {
         *self.font_scale.borrow()
     }
@@ -131,6 +154,12 @@ impl FontConfiguration {
         Ok(metrics)
     }
 
+    /// The (width, height) in whole pixels of a cell of the default font.
+    pub fn cell_pixel_size(&self) -> Result<(usize, usize), Error> {
+        let metrics = self.default_font_metrics()?;
+        Ok((metrics.cell_width.get().ceil() as usize, metrics.cell_height.get().ceil() as usize))
+    }
+
     pub fn match_style(&self, attrs: &CellAttributes) -> &TextStyle {
         macro_rules! attr_match {
             ($ident:ident, $rule:expr) => {
@@ -156,3 +185,59 @@ impl FontConfiguration {
         &self.config.font
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dpi_scale_follows_the_display() {
+        assert_eq!(dpi_scale(96), 1.0);
+        // Moving from a retina display to a standard one and back
+        assert_eq!(dpi_scale(192), 2.0);
+        assert_eq!(dpi_scale(96) / dpi_scale(192), 0.5);
+        assert_eq!(dpi_scale(144), 1.5);
+    }
+
+    #[test]
+    fn antialias_selects_the_render_mode_and_load_target() {
+        use crate::config::FontAntiAliasing;
+        let flags = |antialias| {
+            let mode = ftwrap::render_mode_for(antialias);
+            (mode, ftwrap::compute_load_flags_for_mode(mode))
+        };
+        const LOAD_COLOR: i32 = 1 << 20;
+        assert_eq!(
+            flags(FontAntiAliasing::None),
+            (ftwrap::FT_Render_Mode::FT_RENDER_MODE_MONO, LOAD_COLOR | 2 << 16)
+        );
+        assert_eq!(
+            flags(FontAntiAliasing::Grayscale),
+            (ftwrap::FT_Render_Mode::FT_RENDER_MODE_LIGHT, LOAD_COLOR | 1 << 16)
+        );
+        assert_eq!(
+            flags(FontAntiAliasing::Subpixel),
+            (ftwrap::FT_Render_Mode::FT_RENDER_MODE_LCD, LOAD_COLOR | 3 << 16)
+        );
+    }
+
+    #[test]
+    fn font_scale_is_clamped() {
+        assert_eq!(clamp_font_scale(1.1), 1.1);
+        assert_eq!(clamp_font_scale(0.01), MIN_FONT_SCALE);
+        assert_eq!(clamp_font_scale(100.), MAX_FONT_SCALE);
+    }
+
+    #[test]
+    fn change_scaling_drops_loaded_fonts() {
+        let fonts = FontConfiguration::new(Arc::new(Config::default()));
+        fonts.default_font_metrics().unwrap();
+        assert!(!fonts.fonts.borrow().is_empty());
+        assert!(fonts.metrics.borrow().is_some());
+
+        fonts.change_scaling(1.1, 1.0);
+        assert!(fonts.fonts.borrow().is_empty());
+        assert!(fonts.metrics.borrow().is_none());
+        assert_eq!(fonts.get_font_scale(), 1.1);
+    }
+}
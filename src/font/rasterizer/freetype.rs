@@ -1,6 +1,8 @@
+use crate::config::FontAntiAliasing;
 use crate::font::locator::FontDataHandle;
 use crate::font::rasterizer::FontRasterizer;
 use crate::font::{ftwrap, RasterizedGlyph};
+use crate::window::bitmaps::Image;
 use crate::window::PixelLength;
 use freetype::freetype::FT_GlyphSlotRec_;
 use std::cell::RefCell;
@@ -9,6 +11,8 @@ use std::slice;
 
 pub struct FreeTypeRasterizer {
     has_color: bool,
+    is_scalable: bool,
+    render_mode: ftwrap::FT_Render_Mode,
     face: RefCell<ftwrap::Face>,
     _lib: ftwrap::Library,
 }
@@ -22,12 +26,12 @@ impl FontRasterizer for FreeTypeRasterizer {
     ) -> anyhow::Result<RasterizedGlyph> {
         self.face.borrow_mut().set_font_size(size, dpi)?;
 
-        let render_mode = ftwrap::FT_Render_Mode::FT_RENDER_MODE_LIGHT;
-
+        let render_mode = self.render_mode;
         let load_flags = ftwrap::compute_load_flags_for_mode(render_mode);
 
         let mut face = self.face.borrow_mut();
         let descender = unsafe { (*(*face.face).size).metrics.descender as f64 / 64.0 };
+        let y_ppem = unsafe { f64::from((*(*face.face).size).metrics.y_ppem) };
         let ft_glyph = face.load_and_render_glyph(glyph_pos, load_flags, render_mode)?;
 
         let mode: ftwrap::FT_Pixel_Mode =
@@ -41,7 +45,13 @@ impl FontRasterizer for FreeTypeRasterizer {
         let glyph = match mode {
             ftwrap::FT_Pixel_Mode::FT_PIXEL_MODE_LCD => self.rasterize_lcd(pitch, ft_glyph, data),
             ftwrap::FT_Pixel_Mode::FT_PIXEL_MODE_BGRA => {
-                self.rasterize_bgra(pitch, descender, ft_glyph, data)
+                let glyph = self.rasterize_bgra(pitch, descender, ft_glyph, data);
+                if self.is_scalable || y_ppem == 0. {
+                    glyph
+                } else {
+                    // A fixed strike rarely matches the requested size
+                    scale_glyph(glyph, size * f64::from(dpi) / 72.0 / y_ppem)
+                }
             }
             ftwrap::FT_Pixel_Mode::FT_PIXEL_MODE_GRAY => self.rasterize_gray(pitch, ft_glyph, data),
             ftwrap::FT_Pixel_Mode::FT_PIXEL_MODE_MONO => self.rasterize_mono(pitch, ft_glyph, data),
@@ -141,10 +151,12 @@ impl FreeTypeRasterizer {
             let src_offset = y * pitch as usize;
             let dest_offset = y * width * 4;
             for x in 0..width {
-                let blue = data[src_offset + (x * 3)];
+                let red = data[src_offset + (x * 3)];
                 let green = data[src_offset + (x * 3) + 1];
-                let red = data[src_offset + (x * 3) + 2];
-                let alpha = red | green | blue;
+                let blue = data[src_offset + (x * 3) + 2];
+                // The coverage of each subpixel stays in its channel; the
+                // glyph shader blends them separately
+                let alpha = red.max(green).max(blue);
                 rgba[dest_offset + (x * 4)] = red;
                 rgba[dest_offset + (x * 4) + 1] = green;
                 rgba[dest_offset + (x * 4) + 2] = blue;
@@ -158,7 +170,7 @@ impl FreeTypeRasterizer {
             width,
             bearing_x: PixelLength::new(ft_glyph.bitmap_left as f64),
             bearing_y: PixelLength::new(ft_glyph.bitmap_top as f64),
-            has_color: self.has_color,
+            has_color: false,
         }
     }
 
@@ -257,12 +269,85 @@ impl FreeTypeRasterizer {
         }
     }
 
-    pub fn from_locator(handle: &FontDataHandle) -> anyhow::Result<Self> {
+    pub fn from_locator(
+        handle: &FontDataHandle,
+        antialias: FontAntiAliasing,
+    ) -> anyhow::Result<Self> {
         let lib = ftwrap::Library::new()?;
         let face = lib.face_from_locator(handle)?;
         let has_color = unsafe {
             (((*face.face).face_flags as u32) & (ftwrap::FT_FACE_FLAG_COLOR as u32)) != 0
         };
-        Ok(Self { _lib: lib, face: RefCell::new(face), has_color })
+        let is_scalable = unsafe {
+            (((*face.face).face_flags as u32) & (ftwrap::FT_FACE_FLAG_SCALABLE as u32)) != 0
+        };
+        Ok(Self {
+            _lib: lib,
+            face: RefCell::new(face),
+            has_color,
+            is_scalable,
+            render_mode: ftwrap::render_mode_for(antialias),
+        })
+    }
+}
+
+fn scale_glyph(glyph: RasterizedGlyph, scale: f64) -> RasterizedGlyph {
+    if glyph.width == 0 || glyph.height == 0 || (scale - 1.0).abs() < f64::EPSILON {
+        return glyph;
+    }
+
+    let width = ((glyph.width as f64 * scale).round() as usize).max(1);
+    let height = ((glyph.height as f64 * scale).round() as usize).max(1);
+    let image = Image::with_rgba32(glyph.width, glyph.height, 4 * glyph.width, &glyph.data)
+        .resize(width, height);
+
+    // Image stores BGRA; swap back to the RGBA that RasterizedGlyph carries
+    let mut data: Vec<u8> = image.into();
+    for pixel in data.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+
+    RasterizedGlyph {
+        data,
+        height,
+        width,
+        bearing_x: glyph.bearing_x * scale,
+        bearing_y: glyph.bearing_y * scale,
+        has_color: glyph.has_color,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn closest_fixed_size() {
+        assert_eq!(ftwrap::closest_fixed_size(&[128], 16.), 0);
+        assert_eq!(ftwrap::closest_fixed_size(&[16, 32, 64], 20.), 1);
+        assert_eq!(ftwrap::closest_fixed_size(&[64, 16, 32], 72.), 0);
+    }
+
+    #[test]
+    fn color_strike_is_scaled_to_cell_size() {
+        let strike = 128;
+        let pixel = [0x10, 0x20, 0x30, 0xff];
+        let glyph = RasterizedGlyph {
+            data: pixel.iter().cycle().take(strike * strike * 4).cloned().collect(),
+            height: strike,
+            width: strike,
+            bearing_x: PixelLength::new(8.),
+            bearing_y: PixelLength::new(112.),
+            has_color: true,
+        };
+
+        let glyph = scale_glyph(glyph, 16. / strike as f64);
+        assert_eq!((glyph.width, glyph.height), (16, 16));
+        assert!(glyph.has_color);
+        assert_eq!(glyph.bearing_x, PixelLength::new(1.));
+        assert_eq!(glyph.bearing_y, PixelLength::new(14.));
+        for (got, want) in glyph.data[0..4].iter().zip(pixel.iter()) {
+            assert!((i16::from(*got) - i16::from(*want)).abs() <= 1);
+        }
     }
 }
@@ -1,3 +1,4 @@
+use crate::config::FontAntiAliasing;
 use crate::font::locator::FontDataHandle;
 use crate::window::PixelLength;
 use anyhow::Error;
@@ -52,9 +53,12 @@ impl FontRasterizerSelection {
     pub fn new_rasterizer(
         self,
         handle: &FontDataHandle,
+        antialias: FontAntiAliasing,
     ) -> anyhow::Result<Box<dyn FontRasterizer>> {
         match self {
-            Self::FreeType => Ok(Box::new(freetype::FreeTypeRasterizer::from_locator(handle)?)),
+            Self::FreeType => {
+                Ok(Box::new(freetype::FreeTypeRasterizer::from_locator(handle, antialias)?))
+            }
         }
     }
 }
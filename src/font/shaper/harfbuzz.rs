@@ -33,13 +33,34 @@ struct FontPair {
     font: harfbuzz::Font,
 }
 
+/// Parses the default features followed by `extra`, so that later
+/// settings for the same tag override earlier ones. Entries harfbuzz
+/// can't parse are reported and left out rather than failing every shape.
+fn parse_features(extra: &[String]) -> Vec<harfbuzz::hb_feature_t> {
+    ["kern", "liga", "clig"]
+        .iter()
+        .copied()
+        .chain(extra.iter().map(String::as_str))
+        .filter_map(|feature| match harfbuzz::feature_from_string(feature) {
+            Ok(feature) => Some(feature),
+            Err(err) => {
+                eprintln!("ignoring font feature: {}", err);
+                None
+            }
+        })
+        .collect()
+}
+
 pub struct HarfbuzzShaper {
     fonts: Vec<RefCell<FontPair>>,
+    features: Vec<harfbuzz::hb_feature_t>,
     _lib: ftwrap::Library,
 }
 
 impl HarfbuzzShaper {
-    pub fn new(handles: &[FontDataHandle]) -> anyhow::Result<Self> {
+    /// `features` are OpenType feature settings such as `calt=0`, applied
+    /// after the shaper's own defaults.
+    pub fn new(handles: &[FontDataHandle], features: &[String]) -> anyhow::Result<Self> {
         let lib = ftwrap::Library::new()?;
         let mut fonts = vec![];
         for handle in handles {
@@ -50,7 +71,7 @@ impl HarfbuzzShaper {
             font.set_load_flags(load_flags);
             fonts.push(RefCell::new(FontPair { face, font }));
         }
-        Ok(Self { fonts, _lib: lib })
+        Ok(Self { fonts, features: parse_features(features), _lib: lib })
     }
 
     fn do_shape(
@@ -60,12 +81,6 @@ impl HarfbuzzShaper {
         font_size: f64,
         dpi: u32,
     ) -> anyhow::Result<Vec<GlyphInfo>> {
-        let features = vec![
-            harfbuzz::feature_from_string("kern")?,
-            harfbuzz::feature_from_string("liga")?,
-            harfbuzz::feature_from_string("clig")?,
-        ];
-
         let mut buf = harfbuzz::Buffer::new()?;
         buf.set_script(harfbuzz::HB_SCRIPT_LATIN);
         buf.set_direction(harfbuzz::HB_DIRECTION_LTR);
@@ -77,7 +92,7 @@ impl HarfbuzzShaper {
                 Some(pair) => {
                     let mut pair = pair.borrow_mut();
                     pair.face.set_font_size(font_size, dpi)?;
-                    pair.font.shape(&mut buf, Some(features.as_slice()));
+                    pair.font.shape(&mut buf, Some(self.features.as_slice()));
                 }
                 None => {
                     let chars: Vec<u32> = s.chars().map(|c| c as u32).collect();
@@ -201,3 +216,39 @@ impl FontShaper for HarfbuzzShaper {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    // DejaVu Sans ships an `fi` ligature; run with `cargo test -- --ignored`
+    #[test]
+    #[ignore = "needs DejaVu Sans installed system-wide"]
+    fn features_toggle_ligatures() {
+        let path = PathBuf::from("/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf");
+        assert!(path.exists(), "{} is missing", path.display());
+        let handles = [FontDataHandle::OnDisk { path, index: 0 }];
+
+        let shaper = HarfbuzzShaper::new(&handles, &[]).unwrap();
+        let ligated = shaper.shape("fi", 12., 96).unwrap();
+        assert_eq!(ligated.len(), 1);
+        assert_eq!(ligated[0].num_cells, 2);
+
+        let shaper = HarfbuzzShaper::new(&handles, &["liga=0".to_string()]).unwrap();
+        let separate = shaper.shape("fi", 12., 96).unwrap();
+        assert_eq!(separate.len(), 2);
+        assert_eq!(separate[1].cluster, 1);
+    }
+
+    #[test]
+    fn bad_features_are_skipped() {
+        let features = parse_features(&["ss01".to_string(), "".to_string(), "liga=0".to_string()]);
+        let expected = parse_features(&["ss01".to_string(), "liga=0".to_string()]);
+        assert_eq!(features.len(), 5);
+        let tags = |features: &[harfbuzz::hb_feature_t]| -> Vec<_> {
+            features.iter().map(|f| (f.tag, f.value)).collect()
+        };
+        assert_eq!(tags(&features), tags(&expected));
+    }
+}
@@ -61,9 +61,13 @@ impl FontShaperSelection {
         vec!["Harfbuzz"]
     }
 
-    pub fn new_shaper(self, handles: &[FontDataHandle]) -> anyhow::Result<Box<dyn FontShaper>> {
+    pub fn new_shaper(
+        self,
+        handles: &[FontDataHandle],
+        features: &[String],
+    ) -> anyhow::Result<Box<dyn FontShaper>> {
         match self {
-            Self::Harfbuzz => Ok(Box::new(harfbuzz::HarfbuzzShaper::new(handles)?)),
+            Self::Harfbuzz => Ok(Box::new(harfbuzz::HarfbuzzShaper::new(handles, features)?)),
         }
     }
 }
@@ -0,0 +1,52 @@
+use std::time::{Duration, Instant};
+
+/// Lets a bell through at most once per interval, so that a process
+/// spamming BEL doesn't turn into a stream of beeps.
+pub struct BellLimiter {
+    interval: Duration,
+    last_bell: Option<Instant>,
+}
+
+impl BellLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, last_bell: None }
+    }
+
+    pub fn admit(&mut self) -> bool {
+        self.admit_at(Instant::now())
+    }
+
+    fn admit_at(&mut self, now: Instant) -> bool {
+        match self.last_bell {
+            Some(last) if now.saturating_duration_since(last) < self.interval => false,
+            _ => {
+                self.last_bell = Some(now);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn suppresses_bells_within_the_interval() {
+        let mut limiter = BellLimiter::new(Duration::from_millis(500));
+        let start = Instant::now();
+        assert!(limiter.admit_at(start));
+        assert!(!limiter.admit_at(start));
+        assert!(!limiter.admit_at(start + Duration::from_millis(499)));
+        assert!(limiter.admit_at(start + Duration::from_millis(500)));
+        assert!(!limiter.admit_at(start + Duration::from_millis(700)));
+    }
+
+    #[test]
+    fn zero_interval_admits_every_bell() {
+        let mut limiter = BellLimiter::new(Duration::from_millis(0));
+        let start = Instant::now();
+        assert!(limiter.admit_at(start));
+        assert!(limiter.admit_at(start));
+    }
+}
@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant};
+
+/// How long the cursor takes to glide to a new cell.
+const MOVE_DURATION: Duration = Duration::from_millis(80);
+
+/// Tracks where the drawn cursor is while it glides between cells; the
+/// terminal's own cursor has already jumped to the destination.
+pub struct SmoothCursor {
+    from: (f32, f32),
+    to: (f32, f32),
+    started: Option<Instant>,
+}
+
+impl SmoothCursor {
+    pub fn new() -> Self {
+        Self { from: (0., 0.), to: (0., 0.), started: None }
+    }
+
+    /// Starts gliding from wherever the cursor is drawn now when the
+    /// cell it belongs in changed.
+    pub fn move_to(&mut self, cell: (f32, f32), now: Instant) {
+        if cell == self.to {
+            return;
+        }
+        self.from = self.position_at(now).unwrap_or(self.to);
+        self.to = cell;
+        self.started = Some(now);
+    }
+
+    /// The cell coordinates to draw the cursor at, or None once it has
+    /// arrived and can be drawn in its cell as usual.
+    pub fn position_at(&self, now: Instant) -> Option<(f32, f32)> {
+        let elapsed = now.saturating_duration_since(self.started?);
+        if elapsed >= MOVE_DURATION {
+            return None;
+        }
+        let t = ease_out(elapsed.as_secs_f32() / MOVE_DURATION.as_secs_f32());
+        Some(interpolate(self.from, self.to, t))
+    }
+}
+
+/// Decelerates towards the end, so that the cursor settles into place.
+fn ease_out(t: f32) -> f32 {
+    let t = t.max(0.).min(1.);
+    1. - (1. - t).powi(3)
+}
+
+fn interpolate(from: (f32, f32), to: (f32, f32), t: f32) -> (f32, f32) {
+    (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn easing_starts_fast_and_settles() {
+        assert_eq!(ease_out(0.), 0.);
+        assert_eq!(ease_out(0.5), 0.875);
+        assert_eq!(ease_out(1.), 1.);
+        assert_eq!(ease_out(2.), 1.);
+        assert_eq!(interpolate((2., 4.), (10., 0.), ease_out(0.5)), (9., 0.5));
+    }
+
+    #[test]
+    fn glides_then_arrives() {
+        let mut cursor = SmoothCursor::new();
+        let start = Instant::now();
+        cursor.move_to((4., 2.), start);
+        assert_eq!(cursor.position_at(start), Some((0., 0.)));
+        assert_eq!(cursor.position_at(start + MOVE_DURATION / 2), Some((3.5, 1.75)));
+        assert_eq!(cursor.position_at(start + MOVE_DURATION), None);
+
+        // Moving again mid-glide continues from the drawn position
+        cursor.move_to((0., 0.), start + MOVE_DURATION / 2);
+        assert_eq!(cursor.position_at(start + MOVE_DURATION / 2), Some((3.5, 1.75)));
+    }
+}
@@ -0,0 +1,376 @@
+//! Box drawing (U+2500..U+257F) and block elements (U+2580..U+259F) drawn
+//! to fill the cell exactly, so that they join up with their neighbours
+//! whatever the metrics of the font.
+use super::utilsprites::RenderMetrics;
+use crate::window::bitmaps::{BitmapImage, Image};
+use crate::window::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stroke {
+    None,
+    Light,
+    Heavy,
+    Double,
+}
+
+use self::Stroke::{Double as D, Heavy as H, Light as L, None as N};
+
+pub fn is_block_glyph(c: char) -> bool {
+    ('\u{2500}'..='\u{259f}').contains(&c)
+}
+
+/// Draws `c` as a white mask over the whole of `buffer`, which is expected
+/// to be the size of a cell.
+pub fn draw_block_glyph(c: char, buffer: &mut Image, metrics: &RenderMetrics) {
+    let (width, height) = buffer.image_dimensions();
+    let mut canvas = Canvas {
+        buffer,
+        width: width as isize,
+        height: height as isize,
+        thickness: metrics.underline_height.max(1),
+    };
+    canvas.buffer.clear(Color::rgba(0, 0, 0, 0));
+
+    let code = c as u32;
+    match code {
+        0x2504..=0x250b => {
+            let idx = code - 0x2504;
+            canvas.dashes(if idx < 4 { 3 } else { 4 }, idx % 4 >= 2, idx % 2 == 1);
+        }
+        0x254c..=0x254f => {
+            let idx = code - 0x254c;
+            canvas.dashes(2, idx >= 2, idx % 2 == 1);
+        }
+        0x2571..=0x2573 => canvas.diagonals(code != 0x2572, code != 0x2571),
+        0x2500..=0x257f => {
+            if let Some(strokes) = box_strokes(c) {
+                canvas.box_lines(strokes);
+            }
+        }
+        0x2580..=0x259f => canvas.block(code),
+        _ => {}
+    }
+}
+
+/// The strokes leaving the centre of the cell, as up, right, down, left.
+fn box_strokes(c: char) -> Option<[Stroke; 4]> {
+    Some(match c {
+        '─' => [N, L, N, L],
+        '━' => [N, H, N, H],
+        '│' => [L, N, L, N],
+        '┃' => [H, N, H, N],
+        '┌' | '╭' => [N, L, L, N],
+        '┍' => [N, H, L, N],
+        '┎' => [N, L, H, N],
+        '┏' => [N, H, H, N],
+        '┐' | '╮' => [N, N, L, L],
+        '┑' => [N, N, L, H],
+        '┒' => [N, N, H, L],
+        '┓' => [N, N, H, H],
+        '└' | '╰' => [L, L, N, N],
+        '┕' => [L, H, N, N],
+        '┖' => [H, L, N, N],
+        '┗' => [H, H, N, N],
+        '┘' | '╯' => [L, N, N, L],
+        '┙' => [L, N, N, H],
+        '┚' => [H, N, N, L],
+        '┛' => [H, N, N, H],
+        '├' => [L, L, L, N],
+        '┝' => [L, H, L, N],
+        '┞' => [H, L, L, N],
+        '┟' => [L, L, H, N],
+        '┠' => [H, L, H, N],
+        '┡' => [H, H, L, N],
+        '┢' => [L, H, H, N],
+        '┣' => [H, H, H, N],
+        '┤' => [L, N, L, L],
+        '┥' => [L, N, L, H],
+        '┦' => [H, N, L, L],
+        '┧' => [L, N, H, L],
+        '┨' => [H, N, H, L],
+        '┩' => [H, N, L, H],
+        '┪' => [L, N, H, H],
+        '┫' => [H, N, H, H],
+        '┬' => [N, L, L, L],
+        '┭' => [N, L, L, H],
+        '┮' => [N, H, L, L],
+        '┯' => [N, H, L, H],
+        '┰' => [N, L, H, L],
+        '┱' => [N, L, H, H],
+        '┲' => [N, H, H, L],
+        '┳' => [N, H, H, H],
+        '┴' => [L, L, N, L],
+        '┵' => [L, L, N, H],
+        '┶' => [L, H, N, L],
+        '┷' => [L, H, N, H],
+        '┸' => [H, L, N, L],
+        '┹' => [H, L, N, H],
+        '┺' => [H, H, N, L],
+        '┻' => [H, H, N, H],
+        '┼' => [L, L, L, L],
+        '┽' => [L, L, L, H],
+        '┾' => [L, H, L, L],
+        '┿' => [L, H, L, H],
+        '╀' => [H, L, L, L],
+        '╁' => [L, L, H, L],
+        '╂' => [H, L, H, L],
+        '╃' => [H, L, L, H],
+        '╄' => [H, H, L, L],
+        '╅' => [L, L, H, H],
+        '╆' => [L, H, H, L],
+        '╇' => [H, H, L, H],
+        '╈' => [L, H, H, H],
+        '╉' => [H, L, H, H],
+        '╊' => [H, H, H, L],
+        '╋' => [H, H, H, H],
+        '═' => [N, D, N, D],
+        '║' => [D, N, D, N],
+        '╒' => [N, D, L, N],
+        '╓' => [N, L, D, N],
+        '╔' => [N, D, D, N],
+        '╕' => [N, N, L, D],
+        '╖' => [N, N, D, L],
+        '╗' => [N, N, D, D],
+        '╘' => [L, D, N, N],
+        '╙' => [D, L, N, N],
+        '╚' => [D, D, N, N],
+        '╛' => [L, N, N, D],
+        '╜' => [D, N, N, L],
+        '╝' => [D, N, N, D],
+        '╞' => [L, D, L, N],
+        '╟' => [D, L, D, N],
+        '╠' => [D, D, D, N],
+        '╡' => [L, N, L, D],
+        '╢' => [D, N, D, L],
+        '╣' => [D, N, D, D],
+        '╤' => [N, D, L, D],
+        '╥' => [N, L, D, L],
+        '╦' => [N, D, D, D],
+        '╧' => [L, D, N, D],
+        '╨' => [D, L, N, L],
+        '╩' => [D, D, N, D],
+        '╪' => [L, D, L, D],
+        '╫' => [D, L, D, L],
+        '╬' => [D, D, D, D],
+        '╴' => [N, N, N, L],
+        '╵' => [L, N, N, N],
+        '╶' => [N, L, N, N],
+        '╷' => [N, N, L, N],
+        '╸' => [N, N, N, H],
+        '╹' => [H, N, N, N],
+        '╺' => [N, H, N, N],
+        '╻' => [N, N, H, N],
+        '╼' => [N, H, N, L],
+        '╽' => [L, N, H, N],
+        '╾' => [N, L, N, H],
+        '╿' => [H, N, L, N],
+        _ => return None,
+    })
+}
+
+/// The start and end of a band `width` wide centred on `center`.
+fn span(center: isize, width: isize) -> (isize, isize) {
+    let start = center - width / 2;
+    (start, start + width)
+}
+
+struct Canvas<'a> {
+    buffer: &'a mut Image,
+    width: isize,
+    height: isize,
+    thickness: isize,
+}
+
+impl Canvas<'_> {
+    fn fill(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, color: Color) {
+        if x1 > x0 && y1 > y0 {
+            self.buffer
+                .clear_rect(Rect::new(Point::new(x0, y0), Size::new(x1 - x0, y1 - y0)), color);
+        }
+    }
+
+    fn paint(&mut self, x0: isize, y0: isize, x1: isize, y1: isize) {
+        self.fill(x0, y0, x1, y1, Color::rgb(0xff, 0xff, 0xff));
+    }
+
+    fn stroke_width(&self, stroke: Stroke) -> isize {
+        match stroke {
+            Stroke::None => 0,
+            Stroke::Light => self.thickness,
+            Stroke::Heavy => self.thickness * 2,
+            Stroke::Double => self.thickness * 3,
+        }
+    }
+
+    /// Fills a band across the axis of arm `dir` (0 up, 1 right, 2 down,
+    /// 3 left), covering `along` in the direction of the arm.
+    fn arm_rect(
+        &mut self,
+        dir: usize,
+        along: (isize, isize),
+        across: (isize, isize),
+        color: Color,
+    ) {
+        if dir % 2 == 0 {
+            self.fill(across.0, along.0, across.1, along.1, color);
+        } else {
+            self.fill(along.0, across.0, along.1, across.1, color);
+        }
+    }
+
+    fn box_lines(&mut self, strokes: [Stroke; 4]) {
+        let t = self.thickness;
+        let white = Color::rgb(0xff, 0xff, 0xff);
+        let clear = Color::rgba(0, 0, 0, 0);
+
+        // Double bars are drawn solid and then split down the middle, so
+        // that single strokes crossing them have to be drawn last
+        for pass in 0..3 {
+            for (dir, &stroke) in strokes.iter().enumerate() {
+                let is_double = stroke == Stroke::Double;
+                if stroke == Stroke::None || (pass < 2) != is_double {
+                    continue;
+                }
+                let perpendicular = [strokes[(dir + 1) % 4], strokes[(dir + 3) % 4]];
+                let opposite = strokes[(dir + 2) % 4];
+                let (len, center, cross_center) = if dir % 2 == 0 {
+                    (self.height, self.height / 2, self.width / 2)
+                } else {
+                    (self.width, self.width / 2, self.height / 2)
+                };
+                let towards_start = dir == 0 || dir == 3;
+
+                let along = if pass == 1 && perpendicular.contains(&Stroke::Double) {
+                    let (start, end) = span(center, t);
+                    if towards_start {
+                        (0, end)
+                    } else {
+                        (start, len)
+                    }
+                } else if !is_double
+                    && perpendicular == [Stroke::Double, Stroke::Double]
+                    && opposite == Stroke::None
+                {
+                    // Stops at the near side of a double line running past
+                    let (start, end) = span(center, 3 * t);
+                    if towards_start {
+                        (0, start)
+                    } else {
+                        (end, len)
+                    }
+                } else {
+                    let reach = perpendicular.iter().map(|s| self.stroke_width(*s)).max();
+                    let (start, end) = span(center, reach.unwrap_or(0));
+                    if towards_start {
+                        (0, end)
+                    } else {
+                        (start, len)
+                    }
+                };
+
+                if pass == 1 {
+                    self.arm_rect(dir, along, span(cross_center, t), clear);
+                } else {
+                    let across = span(cross_center, self.stroke_width(stroke));
+                    self.arm_rect(dir, along, across, white);
+                }
+            }
+        }
+    }
+
+    fn dashes(&mut self, count: isize, vertical: bool, heavy: bool) {
+        let (len, cross) =
+            if vertical { (self.height, self.width) } else { (self.width, self.height) };
+        let across = span(cross / 2, if heavy { self.thickness * 2 } else { self.thickness });
+        for idx in 0..count {
+            let start = len * idx / count;
+            let end = len * (idx + 1) / count;
+            let gap = ((end - start) / 3).max(1);
+            let along = (start + gap / 2, end - gap + gap / 2);
+            self.arm_rect(
+                if vertical { 0 } else { 1 },
+                along,
+                across,
+                Color::rgb(0xff, 0xff, 0xff),
+            );
+        }
+    }
+
+    fn diagonals(&mut self, rising: bool, falling: bool) {
+        let (right, bottom) = (self.width - 1, self.height - 1);
+        for offset in 0..self.thickness {
+            let offset = offset - self.thickness / 2;
+            if rising {
+                self.buffer.draw_line(
+                    Point::new(offset, bottom),
+                    Point::new(right + offset, 0),
+                    Color::rgb(0xff, 0xff, 0xff),
+                    Operator::Over,
+                );
+            }
+            if falling {
+                self.buffer.draw_line(
+                    Point::new(offset, 0),
+                    Point::new(right + offset, bottom),
+                    Color::rgb(0xff, 0xff, 0xff),
+                    Operator::Over,
+                );
+            }
+        }
+    }
+
+    fn block(&mut self, code: u32) {
+        let (w, h) = (self.width, self.height);
+        let eighths = |len: isize, n: u32| (len * n as isize + 4) / 8;
+        match code {
+            0x2580 => self.paint(0, 0, w, h / 2),
+            0x2581..=0x2588 => self.paint(0, h - eighths(h, code - 0x2580), w, h),
+            0x2589..=0x258f => self.paint(0, 0, eighths(w, 0x2590 - code), h),
+            0x2590 => self.paint(w / 2, 0, w, h),
+            0x2591..=0x2593 => {
+                for y in 0..h {
+                    for x in 0..w {
+                        let lit = match code {
+                            0x2591 => x % 2 == 0 && y % 2 == 0,
+                            0x2592 => (x + y) % 2 == 0,
+                            _ => x % 2 == 0 || y % 2 == 0,
+                        };
+                        if lit {
+                            self.paint(x, y, x + 1, y + 1);
+                        }
+                    }
+                }
+            }
+            0x2594 => self.paint(0, 0, w, eighths(h, 1).max(1)),
+            0x2595 => self.paint(w - eighths(w, 1).max(1), 0, w, h),
+            _ => {
+                // Quadrants, as upper left, upper right, lower left, lower right
+                let mask = match code {
+                    0x2596 => 0b0100,
+                    0x2597 => 0b1000,
+                    0x2598 => 0b0001,
+                    0x2599 => 0b1101,
+                    0x259a => 0b1001,
+                    0x259b => 0b0111,
+                    0x259c => 0b1011,
+                    0x259d => 0b0010,
+                    0x259e => 0b0110,
+                    _ => 0b1110,
+                };
+                let (cx, cy) = (w / 2, h / 2);
+                if mask & 0b0001 != 0 {
+                    self.paint(0, 0, cx, cy);
+                }
+                if mask & 0b0010 != 0 {
+                    self.paint(cx, 0, w, cy);
+                }
+                if mask & 0b0100 != 0 {
+                    self.paint(0, cy, cx, h);
+                }
+                if mask & 0b1000 != 0 {
+                    self.paint(cx, cy, w, h);
+                }
+            }
+        }
+    }
+}
@@ -1,13 +1,24 @@
+use super::customglyph;
+use super::utilsprites::RenderMetrics;
 use crate::config::TextStyle;
 use crate::font::{FontConfiguration, GlyphInfo};
-use crate::window::bitmaps::atlas::{Atlas, Sprite};
-use crate::window::bitmaps::{Image, Texture2d};
+use crate::window::bitmaps::atlas::{Atlas, AtlasMark, OutOfTextureSpace, Sprite};
+use crate::window::bitmaps::{Image, ImageTexture, Texture2d};
 use crate::window::PixelLength;
 use euclid::num::Zero;
 use glium::backend::Context as GliumContext;
 use glium::texture::SrgbTexture2d;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
 use std::rc::Rc;
+use thiserror::Error;
+
+/// The glyph atlas filled up with glyphs evicted from the cache, and was
+/// emptied to make room. Glyphs handed out before that point are no longer
+/// in the atlas, so the frame has to be painted again.
+#[derive(Debug, Error)]
+#[error("glyph atlas was emptied of evicted glyphs")]
+pub struct AtlasReclaimed;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct GlyphKey {
@@ -26,10 +37,71 @@ pub struct CachedGlyph<T: Texture2d> {
     pub scale: f64,
 }
 
+pub struct LruCache<K, V> {
+    map: HashMap<K, (V, u64)>,
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    capacity: usize,
+}
+
+impl<K: Clone + Eq + Hash, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self { map: HashMap::new(), order: BTreeMap::new(), tick: 0, capacity: capacity.max(1) }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.map.get_mut(key) {
+            Some(entry) => {
+                self.order.remove(&entry.1);
+                self.order.insert(tick, key.clone());
+                entry.1 = tick;
+                Some(&entry.0)
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some((_, last_used)) = self.map.insert(key.clone(), (value, tick)) {
+            self.order.remove(&last_used);
+        }
+        self.order.insert(tick, key);
+
+        if self.map.len() > self.capacity {
+            let oldest = *self.order.keys().next()?;
+            let key = self.order.remove(&oldest)?;
+            let (value, _) = self.map.remove(&key)?;
+            Some((key, value))
+        } else {
+            None
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.order.clear();
+    }
+}
+
 pub struct GlyphCache<T: Texture2d> {
-    glyph_cache: HashMap<GlyphKey, Rc<CachedGlyph<T>>>,
+    glyph_cache: LruCache<GlyphKey, Rc<CachedGlyph<T>>>,
+    block_glyphs: HashMap<char, Rc<CachedGlyph<T>>>,
     pub atlas: Atlas<T>,
     fonts: Rc<FontConfiguration>,
+    /// Where the glyphs start in the atlas, after the sprites allocated
+    /// before the first glyph
+    glyphs_start: Option<AtlasMark>,
+    /// Whether a glyph has been evicted since the atlas was last emptied,
+    /// leaving space in it that only emptying it again gets back
+    evicted: bool,
 }
 
 impl GlyphCache<SrgbTexture2d> {
@@ -37,6 +109,7 @@ impl GlyphCache<SrgbTexture2d> {
         backend: &Rc<GliumContext>,
         fonts: &Rc<FontConfiguration>,
         size: usize,
+        capacity: usize,
     ) -> anyhow::Result<Self> {
         let surface = Rc::new(SrgbTexture2d::empty_with_format(
             backend,
@@ -47,11 +120,38 @@ impl GlyphCache<SrgbTexture2d> {
         )?);
         let atlas = Atlas::new(&surface).expect("failed to create new texture atlas");
 
-        Ok(Self { fonts: Rc::clone(fonts), glyph_cache: HashMap::new(), atlas })
+        Ok(Self {
+            fonts: Rc::clone(fonts),
+            glyph_cache: LruCache::new(capacity),
+            block_glyphs: HashMap::new(),
+            atlas,
+            glyphs_start: None,
+            evicted: false,
+        })
+    }
+}
+
+impl GlyphCache<ImageTexture> {
+    pub fn new_software(fonts: &Rc<FontConfiguration>, size: usize, capacity: usize) -> Self {
+        let surface = Rc::new(ImageTexture::new(size, size));
+        let atlas = Atlas::new(&surface).expect("failed to create new texture atlas");
+
+        Self {
+            fonts: Rc::clone(fonts),
+            glyph_cache: LruCache::new(capacity),
+            block_glyphs: HashMap::new(),
+            atlas,
+            glyphs_start: None,
+            evicted: false,
+        }
     }
 }
 
 impl<T: Texture2d> GlyphCache<T> {
+    pub fn capacity(&self) -> usize {
+        self.glyph_cache.capacity()
+    }
+
     pub fn cached_glyph(
         &mut self,
         info: &GlyphInfo,
@@ -65,10 +165,88 @@ impl<T: Texture2d> GlyphCache<T> {
         }
 
         let glyph = self.load_glyph(info, style)?;
-        self.glyph_cache.insert(key, Rc::clone(&glyph));
+        self.insert_glyph(key, Rc::clone(&glyph));
         Ok(glyph)
     }
 
+    fn insert_glyph(&mut self, key: GlyphKey, glyph: Rc<CachedGlyph<T>>) {
+        if self.glyph_cache.insert(key, glyph).is_some() {
+            self.evicted = true;
+        }
+    }
+
+    /// Allocates `im` in the atlas. When the atlas is full and glyphs have
+    /// been evicted since it was last emptied, the glyphs are all dropped
+    /// and their space freed, and `AtlasReclaimed` is returned so that the
+    /// frame is painted again, bringing back just the glyphs it uses.
+    fn allocate(&mut self, im: &Image) -> anyhow::Result<Sprite<T>> {
+        let atlas = &self.atlas;
+        let start = *self.glyphs_start.get_or_insert_with(|| atlas.mark());
+        match self.atlas.allocate(im) {
+            Ok(sprite) => Ok(sprite),
+            Err(OutOfTextureSpace { .. }) if self.evicted => {
+                self.glyph_cache.clear();
+                self.block_glyphs.clear();
+                self.atlas.rewind(start);
+                self.evicted = false;
+                Err(AtlasReclaimed.into())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Like `cached_glyph`, but box drawing and block elements in `text`
+    /// come from `cached_block_glyph` instead of the font.
+    pub fn cached_cluster_glyph(
+        &mut self,
+        info: &GlyphInfo,
+        style: &TextStyle,
+        text: &str,
+        metrics: &RenderMetrics,
+    ) -> anyhow::Result<Rc<CachedGlyph<T>>> {
+        if info.num_cells == 1 {
+            if let Some(c) = text.get(info.cluster as usize..).and_then(|s| s.chars().next()) {
+                if let Some(glyph) = self.cached_block_glyph(c, metrics)? {
+                    return Ok(glyph);
+                }
+            }
+        }
+        self.cached_glyph(info, style)
+    }
+
+    /// A glyph the size of a cell with `c` drawn procedurally, when `c` is
+    /// a box drawing or block element and `custom_block_glyphs` is set.
+    pub fn cached_block_glyph(
+        &mut self,
+        c: char,
+        metrics: &RenderMetrics,
+    ) -> anyhow::Result<Option<Rc<CachedGlyph<T>>>> {
+        if !self.fonts.custom_block_glyphs() || !customglyph::is_block_glyph(c) {
+            return Ok(None);
+        }
+        if let Some(glyph) = self.block_glyphs.get(&c) {
+            return Ok(Some(Rc::clone(glyph)));
+        }
+
+        let mut buffer =
+            Image::new(metrics.cell_size.width as usize, metrics.cell_size.height as usize);
+        customglyph::draw_block_glyph(c, &mut buffer, metrics);
+        let texture = self.allocate(&buffer)?;
+
+        // Puts the top of the texture at the top of the cell
+        let glyph = Rc::new(CachedGlyph {
+            has_color: false,
+            texture: Some(texture),
+            x_offset: PixelLength::zero(),
+            y_offset: PixelLength::zero(),
+            bearing_x: PixelLength::zero(),
+            bearing_y: PixelLength::new(metrics.cell_size.height as f64) + metrics.descender,
+            scale: 1.0,
+        });
+        self.block_glyphs.insert(c, Rc::clone(&glyph));
+        Ok(Some(glyph))
+    }
+
     #[allow(clippy::float_cmp)]
     fn load_glyph(
         &mut self,
@@ -85,8 +263,12 @@ impl<T: Texture2d> GlyphCache<T> {
         }
         let (cell_width, cell_height) = (metrics.cell_width, metrics.cell_height);
 
-        let scale = if (info.x_advance / f64::from(info.num_cells)).get().floor() > cell_width.get()
-        {
+        let scale = if glyph.has_color {
+            // Color bitmaps are already sized by the rasterizer; only shrink
+            // the ones that would still overflow their cells
+            let max_width = cell_width.get() * f64::from(info.num_cells);
+            (max_width / glyph.width as f64).min(cell_height.get() / glyph.height as f64).min(1.0)
+        } else if (info.x_advance / f64::from(info.num_cells)).get().floor() > cell_width.get() {
             f64::from(info.num_cells) * (cell_width / info.x_advance).get()
         } else if PixelLength::new(glyph.height as f64) > cell_height {
             cell_height.get() / glyph.height as f64
@@ -119,7 +301,7 @@ impl<T: Texture2d> GlyphCache<T> {
             let (scale, raw_im) =
                 if scale != 1.0 { (1.0, raw_im.scale_by(scale)) } else { (scale, raw_im) };
 
-            let tex = self.atlas.allocate(&raw_im)?;
+            let tex = self.allocate(&raw_im)?;
 
             CachedGlyph {
                 has_color: glyph.has_color,
@@ -135,3 +317,94 @@ impl<T: Texture2d> GlyphCache<T> {
         Ok(Rc::new(glyph))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::window::bitmaps::BitmapImage;
+    use std::sync::Arc;
+
+    fn metrics() -> RenderMetrics {
+        RenderMetrics {
+            descender: PixelLength::new(-3.),
+            descender_row: 13,
+            descender_plus_two: 15,
+            underline_height: 1,
+            strike_row: 6,
+            cell_size: crate::window::Size::new(8, 16),
+        }
+    }
+
+    #[test]
+    fn box_drawing_is_drawn_to_fill_the_cell() {
+        let metrics = metrics();
+        let fonts = Rc::new(FontConfiguration::new(Arc::new(Config::default())));
+        let mut cache = GlyphCache::new_software(&fonts, 256, 16);
+
+        let glyph = cache.cached_block_glyph('─', &metrics).unwrap().unwrap();
+        let sprite = glyph.texture.as_ref().unwrap();
+        assert_eq!(sprite.coords.size, metrics.cell_size);
+        let image = sprite.texture.image.borrow();
+        let origin = sprite.coords.origin;
+        for x in 0..8 {
+            let pixel = |y: isize| *image.pixel((origin.x + x) as usize, (origin.y + y) as usize);
+            assert_eq!(pixel(8), 0xffff_ffff, "at {}", x);
+            assert_eq!(pixel(4), 0, "at {}", x);
+        }
+        assert!(cache.cached_block_glyph('a', &metrics).unwrap().is_none());
+
+        let config = Config { custom_block_glyphs: false, ..Config::default() };
+        let fonts = Rc::new(FontConfiguration::new(Arc::new(config)));
+        let mut cache = GlyphCache::new_software(&fonts, 256, 16);
+        assert!(cache.cached_block_glyph('─', &metrics).unwrap().is_none());
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        assert_eq!(cache.insert("a", 1), None);
+        assert_eq!(cache.insert("b", 2), None);
+        assert_eq!(cache.get(&"a"), Some(&1));
+
+        assert_eq!(cache.insert("c", 3), Some(("b", 2)));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        assert_eq!(cache.get(&"c"), Some(&3));
+    }
+
+    #[test]
+    fn atlas_usage_stays_bounded_under_churn() {
+        let fonts = Rc::new(FontConfiguration::new(Arc::new(Config::default())));
+        let mut cache = GlyphCache::new_software(&fonts, 256, 32);
+        // Stands in for the util sprites, which must survive reclaiming
+        let pinned = cache.atlas.allocate(&Image::new(30, 30)).unwrap();
+
+        let mut reclaimed = 0;
+        for glyph_pos in 0..2000 {
+            let key = GlyphKey { font_idx: 0, glyph_pos, style: TextStyle::default() };
+            let texture = match cache.allocate(&Image::new(8, 16)) {
+                Ok(texture) => texture,
+                Err(err) => {
+                    assert!(err.is::<AtlasReclaimed>(), "{}", err);
+                    reclaimed += 1;
+                    assert_eq!(cache.atlas.used_height(), 32);
+                    cache.allocate(&Image::new(8, 16)).unwrap()
+                }
+            };
+            assert!(!texture.coords.intersects(&pinned.coords));
+            let glyph = CachedGlyph {
+                has_color: false,
+                x_offset: PixelLength::zero(),
+                y_offset: PixelLength::zero(),
+                bearing_x: PixelLength::zero(),
+                bearing_y: PixelLength::zero(),
+                texture: Some(texture),
+                scale: 1.0,
+            };
+            cache.insert_glyph(key, Rc::new(glyph));
+        }
+        assert!(reclaimed > 0);
+        assert_eq!(cache.atlas.size(), 256);
+    }
+}
@@ -12,20 +12,73 @@ use crate::window::Dimensions;
 use crate::window::PixelLength;
 use chrono::{DateTime, Local};
 use glium::{uniform, Surface};
+use std::time::{Duration, Instant};
 use sysinfo::{ProcessorExt, System, SystemExt};
 
+const SPRITE_INTERVAL: Duration = Duration::from_millis(100);
+/// How many sprite frames the walk cycle advances per second.
+const SPRITE_FRAMES_PER_SECOND: f32 = 10.0;
+const SYSTEM_REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
 pub mod renderstate;
 
+/// Accumulates elapsed wall-clock time into whole sprite frames, so the
+/// animation speed does not depend on how often the window is painted.
+#[derive(Default)]
+struct SpriteClock {
+    frame: u32,
+    frame_fraction: f32,
+}
+
+impl SpriteClock {
+    /// Advances the clock by `dt` and returns how many pixels the sprite
+    /// should move at `pixels_per_second`.
+    fn advance(&mut self, dt: Duration, pixels_per_second: f32) -> f32 {
+        let secs = dt.as_secs_f32();
+        self.frame_fraction += SPRITE_FRAMES_PER_SECOND * secs;
+        let whole = self.frame_fraction.floor();
+        self.frame = self.frame.wrapping_add(whole as u32);
+        self.frame_fraction -= whole;
+        pixels_per_second * secs
+    }
+}
+
 pub struct Header {
     pub offset: usize,
     sys: System,
-    count: u32,
+    clock: SpriteClock,
+    last_slide: Instant,
+    last_refresh: Instant,
+    read_only: bool,
+    idle: bool,
 }
 
 impl Header {
     pub fn new() -> Self {
         let sys = System::new();
-        Self { offset: 2, count: 0, sys }
+        let now = Instant::now();
+        Self {
+            offset: 2,
+            clock: SpriteClock::default(),
+            sys,
+            last_slide: now,
+            last_refresh: now,
+            read_only: false,
+            idle: false,
+        }
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// While idle the sprite stays put and system stats are not polled.
+    pub fn set_idle(&mut self, idle: bool) {
+        self.idle = idle;
+    }
+
+    pub fn is_animation_due(&self) -> bool {
+        self.last_slide.elapsed() >= SPRITE_INTERVAL
     }
 
     pub fn paint(
@@ -33,19 +86,24 @@ impl Header {
         gl_state: &RenderState,
         palette: &ColorPalette,
         dimensions: &Dimensions,
-        frame_count: u32,
         render_metrics: &RenderMetrics,
         fonts: &FontConfiguration,
         frame: &mut glium::Frame,
     ) -> anyhow::Result<()> {
         let w = dimensions.pixel_width as f32 as f32 / 2.0;
-        if frame_count % 6 == 0 {
-            self.count += 1;
-            gl_state.header.slide_sprite(w);
+        let now = Instant::now();
+        if self.idle {
+            // Don't let the time spent idle turn into one big jump on wake.
+            self.last_slide = now;
+        } else {
+            let delta = self.clock.advance(now - self.last_slide, gl_state.header.sprite_speed);
+            gl_state.header.slide_sprite(w, delta);
+            self.last_slide = now;
         }
 
-        if frame_count % 30 == 0 {
+        if !self.idle && self.last_refresh.elapsed() >= SYSTEM_REFRESH_INTERVAL {
             self.sys.refresh_system();
+            self.last_refresh = Instant::now();
         }
 
         let projection = euclid::Transform3D::<f32, f32, f32>::ortho(
@@ -91,8 +149,8 @@ impl Header {
         )?;
 
         let number_of_sprites = gl_state.header.spritesheet.sprites.len();
-        let sprite =
-            &gl_state.header.spritesheet.sprites[(self.count % number_of_sprites as u32) as usize];
+        let sprite = &gl_state.header.spritesheet.sprites
+            [(self.clock.frame % number_of_sprites as u32) as usize];
         frame.draw(
             &*gl_state.header.sprite_vertex_buffer.borrow(),
             &gl_state.header.sprite_index_buffer,
@@ -168,7 +226,10 @@ impl Header {
     fn compute_header_text(&self, number_of_vertices: usize) -> String {
         let now: DateTime<Local> = Local::now();
         let current_time = now.format("%H:%M:%S").to_string();
-        let cpu_load = format!("CPU:{}%", self.sys.global_processor_info().cpu_usage().round());
+        let mut cpu_load = format!("CPU:{}%", self.sys.global_processor_info().cpu_usage().round());
+        if self.read_only {
+            cpu_load.push_str(" [read-only]");
+        }
         let indent = std::cmp::max(
             0,
             (number_of_vertices / VERTICES_PER_CELL) as i32
@@ -183,3 +244,29 @@ impl Header {
 fn rgbcolor_to_window_color(color: RgbColor) -> Color {
     Color::rgba(color.red, color.green, color.blue, 0xff)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(fps: u32, seconds: u32) -> (f32, u32) {
+        let mut clock = SpriteClock::default();
+        let dt = Duration::from_secs(1) / fps;
+        let mut distance = 0.0;
+        for _ in 0..fps * seconds {
+            distance += clock.advance(dt, 100.0);
+        }
+        (distance, clock.frame)
+    }
+
+    #[test]
+    fn sprite_clock_is_independent_of_frame_rate() {
+        let (distance_60, frames_60) = run(60, 2);
+        let (distance_120, frames_120) = run(120, 2);
+
+        assert!((distance_60 - 200.0).abs() < 0.1, "{}", distance_60);
+        assert!((distance_120 - 200.0).abs() < 0.1, "{}", distance_120);
+        assert!((19..=20).contains(&frames_60), "{}", frames_60);
+        assert!((19..=20).contains(&frames_120), "{}", frames_120);
+    }
+}
@@ -1,15 +1,16 @@
 use crate::config::Theme;
+use crate::error::Error;
 use crate::gui::quad::*;
 use crate::gui::spritesheet::*;
 use crate::gui::utilsprites::RenderMetrics;
 use crate::window::color::Color;
-use anyhow::anyhow;
 use glium::backend::Context as GliumContext;
 use glium::{IndexBuffer, VertexBuffer};
 use std::cell::RefCell;
 use std::rc::Rc;
 
-const SPRITE_SPEED: f32 = 10.0;
+/// Horizontal sprite speed, in pixels per second.
+const SPRITE_SPEED: f32 = 100.0;
 
 fn rect_vertex_shader(version: &str) -> String {
     format!(
@@ -104,8 +105,9 @@ impl HeaderRenderState {
             };
         }
 
-        let rect_program = rect_program
-            .ok_or_else(|| anyhow!("Failed to compile shaders: {}", header_errors.join("\n")))?;
+        let rect_program = rect_program.ok_or_else(|| {
+            Error::Render(format!("Failed to compile shaders: {}", header_errors.join("\n")))
+        })?;
 
         let color = Color::rgba(theme.color.red, theme.color.green, theme.color.blue, 0xff);
 
@@ -141,8 +143,9 @@ impl HeaderRenderState {
             };
         }
 
-        let sprite_program = sprite_program
-            .ok_or_else(|| anyhow!("Failed to compile shaders: {}", sprite_errors.join("\n")))?;
+        let sprite_program = sprite_program.ok_or_else(|| {
+            Error::Render(format!("Failed to compile shaders: {}", sprite_errors.join("\n")))
+        })?;
 
         let (sprite_vertex_buffer, sprite_index_buffer) = Self::compute_sprite_vertices(
             &context,
@@ -387,11 +390,11 @@ impl HeaderRenderState {
         ))
     }
 
-    pub fn slide_sprite(&self, width: f32) {
+    /// Moves the sprite right by `delta` pixels, wrapping around past `width`.
+    pub fn slide_sprite(&self, width: f32, delta: f32) {
         let mut vb = self.sprite_vertex_buffer.borrow_mut();
         let mut vert = { vb.slice_mut(0..4).unwrap().map() };
 
-        let delta = self.sprite_speed;
         let sprite_width = self.sprite_size.0;
 
         if vert[V_TOP_LEFT].position.0 > width {
@@ -0,0 +1,49 @@
+use std::time::{Duration, Instant};
+
+/// Decides whether the user has walked away, based on the time since the
+/// last input or output event.
+pub struct IdleTracker {
+    timeout: Option<Duration>,
+    last_activity: Instant,
+}
+
+impl IdleTracker {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self { timeout, last_activity: Instant::now() }
+    }
+
+    pub fn record_activity(&mut self) {
+        self.last_activity = Instant::now();
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.is_idle_at(Instant::now())
+    }
+
+    fn is_idle_at(&self, now: Instant) -> bool {
+        match self.timeout {
+            Some(timeout) => now.saturating_duration_since(self.last_activity) >= timeout,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn idle_after_timeout() {
+        let tracker = IdleTracker::new(Some(Duration::from_secs(60)));
+        let start = tracker.last_activity;
+        assert!(!tracker.is_idle_at(start));
+        assert!(!tracker.is_idle_at(start + Duration::from_secs(59)));
+        assert!(tracker.is_idle_at(start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn never_idle_without_timeout() {
+        let tracker = IdleTracker::new(None);
+        assert!(!tracker.is_idle_at(tracker.last_activity + Duration::from_secs(86400)));
+    }
+}
@@ -1,12 +1,23 @@
+use crate::config::{Config, Renderer};
 use crate::font::FontConfiguration;
 use crate::mux::Mux;
 use crate::window::*;
 use std::rc::Rc;
+use std::sync::Arc;
 
+mod bell;
+mod cursor;
+mod customglyph;
 mod glyphcache;
 mod header;
+mod idle;
+mod notification;
+mod overlay;
+mod paste;
 mod quad;
 mod renderstate;
+mod scroll;
+mod softrender;
 mod spritesheet;
 mod utilsprites;
 mod window;
@@ -15,13 +26,18 @@ pub struct GuiFrontEnd {
     connection: Rc<Connection>,
 }
 
-pub fn new() -> anyhow::Result<Rc<dyn FrontEnd>> {
-    let front_end = GuiFrontEnd::new()?;
+pub fn new(config: &Arc<Config>) -> anyhow::Result<Rc<dyn FrontEnd>> {
+    let front_end = GuiFrontEnd::new(config)?;
     Ok(front_end)
 }
 
 impl GuiFrontEnd {
-    pub fn new() -> anyhow::Result<Rc<dyn FrontEnd>> {
+    pub fn new(config: &Arc<Config>) -> anyhow::Result<Rc<dyn FrontEnd>> {
+        set_max_fps(config.max_fps);
+        if config.renderer == Renderer::Cpu && cfg!(target_os = "macos") {
+            anyhow::bail!("the cpu renderer is not supported on macOS");
+        }
+        set_software_rendering(config.renderer == Renderer::Cpu);
         let connection = Connection::init()?;
         let front_end = Rc::new(GuiFrontEnd { connection });
         Ok(front_end)
@@ -39,6 +55,8 @@ impl FrontEnd for GuiFrontEnd {
             let mux = Mux::get().unwrap();
             if mux.can_close() {
                 Connection::get().unwrap().terminate_message_loop();
+            } else {
+                mux.report_process_exit();
             }
         });
 
@@ -0,0 +1,27 @@
+use std::process::{Command, Stdio};
+
+/// Shows a desktop notification using the platform's command line tool.
+pub fn show(title: &str, body: &str) {
+    let mut cmd = command(title, body);
+    cmd.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    if let Err(err) = cmd.spawn() {
+        eprintln!("failed to show notification: {}", err);
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn command(title: &str, body: &str) -> Command {
+    fn quote(s: &str) -> String {
+        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+    let mut cmd = Command::new("osascript");
+    cmd.arg("-e").arg(format!("display notification {} with title {}", quote(body), quote(title)));
+    cmd
+}
+
+#[cfg(not(target_os = "macos"))]
+fn command(title: &str, body: &str) -> Command {
+    let mut cmd = Command::new("notify-send");
+    cmd.arg("--app-name=miro").arg("--").arg(title).arg(body);
+    cmd
+}
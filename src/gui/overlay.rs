@@ -0,0 +1,71 @@
+use crate::term::{CellAttributes, Line};
+
+/// Transient UI, such as the target of a hovered link, composited over the
+/// bottom row of the terminal after its lines and before the header.
+pub trait Overlay {
+    /// Produces the row to draw; it must be exactly `cols` cells wide.
+    fn line(&self, cols: usize) -> Line;
+}
+
+/// A single line of text in reverse video, truncated to fit.
+pub struct TextOverlay {
+    text: String,
+    attrs: CellAttributes,
+}
+
+impl TextOverlay {
+    pub fn new(text: &str) -> Self {
+        let mut attrs = CellAttributes::default();
+        attrs.set_reverse(true);
+        Self { text: text.to_string(), attrs }
+    }
+}
+
+impl Overlay for TextOverlay {
+    fn line(&self, cols: usize) -> Line {
+        let text = Line::from_text(&self.text, &self.attrs);
+        let mut line = Line::from_text(&" ".repeat(cols), &self.attrs);
+        for (idx, cell) in text.visible_cells() {
+            // A wide glyph that would straddle the edge is dropped entirely
+            if idx + cell.width() > cols {
+                break;
+            }
+            line.set_cell(idx, cell.clone());
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::font::FontConfiguration;
+    use std::sync::Arc;
+
+    const WIDTHS: &[usize] = &[0, 1, 5, 21, 22, 23, 80];
+
+    #[test]
+    fn text_overlay_fits_any_width() {
+        let overlay = TextOverlay::new("https://example.com/\u{4e16}\u{754c}");
+        for cols in WIDTHS {
+            let line = overlay.line(*cols);
+            assert_eq!(line.cells().len(), *cols);
+            assert!(line.cells().iter().all(|cell| cell.attrs().reverse()));
+        }
+        assert_eq!(overlay.line(21).as_str().trim_end(), "https://example.com/");
+        assert_eq!(overlay.line(22).as_str(), "https://example.com/\u{4e16}");
+    }
+
+    #[test]
+    #[ignore = "depends on the system fonts"]
+    fn text_overlay_shapes_at_any_width() {
+        let overlay = TextOverlay::new("https://example.com/\u{4e16}\u{754c}");
+        let fonts = FontConfiguration::new(Arc::new(Config::default()));
+        let font = fonts.resolve_font(&Default::default()).unwrap();
+        for cols in WIDTHS {
+            let text = overlay.line(*cols).as_str();
+            assert!(font.shape(&text).unwrap().len() <= *cols);
+        }
+    }
+}
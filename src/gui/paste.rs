@@ -0,0 +1,35 @@
+/// Whether a paste should wait for the user to confirm it: without
+/// bracketed paste every line break in it reaches the shell as Enter.
+pub fn needs_confirmation(text: &str, bracketed_paste: bool) -> bool {
+    !bracketed_paste && text.contains(|c| c == '\n' || c == '\r')
+}
+
+pub fn prompt(text: &str) -> String {
+    let lines =
+        text.replace("\r\n", "\n").replace('\r', "\n").trim_end_matches('\n').lines().count();
+    match lines {
+        0 | 1 => "Paste 1 line? [y/N]".to_string(),
+        n => format!("Paste {} lines? [y/N]", n),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_multiline_pastes_without_bracketed_paste_are_confirmed() {
+        assert!(!needs_confirmation("ls -l", false));
+        assert!(!needs_confirmation("ls -l", true));
+        assert!(needs_confirmation("cd /\nrm -rf *", false));
+        assert!(needs_confirmation("make\r", false));
+        assert!(!needs_confirmation("cd /\nrm -rf *", true));
+    }
+
+    #[test]
+    fn prompt_counts_lines() {
+        assert_eq!(prompt("a\nb\n"), "Paste 2 lines? [y/N]");
+        assert_eq!(prompt("a\r\nb\r\nc"), "Paste 3 lines? [y/N]");
+        assert_eq!(prompt("make\n"), "Paste 1 line? [y/N]");
+    }
+}
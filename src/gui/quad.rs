@@ -1,3 +1,4 @@
+use crate::term::LineSize;
 use crate::window::bitmaps::TextureRect;
 use crate::window::Color;
 use glium::VertexBuffer;
@@ -55,6 +56,21 @@ glium::implement_vertex!(RectVertex, position, color);
 pub struct Quads {
     pub cols: usize,
     pub row_starts: Vec<usize>,
+    /// Position of the top left corner of the first cell, and the size of
+    /// a cell, used to reposition quads for double width and height lines.
+    pub origin: (f32, f32),
+    pub cell_size: (f32, f32),
+}
+
+/// The factors by which the quads of a line of the given size are
+/// stretched.  The lower half of a double height line is covered by the
+/// glyphs of its upper half, so only its width is doubled.
+pub fn line_scale(size: LineSize) -> (f32, f32) {
+    match size {
+        LineSize::Single => (1., 1.),
+        LineSize::DoubleWidth | LineSize::DoubleHeightBottom => (2., 1.),
+        LineSize::DoubleHeightTop => (2., 2.),
+    }
 }
 
 pub struct MappedQuads<'a> {
@@ -78,6 +94,23 @@ impl<'a> MappedQuads<'a> {
         Ok(Quad { vert: &mut self.mapping[start..start + VERTICES_PER_CELL] })
     }
 
+    /// Like `cell`, but also moves the quad so that it covers logical
+    /// column `x` of a line of the given size.
+    pub fn sized_cell<'b>(
+        &'b mut self,
+        x: usize,
+        y: usize,
+        size: LineSize,
+    ) -> anyhow::Result<Quad<'b>> {
+        let (scale_x, scale_y) = line_scale(size);
+        let (cell_width, cell_height) = self.quads.cell_size;
+        let left = self.quads.origin.0 + x as f32 * cell_width * scale_x;
+        let top = self.quads.origin.1 + y as f32 * cell_height;
+        let mut quad = self.cell(x, y)?;
+        quad.set_position(left, top, left + cell_width * scale_x, top + cell_height * scale_y);
+        Ok(quad)
+    }
+
     pub fn cols(&self) -> usize {
         self.quads.cols * VERTICES_PER_CELL
     }
@@ -95,6 +128,13 @@ pub struct Quad<'a> {
 }
 
 impl<'a> Quad<'a> {
+    pub fn set_position(&mut self, left: f32, top: f32, right: f32, bottom: f32) {
+        self.vert[V_TOP_LEFT].position = (left, top);
+        self.vert[V_TOP_RIGHT].position = (right, top);
+        self.vert[V_BOT_LEFT].position = (left, bottom);
+        self.vert[V_BOT_RIGHT].position = (right, bottom);
+    }
+
     pub fn set_texture(&mut self, coords: TextureRect) {
         self.vert[V_TOP_LEFT].tex = (coords.min_x(), coords.min_y());
         self.vert[V_TOP_RIGHT].tex = (coords.max_x(), coords.min_y());
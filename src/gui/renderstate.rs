@@ -3,8 +3,8 @@ use super::header::renderstate::HeaderRenderState;
 use super::quad::*;
 use super::utilsprites::{RenderMetrics, UtilSprites};
 use crate::config::Theme;
+use crate::error::Error;
 use crate::font::FontConfiguration;
-use anyhow::anyhow;
 use glium::backend::Context as GliumContext;
 use glium::texture::SrgbTexture2d;
 use glium::{IndexBuffer, VertexBuffer};
@@ -34,6 +34,9 @@ pub struct RenderState {
     pub glyph_program: glium::Program,
     pub glyph_vertex_buffer: RefCell<VertexBuffer<Vertex>>,
     pub glyph_index_buffer: IndexBuffer<u32>,
+    /// A single quad for the gliding cursor, moved every animated frame.
+    pub cursor_vertex_buffer: RefCell<VertexBuffer<RectVertex>>,
+    pub cursor_index_buffer: IndexBuffer<u32>,
     pub header: HeaderRenderState,
     pub quads: Quads,
 }
@@ -44,11 +47,13 @@ impl RenderState {
         fonts: &Rc<FontConfiguration>,
         metrics: &RenderMetrics,
         size: usize,
+        glyph_cache_size: usize,
         pixel_width: usize,
         pixel_height: usize,
         theme: &Theme,
     ) -> anyhow::Result<Self> {
-        let glyph_cache = RefCell::new(GlyphCache::new_gl(&context, fonts, size)?);
+        let glyph_cache =
+            RefCell::new(GlyphCache::new_gl(&context, fonts, size, glyph_cache_size)?);
         let util_sprites = UtilSprites::new(&mut *glyph_cache.borrow_mut(), metrics)?;
         let mut glyph_errors = vec![];
         let mut glyph_program = None;
@@ -72,8 +77,9 @@ impl RenderState {
             };
         }
 
-        let glyph_program = glyph_program
-            .ok_or_else(|| anyhow!("Failed to compile shaders: {}", glyph_errors.join("\n")))?;
+        let glyph_program = glyph_program.ok_or_else(|| {
+            Error::Render(format!("Failed to compile shaders: {}", glyph_errors.join("\n")))
+        })?;
 
         let (glyph_vertex_buffer, glyph_index_buffer, quads) = Self::compute_glyph_vertices(
             &context,
@@ -82,6 +88,9 @@ impl RenderState {
             pixel_height as f32,
         )?;
 
+        let (cursor_vertex_buffer, cursor_index_buffer) =
+            HeaderRenderState::compute_rect_vertices(&context, (0., 0., 0., 0.), 0., 0., 0.)?;
+
         let header =
             HeaderRenderState::new(context.clone(), theme, metrics, pixel_width, pixel_height)?;
 
@@ -92,6 +101,8 @@ impl RenderState {
             glyph_program,
             glyph_vertex_buffer: RefCell::new(glyph_vertex_buffer),
             glyph_index_buffer,
+            cursor_vertex_buffer: RefCell::new(cursor_vertex_buffer),
+            cursor_index_buffer,
             header,
             quads,
         })
@@ -123,7 +134,8 @@ impl RenderState {
         size: Option<usize>,
     ) -> anyhow::Result<()> {
         let size = size.unwrap_or_else(|| self.glyph_cache.borrow().atlas.size());
-        let mut glyph_cache = GlyphCache::new_gl(&self.context, fonts, size)?;
+        let capacity = self.glyph_cache.borrow().capacity();
+        let mut glyph_cache = GlyphCache::new_gl(&self.context, fonts, size, capacity)?;
         self.util_sprites = UtilSprites::new(&mut glyph_cache, metrics)?;
         *self.glyph_cache.borrow_mut() = glyph_cache;
         Ok(())
@@ -144,6 +156,8 @@ impl RenderState {
         let num_rows = height as usize / cell_height as usize;
         let mut quads = Quads::default();
         quads.cols = num_cols;
+        quads.origin = (width / -2.0, height / -2.0);
+        quads.cell_size = (cell_width, cell_height);
 
         let mut define_quad = |left, top, right, bottom| -> u32 {
             let idx = verts.len() as u32;
@@ -0,0 +1,60 @@
+use crate::window::WHEEL_DELTA;
+
+/// Turns wheel deltas, which high resolution devices deliver in fractions
+/// of a notch, into whole lines, carrying the remainder to the next event.
+pub struct WheelAccumulator {
+    lines_per_notch: f64,
+    remainder: f64,
+}
+
+impl WheelAccumulator {
+    pub fn new(lines_per_notch: f64) -> Self {
+        Self { lines_per_notch, remainder: 0. }
+    }
+
+    /// Adds `delta`, in `WHEEL_DELTA` units per notch, and returns the whole
+    /// number of lines to scroll; positive is up.
+    pub fn accumulate(&mut self, delta: i16) -> i64 {
+        let lines = f64::from(delta) / f64::from(WHEEL_DELTA) * self.lines_per_notch;
+        // Reversing direction drops whatever was left over from before
+        if self.remainder * lines < 0. {
+            self.remainder = 0.;
+        }
+        let total = self.remainder + lines;
+        let whole = total.trunc();
+        self.remainder = total - whole;
+        whole as i64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sub_line_deltas_add_up_to_whole_lines() {
+        let mut wheel = WheelAccumulator::new(1.);
+        assert_eq!(wheel.accumulate(WHEEL_DELTA / 4), 0);
+        assert_eq!(wheel.accumulate(WHEEL_DELTA / 4), 0);
+        assert_eq!(wheel.accumulate(WHEEL_DELTA / 4), 0);
+        assert_eq!(wheel.accumulate(WHEEL_DELTA / 2), 1);
+        assert_eq!(wheel.accumulate(WHEEL_DELTA / 4), 0);
+        assert_eq!(wheel.accumulate(WHEEL_DELTA / 2), 1);
+
+        // Changing direction starts from scratch
+        assert_eq!(wheel.accumulate(WHEEL_DELTA / 2), 0);
+        assert_eq!(wheel.accumulate(-WHEEL_DELTA / 2), 0);
+        assert_eq!(wheel.accumulate(-WHEEL_DELTA / 2), -1);
+    }
+
+    #[test]
+    fn notches_are_multiplied() {
+        let mut wheel = WheelAccumulator::new(3.);
+        assert_eq!(wheel.accumulate(WHEEL_DELTA), 3);
+        assert_eq!(wheel.accumulate(-2 * WHEEL_DELTA), -6);
+
+        let mut wheel = WheelAccumulator::new(1.5);
+        assert_eq!(wheel.accumulate(WHEEL_DELTA), 1);
+        assert_eq!(wheel.accumulate(WHEEL_DELTA), 2);
+    }
+}
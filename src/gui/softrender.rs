@@ -0,0 +1,421 @@
+use super::glyphcache::GlyphCache;
+use super::utilsprites::{RenderMetrics, UtilSprites};
+use super::window::{
+    compute_cell_fg_bg, is_blank_cluster, resolve_cluster_colors, rgbcolor_to_window_color,
+};
+use crate::font::FontConfiguration;
+use crate::term::color::ColorPalette;
+use crate::term::{self, CursorPosition, Line, Terminal};
+use crate::window::bitmaps::atlas::Sprite;
+use crate::window::bitmaps::{BitmapImage, Image, ImageTexture};
+use crate::window::*;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Paints terminal lines into an `Image` without touching the GPU, for
+/// machines where OpenGL is unavailable. The header band is drawn as a
+/// plain bar; the sprite and status text are GPU only.
+pub struct SoftwareRenderer {
+    fonts: Rc<FontConfiguration>,
+    glyph_cache: GlyphCache<ImageTexture>,
+    util_sprites: UtilSprites<ImageTexture>,
+    minimum_contrast_ratio: Option<f64>,
+    blank_cell_fast_path: bool,
+}
+
+impl SoftwareRenderer {
+    pub fn new(
+        fonts: &Rc<FontConfiguration>,
+        metrics: &RenderMetrics,
+        size: usize,
+        glyph_cache_size: usize,
+        minimum_contrast_ratio: Option<f64>,
+        blank_cell_fast_path: bool,
+    ) -> anyhow::Result<Self> {
+        let mut glyph_cache = GlyphCache::new_software(fonts, size, glyph_cache_size);
+        let util_sprites = UtilSprites::new(&mut glyph_cache, metrics)?;
+        Ok(Self {
+            fonts: Rc::clone(fonts),
+            glyph_cache,
+            util_sprites,
+            minimum_contrast_ratio,
+            blank_cell_fast_path,
+        })
+    }
+
+    pub fn recreate_texture_atlas(
+        &mut self,
+        metrics: &RenderMetrics,
+        size: Option<usize>,
+    ) -> anyhow::Result<()> {
+        let size = size.unwrap_or_else(|| self.glyph_cache.atlas.size());
+        *self = Self::new(
+            &self.fonts,
+            metrics,
+            size,
+            self.glyph_cache.capacity(),
+            self.minimum_contrast_ratio,
+            self.blank_cell_fast_path,
+        )?;
+        Ok(())
+    }
+
+    /// Paints every visible line of `term` into a new image the size of its
+    /// screen, without a window or header, so that what the renderer draws
+    /// can be checked offscreen.
+    #[allow(dead_code)]
+    pub fn render_offscreen(
+        &mut self,
+        term: &mut Terminal,
+        metrics: &RenderMetrics,
+    ) -> anyhow::Result<Image> {
+        let (rows, cols) = term.physical_dimensions();
+        let mut image = Image::new(
+            cols * metrics.cell_size.width as usize,
+            rows * metrics.cell_size.height as usize,
+        );
+        let palette = term.palette().clone();
+        let cursor = term.cursor_pos();
+
+        term.make_all_lines_dirty();
+        for (line_idx, line, selrange) in term.get_dirty_lines() {
+            self.paint_line(
+                &mut image, line_idx, line, selrange, &cursor, true, &palette, metrics,
+            )?;
+        }
+        term.clean_dirty_lines();
+        Ok(image)
+    }
+
+    pub fn paint_header(&self, image: &mut Image, rows: usize, color: Color, cell_height: usize) {
+        let (width, _) = image.image_dimensions();
+        image.clear_rect(
+            Rect::new(Point::new(0, 0), Size::new(width as isize, (rows * cell_height) as isize)),
+            color,
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn paint_line(
+        &mut self,
+        image: &mut Image,
+        line_idx: usize,
+        line: &Line,
+        selection: Range<usize>,
+        cursor: &CursorPosition,
+        focused: bool,
+        palette: &ColorPalette,
+        metrics: &RenderMetrics,
+    ) -> anyhow::Result<()> {
+        let (image_width, _) = image.image_dimensions();
+        let line_size = line.line_size();
+        let cell_width = if line_size == term::LineSize::Single {
+            metrics.cell_size.width
+        } else {
+            metrics.cell_size.width * 2
+        };
+        let cell_height = metrics.cell_size.height;
+        let num_cols = image_width / cell_width.max(1) as usize;
+        let top = line_idx as isize * cell_height;
+        let cell_origin = |cell_idx: usize| Point::new(cell_idx as isize * cell_width, top);
+
+        image.clear_rect(
+            Rect::new(Point::new(0, top), Size::new(image_width as isize, cell_height)),
+            rgbcolor_to_window_color(palette.background),
+        );
+
+        let mut last_cell_idx = None;
+        for cluster in line.cluster() {
+            let attrs = &cluster.attrs;
+            let style = self.fonts.match_style(attrs);
+            let (fg_color, bg_color) =
+                resolve_cluster_colors(attrs, style, palette, self.minimum_contrast_ratio);
+            let underline = if attrs.predicted() && attrs.underline() == term::Underline::None {
+                term::Underline::Single
+            } else {
+                attrs.underline()
+            };
+            let decoration =
+                self.util_sprites.select_sprite(false, attrs.strikethrough(), underline).clone();
+
+            let blank = self.blank_cell_fast_path && is_blank_cluster(&cluster);
+            let first_cell = cluster.byte_to_cell_idx.first().copied().unwrap_or(0);
+            let last_cell = cluster.byte_to_cell_idx.last().copied().unwrap_or(first_cell);
+            let last_cell = last_cell
+                + line.cells().get(last_cell).map(|cell| cell.width()).unwrap_or(1).max(1)
+                - 1;
+            let mut glyph_colors = vec![];
+            for cell_idx in first_cell..=last_cell.min(num_cols.saturating_sub(1)) {
+                let (glyph_color, bg, _) = compute_cell_fg_bg(
+                    focused, line_idx, cell_idx, cursor, &selection, fg_color, bg_color, palette,
+                );
+                let origin = cell_origin(cell_idx);
+                image.clear_rect(Rect::new(origin, Size::new(cell_width, cell_height)), bg);
+                if !blank {
+                    blit(image, origin, &decoration, Operator::MultiplyThenOver(glyph_color));
+                }
+                glyph_colors.push(glyph_color);
+                last_cell_idx = Some(cell_idx);
+            }
+
+            // Blank runs don't need to be shaped, which keeps the common
+            // case of empty space away from the font machinery
+            if blank
+                || cluster.text.trim().is_empty()
+                || attrs.invisible()
+                || line_size == term::LineSize::DoubleHeightBottom
+            {
+                continue;
+            }
+
+            let glyph_info = {
+                let font = self.fonts.resolve_font(style)?;
+                font.shape(&cluster.text)?
+            };
+            for info in &glyph_info {
+                let cell_idx = cluster.byte_to_cell_idx[info.cluster as usize];
+                if cell_idx >= num_cols {
+                    break;
+                }
+                let glyph =
+                    self.glyph_cache.cached_cluster_glyph(info, style, &cluster.text, metrics)?;
+                let texture = match glyph.texture.as_ref() {
+                    Some(texture) => texture,
+                    None => continue,
+                };
+                let left = (glyph.x_offset + glyph.bearing_x).get() as isize;
+                let glyph_top = ((PixelLength::new(cell_height as f64) + metrics.descender)
+                    - (glyph.y_offset + glyph.bearing_y))
+                    .get() as isize;
+                let origin = cell_origin(cell_idx);
+                let operator = if glyph.has_color {
+                    Operator::Over
+                } else {
+                    let color = glyph_colors.get(cell_idx - first_cell).copied();
+                    Operator::MultiplyThenOver(color.unwrap_or_else(|| Color(0)))
+                };
+                blit(image, Point::new(origin.x + left, origin.y + glyph_top), texture, operator);
+            }
+        }
+
+        let trailing = last_cell_idx.map(|idx| idx + 1).unwrap_or(0);
+        for cell_idx in trailing..num_cols {
+            let (_, bg, _) = compute_cell_fg_bg(
+                focused,
+                line_idx,
+                cell_idx,
+                cursor,
+                &selection,
+                palette.foreground,
+                palette.background,
+                palette,
+            );
+            image.clear_rect(
+                Rect::new(cell_origin(cell_idx), Size::new(cell_width, cell_height)),
+                bg,
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn blit(image: &mut Image, dest: Point, sprite: &Sprite<ImageTexture>, operator: Operator) {
+    image.draw_image(dest, Some(sprite.coords), &*sprite.texture.image.borrow(), operator);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::term::color::ColorAttribute;
+    use crate::term::{Cell, CellAttributes};
+    use crate::testing::TestHost;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    /// Compares `image` with `src/gui/golden/<name>.png`, allowing each
+    /// channel to be off by `tolerance` so that small differences in font
+    /// hinting don't fail the test. The golden image is written instead
+    /// when `MIRO_UPDATE_GOLDEN` is set; a missing one is a failure.
+    fn assert_matches_golden(image: &Image, name: &str, tolerance: u8) {
+        let path: PathBuf =
+            [env!("CARGO_MANIFEST_DIR"), "src", "gui", "golden", &format!("{}.png", name)]
+                .iter()
+                .collect();
+        let (width, height) = image.image_dimensions();
+        let pixels: Vec<u8> = image
+            .pixels()
+            .iter()
+            .flat_map(|&pixel| {
+                let (red, green, blue, alpha) = Color(pixel).as_rgba();
+                vec![red, green, blue, alpha]
+            })
+            .collect();
+
+        if std::env::var_os("MIRO_UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            image::RgbaImage::from_raw(width as u32, height as u32, pixels)
+                .unwrap()
+                .save(&path)
+                .unwrap();
+            eprintln!("wrote golden image {}", path.display());
+            return;
+        }
+
+        assert!(
+            path.exists(),
+            "no golden image at {}; run with MIRO_UPDATE_GOLDEN=1 to create it",
+            path.display()
+        );
+        let golden = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(golden.dimensions(), (width as u32, height as u32), "size of {}", name);
+        for (idx, (actual, expected)) in pixels.chunks(4).zip(golden.pixels()).enumerate() {
+            let close = actual
+                .iter()
+                .zip(expected.0.iter())
+                .all(|(a, e)| (i16::from(*a) - i16::from(*e)).abs() <= i16::from(tolerance));
+            assert!(
+                close,
+                "{} differs at {},{}: {:?} != {:?}",
+                name,
+                idx % width,
+                idx / width,
+                actual,
+                expected.0
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "depends on the system fonts; create golden/hello.png with MIRO_UPDATE_GOLDEN=1"]
+    fn hello_matches_golden_image() {
+        let fonts = Rc::new(FontConfiguration::new(Arc::new(Config::default())));
+        let metrics = RenderMetrics::new(&fonts);
+        let mut renderer = SoftwareRenderer::new(&fonts, &metrics, 1024, 64, None, true).unwrap();
+        let mut term = Terminal::new(2, 8, 0, 0, 0, vec![]);
+        term.advance_bytes("Hello\r\n\x1b[32;4mworld\x1b[m", &mut TestHost::new());
+
+        let image = renderer.render_offscreen(&mut term, &metrics).unwrap();
+        assert_matches_golden(&image, "hello", 16);
+    }
+
+    fn metrics() -> RenderMetrics {
+        RenderMetrics {
+            descender: PixelLength::new(-3.),
+            descender_row: 13,
+            descender_plus_two: 15,
+            underline_height: 1,
+            strike_row: 6,
+            cell_size: Size::new(8, 16),
+        }
+    }
+
+    #[test]
+    fn cells_match_golden_image() {
+        let fonts = Rc::new(FontConfiguration::new(Arc::new(Config::default())));
+        let metrics = metrics();
+        let mut renderer = SoftwareRenderer::new(&fonts, &metrics, 256, 16, None, true).unwrap();
+        let mut term = Terminal::new(2, 4, 0, 0, 0, vec![]);
+        term.advance_bytes("\x1b[41m  \x1b[42m \x1b[m\r\n\x1b[44m \x1b[m ", &mut TestHost::new());
+
+        let image = renderer.render_offscreen(&mut term, &metrics).unwrap();
+        assert_matches_golden(&image, "cells", 0);
+    }
+
+    #[test]
+    fn blank_runs_paint_like_the_slow_path() {
+        let fonts = Rc::new(FontConfiguration::new(Arc::new(Config::default())));
+        let metrics = metrics();
+        let palette = ColorPalette::default();
+        let cursor = CursorPosition { x: 2, y: 0 };
+        let line = Line::with_width(4);
+
+        let paint = |blank_cell_fast_path| {
+            let mut renderer =
+                SoftwareRenderer::new(&fonts, &metrics, 256, 16, None, blank_cell_fast_path)
+                    .unwrap();
+            let mut image = Image::new(32, 16);
+            renderer
+                .paint_line(&mut image, 0, &line, 1..2, &cursor, true, &palette, &metrics)
+                .unwrap();
+            image
+        };
+
+        let fast = paint(true);
+        let slow = paint(false);
+        for y in 0..16 {
+            for x in 0..32 {
+                assert_eq!(fast.pixel(x, y), slow.pixel(x, y), "at {},{}", x, y);
+            }
+        }
+    }
+
+    /// Times a full screen of blank lines with and without the fast path.
+    /// Run with `cargo test --release -- --ignored --nocapture
+    /// bench_blank_screen`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_blank_screen() {
+        let fonts = Rc::new(FontConfiguration::new(Arc::new(Config::default())));
+        let metrics = metrics();
+        let palette = ColorPalette::default();
+        let cursor = CursorPosition { x: 0, y: 0 };
+        let line = Line::with_width(200);
+        let mut image = Image::new(200 * 8, 50 * 16);
+
+        for &blank_cell_fast_path in &[true, false] {
+            let mut renderer =
+                SoftwareRenderer::new(&fonts, &metrics, 256, 16, None, blank_cell_fast_path)
+                    .unwrap();
+            let start = std::time::Instant::now();
+            for _ in 0..20 {
+                for line_idx in 0..50 {
+                    renderer
+                        .paint_line(
+                            &mut image,
+                            line_idx,
+                            &line,
+                            0..0,
+                            &cursor,
+                            true,
+                            &palette,
+                            &metrics,
+                        )
+                        .unwrap();
+                }
+            }
+            eprintln!(
+                "blank_cell_fast_path={}: {:?} per frame",
+                blank_cell_fast_path,
+                start.elapsed() / 20
+            );
+        }
+    }
+
+    #[test]
+    fn paints_cell_backgrounds_into_an_image() {
+        let fonts = Rc::new(FontConfiguration::new(Arc::new(Config::default())));
+        let metrics = metrics();
+        let mut renderer = SoftwareRenderer::new(&fonts, &metrics, 256, 16, None, true).unwrap();
+        let palette = ColorPalette::default();
+
+        let mut line = Line::with_width(4);
+        let mut attrs = CellAttributes::default();
+        attrs.set_background(ColorAttribute::PaletteIndex(1));
+        line.set_cell(1, Cell::new(' ', attrs));
+
+        let mut image = Image::new(32, 32);
+        renderer.paint_header(&mut image, 1, Color::rgb(1, 2, 3), 16);
+        let cursor = CursorPosition { x: 3, y: 1 };
+        renderer.paint_line(&mut image, 1, &line, 0..0, &cursor, true, &palette, &metrics).unwrap();
+
+        let pixel = |x, y| Color(*image.pixel(x, y)).as_rgba();
+        let rgb = |c: crate::core::color::RgbColor| (c.red, c.green, c.blue, 0xff);
+
+        assert_eq!(pixel(4, 4), (1, 2, 3, 0xff));
+        assert_eq!(pixel(4, 20), rgb(palette.background));
+        assert_eq!(pixel(12, 20), rgb(palette.colors.0[1]));
+        assert_eq!(pixel(28, 20), rgb(palette.cursor_bg));
+    }
+}
@@ -20,9 +20,8 @@ pub struct RenderMetrics {
 impl RenderMetrics {
     pub fn new(fonts: &Rc<FontConfiguration>) -> Self {
         let metrics = fonts.default_font_metrics().expect("failed to get font metrics!?");
-
-        let (cell_height, cell_width) =
-            (metrics.cell_height.get().ceil() as usize, metrics.cell_width.get().ceil() as usize);
+        let (cell_width, cell_height) =
+            fonts.cell_pixel_size().expect("failed to get font metrics!?");
 
         let underline_height = metrics.underline_thickness.get().round() as isize;
 
@@ -257,16 +256,24 @@ impl<T: Texture2d> UtilSprites<T> {
     ) -> &Sprite<T> {
         match (is_highlited_hyperlink, is_strike_through, underline) {
             (true, false, Underline::None) => &self.single_underline,
-            (true, false, Underline::Single) => &self.double_underline,
+            (true, false, Underline::Single) | (true, false, Underline::Curly) => {
+                &self.double_underline
+            }
             (true, false, Underline::Double) => &self.single_underline,
             (true, true, Underline::None) => &self.strike_through,
-            (true, true, Underline::Single) => &self.single_and_strike,
+            (true, true, Underline::Single) | (true, true, Underline::Curly) => {
+                &self.single_and_strike
+            }
             (true, true, Underline::Double) => &self.double_and_strike,
             (false, false, Underline::None) => &self.white_space,
-            (false, false, Underline::Single) => &self.single_underline,
+            (false, false, Underline::Single) | (false, false, Underline::Curly) => {
+                &self.single_underline
+            }
             (false, false, Underline::Double) => &self.double_underline,
             (false, true, Underline::None) => &self.strike_through,
-            (false, true, Underline::Single) => &self.single_and_strike,
+            (false, true, Underline::Single) | (false, true, Underline::Curly) => {
+                &self.single_and_strike
+            }
             (false, true, Underline::Double) => &self.double_and_strike,
         }
     }
@@ -1,34 +1,54 @@
+use super::bell::BellLimiter;
+use super::cursor::SmoothCursor;
+use super::glyphcache::AtlasReclaimed;
 use super::header::Header;
+use super::idle::IdleTracker;
+use super::overlay::{Overlay, TextOverlay};
+use super::paste;
 use super::quad::*;
 use super::renderstate::RenderState;
+use super::scroll::WheelAccumulator;
+use super::softrender::SoftwareRenderer;
 use super::utilsprites::RenderMetrics;
+use crate::config::{CursorAnimation, TextStyle};
+use crate::core::cellcluster::CellCluster;
 use crate::core::color::RgbColor;
+use crate::core::hyperlink::Hyperlink;
 use crate::core::promise;
 use crate::core::surface::CursorShape;
-use crate::font::FontConfiguration;
+use crate::font::{self, FontConfiguration};
 use crate::mux::tab::Tab;
 use crate::mux::Mux;
 use crate::pty::PtySize;
 use crate::term;
-use crate::term::clipboard::{Clipboard, SystemClipboard};
+use crate::term::clipboard::{Clipboard, ClipboardSelection, SystemClipboard};
 use crate::term::color::ColorPalette;
 use crate::term::keyassignment::{KeyAssignment, KeyMap};
 use crate::term::Terminal;
-use crate::term::{CursorPosition, Line};
+use crate::term::{CursorPosition, Line, ResizeRequest};
 use crate::window;
 use crate::window::bitmaps::atlas::OutOfTextureSpace;
 use crate::window::bitmaps::atlas::SpriteSlice;
 use crate::window::bitmaps::Texture2d;
 use crate::window::*;
+use anyhow::Context as _;
+use chrono::Local;
 use glium::{uniform, Surface};
 use std::any::Any;
 use std::cell::Ref;
 use std::ops::Range;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 const ATLAS_SIZE: usize = 4096;
+const MAX_ATLAS_SIZE: usize = 16384;
+/// The software atlas lives in system memory, so it starts out small and
+/// grows on demand.
+const SOFTWARE_ATLAS_SIZE: usize = 1024;
+/// Passed when painting rows that must not show the text cursor.
+const NO_CURSOR: CursorPosition = CursorPosition { x: 0, y: -1 };
 
 #[derive(Debug, Clone, Copy)]
 struct RowsAndCols {
@@ -42,12 +62,24 @@ pub struct TermWindow {
     dimensions: Dimensions,
     render_metrics: RenderMetrics,
     render_state: Option<RenderState>,
+    software: Option<SoftwareRenderer>,
     clipboard: Arc<dyn Clipboard>,
     keys: KeyMap,
-    frame_count: u32,
     terminal_size: PtySize,
     header: Header,
     focused: Option<Instant>,
+    idle: IdleTracker,
+    wheel: WheelAccumulator,
+    bell: BellLimiter,
+    smooth_cursor: Option<SmoothCursor>,
+    /// Where the gliding cursor is drawn this frame, in cells
+    cursor_glide: Option<(f32, f32)>,
+    overlay: Option<Box<dyn Overlay>>,
+    hovered_link: Option<Arc<Hyperlink>>,
+    /// How far back the viewport was scrolled when the overlay last said so
+    lines_below: usize,
+    /// A paste waiting on the user to confirm it from the overlay
+    pending_paste: Option<String>,
 }
 
 struct Host<'a> {
@@ -73,6 +105,59 @@ impl<'a> term::TerminalHost for Host<'a> {
         let link = link.clone();
         promise::spawn(async move { if let Err(_) = open::that(link.uri()) {} });
     }
+
+    fn show_notification(&mut self, title: Option<&str>, body: &str) {
+        notify(title.unwrap_or("miro"), body);
+    }
+}
+
+/// Shows a desktop notification unless the user turned them off.
+fn notify(title: &str, body: &str) {
+    if Mux::get().unwrap().config().enable_notifications {
+        super::notification::show(title, body);
+    }
+}
+
+/// The pixel size of the part of a window of `dimensions` that the
+/// terminal is drawn in, below `header_rows` rows of `cell_height`.
+fn terminal_pixel_size(
+    dimensions: &Dimensions,
+    header_rows: usize,
+    cell_height: usize,
+) -> (usize, usize) {
+    (dimensions.pixel_width, dimensions.pixel_height.saturating_sub(header_rows * cell_height))
+}
+
+/// The window size for a `request` from the application, in whole cells
+/// and with room for `header_rows`, shrunk to fit `work_area` when the
+/// screen size is known.
+fn resize_request_pixels(
+    request: ResizeRequest,
+    cell_width: usize,
+    cell_height: usize,
+    header_rows: usize,
+    work_area: Option<(usize, usize)>,
+) -> (usize, usize) {
+    let (max_width, max_height) = work_area.unwrap_or((usize::max_value(), usize::max_value()));
+    let max_width = max_width.min(u16::max_value() as usize);
+    let max_height = max_height.min(u16::max_value() as usize);
+
+    let rows = (request.rows + header_rows).min(max_height / cell_height).max(header_rows + 1);
+    let cols = request.cols.min(max_width / cell_width).max(1);
+    (cols * cell_width, rows * cell_height)
+}
+
+/// Writes the scrollback to a timestamped file in `$HOME`.
+fn save_scrollback(tab: &Ref<Tab>) -> anyhow::Result<PathBuf> {
+    let dir = match std::env::var_os("HOME") {
+        Some(home) if !home.is_empty() => PathBuf::from(home),
+        _ => anyhow::bail!("HOME is not set, so there is nowhere to save it"),
+    };
+    let path = dir.join(format!("miro-scrollback-{}.txt", Local::now().format("%Y%m%d-%H%M%S")));
+    let mut file = std::fs::File::create(&path)
+        .with_context(|| format!("failed to create {}", path.display()))?;
+    tab.renderer().export_scrollback(&mut file, false)?;
+    Ok(path)
 }
 
 impl WindowCallbacks for TermWindow {
@@ -88,6 +173,7 @@ impl WindowCallbacks for TermWindow {
             &self.fonts,
             &self.render_metrics,
             ATLAS_SIZE,
+            mux.config().glyph_cache_size,
             self.dimensions.pixel_width,
             self.dimensions.pixel_height,
             &mux.config().theme,
@@ -102,11 +188,30 @@ impl WindowCallbacks for TermWindow {
         Ok(())
     }
 
+    fn created_software(&mut self, window: &Window) -> anyhow::Result<()> {
+        self.window.replace(window.clone());
+        let mux = Mux::get().unwrap();
+        self.software = Some(SoftwareRenderer::new(
+            &self.fonts,
+            &self.render_metrics,
+            SOFTWARE_ATLAS_SIZE,
+            mux.config().glyph_cache_size,
+            mux.config().minimum_contrast_ratio,
+            mux.config().blank_cell_fast_path,
+        )?);
+
+        window.show();
+
+        Ok(())
+    }
+
     fn focus_change(&mut self, focused: bool) {
         self.focused = if focused { Some(Instant::now()) } else { None };
+        self.idle.record_activity();
         let mux = Mux::get().unwrap();
         let tab = mux.get_tab();
         tab.renderer().make_all_lines_dirty();
+        tab.focus_changed(focused).ok();
     }
 
     fn can_close(&self) -> bool {
@@ -125,6 +230,7 @@ impl WindowCallbacks for TermWindow {
         use window::MouseButtons as WMB;
         use window::MouseEventKind as WMEK;
 
+        self.idle.record_activity();
         let mux = Mux::get().unwrap();
         let tab = mux.get_tab();
 
@@ -132,6 +238,19 @@ impl WindowCallbacks for TermWindow {
         let y = (event.y as isize / self.render_metrics.cell_size.height) as i64;
 
         let adjusted_y = y.saturating_sub(self.header.offset as i64);
+        let header_height = self.header.offset as isize * self.render_metrics.cell_size.height;
+        let pixel_y = (event.y as isize - header_height).max(0) as usize;
+
+        let wheel_lines = match event.kind {
+            WMEK::VertWheel(delta) => {
+                let lines = self.wheel.accumulate(delta);
+                if lines == 0 {
+                    return;
+                }
+                lines
+            }
+            _ => 0,
+        };
 
         tab.mouse_event(
             term::MouseEvent {
@@ -157,23 +276,35 @@ impl WindowCallbacks for TermWindow {
                             TMB::None
                         }
                     }
-                    WMEK::VertWheel(amount) => {
-                        if amount > 0 {
-                            TMB::WheelUp(amount as usize)
+                    WMEK::VertWheel(_) => {
+                        if wheel_lines > 0 {
+                            TMB::WheelUp(wheel_lines as usize)
                         } else {
-                            TMB::WheelDown((-amount) as usize)
+                            TMB::WheelDown((-wheel_lines) as usize)
                         }
                     }
                     WMEK::HorzWheel(_) => TMB::None,
                 },
                 x,
                 y: adjusted_y,
+                pixel_x: event.x as usize,
+                pixel_y,
                 modifiers: window_mods_to_termwiz_mods(event.modifiers),
             },
             &mut Host { writer: &mut *tab.writer(), context, clipboard: &self.clipboard },
         )
         .ok();
 
+        let highlight = tab.renderer().current_highlight();
+        if highlight != self.hovered_link && self.pending_paste.is_none() {
+            let overlay = highlight
+                .as_ref()
+                .map(|link| Box::new(TextOverlay::new(link.uri())) as Box<dyn Overlay>);
+            self.set_overlay(&tab, overlay);
+            self.hovered_link = highlight;
+            self.lines_below = 0;
+        }
+
         match event.kind {
             WMEK::Move => {}
             WMEK::Press(_) => {
@@ -203,10 +334,19 @@ impl WindowCallbacks for TermWindow {
         self.scaling_changed(dimensions, self.fonts.get_font_scale());
     }
 
+    fn dpi_changed(&mut self, dpi: usize) {
+        if dpi == self.dimensions.dpi {
+            return;
+        }
+        let dimensions = Dimensions { dpi, ..self.dimensions };
+        self.scaling_changed(dimensions, self.fonts.get_font_scale());
+    }
+
     fn key_event(&mut self, key: &KeyEvent, _context: &dyn WindowOps) -> bool {
         if !key.key_is_down {
             return false;
         }
+        self.idle.record_activity();
 
         enum Key {
             Code(crate::core::input::KeyCode),
@@ -286,6 +426,17 @@ impl WindowCallbacks for TermWindow {
         let tab = mux.get_tab();
         let modifiers = window_mods_to_termwiz_mods(key.modifiers);
 
+        if self.pending_paste.is_some() {
+            use window::KeyCode as WK;
+            match key.key {
+                // Modifiers on their own neither confirm nor cancel
+                WK::Shift | WK::Control | WK::Alt | WK::Super | WK::CapsLock => {}
+                WK::Char('y') | WK::Char('Y') => self.finish_pending_paste(&tab, true),
+                _ => self.finish_pending_paste(&tab, false),
+            }
+            return true;
+        }
+
         if let Some(key) = &key.raw_key {
             if let Key::Code(key) = win_key_code_to_termwiz_key_code(&key) {
                 if let Some(assignment) = self.keys.lookup(key, modifiers) {
@@ -314,7 +465,7 @@ impl WindowCallbacks for TermWindow {
                 }
             }
             Key::Composed(s) => {
-                tab.writer().write_all(s.as_bytes()).ok();
+                tab.send_ime_commit(&s).ok();
                 return true;
             }
             Key::None => {}
@@ -323,24 +474,45 @@ impl WindowCallbacks for TermWindow {
         false
     }
 
-    fn paint(&mut self, frame: &mut glium::Frame) {
+    fn ime_commit(&mut self, text: &str) {
+        self.idle.record_activity();
         let mux = Mux::get().unwrap();
         let tab = mux.get_tab();
+        tab.send_ime_commit(text).ok();
+    }
 
-        self.update_text_cursor(&tab);
-        self.update_title();
+    fn needs_paint(&self) -> bool {
+        let mux = Mux::get().unwrap();
+        let tab = mux.get_tab();
+        (!self.idle.is_idle() && self.header.is_animation_due())
+            || tab.renderer().has_dirty_lines()
+            || tab.renderer().is_bell_pending()
+            || self.cursor_glide.is_some()
+    }
+
+    fn paint(&mut self, frame: &mut glium::Frame) {
+        let mux = Mux::get().unwrap();
+        let tab = mux.get_tab();
+        self.prepare_paint(&tab);
 
         if let Err(err) = self.paint_screen(&tab, frame) {
-            if let Some(&OutOfTextureSpace { size }) = err.downcast_ref::<OutOfTextureSpace>() {
-                if let Err(_) = self.recreate_texture_atlas(Some(size)) {
-                    self.recreate_texture_atlas(None)
-                        .expect("OutOfTextureSpace and failed to recreate atlas");
-                }
-                tab.renderer().make_all_lines_dirty();
+            if self.handle_out_of_texture_space(&tab, &err) {
                 return self.paint(frame);
             }
         }
     }
+
+    fn paint_software(&mut self, image: &mut crate::window::bitmaps::Image) {
+        let mux = Mux::get().unwrap();
+        let tab = mux.get_tab();
+        self.prepare_paint(&tab);
+
+        if let Err(err) = self.paint_screen_software(&tab, image) {
+            if self.handle_out_of_texture_space(&tab, &err) {
+                return self.paint_software(image);
+            }
+        }
+    }
 }
 
 impl TermWindow {
@@ -359,6 +531,9 @@ impl TermWindow {
         };
 
         let header = Header::new();
+        let idle = IdleTracker::new(mux.config().idle_timeout_secs.map(Duration::from_secs));
+        let wheel = WheelAccumulator::new(mux.config().scroll_lines_per_wheel);
+        let bell = BellLimiter::new(Duration::from_millis(mux.config().bell_rate_limit_ms));
 
         let dimensions = Dimensions {
             pixel_width: (terminal_size.cols * render_metrics.cell_size.width as u16) as usize,
@@ -367,9 +542,15 @@ impl TermWindow {
             dpi: 96,
         };
 
+        let config = mux.config();
+        let mut keys = KeyMap::new();
+        if config.xon_xoff {
+            keys.bind_flow_control();
+        }
+        keys.bind(&config.keys);
         Window::new_window(
-            "miro",
-            "miro",
+            &config.window_class,
+            config.fixed_title.as_ref().unwrap_or(&config.window_title),
             dimensions.pixel_width,
             dimensions.pixel_height,
             Box::new(Self {
@@ -379,17 +560,64 @@ impl TermWindow {
                 render_metrics,
                 dimensions,
                 render_state: None,
+                software: None,
                 clipboard: Arc::new(SystemClipboard::new()),
-                keys: KeyMap::new(),
+                keys,
                 header,
-                frame_count: 0,
                 terminal_size,
+                idle,
+                wheel,
+                bell,
+                smooth_cursor: match mux.config().cursor_animation {
+                    CursorAnimation::SmoothMove => Some(SmoothCursor::new()),
+                    CursorAnimation::None => None,
+                },
+                cursor_glide: None,
+                overlay: None,
+                hovered_link: None,
+                lines_below: 0,
+                pending_paste: None,
             }),
         )?;
 
         Ok(())
     }
 
+    fn prepare_paint(&mut self, tab: &Ref<Tab>) {
+        if tab.renderer().has_dirty_lines() {
+            self.idle.record_activity();
+        }
+        self.header.set_idle(self.idle.is_idle());
+
+        self.update_text_cursor(tab);
+        self.update_smooth_cursor(tab);
+        self.update_title();
+        self.apply_resize_request(tab);
+        self.ring_bell(tab);
+        self.update_scroll_hint(tab);
+        self.answer_position_reports(tab);
+    }
+
+    /// Grows the glyph atlas when `err` says it filled up, or repaints after
+    /// the glyph cache emptied it; returns true when the frame should be
+    /// painted again.
+    fn handle_out_of_texture_space(&mut self, tab: &Ref<Tab>, err: &anyhow::Error) -> bool {
+        if err.is::<AtlasReclaimed>() {
+            tab.renderer().make_all_lines_dirty();
+            return true;
+        }
+        if let Some(&OutOfTextureSpace { size }) = err.downcast_ref::<OutOfTextureSpace>() {
+            let size = if size <= MAX_ATLAS_SIZE { Some(size) } else { None };
+            if let Err(_) = self.recreate_texture_atlas(size) {
+                self.recreate_texture_atlas(None)
+                    .expect("OutOfTextureSpace and failed to recreate atlas");
+            }
+            tab.renderer().make_all_lines_dirty();
+            return true;
+        }
+        false
+    }
+
     fn update_title(&mut self) {
         let mux = Mux::get().unwrap();
         let tab = mux.get_tab();
@@ -400,6 +628,95 @@ impl TermWindow {
         }
     }
 
+    fn update_smooth_cursor(&mut self, tab: &Ref<Tab>) {
+        let smooth = match self.smooth_cursor.as_mut() {
+            Some(smooth) => smooth,
+            None => return,
+        };
+        let now = Instant::now();
+        let cursor = tab.renderer().cursor_pos();
+        smooth.move_to((cursor.x as f32, (cursor.y + self.header.offset as i64) as f32), now);
+        let glide = smooth.position_at(now);
+        // The cursor has arrived; draw it back into its cell
+        if glide.is_none() && self.cursor_glide.is_some() {
+            tab.renderer().make_all_lines_dirty();
+        }
+        self.cursor_glide = glide;
+    }
+
+    /// Tells the user how far back they are while the viewport is scrolled
+    /// into the scrollback; a hovered link or paste prompt takes precedence.
+    fn update_scroll_hint(&mut self, tab: &Ref<Tab>) {
+        if self.hovered_link.is_some() || self.pending_paste.is_some() {
+            return;
+        }
+        let below = {
+            let term = tab.renderer();
+            let (top, total) = term.scroll_position();
+            total - term.physical_dimensions().0 - top
+        };
+        if below == self.lines_below {
+            return;
+        }
+        self.lines_below = below;
+        let overlay = if below > 0 {
+            let text = format!("{} lines below", below);
+            Some(Box::new(TextOverlay::new(&text)) as Box<dyn Overlay>)
+        } else {
+            None
+        };
+        self.set_overlay(tab, overlay);
+    }
+
+    fn answer_position_reports(&mut self, tab: &Ref<Tab>) {
+        let reports = tab.take_position_reports();
+        if reports.is_empty() {
+            return;
+        }
+        let origin = match self.window.as_ref().and_then(|window| window.get_position()) {
+            Some(origin) => origin,
+            None => return,
+        };
+        let header_height = self.header.offset as isize * self.render_metrics.cell_size.height;
+        for report in reports {
+            let y = match report {
+                term::PositionReport::Window => origin.y,
+                term::PositionReport::TextArea => origin.y + header_height,
+            };
+            tab.report_position(report, origin.x, y).ok();
+        }
+    }
+
+    /// Beeps when focused; otherwise asks the window manager to draw
+    /// attention to the window.
+    fn ring_bell(&mut self, tab: &Ref<Tab>) {
+        if !tab.take_bell() || !self.bell.admit() {
+            return;
+        }
+        if let Some(window) = self.window.as_ref() {
+            if self.focused.is_some() {
+                window.beep();
+            } else {
+                window.request_attention();
+            }
+        }
+    }
+
+    fn apply_resize_request(&mut self, tab: &Ref<Tab>) {
+        if let Some(request) = tab.take_resize_request() {
+            if let Some(window) = self.window.as_ref() {
+                let (width, height) = resize_request_pixels(
+                    request,
+                    self.render_metrics.cell_size.width as usize,
+                    self.render_metrics.cell_size.height as usize,
+                    self.header.offset,
+                    window.get_work_area_size(),
+                );
+                window.set_inner_size(width, height);
+            }
+        }
+    }
+
     fn update_text_cursor(&mut self, tab: &Ref<Tab>) {
         let term = tab.renderer();
         let cursor = term.cursor_pos();
@@ -425,7 +742,20 @@ impl TermWindow {
             ToggleFullScreen => {}
             Copy => {}
             Paste => {
-                tab.trickle_paste(self.clipboard.get_contents()?)?;
+                let text =
+                    tab.clipboard_paste_text(&*self.clipboard, ClipboardSelection::Clipboard)?;
+                if let Some(text) = text {
+                    let bracketed = tab.renderer().bracketed_paste_enabled();
+                    if Mux::get().unwrap().config().confirm_multiline_paste
+                        && paste::needs_confirmation(&text, bracketed)
+                    {
+                        let prompt = Box::new(TextOverlay::new(&paste::prompt(&text)));
+                        self.set_overlay(tab, Some(prompt));
+                        self.pending_paste = Some(text);
+                    } else {
+                        tab.trickle_paste(text)?;
+                    }
+                }
             }
             DecreaseFontSize => self.decrease_font_size(),
             IncreaseFontSize => self.increase_font_size(),
@@ -435,6 +765,18 @@ impl TermWindow {
                     w.hide();
                 }
             }
+            SaveScrollback => match save_scrollback(tab) {
+                Ok(path) => notify("Scrollback saved", &path.display().to_string()),
+                Err(err) => {
+                    notify("Failed to save scrollback", &format!("{:#}", err));
+                    return Err(err);
+                }
+            },
+            ScrollToPrevPrompt => tab.renderer().scroll_to_prev_prompt(),
+            ScrollToNextPrompt => tab.renderer().scroll_to_next_prompt(),
+            SendString(s) => tab.send_bytes(s.as_bytes())?,
+            PauseOutput => Mux::get().unwrap().pause_output(),
+            ResumeOutput => Mux::get().unwrap().resume_output(),
         };
         Ok(())
     }
@@ -462,21 +804,25 @@ impl TermWindow {
     }
 
     fn apply_scale_change(&mut self, dimensions: &Dimensions, font_scale: f64) {
-        self.fonts.change_scaling(font_scale, dimensions.dpi as f64 / 96.);
+        self.fonts.change_scaling(font_scale, font::dpi_scale(dimensions.dpi));
         self.render_metrics = RenderMetrics::new(&self.fonts);
-        let gl_state = self.render_state.as_mut().unwrap();
-        gl_state
-            .header
-            .change_scaling(
-                dimensions.dpi as f32 / 96.,
-                self.dimensions.pixel_width,
-                self.dimensions.pixel_height,
-            )
-            .expect("failed to rescale header");
+        if let Some(gl_state) = self.render_state.as_mut() {
+            gl_state
+                .header
+                .change_scaling(
+                    font::dpi_scale(dimensions.dpi) as f32,
+                    self.dimensions.pixel_width,
+                    self.dimensions.pixel_height,
+                )
+                .expect("failed to rescale header");
+        }
         self.recreate_texture_atlas(None).expect("failed to recreate atlas");
     }
 
     fn recreate_texture_atlas(&mut self, size: Option<usize>) -> anyhow::Result<()> {
+        if let Some(software) = self.software.as_mut() {
+            return software.recreate_texture_atlas(&self.render_metrics, size);
+        }
         self.render_state.as_mut().unwrap().recreate_texture_atlas(
             &self.fonts,
             &self.render_metrics,
@@ -491,58 +837,42 @@ impl TermWindow {
     ) {
         self.dimensions = *dimensions;
 
-        let (size, dims) = if let Some(cell_dims) = scale_changed_cells {
-            let size = PtySize {
-                rows: cell_dims.rows as u16,
-                cols: cell_dims.cols as u16,
-                pixel_height: cell_dims.rows as u16 * self.render_metrics.cell_size.height as u16,
-                pixel_width: cell_dims.cols as u16 * self.render_metrics.cell_size.width as u16,
-            };
-
-            let rows = size.rows + self.header.offset as u16;
-            let cols = size.cols;
-
-            let pixel_height = rows * self.render_metrics.cell_size.height as u16;
+        let cell_width = self.render_metrics.cell_size.width as usize;
+        let cell_height = self.render_metrics.cell_size.height as usize;
+        let header_height = self.header.offset * cell_height;
 
-            let pixel_width = cols * self.render_metrics.cell_size.width as u16;
+        // The terminal gets the window less the header rows
+        let (pixel_width, pixel_height, dims) = if let Some(cell_dims) = scale_changed_cells {
+            let pixel_width = cell_dims.cols * cell_width;
+            let pixel_height = cell_dims.rows * cell_height;
 
             let dims = Dimensions {
-                pixel_width: pixel_width as usize,
-                pixel_height: pixel_height as usize,
+                pixel_width,
+                pixel_height: pixel_height + header_height,
                 dpi: dimensions.dpi,
             };
 
-            (size, dims)
+            (pixel_width, pixel_height, dims)
         } else {
-            let rows = (dimensions.pixel_height / self.render_metrics.cell_size.height as usize)
-                .saturating_sub(self.header.offset);
-            let cols = dimensions.pixel_width / self.render_metrics.cell_size.width as usize;
-
-            let size = PtySize {
-                rows: rows as u16,
-                cols: cols as u16,
-                pixel_height: dimensions.pixel_height as u16,
-                pixel_width: dimensions.pixel_width as u16,
-            };
-
-            (size, *dimensions)
+            let (pixel_width, pixel_height) =
+                terminal_pixel_size(dimensions, self.header.offset, cell_height);
+            (pixel_width, pixel_height, *dimensions)
         };
 
         let mux = Mux::get().unwrap();
         let tab = mux.get_tab();
-        let gl_state = self.render_state.as_mut().unwrap();
-
-        gl_state
-            .advise_of_window_size_change(
-                &self.render_metrics,
-                dimensions.pixel_width,
-                dimensions.pixel_height,
-            )
-            .expect("failed to advise of resize");
-
-        self.terminal_size = size;
+        if let Some(gl_state) = self.render_state.as_mut() {
+            gl_state
+                .advise_of_window_size_change(
+                    &self.render_metrics,
+                    dimensions.pixel_width,
+                    dimensions.pixel_height,
+                )
+                .expect("failed to advise of resize");
+        }
 
-        tab.resize(size).ok();
+        self.terminal_size = tab.resize_pixels(pixel_width, pixel_height, cell_width, cell_height);
+        tab.renderer().make_all_lines_dirty();
         self.update_title();
 
         if let Some(_) = scale_changed_cells {
@@ -553,26 +883,27 @@ impl TermWindow {
     }
 
     fn decrease_font_size(&mut self) {
-        self.scaling_changed(self.dimensions, self.fonts.get_font_scale() * 0.9);
+        let font_scale = font::clamp_font_scale(self.fonts.get_font_scale() * 0.9);
+        self.scaling_changed(self.dimensions, font_scale);
     }
     fn increase_font_size(&mut self) {
-        self.scaling_changed(self.dimensions, self.fonts.get_font_scale() * 1.1);
+        let font_scale = font::clamp_font_scale(self.fonts.get_font_scale() * 1.1);
+        self.scaling_changed(self.dimensions, font_scale);
     }
     fn reset_font_size(&mut self) {
         self.scaling_changed(self.dimensions, 1.);
     }
 
     fn paint_screen(&mut self, tab: &Ref<Tab>, frame: &mut glium::Frame) -> anyhow::Result<()> {
-        self.frame_count += 1;
         let palette = tab.palette();
         let gl_state = self.render_state.as_ref().unwrap();
         self.clear(&palette, frame);
         self.paint_term(tab, &gl_state, &palette, frame)?;
+        self.header.set_read_only(tab.is_read_only());
         self.header.paint(
             &gl_state,
             &palette,
             &self.dimensions,
-            self.frame_count,
             &self.render_metrics,
             self.fonts.as_ref(),
             frame,
@@ -581,6 +912,81 @@ impl TermWindow {
         Ok(())
     }
 
+    fn finish_pending_paste(&mut self, tab: &Ref<Tab>, confirmed: bool) {
+        let text = self.pending_paste.take();
+        self.set_overlay(tab, None);
+        self.hovered_link = None;
+        self.lines_below = 0;
+        if let Some(text) = text.filter(|_| confirmed) {
+            tab.trickle_paste(text).ok();
+        }
+    }
+
+    fn set_overlay(&mut self, tab: &Ref<Tab>, overlay: Option<Box<dyn Overlay>>) {
+        self.overlay = overlay;
+        // Whatever the overlay covered, or is about to cover, needs repainting
+        tab.renderer().make_all_lines_dirty();
+    }
+
+    /// The overlay line and where it goes, if there is one.
+    fn overlay_line(&self, term: &Terminal) -> Option<(usize, Line)> {
+        let overlay = self.overlay.as_ref()?;
+        let (rows, cols) = term.physical_dimensions();
+        Some((self.header.offset + rows.saturating_sub(1), overlay.line(cols)))
+    }
+
+    fn paint_screen_software(
+        &mut self,
+        tab: &Ref<Tab>,
+        image: &mut crate::window::bitmaps::Image,
+    ) -> anyhow::Result<()> {
+        let palette = tab.palette();
+        let mut term = tab.renderer();
+        let overlay = self.overlay_line(&term);
+        let software = self.software.as_mut().unwrap();
+        let offset = self.header.offset;
+        let cursor = {
+            let cursor = term.cursor_pos();
+            CursorPosition { x: cursor.x, y: cursor.y + offset as i64 }
+        };
+
+        let theme_color = Mux::get().unwrap().config().theme.color;
+        software.paint_header(
+            image,
+            offset,
+            rgbcolor_to_window_color(theme_color),
+            self.render_metrics.cell_size.height as usize,
+        );
+
+        for (line_idx, line, selrange) in term.get_dirty_lines() {
+            software.paint_line(
+                image,
+                line_idx + offset,
+                &line,
+                selrange,
+                &cursor,
+                self.focused.is_some(),
+                &palette,
+                &self.render_metrics,
+            )?;
+        }
+        if let Some((line_idx, line)) = overlay {
+            software.paint_line(
+                image,
+                line_idx,
+                &line,
+                0..0,
+                &NO_CURSOR,
+                self.focused.is_some(),
+                &palette,
+                &self.render_metrics,
+            )?;
+        }
+        term.clean_dirty_lines();
+
+        Ok(())
+    }
+
     fn paint_term(
         &self,
         tab: &Ref<Tab>,
@@ -597,6 +1003,8 @@ impl TermWindow {
             let cursor = term.cursor_pos();
             CursorPosition { x: cursor.x, y: cursor.y + self.header.offset as i64 }
         };
+        // While gliding the cursor is drawn on its own rather than in a cell
+        let cursor = if self.cursor_glide.is_some() { NO_CURSOR } else { cursor };
 
         let empty_line = Line::from("");
         for i in 0..=self.header.offset - 1 {
@@ -615,6 +1023,17 @@ impl TermWindow {
                 &mut quads,
             )?;
         }
+        if let Some((line_idx, line)) = self.overlay_line(&term) {
+            self.render_screen_line(
+                line_idx,
+                &line,
+                0..0,
+                &NO_CURSOR,
+                &*term,
+                &palette,
+                &mut quads,
+            )?;
+        }
 
         let tex = gl_state.glyph_cache.borrow().atlas.texture();
         let projection = euclid::Transform3D::<f32, f32, f32>::ortho(
@@ -656,11 +1075,51 @@ impl TermWindow {
             &draw_params,
         )?;
 
+        if let Some(glide) = self.cursor_glide {
+            self.paint_gliding_cursor(gl_state, palette, frame, glide, projection, &draw_params)?;
+        }
+
         term.clean_dirty_lines();
 
         Ok(())
     }
 
+    fn paint_gliding_cursor(
+        &self,
+        gl_state: &RenderState,
+        palette: &ColorPalette,
+        frame: &mut glium::Frame,
+        (x, y): (f32, f32),
+        projection: [[f32; 4]; 4],
+        draw_params: &glium::DrawParameters,
+    ) -> anyhow::Result<()> {
+        let cell_width = self.render_metrics.cell_size.width as f32;
+        let cell_height = self.render_metrics.cell_size.height as f32;
+        let left = x * cell_width - self.dimensions.pixel_width as f32 / 2.0;
+        let top = y * cell_height - self.dimensions.pixel_height as f32 / 2.0;
+        let color = rgbcolor_to_window_color(palette.cursor_bg).to_tuple_rgba();
+
+        let mut vb = gl_state.cursor_vertex_buffer.borrow_mut();
+        {
+            let mut vert = vb.slice_mut(0..4).unwrap().map();
+            vert[V_TOP_LEFT] = RectVertex { position: (left, top), color };
+            vert[V_TOP_RIGHT] = RectVertex { position: (left + cell_width, top), color };
+            vert[V_BOT_LEFT] = RectVertex { position: (left, top + cell_height), color };
+            vert[V_BOT_RIGHT] =
+                RectVertex { position: (left + cell_width, top + cell_height), color };
+        }
+        frame.draw(
+            &*vb,
+            &gl_state.cursor_index_buffer,
+            &gl_state.header.rect_program,
+            &uniform! {
+                projection: projection,
+            },
+            draw_params,
+        )?;
+        Ok(())
+    }
+
     fn render_screen_line(
         &self,
         line_idx: usize,
@@ -672,9 +1131,19 @@ impl TermWindow {
         quads: &mut MappedQuads,
     ) -> anyhow::Result<()> {
         let gl_state = self.render_state.as_ref().unwrap();
-        let (_num_rows, num_cols) = terminal.physical_dimensions();
+        let (_num_rows, physical_cols) = terminal.physical_dimensions();
+        let line_size = line.line_size();
+        let (scale_x, scale_y) = line_scale(line_size);
+        let num_cols = if line_size == term::LineSize::Single {
+            physical_cols
+        } else {
+            (physical_cols / 2).max(1)
+        };
 
         let current_highlight = terminal.current_highlight();
+        let config = Mux::get().unwrap().config();
+        let minimum_contrast_ratio = config.minimum_contrast_ratio;
+        let blank_cell_fast_path = config.blank_cell_fast_path;
         let cursor_border_color = rgbcolor_to_window_color(palette.cursor_border);
 
         let cell_clusters = line.cluster();
@@ -686,37 +1155,28 @@ impl TermWindow {
                 _ => false,
             };
             let style = self.fonts.match_style(attrs);
-
-            let bg_color = palette.resolve_bg(attrs.background);
-            let fg_color = match attrs.foreground {
-                term::color::ColorAttribute::Default => {
-                    if let Some(fg) = style.foreground {
-                        fg
-                    } else {
-                        palette.resolve_fg(attrs.foreground)
-                    }
-                }
-                term::color::ColorAttribute::PaletteIndex(idx) if idx < 8 => {
-                    let idx =
-                        if attrs.intensity() == term::Intensity::Bold { idx + 8 } else { idx };
-                    palette.resolve_fg(term::color::ColorAttribute::PaletteIndex(idx))
+            let (fg_color, bg_color) =
+                resolve_cluster_colors(attrs, style, palette, minimum_contrast_ratio);
+
+            if blank_cell_fast_path && is_blank_cluster(&cluster) {
+                let first_cell = cluster.byte_to_cell_idx[0];
+                let end = (first_cell + cluster.byte_to_cell_idx.len()).min(num_cols);
+                self.render_blank_cells(
+                    line_idx,
+                    line_size,
+                    first_cell..end,
+                    fg_color,
+                    bg_color,
+                    cursor,
+                    &selection,
+                    palette,
+                    quads,
+                )?;
+                if end > first_cell {
+                    last_cell_idx = end - 1;
                 }
-                _ => palette.resolve_fg(attrs.foreground),
-            };
-
-            let (fg_color, bg_color) = {
-                let mut fg = fg_color;
-                let mut bg = bg_color;
-
-                if attrs.reverse() {
-                    std::mem::swap(&mut fg, &mut bg);
-                }
-
-                (fg, bg)
-            };
-
-            let glyph_color = rgbcolor_to_window_color(fg_color);
-            let bg_color = rgbcolor_to_window_color(bg_color);
+                continue;
+            }
 
             let glyph_info = {
                 let font = self.fonts.resolve_font(style)?;
@@ -725,7 +1185,12 @@ impl TermWindow {
 
             for info in &glyph_info {
                 let cell_idx = cluster.byte_to_cell_idx[info.cluster as usize];
-                let glyph = gl_state.glyph_cache.borrow_mut().cached_glyph(info, style)?;
+                let glyph = gl_state.glyph_cache.borrow_mut().cached_cluster_glyph(
+                    info,
+                    style,
+                    &cluster.text,
+                    &self.render_metrics,
+                )?;
 
                 let left = (glyph.x_offset + glyph.bearing_x).get() as f32;
                 let top = ((PixelLength::new(self.render_metrics.cell_size.height as f64)
@@ -733,9 +1198,15 @@ impl TermWindow {
                     - (glyph.y_offset + glyph.bearing_y))
                     .get() as f32;
 
+                // Locally echoed text stays underlined until the host confirms it
+                let underline = if attrs.predicted() && attrs.underline() == term::Underline::None {
+                    term::Underline::Single
+                } else {
+                    attrs.underline()
+                };
                 let underline_tex_rect = gl_state
                     .util_sprites
-                    .select_sprite(is_highlited_hyperlink, attrs.strikethrough(), attrs.underline())
+                    .select_sprite(is_highlited_hyperlink, attrs.strikethrough(), underline)
                     .texture_coords();
 
                 for glyph_idx in 0..info.num_cells as usize {
@@ -746,12 +1217,13 @@ impl TermWindow {
                     }
                     last_cell_idx = cell_idx;
 
-                    let (glyph_color, bg_color, cursor_shape) = self.compute_cell_fg_bg(
+                    let (glyph_color, bg_color, cursor_shape) = compute_cell_fg_bg(
+                        self.focused.is_some(),
                         line_idx,
                         cell_idx,
                         cursor,
                         &selection,
-                        glyph_color,
+                        fg_color,
                         bg_color,
                         palette,
                     );
@@ -776,12 +1248,22 @@ impl TermWindow {
                     let right = pixel_rect.size.width as f32 + left
                         - self.render_metrics.cell_size.width as f32;
 
-                    let mut quad = quads.cell(cell_idx, line_idx)?;
+                    let mut quad = quads.sized_cell(cell_idx, line_idx, line_size)?;
 
                     quad.set_fg_color(glyph_color);
                     quad.set_bg_color(bg_color);
-                    quad.set_texture(texture_rect);
-                    quad.set_texture_adjust(left, top, right, bottom);
+                    if line_size == term::LineSize::DoubleHeightBottom {
+                        quad.set_texture(gl_state.util_sprites.white_space.texture_coords());
+                        quad.set_texture_adjust(0., 0., 0., 0.);
+                    } else {
+                        quad.set_texture(texture_rect);
+                        quad.set_texture_adjust(
+                            left * scale_x,
+                            top * scale_y,
+                            right * scale_x,
+                            bottom * scale_y,
+                        );
+                    }
                     quad.set_underline(underline_tex_rect);
                     quad.set_has_color(glyph.has_color);
                     quad.set_cursor(
@@ -792,66 +1274,77 @@ impl TermWindow {
             }
         }
 
-        let white_space = gl_state.util_sprites.white_space.texture_coords();
-
-        for cell_idx in last_cell_idx + 1..num_cols {
-            let (glyph_color, bg_color, cursor_shape) = self.compute_cell_fg_bg(
-                line_idx,
-                cell_idx,
-                cursor,
-                &selection,
-                rgbcolor_to_window_color(palette.foreground),
-                rgbcolor_to_window_color(palette.background),
-                palette,
-            );
-
-            let mut quad = quads.cell(cell_idx, line_idx)?;
+        self.render_blank_cells(
+            line_idx,
+            line_size,
+            last_cell_idx + 1..num_cols,
+            palette.foreground,
+            palette.background,
+            cursor,
+            &selection,
+            palette,
+            quads,
+        )?;
 
-            quad.set_bg_color(bg_color);
-            quad.set_fg_color(glyph_color);
-            quad.set_texture(white_space);
-            quad.set_texture_adjust(0., 0., 0., 0.);
-            quad.set_underline(white_space);
-            quad.set_has_color(false);
-            quad.set_cursor(gl_state.util_sprites.cursor_sprite(cursor_shape).texture_coords());
-            quad.set_cursor_color(cursor_border_color);
+        // The columns a double width line gives up collapse to nothing
+        for cell_idx in num_cols..physical_cols {
+            quads.cell(cell_idx, line_idx)?.set_position(0., 0., 0., 0.);
         }
 
         Ok(())
     }
 
+    /// Fills `cells` with quads that only show the background.  The colors
+    /// are resolved once for the run; only the cursor and selected cells
+    /// need their own.
     #[allow(clippy::too_many_arguments)]
-    fn compute_cell_fg_bg(
+    fn render_blank_cells(
         &self,
         line_idx: usize,
-        cell_idx: usize,
+        line_size: term::LineSize,
+        cells: Range<usize>,
+        fg_color: RgbColor,
+        bg_color: RgbColor,
         cursor: &CursorPosition,
         selection: &Range<usize>,
-        fg_color: Color,
-        bg_color: Color,
         palette: &ColorPalette,
-    ) -> (Color, Color, CursorShape) {
-        let selected = selection.contains(&cell_idx);
-
-        let is_cursor = line_idx as i64 == cursor.y && cursor.x == cell_idx;
-
-        let cursor_shape = if is_cursor { CursorShape::SteadyBlock } else { CursorShape::Hidden };
-
-        let (fg_color, bg_color) = match (selected, self.focused.is_some(), cursor_shape) {
-            (true, _, CursorShape::Hidden) => (
-                rgbcolor_to_window_color(palette.selection_fg),
-                rgbcolor_to_window_color(palette.selection_bg),
-            ),
-
-            (_, true, CursorShape::BlinkingBlock) | (_, true, CursorShape::SteadyBlock) => (
-                rgbcolor_to_window_color(palette.cursor_fg),
-                rgbcolor_to_window_color(palette.cursor_bg),
-            ),
+        quads: &mut MappedQuads,
+    ) -> anyhow::Result<()> {
+        let gl_state = self.render_state.as_ref().unwrap();
+        let white_space = gl_state.util_sprites.white_space.texture_coords();
+        let cursor_border_color = rgbcolor_to_window_color(palette.cursor_border);
+        let plain_fg = rgbcolor_to_window_color(fg_color);
+        let plain_bg = rgbcolor_to_window_color(bg_color);
+
+        for cell_idx in cells {
+            let is_cursor = line_idx as i64 == cursor.y && cursor.x == cell_idx;
+            let (glyph_color, bg, cursor_shape) = if is_cursor || selection.contains(&cell_idx) {
+                compute_cell_fg_bg(
+                    self.focused.is_some(),
+                    line_idx,
+                    cell_idx,
+                    cursor,
+                    selection,
+                    fg_color,
+                    bg_color,
+                    palette,
+                )
+            } else {
+                (plain_fg, plain_bg, CursorShape::Hidden)
+            };
 
-            _ => (fg_color, bg_color),
-        };
+            let mut quad = quads.sized_cell(cell_idx, line_idx, line_size)?;
 
-        (fg_color, bg_color, cursor_shape)
+            quad.set_bg_color(bg);
+            quad.set_fg_color(glyph_color);
+            quad.set_texture(white_space);
+            quad.set_texture_adjust(0., 0., 0., 0.);
+            quad.set_underline(white_space);
+            quad.set_has_color(false);
+            quad.set_cursor(gl_state.util_sprites.cursor_sprite(cursor_shape).texture_coords());
+            quad.set_cursor_color(cursor_border_color);
+        }
+        Ok(())
     }
 
     fn clear(&self, palette: &ColorPalette, frame: &mut glium::Frame) {
@@ -861,7 +1354,84 @@ impl TermWindow {
     }
 }
 
-fn rgbcolor_to_window_color(color: RgbColor) -> Color {
+/// Resolves the foreground and background of a cluster, before selection
+/// and cursor are taken into account.
+pub(super) fn resolve_cluster_colors(
+    attrs: &term::CellAttributes,
+    style: &TextStyle,
+    palette: &ColorPalette,
+    minimum_contrast_ratio: Option<f64>,
+) -> (RgbColor, RgbColor) {
+    let bg_color = palette.resolve_bg(attrs.background);
+    let fg_color = match attrs.foreground {
+        term::color::ColorAttribute::Default => {
+            if let Some(fg) = style.foreground {
+                fg
+            } else {
+                palette.resolve_fg(attrs.foreground)
+            }
+        }
+        term::color::ColorAttribute::PaletteIndex(idx) if idx < 8 => {
+            let idx = if attrs.intensity() == term::Intensity::Bold { idx + 8 } else { idx };
+            palette.resolve_fg(term::color::ColorAttribute::PaletteIndex(idx))
+        }
+        _ => palette.resolve_fg(attrs.foreground),
+    };
+
+    let (fg_color, bg_color) =
+        if attrs.reverse() { (bg_color, fg_color) } else { (fg_color, bg_color) };
+    match minimum_contrast_ratio {
+        Some(ratio) => (fg_color.with_minimum_contrast(bg_color, ratio), bg_color),
+        None => (fg_color, bg_color),
+    }
+}
+
+/// Whether `cluster` draws nothing but its background, so that it can be
+/// painted without shaping or decorations.
+pub(super) fn is_blank_cluster(cluster: &CellCluster) -> bool {
+    let attrs = &cluster.attrs;
+    cluster.text.bytes().all(|b| b == b' ')
+        && attrs.underline() == term::Underline::None
+        && !attrs.strikethrough()
+        && !attrs.predicted()
+        && attrs.hyperlink.is_none()
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) fn compute_cell_fg_bg(
+    focused: bool,
+    line_idx: usize,
+    cell_idx: usize,
+    cursor: &CursorPosition,
+    selection: &Range<usize>,
+    fg_color: RgbColor,
+    bg_color: RgbColor,
+    palette: &ColorPalette,
+) -> (Color, Color, CursorShape) {
+    let selected = selection.contains(&cell_idx);
+
+    let is_cursor = line_idx as i64 == cursor.y && cursor.x == cell_idx;
+
+    let cursor_shape = if is_cursor { CursorShape::SteadyBlock } else { CursorShape::Hidden };
+
+    let (fg_color, bg_color) = match (selected, focused, cursor_shape) {
+        (true, _, CursorShape::Hidden) => (
+            rgbcolor_to_window_color(palette.selection_fg),
+            rgbcolor_to_window_color(palette.selection_bg),
+        ),
+
+        (_, true, CursorShape::BlinkingBlock) | (_, true, CursorShape::SteadyBlock) => {
+            let (fg, bg) = palette.resolve_cursor(bg_color);
+            (rgbcolor_to_window_color(fg), rgbcolor_to_window_color(bg))
+        }
+
+        _ => (rgbcolor_to_window_color(fg_color), rgbcolor_to_window_color(bg_color)),
+    };
+
+    (fg_color, bg_color, cursor_shape)
+}
+
+pub(super) fn rgbcolor_to_window_color(color: RgbColor) -> Color {
     Color::rgba(color.red, color.green, color.blue, 0xff)
 }
 
@@ -881,3 +1451,59 @@ fn window_mods_to_termwiz_mods(modifiers: window::Modifiers) -> crate::core::inp
     }
     result
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{MockChild, MockPty, TestHost};
+
+    #[test]
+    fn cell_size_reports_leave_out_header_and_remainder() {
+        let terminal = Terminal::new(3, 10, 0, 0, 0, vec![]);
+        let tab = Tab::new(terminal, Box::new(MockChild { status: None }), Box::new(MockPty));
+        let dimensions = Dimensions { pixel_width: 805, pixel_height: 421, dpi: 96 };
+
+        let (pixel_width, pixel_height) = terminal_pixel_size(&dimensions, 1, 16);
+        let size = tab.resize_pixels(pixel_width, pixel_height, 8, 16);
+        assert_eq!((size.rows, size.cols), (25, 100));
+
+        let mut host = TestHost::new();
+        tab.advance_bytes(b"\x1b[16t\x1b[14t", &mut host);
+        assert_eq!(String::from_utf8(host.writer).unwrap(), "\x1b[6;16;8t\x1b[4;400;800t");
+    }
+
+    #[test]
+    fn pixel_resize_requests_use_the_rendered_cell_size() {
+        let mut terminal = Terminal::new(3, 10, 0, 0, 0, vec![]);
+        terminal.set_allow_resize_escape(true);
+        let tab = Tab::new(terminal, Box::new(MockChild { status: None }), Box::new(MockPty));
+        let dimensions = Dimensions { pixel_width: 805, pixel_height: 421, dpi: 96 };
+
+        let (pixel_width, pixel_height) = terminal_pixel_size(&dimensions, 1, 16);
+        tab.resize_pixels(pixel_width, pixel_height, 8, 16);
+        assert_eq!(tab.renderer().cell_pixel_size(), (8, 16));
+
+        tab.advance_bytes(b"\x1b[4;320;480t", &mut TestHost::new());
+        let request = tab.take_resize_request().unwrap();
+        assert_eq!((request.rows, request.cols), (20, 60));
+    }
+
+    #[test]
+    fn pixel_resize_requests_fit_the_work_area() {
+        let mut terminal = Terminal::new(3, 10, 0, 0, 0, vec![]);
+        terminal.set_allow_resize_escape(true);
+        let tab = Tab::new(terminal, Box::new(MockChild { status: None }), Box::new(MockPty));
+        tab.resize_pixels(800, 400, 8, 16);
+
+        tab.advance_bytes(b"\x1b[4;30000;60000t", &mut TestHost::new());
+        let request = tab.take_resize_request().unwrap();
+        assert_eq!((request.rows, request.cols), (1875, 7500));
+
+        let work_area = Some((1920, 1050));
+        assert_eq!(resize_request_pixels(request, 8, 16, 1, work_area), (1920, 1040));
+        assert_eq!(resize_request_pixels(request, 8, 16, 1, None), (65528, 65520));
+
+        let small = ResizeRequest { rows: 20, cols: 60 };
+        assert_eq!(resize_request_pixels(small, 8, 16, 1, work_area), (480, 336));
+    }
+}
@@ -1,8 +1,9 @@
 use clap::{crate_description, crate_name, crate_version, AppSettings, Arg, Command};
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
 
-use crate::config::Theme;
+use crate::config::{Renderer, Theme};
 use crate::font::FontConfiguration;
 use crate::mux::Mux;
 use crate::pty::PtySize;
@@ -10,18 +11,42 @@ use crate::term::color::RgbColor;
 
 mod config;
 mod core;
+mod error;
 mod font;
 mod gui;
 mod mux;
 mod pty;
 mod term;
+#[cfg(test)]
+mod testing;
 mod window;
 
-fn run(theme: Theme) -> anyhow::Result<()> {
-    let config = Arc::new(config::Config::default_config(theme));
+fn run(
+    theme: Theme,
+    config_file: Option<PathBuf>,
+    cwd: Option<PathBuf>,
+    colors: Option<PathBuf>,
+    renderer: Option<Renderer>,
+    prog: Option<Vec<String>>,
+) -> anyhow::Result<()> {
+    let mut config = config::Config::load(config_file.as_deref(), theme)?;
+    if cwd.is_some() {
+        config.default_cwd = cwd;
+    }
+    if let Some(colors) = colors {
+        config.colors = Some(config::Palette::load(&colors)?);
+    }
+    if let Some(renderer) = renderer {
+        config.renderer = renderer;
+    }
+    let config = Arc::new(config);
     let fontconfig = Rc::new(FontConfiguration::new(Arc::clone(&config)));
-    let gui = gui::new()?;
-    let mux = Rc::new(mux::Mux::new(&config, PtySize::default())?);
+    let gui = gui::new(&config)?;
+    let cmd = match prog {
+        Some(prog) => Some(config.build_prog(Some(&prog))?),
+        None => None,
+    };
+    let mux = Rc::new(mux::Mux::new(&config, PtySize::default(), cmd)?);
     Mux::set_mux(&mux);
 
     mux.start()?;
@@ -46,6 +71,39 @@ fn main() -> anyhow::Result<()> {
                 .hide_default_value(true)
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("Load the configuration from this TOML file.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("cwd")
+                .long("cwd")
+                .alias("working-directory")
+                .help("Start the shell in this directory.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("colors")
+                .long("colors")
+                .help("Load the color scheme from this JSON file.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("renderer")
+                .long("renderer")
+                .help("Draw with the GPU or, where OpenGL is unavailable, the CPU.")
+                .possible_values(&["gpu", "cpu"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("prog")
+                .help("Run this program instead of the shell, e.g. `miro -- htop`.")
+                .multiple_values(true)
+                .last(true),
+        )
         .get_matches();
 
     let theme = match matches.value_of("theme") {
@@ -73,5 +131,15 @@ fn main() -> anyhow::Result<()> {
         _ => unreachable!("not possible"),
     };
 
-    run(theme)
+    run(
+        theme,
+        matches.value_of("config").map(PathBuf::from),
+        matches.value_of("cwd").map(PathBuf::from),
+        matches.value_of("colors").map(PathBuf::from),
+        matches.value_of("renderer").map(|renderer| match renderer {
+            "cpu" => Renderer::Cpu,
+            _ => Renderer::Gpu,
+        }),
+        matches.values_of("prog").map(|prog| prog.map(String::from).collect()),
+    )
 }
@@ -3,25 +3,93 @@ use crate::core::hyperlink::Hyperlink;
 use crate::core::promise;
 use crate::core::ratelim::RateLimiter;
 use crate::mux::tab::Tab;
-use crate::pty::{unix, PtySize, PtySystem};
+use crate::pty::{unix, CommandBuilder, PtySize, PtySystem};
 use crate::term::clipboard::Clipboard;
-use crate::term::TerminalHost;
+use crate::term::{Terminal, TerminalHost};
 use anyhow::bail;
 use std::cell::{Ref, RefCell};
 use std::io::Read;
-use std::process::Command;
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
 
 pub mod tab;
 
+/// While output is paused the reader stops reading from the pty once this
+/// much is buffered, so that the application blocks on its writes.
+const MAX_PAUSED_OUTPUT: usize = 1024 * 1024;
+
 pub struct Mux {
     tab: RefCell<Tab>,
     config: Arc<Config>,
+    output: Arc<PendingOutput>,
+}
+
+/// Output read from the pty that the main thread has yet to parse.
+/// Reads that arrive while a flush is already scheduled are appended
+/// to it rather than scheduling another one.
+#[derive(Default)]
+struct PendingOutput {
+    state: Mutex<PendingState>,
+    resumed: Condvar,
+}
+
+#[derive(Default)]
+struct PendingState {
+    data: Vec<u8>,
+    paused: bool,
+}
+
+impl PendingOutput {
+    /// Appends `data`; returns true if the caller must schedule a flush.
+    /// Blocks while paused with `MAX_PAUSED_OUTPUT` already buffered.
+    fn push(&self, data: &[u8]) -> bool {
+        let mut state = self.state.lock().unwrap();
+        while state.paused && state.data.len() >= MAX_PAUSED_OUTPUT {
+            state = self.resumed.wait(state).unwrap();
+        }
+        let was_empty = state.data.is_empty();
+        state.data.extend_from_slice(data);
+        was_empty && !state.paused
+    }
+
+    /// Returns the buffered output, or nothing while paused.
+    fn take(&self) -> Vec<u8> {
+        let mut state = self.state.lock().unwrap();
+        if state.paused {
+            return vec![];
+        }
+        std::mem::take(&mut state.data)
+    }
+
+    fn pause(&self) {
+        self.state.lock().unwrap().paused = true;
+    }
+
+    /// Returns true if output was held back and a flush must be scheduled.
+    fn resume(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let was_paused = std::mem::replace(&mut state.paused, false);
+        self.resumed.notify_all();
+        was_paused && !state.data.is_empty()
+    }
 }
 
-fn read_from_tab_pty(config: Arc<Config>, mut reader: Box<dyn std::io::Read>) {
+fn schedule_flush(pending: &Arc<PendingOutput>) {
+    let pending = Arc::clone(pending);
+    promise::spawn_into_main_thread_with_low_priority(async move {
+        let data = pending.take();
+        let mux = Mux::get().unwrap();
+        let tab = mux.get_tab();
+        tab.advance_bytes(&data, &mut Host { writer: &mut *tab.writer() });
+    });
+}
+
+fn read_from_tab_pty(
+    config: Arc<Config>,
+    mut reader: Box<dyn std::io::Read>,
+    pending: Arc<PendingOutput>,
+) {
     const BUFSIZE: usize = 32 * 1024;
     let mut buf = [0; BUFSIZE];
 
@@ -38,12 +106,9 @@ fn read_from_tab_pty(config: Arc<Config>, mut reader: Box<dyn std::io::Read>) {
             }
             Ok(size) => {
                 lim.blocking_admittance_check(size as u32);
-                let data = buf[0..size].to_vec();
-                promise::spawn_into_main_thread_with_low_priority(async move {
-                    let mux = Mux::get().unwrap();
-                    let tab = mux.get_tab();
-                    tab.advance_bytes(&data, &mut Host { writer: &mut *tab.writer() });
-                });
+                if pending.push(&buf[0..size]) {
+                    schedule_flush(&pending);
+                }
             }
         }
     }
@@ -72,38 +137,93 @@ impl<'a> TerminalHost for Host<'a> {
     fn set_title(&mut self, _title: &str) {}
 }
 
+/// A terminal of `size` with the settings from `config` applied.
+fn new_terminal(config: &Config, size: PtySize) -> Terminal {
+    let mut terminal = Terminal::new(
+        size.rows as usize,
+        size.cols as usize,
+        size.pixel_width as usize,
+        size.pixel_height as usize,
+        config.scrollback_lines.unwrap_or(3500),
+        config.all_hyperlink_rules(),
+    );
+    terminal.set_scrollback_in_alt_screen(config.scrollback_in_alt_screen);
+    terminal.set_allow_resize_escape(config.allow_resize_escape);
+    terminal.set_allow_window_ops(config.allow_window_ops);
+    terminal.set_answerback(&config.answerback);
+    terminal.set_default_title(&config.window_title);
+    if let Some(title) = &config.fixed_title {
+        terminal.set_fixed_title(title);
+    }
+    terminal.set_predictive_echo(config.predictive_echo);
+    terminal.set_read_only(config.read_only);
+    terminal.set_paste_filter(config.paste_filter);
+    terminal.set_copy_wrapped_lines_as(config.copy_wrapped_lines_as);
+    terminal.set_copy_tabs_as_tabs(config.copy_tabs_as_tabs);
+    terminal.set_image_paste_behavior(config.image_paste_behavior);
+    terminal.set_background_color_erase(config.background_color_erase);
+    terminal.set_max_line_length(config.max_line_length);
+    terminal.set_compress_scrollback(config.compress_scrollback);
+    terminal.set_backspace_sends(config.backspace_sends);
+    terminal.set_delete_sends(config.delete_sends);
+    terminal.set_ctrl_space_sends(config.ctrl_space_sends);
+    if let Some(colors) = config.colors.clone() {
+        terminal.set_palette(colors.into());
+    }
+    terminal
+}
+
 thread_local! {
     static MUX: RefCell<Option<Rc<Mux>>> = RefCell::new(None);
 }
 
 impl Mux {
-    pub fn new(config: &Arc<Config>, size: PtySize) -> anyhow::Result<Self> {
+    /// Starts `cmd` in a new terminal, or the configured shell when None.
+    pub fn new(
+        config: &Arc<Config>,
+        size: PtySize,
+        cmd: Option<CommandBuilder>,
+    ) -> anyhow::Result<Self> {
+        let cmd = match cmd {
+            Some(cmd) => cmd,
+            None => config.build_prog(None)?,
+        };
         let pty_system = Box::new(unix::UnixPtySystem);
         let pair = pty_system.openpty(size)?;
-        let child = pair.slave.spawn_command(Command::new(crate::pty::get_shell()?))?;
-
-        let terminal = crate::term::Terminal::new(
-            size.rows as usize,
-            size.cols as usize,
-            size.pixel_width as usize,
-            size.pixel_height as usize,
-            config.scrollback_lines.unwrap_or(3500),
-            config.hyperlink_rules.clone(),
-        );
+        let child = pair.slave.spawn_command(cmd.as_command())?;
 
-        let tab = Tab::new(terminal, child, pair.master);
+        let terminal = new_terminal(config, size);
+        let mut tab = Tab::new(terminal, child, pair.master);
+        tab.set_close_on_exit(config.close_on_exit);
 
-        Ok(Self { tab: RefCell::new(tab), config: Arc::clone(config) })
+        Ok(Self {
+            tab: RefCell::new(tab),
+            config: Arc::clone(config),
+            output: Arc::new(PendingOutput::default()),
+        })
     }
 
     pub fn start(&self) -> anyhow::Result<()> {
         let reader = self.tab.borrow().reader()?;
         let config = Arc::clone(&self.config);
-        thread::spawn(move || read_from_tab_pty(config, reader));
+        let output = Arc::clone(&self.output);
+        thread::spawn(move || read_from_tab_pty(config, reader, output));
 
         Ok(())
     }
 
+    /// Stops parsing output from the pty; it is buffered until
+    /// `resume_output`, and past a limit the pty is no longer read.
+    pub fn pause_output(&self) {
+        self.output.pause();
+    }
+
+    pub fn resume_output(&self) {
+        if self.output.resume() {
+            schedule_flush(&self.output);
+        }
+    }
+
     pub fn config(&self) -> &Arc<Config> {
         &self.config
     }
@@ -135,4 +255,155 @@ impl Mux {
     pub fn can_close(&self) -> bool {
         self.tab.borrow().can_close()
     }
+
+    pub fn report_process_exit(&self) {
+        let tab = self.tab.borrow();
+        tab.report_process_exit(&mut Host { writer: &mut *tab.writer() });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestHost;
+
+    #[test]
+    fn configured_values_reach_the_terminal() {
+        let config = Config {
+            window_title: "scratchpad".to_string(),
+            answerback: "miro".to_string(),
+            read_only: true,
+            ..Config::default()
+        };
+        let size = PtySize { rows: 3, cols: 10, pixel_width: 0, pixel_height: 0 };
+        let mut term = new_terminal(&config, size);
+        assert_eq!(term.get_title(), "scratchpad");
+        assert!(term.is_read_only());
+        assert_eq!(term.physical_dimensions(), (3, 10));
+
+        let mut host = TestHost::new();
+        term.advance_bytes(b"\x05", &mut host);
+        assert_eq!(host.writer, b"miro");
+    }
+
+    #[test]
+    fn pending_output_schedules_one_flush_per_batch() {
+        let pending = PendingOutput::default();
+        assert!(pending.push(b"abc"));
+        assert!(!pending.push(b"def"));
+        assert_eq!(pending.take(), b"abcdef");
+        assert!(pending.take().is_empty());
+        assert!(pending.push(b"g"));
+    }
+
+    #[test]
+    fn paused_output_is_held_then_applied_in_order() {
+        let pending = PendingOutput::default();
+        assert!(pending.push(b"one "));
+        pending.pause();
+        assert!(!pending.push(b"two "));
+        assert!(pending.take().is_empty());
+        assert!(!pending.push(b"three"));
+        assert!(pending.resume());
+        assert!(!pending.resume());
+
+        let mut term = Terminal::new(2, 20, 0, 0, 100, vec![]);
+        term.advance_bytes(pending.take(), &mut TestHost::new());
+        assert_eq!(term.screen().lines[0].as_str().trim_end(), "one two three");
+    }
+
+    #[test]
+    fn paused_reader_blocks_once_the_buffer_is_full() {
+        let pending = Arc::new(PendingOutput::default());
+        pending.pause();
+        pending.push(&b"x".repeat(MAX_PAUSED_OUTPUT));
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let reader = {
+            let pending = Arc::clone(&pending);
+            thread::spawn(move || {
+                pending.push(b"y");
+                tx.send(()).unwrap();
+            })
+        };
+        let wait = std::time::Duration::from_millis(50);
+        assert!(rx.recv_timeout(wait).is_err());
+
+        assert!(pending.resume());
+        rx.recv().unwrap();
+        reader.join().unwrap();
+        assert_eq!(pending.take().len(), MAX_PAUSED_OUTPUT + 1);
+    }
+
+    #[test]
+    fn coalesced_output_matches_per_chunk_output() {
+        let chunks: &[&[u8]] = &[
+            b"hello\x1b[3",
+            b"1mred\x1b",
+            b"[0m\r\nsecond line that wraps around\x1b[2;",
+            b"5H\xe2\x9c",
+            b"\x94 done\x1b[6n",
+        ];
+
+        let mut per_chunk = Terminal::new(4, 20, 0, 0, 100, vec![]);
+        let mut per_chunk_host = TestHost::new();
+        for chunk in chunks {
+            per_chunk.advance_bytes(chunk, &mut per_chunk_host);
+        }
+
+        let pending = PendingOutput::default();
+        for chunk in chunks {
+            pending.push(chunk);
+        }
+        let mut coalesced = Terminal::new(4, 20, 0, 0, 100, vec![]);
+        let mut coalesced_host = TestHost::new();
+        coalesced.advance_bytes(pending.take(), &mut coalesced_host);
+
+        let text = |term: &Terminal| -> Vec<String> {
+            term.screen().lines.iter().map(|line| line.as_str()).collect()
+        };
+        assert_eq!(text(&per_chunk), text(&coalesced));
+        assert_eq!(per_chunk.cursor_pos(), coalesced.cursor_pos());
+        assert_eq!(per_chunk_host.writer, coalesced_host.writer);
+    }
+
+    /// Times `advance_bytes` over a flood of colored output, fed a pty read
+    /// at a time and coalesced. Run with `cargo test --release -- --ignored
+    /// --nocapture bench_advance_bytes`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_advance_bytes() {
+        let mut flood = vec![];
+        for i in 0..100_000 {
+            flood.extend_from_slice(
+                format!("\x1b[3{}mline {} of the flood\x1b[0m and some more text\r\n", i % 8, i)
+                    .as_bytes(),
+            );
+        }
+        let read_size = 4096;
+
+        let mut per_chunk = Terminal::new(50, 200, 0, 0, 3500, vec![]);
+        let start = std::time::Instant::now();
+        for chunk in flood.chunks(read_size) {
+            per_chunk.advance_bytes(chunk, &mut TestHost::new());
+        }
+        let per_chunk_time = start.elapsed();
+
+        let pending = PendingOutput::default();
+        let mut coalesced = Terminal::new(50, 200, 0, 0, 3500, vec![]);
+        let start = std::time::Instant::now();
+        for chunk in flood.chunks(read_size) {
+            pending.push(chunk);
+        }
+        coalesced.advance_bytes(pending.take(), &mut TestHost::new());
+        let coalesced_time = start.elapsed();
+
+        assert_eq!(per_chunk.cursor_pos(), coalesced.cursor_pos());
+        let mb = flood.len() as f64 / (1024. * 1024.);
+        eprintln!(
+            "per chunk: {:.1} MiB/s, coalesced: {:.1} MiB/s",
+            mb / per_chunk_time.as_secs_f64(),
+            mb / coalesced_time.as_secs_f64()
+        );
+    }
 }
@@ -1,42 +1,22 @@
 use crate::core::promise;
 use crate::mux::Mux;
-use crate::pty::{Child, MasterPty, PtySize};
+use crate::pty::{Child, ExitStatus, MasterPty, PtySize};
+use crate::term::clipboard::{Clipboard, ClipboardSelection};
 use crate::term::color::ColorPalette;
-use crate::term::{KeyCode, KeyModifiers, MouseEvent, Terminal, TerminalHost};
-use std::cell::{RefCell, RefMut};
-use std::sync::{Arc, Mutex};
-
-const PASTE_CHUNK_SIZE: usize = 1024;
-
-struct Paste {
-    text: String,
-    offset: usize,
-}
-
-fn schedule_next_paste(paste: &Arc<Mutex<Paste>>) {
-    let paste = Arc::clone(paste);
-    promise::spawn(async move {
-        let mut locked = paste.lock().unwrap();
-        let mux = Mux::get().unwrap();
-        let tab = mux.get_tab();
-
-        let remain = locked.text.len() - locked.offset;
-        let chunk = remain.min(PASTE_CHUNK_SIZE);
-        let text_slice = &locked.text[locked.offset..locked.offset + chunk];
-        tab.send_paste(text_slice).unwrap();
-
-        if chunk < remain {
-            locked.offset += chunk;
-            schedule_next_paste(&paste);
-        }
-    });
-}
+use crate::term::{
+    KeyCode, KeyModifiers, MouseEvent, PositionReport, ResizeRequest, Terminal, TerminalHost,
+};
+use std::cell::{Cell, RefCell, RefMut};
 
 pub struct Tab {
     terminal: RefCell<Terminal>,
     process: RefCell<Box<dyn Child>>,
     pty: RefCell<Box<dyn MasterPty>>,
     can_close: bool,
+    close_on_exit: bool,
+    exit_status: Cell<Option<ExitStatus>>,
+    exit_reported: Cell<bool>,
+    flush_scheduled: Cell<bool>,
 }
 
 impl Tab {
@@ -44,20 +24,35 @@ impl Tab {
         RefMut::map(self.terminal.borrow_mut(), |t| &mut *t)
     }
 
+    /// Pastes `text` without waiting for it all to reach the pty; the
+    /// rest is fed in from the event loop.
     pub fn trickle_paste(&self, text: String) -> anyhow::Result<()> {
-        if text.len() <= PASTE_CHUNK_SIZE {
-            self.send_paste(&text)?;
-        } else {
-            self.send_paste(&text[0..PASTE_CHUNK_SIZE])?;
+        self.send_paste(&text)?;
+        self.schedule_pending_input();
+        Ok(())
+    }
 
-            let paste = Arc::new(Mutex::new(Paste { text, offset: PASTE_CHUNK_SIZE }));
-            schedule_next_paste(&paste);
+    fn schedule_pending_input(&self) {
+        if self.flush_scheduled.get() || !self.terminal.borrow().has_pending_input() {
+            return;
         }
-        Ok(())
+        self.flush_scheduled.set(true);
+        promise::spawn(async move {
+            let mux = Mux::get().unwrap();
+            let tab = mux.get_tab();
+            tab.flush_scheduled.set(false);
+            let flushed = tab.terminal.borrow_mut().flush_pending_input(&mut *tab.pty.borrow_mut());
+            if let Err(err) = flushed {
+                eprintln!("failed to write paste, dropping the rest: {}", err);
+                return;
+            }
+            tab.schedule_pending_input();
+        });
     }
 
     pub fn advance_bytes(&self, buf: &[u8], host: &mut dyn TerminalHost) {
-        self.terminal.borrow_mut().advance_bytes(buf, host)
+        self.terminal.borrow_mut().advance_bytes(buf, host);
+        self.schedule_pending_input();
     }
 
     pub fn mouse_event(
@@ -65,24 +60,48 @@ impl Tab {
         event: MouseEvent,
         host: &mut dyn TerminalHost,
     ) -> anyhow::Result<()> {
-        self.terminal.borrow_mut().mouse_event(event, host)
+        self.terminal.borrow_mut().mouse_event(event, host)?;
+        self.schedule_pending_input();
+        Ok(())
     }
 
     pub fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
-        self.terminal.borrow_mut().key_down(key, mods, &mut *self.pty.borrow_mut())
+        self.terminal.borrow_mut().key_down(key, mods, &mut *self.pty.borrow_mut())?;
+        self.schedule_pending_input();
+        Ok(())
     }
 
-    pub fn resize(&self, size: PtySize) -> anyhow::Result<()> {
-        self.pty.borrow_mut().resize(size)?;
-        self.terminal.borrow_mut().resize(
-            size.rows as usize,
-            size.cols as usize,
-            size.pixel_width as usize,
-            size.pixel_height as usize,
-        );
+    pub fn send_bytes(&self, bytes: &[u8]) -> anyhow::Result<()> {
+        self.terminal.borrow_mut().send_bytes(bytes, &mut *self.pty.borrow_mut())?;
+        self.schedule_pending_input();
         Ok(())
     }
 
+    /// Resizes the terminal to fill `pixel_width` x `pixel_height`, the part
+    /// of the window it is drawn in, and gives the pty the size of the cells
+    /// that fit; returns that size.
+    pub fn resize_pixels(
+        &self,
+        pixel_width: usize,
+        pixel_height: usize,
+        cell_width: usize,
+        cell_height: usize,
+    ) -> PtySize {
+        let mut terminal = self.terminal.borrow_mut();
+        terminal.resize_pixels(pixel_width, pixel_height, cell_width, cell_height);
+        let (rows, cols) = terminal.physical_dimensions();
+        let size = PtySize {
+            rows: rows as u16,
+            cols: cols as u16,
+            pixel_width: (cols * cell_width) as u16,
+            pixel_height: (rows * cell_height) as u16,
+        };
+        if let Err(err) = self.pty.borrow_mut().resize(size) {
+            eprintln!("failed to resize pty: {}", err);
+        }
+        size
+    }
+
     pub fn writer(&self) -> RefMut<dyn std::io::Write> {
         self.pty.borrow_mut()
     }
@@ -91,14 +110,61 @@ impl Tab {
         self.pty.borrow_mut().try_clone_reader()
     }
 
+    pub fn send_ime_commit(&self, text: &str) -> anyhow::Result<()> {
+        self.terminal.borrow_mut().send_ime_commit(text, &mut *self.pty.borrow_mut())?;
+        self.schedule_pending_input();
+        Ok(())
+    }
+
     fn send_paste(&self, text: &str) -> anyhow::Result<()> {
         self.terminal.borrow_mut().send_paste(text, &mut *self.pty.borrow_mut())
     }
 
+    pub fn is_read_only(&self) -> bool {
+        self.terminal.borrow().is_read_only()
+    }
+
     pub fn get_title(&self) -> String {
         self.terminal.borrow_mut().get_title().to_string()
     }
 
+    pub fn take_resize_request(&self) -> Option<ResizeRequest> {
+        self.terminal.borrow_mut().take_resize_request()
+    }
+
+    pub fn clipboard_paste_text(
+        &self,
+        clipboard: &dyn Clipboard,
+        selection: ClipboardSelection,
+    ) -> anyhow::Result<Option<String>> {
+        self.terminal.borrow_mut().clipboard_paste_text(clipboard, selection)
+    }
+
+    pub fn take_position_reports(&self) -> Vec<PositionReport> {
+        self.terminal.borrow_mut().take_position_reports()
+    }
+
+    pub fn report_position(
+        &self,
+        report: PositionReport,
+        x: isize,
+        y: isize,
+    ) -> anyhow::Result<()> {
+        self.terminal.borrow_mut().report_position(report, x, y, &mut *self.pty.borrow_mut())?;
+        self.schedule_pending_input();
+        Ok(())
+    }
+
+    pub fn focus_changed(&self, focused: bool) -> anyhow::Result<()> {
+        self.terminal.borrow_mut().focus_changed(focused, &mut *self.pty.borrow_mut())?;
+        self.schedule_pending_input();
+        Ok(())
+    }
+
+    pub fn take_bell(&self) -> bool {
+        self.terminal.borrow_mut().take_bell()
+    }
+
     pub fn palette(&self) -> ColorPalette {
         self.terminal.borrow().palette().clone()
     }
@@ -107,16 +173,41 @@ impl Tab {
         self.can_close = true;
     }
 
+    pub fn set_close_on_exit(&mut self, close_on_exit: bool) {
+        self.close_on_exit = close_on_exit;
+    }
+
     pub fn can_close(&self) -> bool {
-        self.can_close || self.is_dead()
+        self.can_close || (self.close_on_exit && self.is_dead())
     }
 
     pub fn is_dead(&self) -> bool {
-        if let Ok(None) = self.process.borrow_mut().try_wait() {
-            false
-        } else {
-            true
+        if self.exit_status.get().is_some() {
+            return true;
+        }
+        match self.process.borrow_mut().try_wait() {
+            Ok(None) => false,
+            Ok(Some(status)) => {
+                self.exit_status.set(Some(status));
+                true
+            }
+            Err(_) => true,
+        }
+    }
+
+    pub fn process_exited(&self) -> Option<ExitStatus> {
+        self.is_dead();
+        self.exit_status.get()
+    }
+
+    /// Leaves a note in the terminal once the shell has exited, for tabs
+    /// that stay open after their process completes.
+    pub fn report_process_exit(&self, host: &mut dyn TerminalHost) {
+        if self.exit_reported.get() || self.process_exited().is_none() {
+            return;
         }
+        self.exit_reported.set(true);
+        self.advance_bytes(b"\r\n[process completed]", host);
     }
 
     pub fn new(terminal: Terminal, process: Box<dyn Child>, pty: Box<dyn MasterPty>) -> Self {
@@ -125,6 +216,10 @@ impl Tab {
             process: RefCell::new(process),
             pty: RefCell::new(pty),
             can_close: false,
+            close_on_exit: true,
+            exit_status: Cell::new(None),
+            exit_reported: Cell::new(false),
+            flush_scheduled: Cell::new(false),
         }
     }
 }
@@ -135,3 +230,53 @@ impl Drop for Tab {
         self.process.borrow_mut().wait().ok();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{MockChild, MockPty, TestHost};
+    use std::os::unix::process::ExitStatusExt;
+
+    fn new_tab(status: Option<ExitStatus>) -> Tab {
+        let terminal = Terminal::new(3, 40, 0, 0, 0, vec![]);
+        Tab::new(terminal, Box::new(MockChild { status }), Box::new(MockPty))
+    }
+
+    #[test]
+    fn running_process_has_no_exit_status() {
+        let tab = new_tab(None);
+        assert_eq!(tab.process_exited(), None);
+        assert!(!tab.can_close());
+    }
+
+    #[test]
+    fn exited_process_reports_status() {
+        let status: ExitStatus = std::process::ExitStatus::from_raw(1 << 8).into();
+        let mut tab = new_tab(Some(status));
+        tab.set_close_on_exit(false);
+
+        assert_eq!(tab.process_exited(), Some(status));
+        assert!(!status.success());
+        assert!(!tab.can_close());
+
+        let mut host = TestHost::new();
+        tab.report_process_exit(&mut host);
+        tab.report_process_exit(&mut host);
+        let text: Vec<String> = tab
+            .renderer()
+            .screen()
+            .lines
+            .iter()
+            .map(|line| line.as_str().trim_end().into())
+            .collect();
+        assert_eq!(text, vec!["", "[process completed]", ""]);
+    }
+
+    #[test]
+    fn pty_gets_whole_cells_only() {
+        let tab = new_tab(None);
+        let size = tab.resize_pixels(805, 405, 8, 16);
+        assert_eq!(size, PtySize { rows: 25, cols: 100, pixel_width: 800, pixel_height: 400 });
+        assert_eq!(tab.renderer().physical_dimensions(), (25, 100));
+    }
+}
@@ -1,6 +1,9 @@
+use crate::error::Result;
 use anyhow::anyhow;
 use serde_derive::*;
+use std::ffi::{OsStr, OsString};
 use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 pub mod unix;
@@ -42,10 +45,59 @@ pub trait SlavePty {
     fn spawn_command(&self, cmd: Command) -> anyhow::Result<Box<dyn Child>>;
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandBuilder {
+    args: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
+    cwd: Option<PathBuf>,
+}
+
+impl CommandBuilder {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Self {
+        Self { args: vec![program.as_ref().to_owned()], envs: vec![], cwd: None }
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, value: V) -> &mut Self {
+        self.envs.push((key.as_ref().to_owned(), value.as_ref().to_owned()));
+        self
+    }
+
+    pub fn cwd<D: AsRef<Path>>(&mut self, dir: D) -> &mut Self {
+        let dir = dir.as_ref();
+        self.cwd = Some(match std::env::var_os("HOME") {
+            Some(home) if dir.is_relative() => PathBuf::from(home).join(dir),
+            _ => dir.to_path_buf(),
+        });
+        self
+    }
+
+    pub fn as_command(&self) -> Command {
+        let mut cmd = Command::new(&self.args[0]);
+        cmd.args(&self.args[1..]);
+        cmd.envs(self.envs.iter().map(|(k, v)| (k, v)));
+        if let Some(cwd) = &self.cwd {
+            cmd.current_dir(cwd);
+        }
+        cmd
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ExitStatus {
     successful: bool,
 }
 
+impl ExitStatus {
+    pub fn success(&self) -> bool {
+        self.successful
+    }
+}
+
 impl From<std::process::ExitStatus> for ExitStatus {
     fn from(status: std::process::ExitStatus) -> ExitStatus {
         ExitStatus { successful: status.success() }
@@ -58,7 +110,7 @@ pub struct PtyPair {
 }
 
 pub trait PtySystem {
-    fn openpty(&self, size: PtySize) -> anyhow::Result<PtyPair>;
+    fn openpty(&self, size: PtySize) -> Result<PtyPair>;
 }
 
 impl Child for std::process::Child {
@@ -95,3 +147,34 @@ pub fn get_shell() -> anyhow::Result<String> {
         }
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command_builder() {
+        let mut builder = CommandBuilder::new("/bin/sh");
+        builder.arg("-l").env("MIRO_TEST", "1").cwd("/tmp");
+        let cmd = builder.as_command();
+
+        assert_eq!(cmd.get_program(), "/bin/sh");
+        assert_eq!(cmd.get_args().collect::<Vec<_>>(), vec!["-l"]);
+        assert_eq!(cmd.get_current_dir(), Some(Path::new("/tmp")));
+        assert!(cmd
+            .get_envs()
+            .any(|(k, v)| k == OsStr::new("MIRO_TEST") && v == Some(OsStr::new("1"))));
+    }
+
+    #[test]
+    fn relative_cwd_is_resolved_against_home() {
+        let home = match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home),
+            None => return,
+        };
+        let mut builder = CommandBuilder::new("/bin/sh");
+        builder.cwd("projects");
+        let cmd = builder.as_command();
+        assert_eq!(cmd.get_current_dir(), Some(home.join("projects").as_path()));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::{Error, Result};
 use crate::pty::{Child, MasterPty, PtyPair, PtySize, PtySystem, SlavePty};
 use anyhow::bail;
 use filedescriptor::FileDescriptor;
@@ -13,34 +14,38 @@ use std::ptr;
 pub struct UnixPtySystem;
 
 impl PtySystem for UnixPtySystem {
-    fn openpty(&self, size: PtySize) -> anyhow::Result<PtyPair> {
-        let mut master: RawFd = -1;
-        let mut slave: RawFd = -1;
+    fn openpty(&self, size: PtySize) -> Result<PtyPair> {
+        openpty(size).map_err(Error::PtyOpen)
+    }
+}
 
-        let mut size = winsize {
-            ws_row: size.rows,
-            ws_col: size.cols,
-            ws_xpixel: size.pixel_width,
-            ws_ypixel: size.pixel_height,
-        };
+fn openpty(size: PtySize) -> anyhow::Result<PtyPair> {
+    let mut master: RawFd = -1;
+    let mut slave: RawFd = -1;
 
-        let result = unsafe {
-            #[cfg_attr(feature = "cargo-clippy", allow(clippy::unnecessary_mut_passed))]
-            libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null_mut(), &mut size)
-        };
+    let mut size = winsize {
+        ws_row: size.rows,
+        ws_col: size.cols,
+        ws_xpixel: size.pixel_width,
+        ws_ypixel: size.pixel_height,
+    };
 
-        if result != 0 {
-            bail!("failed to openpty: {:?}", io::Error::last_os_error());
-        }
+    let result = unsafe {
+        #[cfg_attr(feature = "cargo-clippy", allow(clippy::unnecessary_mut_passed))]
+        libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null_mut(), &mut size)
+    };
 
-        let master = UnixMasterPty { fd: unsafe { FileDescriptor::from_raw_fd(master) } };
-        let slave = UnixSlavePty { fd: unsafe { FileDescriptor::from_raw_fd(slave) } };
+    if result != 0 {
+        bail!("failed to openpty: {:?}", io::Error::last_os_error());
+    }
 
-        cloexec(master.fd.as_raw_fd())?;
-        cloexec(slave.fd.as_raw_fd())?;
+    let master = UnixMasterPty { fd: unsafe { FileDescriptor::from_raw_fd(master) } };
+    let slave = UnixSlavePty { fd: unsafe { FileDescriptor::from_raw_fd(slave) } };
 
-        Ok(PtyPair { master: Box::new(master), slave: Box::new(slave) })
-    }
+    cloexec(master.fd.as_raw_fd())?;
+    cloexec(slave.fd.as_raw_fd())?;
+
+    Ok(PtyPair { master: Box::new(master), slave: Box::new(slave) })
 }
 
 pub struct UnixMasterPty {
@@ -2,9 +2,43 @@ use anyhow::anyhow;
 use clipboard::{ClipboardContext, ClipboardProvider};
 use std::sync::Mutex;
 
+#[cfg(all(unix, not(target_os = "macos")))]
+type PrimaryContext =
+    clipboard::x11_clipboard::X11ClipboardContext<clipboard::x11_clipboard::Primary>;
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+type PrimaryContext = ClipboardContext;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardSelection {
+    Clipboard,
+    Primary,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardContents {
+    Text(String),
+    /// Encoded image data, with the file extension for its format
+    Image {
+        data: Vec<u8>,
+        extension: String,
+    },
+}
+
 pub trait Clipboard {
-    fn get_contents(&self) -> anyhow::Result<String>;
-    fn set_contents(&self, data: Option<String>) -> anyhow::Result<()>;
+    fn get_contents(&self, selection: ClipboardSelection) -> anyhow::Result<String>;
+    /// Like `get_contents`, but tells text apart from other content; the
+    /// default assumes the clipboard only ever holds text.
+    fn get_typed_contents(
+        &self,
+        selection: ClipboardSelection,
+    ) -> anyhow::Result<ClipboardContents> {
+        self.get_contents(selection).map(ClipboardContents::Text)
+    }
+    fn set_contents(
+        &self,
+        selection: ClipboardSelection,
+        data: Option<String>,
+    ) -> anyhow::Result<()>;
 }
 
 pub struct SystemClipboard {
@@ -13,18 +47,30 @@ pub struct SystemClipboard {
 
 struct Inner {
     clipboard: Option<ClipboardContext>,
+    primary: Option<PrimaryContext>,
 }
 
-impl Inner {
-    fn new() -> Self {
-        Self { clipboard: None }
+fn provider<T: ClipboardProvider>(slot: &mut Option<T>) -> anyhow::Result<&mut T> {
+    if slot.is_none() {
+        *slot = Some(T::new().map_err(|e| anyhow!("{}", e))?);
     }
+    Ok(slot.as_mut().unwrap())
+}
 
-    fn clipboard(&mut self) -> anyhow::Result<&mut ClipboardContext> {
-        if self.clipboard.is_none() {
-            self.clipboard = Some(ClipboardContext::new().map_err(|e| anyhow!("{}", e))?);
-        }
-        Ok(self.clipboard.as_mut().unwrap())
+fn get<T: ClipboardProvider>(slot: &mut Option<T>) -> anyhow::Result<String> {
+    provider(slot)?.get_contents().map_err(|e| anyhow!("{}", e))
+}
+
+fn set<T: ClipboardProvider>(slot: &mut Option<T>, data: Option<String>) -> anyhow::Result<()> {
+    let clip = provider(slot)?;
+    clip.set_contents(data.unwrap_or_else(|| "".into())).map_err(|e| anyhow!("{}", e))?;
+
+    clip.get_contents().map(|_| ()).map_err(|e| anyhow!("{}", e))
+}
+
+impl Inner {
+    fn new() -> Self {
+        Self { clipboard: None, primary: None }
     }
 }
 
@@ -35,16 +81,217 @@ impl SystemClipboard {
 }
 
 impl Clipboard for SystemClipboard {
-    fn get_contents(&self) -> anyhow::Result<String> {
+    fn get_contents(&self, selection: ClipboardSelection) -> anyhow::Result<String> {
         let mut inner = self.inner.lock().unwrap();
-        inner.clipboard()?.get_contents().map_err(|e| anyhow!("{}", e))
+        match selection {
+            ClipboardSelection::Clipboard => get(&mut inner.clipboard),
+            ClipboardSelection::Primary => get(&mut inner.primary),
+        }
     }
 
-    fn set_contents(&self, data: Option<String>) -> anyhow::Result<()> {
+    fn get_typed_contents(
+        &self,
+        selection: ClipboardSelection,
+    ) -> anyhow::Result<ClipboardContents> {
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            if let Some(image) = x11::get_image(selection)? {
+                return Ok(image);
+            }
+        }
+        self.get_contents(selection).map(ClipboardContents::Text)
+    }
+
+    fn set_contents(
+        &self,
+        selection: ClipboardSelection,
+        data: Option<String>,
+    ) -> anyhow::Result<()> {
         let mut inner = self.inner.lock().unwrap();
-        let clip = inner.clipboard()?;
-        clip.set_contents(data.unwrap_or_else(|| "".into())).map_err(|e| anyhow!("{}", e))?;
+        match selection {
+            ClipboardSelection::Clipboard => set(&mut inner.clipboard, data),
+            ClipboardSelection::Primary => set(&mut inner.primary, data),
+        }
+    }
+}
 
-        clip.get_contents().map(|_| ()).map_err(|e| anyhow!("{}", e))
+/// Reads images off the X11 selections, which the `clipboard` crate only
+/// hands out as text.
+#[cfg(all(unix, not(target_os = "macos")))]
+mod x11 {
+    use super::{ClipboardContents, ClipboardSelection};
+    use anyhow::{anyhow, bail};
+    use std::time::{Duration, Instant};
+
+    const TIMEOUT: Duration = Duration::from_secs(3);
+
+    /// Targets that mean the selection holds text, which is pasted as such
+    const TEXT_TARGETS: &[&str] = &["UTF8_STRING", "STRING", "TEXT", "text/plain;charset=utf-8"];
+
+    /// The image formats we can paste, most preferred first, with the file
+    /// extension for each
+    const IMAGE_TARGETS: &[(&str, &str)] =
+        &[("image/png", "png"), ("image/jpeg", "jpg"), ("image/gif", "gif"), ("image/bmp", "bmp")];
+
+    /// The image held by `selection`, if it holds one and no text.
+    pub fn get_image(selection: ClipboardSelection) -> anyhow::Result<Option<ClipboardContents>> {
+        let requestor = Requestor::new()?;
+        let selection = match selection {
+            ClipboardSelection::Clipboard => requestor.atom("CLIPBOARD")?,
+            ClipboardSelection::Primary => xcb::ATOM_PRIMARY,
+        };
+
+        let targets = match requestor.convert(selection, requestor.atom("TARGETS")?)? {
+            Some(targets) => targets,
+            None => return Ok(None),
+        };
+        let targets: Vec<xcb::Atom> = targets
+            .chunks_exact(4)
+            .map(|atom| u32::from_ne_bytes([atom[0], atom[1], atom[2], atom[3]]))
+            .collect();
+
+        for name in TEXT_TARGETS {
+            if targets.contains(&requestor.atom(name)?) {
+                return Ok(None);
+            }
+        }
+        for (name, extension) in IMAGE_TARGETS {
+            let target = requestor.atom(name)?;
+            if !targets.contains(&target) {
+                continue;
+            }
+            if let Some(data) = requestor.convert(selection, target)? {
+                return Ok(Some(ClipboardContents::Image {
+                    data,
+                    extension: extension.to_string(),
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// A connection with a hidden window for selection owners to deliver
+    /// their data to.
+    struct Requestor {
+        conn: xcb::Connection,
+        window: xcb::Window,
+        property: xcb::Atom,
+    }
+
+    impl Requestor {
+        fn new() -> anyhow::Result<Self> {
+            let (conn, screen_num) = xcb::Connection::connect(None)?;
+            let window = conn.generate_id();
+            {
+                let screen = conn
+                    .get_setup()
+                    .roots()
+                    .nth(screen_num as usize)
+                    .ok_or_else(|| anyhow!("no screen?"))?;
+                xcb::create_window(
+                    &conn,
+                    xcb::COPY_FROM_PARENT as u8,
+                    window,
+                    screen.root(),
+                    0,
+                    0,
+                    1,
+                    1,
+                    0,
+                    xcb::WINDOW_CLASS_INPUT_OUTPUT as u16,
+                    screen.root_visual(),
+                    &[(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)],
+                )
+                .request_check()?;
+            }
+            let property = xcb::intern_atom(&conn, false, "XSEL_DATA").get_reply()?.atom();
+            Ok(Self { conn, window, property })
+        }
+
+        fn atom(&self, name: &str) -> anyhow::Result<xcb::Atom> {
+            Ok(xcb::intern_atom(&self.conn, false, name).get_reply()?.atom())
+        }
+
+        /// Asks the owner of `selection` for its contents as `target`; None
+        /// when there is no owner or it can't provide that target.
+        fn convert(
+            &self,
+            selection: xcb::Atom,
+            target: xcb::Atom,
+        ) -> anyhow::Result<Option<Vec<u8>>> {
+            xcb::convert_selection(
+                &self.conn,
+                self.window,
+                selection,
+                target,
+                self.property,
+                xcb::CURRENT_TIME,
+            );
+            self.conn.flush();
+
+            let event =
+                self.wait_for(|event| event.response_type() & 0x7f == xcb::SELECTION_NOTIFY)?;
+            let notify: &xcb::SelectionNotifyEvent = unsafe { xcb::cast_event(&event) };
+            if notify.property() == xcb::NONE {
+                return Ok(None);
+            }
+
+            let incr = self.atom("INCR")?;
+            let reply = self.take_property()?;
+            if reply.type_() != incr {
+                return Ok(Some(reply.value::<u8>().to_vec()));
+            }
+
+            // Large data comes in chunks, each written to the property once
+            // we have deleted the one before; an empty chunk ends it
+            let mut data = vec![];
+            loop {
+                self.wait_for(|event| {
+                    if event.response_type() & 0x7f != xcb::PROPERTY_NOTIFY {
+                        return false;
+                    }
+                    let notify: &xcb::PropertyNotifyEvent = unsafe { xcb::cast_event(event) };
+                    notify.atom() == self.property
+                        && notify.state() == xcb::PROPERTY_NEW_VALUE as u8
+                })?;
+                let reply = self.take_property()?;
+                if reply.value_len() == 0 {
+                    return Ok(Some(data));
+                }
+                data.extend_from_slice(reply.value::<u8>());
+            }
+        }
+
+        /// Reads and deletes our property.
+        fn take_property(&self) -> anyhow::Result<xcb::GetPropertyReply> {
+            let cookie = xcb::get_property(
+                &self.conn,
+                true,
+                self.window,
+                self.property,
+                xcb::ATOM_ANY,
+                0,
+                u32::max_value(),
+            );
+            self.conn.flush();
+            Ok(cookie.get_reply()?)
+        }
+
+        fn wait_for(
+            &self,
+            matches: impl Fn(&xcb::GenericEvent) -> bool,
+        ) -> anyhow::Result<xcb::GenericEvent> {
+            let deadline = Instant::now() + TIMEOUT;
+            loop {
+                match self.conn.poll_for_event() {
+                    Some(event) if matches(&event) => return Ok(event),
+                    Some(_) => {}
+                    None if Instant::now() > deadline => {
+                        bail!("timed out waiting for the selection owner")
+                    }
+                    None => std::thread::sleep(Duration::from_millis(5)),
+                }
+            }
+        }
     }
 }
@@ -10,7 +10,9 @@ pub struct ColorPalette {
     pub colors: Palette256,
     pub foreground: RgbColor,
     pub background: RgbColor,
-    pub cursor_fg: RgbColor,
+    /// The color of the glyph under a block cursor; when `None` the glyph
+    /// takes the background color of the cell it sits in.
+    pub cursor_fg: Option<RgbColor>,
     pub cursor_bg: RgbColor,
     pub cursor_border: RgbColor,
     pub selection_fg: RgbColor,
@@ -41,6 +43,12 @@ impl ColorPalette {
             | ColorAttribute::TrueColorWithDefaultFallback(color) => color,
         }
     }
+
+    /// Resolves the (glyph, block) colors for the cell under the cursor,
+    /// given the cell's own resolved background.
+    pub fn resolve_cursor(&self, bg: RgbColor) -> (RgbColor, RgbColor) {
+        (self.cursor_fg.unwrap_or(bg), self.cursor_bg)
+    }
 }
 
 impl Default for ColorPalette {
@@ -93,7 +101,7 @@ impl Default for ColorPalette {
 
         let cursor_bg = RgbColor::new(0x52, 0xad, 0x70);
         let cursor_border = RgbColor::new(0x52, 0xad, 0x70);
-        let cursor_fg = colors[AnsiColor::Black as usize];
+        let cursor_fg = None;
 
         let selection_fg = colors[AnsiColor::Black as usize];
         let selection_bg = RgbColor::new(0xff, 0xfa, 0xcd);
@@ -10,6 +10,51 @@ use std::time::{Duration, Instant};
 pub use crate::core::input::KeyCode;
 pub use crate::core::input::Modifiers as KeyModifiers;
 
+/// What the Backspace key sends to the application.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum BackspaceSends {
+    /// DEL (0x7f)
+    Delete,
+    /// ^H (0x08)
+    BackspaceCtrlH,
+}
+
+impl Default for BackspaceSends {
+    fn default() -> Self {
+        BackspaceSends::BackspaceCtrlH
+    }
+}
+
+/// What the Delete key sends to the application.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DeleteSends {
+    /// DEL (0x7f)
+    Delete,
+    /// `CSI 3 ~`
+    EscapeSequence,
+}
+
+impl Default for DeleteSends {
+    fn default() -> Self {
+        DeleteSends::Delete
+    }
+}
+
+/// What Ctrl+Space and Ctrl+@ send to the application.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CtrlSpaceSends {
+    /// NUL (0x00), which readline binds to set-mark
+    Nul,
+    /// `CSI 32 ; 5 u` or `CSI 64 ; 5 u`, so that the two can be told apart
+    CsiU,
+}
+
+impl Default for CtrlSpaceSends {
+    fn default() -> Self {
+        CtrlSpaceSends::Nul
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub enum MouseButton {
     Left,
@@ -32,6 +77,9 @@ pub struct MouseEvent {
     pub kind: MouseEventKind,
     pub x: usize,
     pub y: VisibleRowIndex,
+    /// The position in pixels, relative to the top left of the terminal
+    pub pixel_x: usize,
+    pub pixel_y: usize,
     pub button: MouseButton,
     pub modifiers: KeyModifiers,
 }
@@ -1,7 +1,9 @@
 use crate::term::{KeyCode, KeyModifiers};
+use serde::{self, Deserialize, Deserializer};
+use serde_derive::*;
 use std::collections::HashMap;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
 pub enum KeyAssignment {
     ToggleFullScreen,
     Copy,
@@ -10,6 +12,78 @@ pub enum KeyAssignment {
     DecreaseFontSize,
     ResetFontSize,
     Hide,
+    SaveScrollback,
+    ScrollToPrevPrompt,
+    ScrollToNextPrompt,
+    PauseOutput,
+    ResumeOutput,
+    /// Written to the pty as-is, for macros
+    SendString(String),
+}
+
+/// A `[[keys]]` entry in the config, such as
+/// `{ key = "F5", mods = "CTRL|SHIFT", action = { SendString = "make\r" } }`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeyBinding {
+    #[serde(deserialize_with = "deserialize_key")]
+    key: KeyCode,
+    #[serde(default, deserialize_with = "deserialize_mods")]
+    mods: KeyModifiers,
+    action: KeyAssignment,
+}
+
+/// A single character, `F1` to `F24`, or the name of a key such as `Enter`.
+fn deserialize_key<'de, D>(deserializer: D) -> Result<KeyCode, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    let mut chars = s.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(c));
+    }
+    if let Some(n) = s.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()) {
+        if (1..=24).contains(&n) {
+            return Ok(KeyCode::Function(n));
+        }
+    }
+    Ok(match s.as_str() {
+        "Backspace" => KeyCode::Backspace,
+        "Delete" => KeyCode::Delete,
+        "DownArrow" => KeyCode::DownArrow,
+        "End" => KeyCode::End,
+        "Enter" => KeyCode::Enter,
+        "Escape" => KeyCode::Escape,
+        "Home" => KeyCode::Home,
+        "Insert" => KeyCode::Insert,
+        "LeftArrow" => KeyCode::LeftArrow,
+        "PageDown" => KeyCode::PageDown,
+        "PageUp" => KeyCode::PageUp,
+        "RightArrow" => KeyCode::RightArrow,
+        "Tab" => KeyCode::Tab,
+        "UpArrow" => KeyCode::UpArrow,
+        _ => return Err(serde::de::Error::custom(format!("unknown key {:?}", s))),
+    })
+}
+
+/// Modifier names joined by `|`, e.g. `CTRL|SHIFT`.
+fn deserialize_mods<'de, D>(deserializer: D) -> Result<KeyModifiers, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    let mut mods = KeyModifiers::NONE;
+    for name in s.split('|').map(str::trim).filter(|name| !name.is_empty()) {
+        mods |= match name {
+            "NONE" => KeyModifiers::NONE,
+            "SHIFT" => KeyModifiers::SHIFT,
+            "ALT" => KeyModifiers::ALT,
+            "CTRL" => KeyModifiers::CTRL,
+            "SUPER" => KeyModifiers::SUPER,
+            _ => return Err(serde::de::Error::custom(format!("unknown modifier {:?}", name))),
+        };
+    }
+    Ok(mods)
 }
 
 pub struct KeyMap(HashMap<(KeyCode, KeyModifiers), KeyAssignment>);
@@ -41,6 +115,12 @@ impl KeyMap {
             [KeyModifiers::ALT, KeyCode::Enter, ToggleFullScreen],
             [KeyModifiers::SUPER, KeyCode::Char('m'), Hide],
             [ctrl_shift, KeyCode::Char('m'), Hide],
+            [KeyModifiers::SUPER, KeyCode::Char('s'), SaveScrollback],
+            [ctrl_shift, KeyCode::Char('s'), SaveScrollback],
+            [KeyModifiers::SUPER, KeyCode::UpArrow, ScrollToPrevPrompt],
+            [KeyModifiers::SUPER, KeyCode::DownArrow, ScrollToNextPrompt],
+            [ctrl_shift, KeyCode::Char('z'), ScrollToPrevPrompt],
+            [ctrl_shift, KeyCode::Char('x'), ScrollToNextPrompt],
             [KeyModifiers::CTRL, KeyCode::Char('-'), DecreaseFontSize],
             [KeyModifiers::CTRL, KeyCode::Char('0'), ResetFontSize],
             [KeyModifiers::CTRL, KeyCode::Char('='), IncreaseFontSize],
@@ -52,7 +132,82 @@ impl KeyMap {
         Self(map)
     }
 
+    /// Binds Ctrl-S and Ctrl-Q to pausing and resuming output.
+    pub fn bind_flow_control(&mut self) {
+        let ctrl = KeyModifiers::CTRL;
+        self.0.insert((KeyCode::Char('s'), ctrl), KeyAssignment::PauseOutput);
+        self.0.insert((KeyCode::Char('q'), ctrl), KeyAssignment::ResumeOutput);
+    }
+
+    /// Adds the bindings from the config, replacing defaults for the same keys.
+    pub fn bind(&mut self, bindings: &[KeyBinding]) {
+        for binding in bindings {
+            self.0.insert((binding.key, binding.mods), binding.action.clone());
+        }
+    }
+
     pub fn lookup(&self, key: KeyCode, mods: KeyModifiers) -> Option<KeyAssignment> {
         self.0.get(&(key, mods)).cloned()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn paste_bindings() {
+        let map = KeyMap::new();
+        for (key, mods) in &[
+            (KeyCode::Insert, KeyModifiers::SHIFT),
+            (KeyCode::Char('v'), KeyModifiers::CTRL | KeyModifiers::SHIFT),
+            (KeyCode::Char('v'), KeyModifiers::SUPER),
+        ] {
+            match map.lookup(*key, *mods) {
+                Some(KeyAssignment::Paste) => {}
+                other => panic!("{:?} {:?} mapped to {:?}", key, mods, other),
+            }
+        }
+        assert!(map.lookup(KeyCode::Insert, KeyModifiers::NONE).is_none());
+    }
+
+    #[test]
+    fn config_bindings_send_strings() {
+        #[derive(Deserialize)]
+        struct Keys {
+            keys: Vec<KeyBinding>,
+        }
+        let Keys { keys } = toml::from_str(
+            r#"
+            keys = [
+                { key = "F5", mods = "CTRL|SHIFT", action = { SendString = "make\r" } },
+                { key = "v", mods = "SUPER", action = { SendString = "vim\r" } },
+                { key = "Enter", action = "Copy" },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        let mut map = KeyMap::new();
+        map.bind(&keys);
+        match map.lookup(KeyCode::Function(5), KeyModifiers::CTRL | KeyModifiers::SHIFT) {
+            Some(KeyAssignment::SendString(s)) => assert_eq!(s, "make\r"),
+            other => panic!("F5 mapped to {:?}", other),
+        }
+        match map.lookup(KeyCode::Char('v'), KeyModifiers::SUPER) {
+            Some(KeyAssignment::SendString(s)) => assert_eq!(s, "vim\r"),
+            other => panic!("Super-V mapped to {:?}", other),
+        }
+        assert!(matches!(
+            map.lookup(KeyCode::Enter, KeyModifiers::NONE),
+            Some(KeyAssignment::Copy)
+        ));
+
+        for bad in &[
+            r#"keys = [{ key = "F25", action = "Copy" }]"#,
+            r#"keys = [{ key = "a", mods = "HYPER", action = "Copy" }]"#,
+        ] {
+            assert!(toml::from_str::<Keys>(bad).is_err(), "{}", bad);
+        }
+    }
+}
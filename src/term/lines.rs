@@ -0,0 +1,232 @@
+use super::*;
+use std::cell::OnceCell;
+use std::collections::VecDeque;
+use std::ops::{Index, IndexMut};
+
+/// A line as the screen stores it: packed once it has scrolled into the
+/// scrollback, when compression is on.
+#[derive(Debug, Clone)]
+enum StoredLine {
+    Plain(Line),
+    /// The packed line, and its expansion once something has read it
+    Packed(CompressedLine, OnceCell<Line>),
+}
+
+impl StoredLine {
+    fn get(&self) -> &Line {
+        match self {
+            StoredLine::Plain(line) => line,
+            StoredLine::Packed(packed, expanded) => expanded.get_or_init(|| packed.expand()),
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut Line {
+        if let StoredLine::Packed(packed, expanded) = self {
+            let line = expanded.take().unwrap_or_else(|| packed.expand());
+            *self = StoredLine::Plain(line);
+        }
+        match self {
+            StoredLine::Plain(line) => line,
+            StoredLine::Packed(..) => unreachable!(),
+        }
+    }
+
+    fn into_line(self) -> Line {
+        match self {
+            StoredLine::Plain(line) => line,
+            StoredLine::Packed(packed, expanded) => {
+                expanded.into_inner().unwrap_or_else(|| packed.expand())
+            }
+        }
+    }
+
+    fn pack(&mut self) {
+        match self {
+            StoredLine::Plain(line) => {
+                if let Some(packed) = line.compress() {
+                    *self = StoredLine::Packed(packed, OnceCell::new());
+                }
+            }
+            StoredLine::Packed(_, expanded) => {
+                expanded.take();
+            }
+        }
+    }
+
+    fn set_dirty(&mut self, dirty: bool) {
+        let set = |line: &mut Line| {
+            if dirty {
+                line.set_dirty()
+            } else {
+                line.clear_dirty()
+            }
+        };
+        match self {
+            StoredLine::Plain(line) => set(line),
+            StoredLine::Packed(packed, expanded) => {
+                if dirty {
+                    packed.set_dirty()
+                } else {
+                    packed.clear_dirty()
+                }
+                if let Some(line) = expanded.get_mut() {
+                    set(line);
+                }
+            }
+        }
+    }
+
+    fn has_hyperlink(&self) -> bool {
+        match self {
+            StoredLine::Plain(line) => line.has_hyperlink(),
+            StoredLine::Packed(packed, _) => packed.has_hyperlink(),
+        }
+    }
+}
+
+/// The lines of a `Screen`, oldest first. Lines read through a shared
+/// reference are expanded on demand, so that packed scrollback looks just
+/// like the rest; mutable access unpacks a line for good.
+#[derive(Debug, Clone, Default)]
+pub struct Lines {
+    lines: VecDeque<StoredLine>,
+}
+
+impl Lines {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { lines: VecDeque::with_capacity(capacity) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.lines.capacity()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.lines.reserve(additional)
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Line> {
+        self.lines.iter().map(StoredLine::get)
+    }
+
+    pub fn into_lines(self) -> impl Iterator<Item = Line> {
+        self.lines.into_iter().map(StoredLine::into_line)
+    }
+
+    pub fn get_mut(&mut self, idx: usize) -> Option<&mut Line> {
+        self.lines.get_mut(idx).map(StoredLine::get_mut)
+    }
+
+    pub fn push_back(&mut self, line: Line) {
+        self.lines.push_back(StoredLine::Plain(line))
+    }
+
+    pub fn pop_front(&mut self) -> Option<Line> {
+        self.lines.pop_front().map(StoredLine::into_line)
+    }
+
+    pub fn insert(&mut self, idx: usize, line: Line) {
+        self.lines.insert(idx, StoredLine::Plain(line))
+    }
+
+    pub fn remove(&mut self, idx: usize) -> Option<Line> {
+        self.lines.remove(idx).map(StoredLine::into_line)
+    }
+
+    /// Discards the oldest `count` lines without expanding them.
+    pub fn erase_front(&mut self, count: usize) {
+        self.lines.drain(0..count);
+    }
+
+    /// Packs the line at `idx`, or drops the expansion kept from reading
+    /// it when it is already packed. Lines that `Line::compress` can't
+    /// pack are left as they are.
+    pub fn pack(&mut self, idx: usize) {
+        self.lines[idx].pack()
+    }
+
+    pub fn set_all_dirty(&mut self) {
+        for line in &mut self.lines {
+            line.set_dirty(true);
+        }
+    }
+
+    pub fn clear_all_dirty(&mut self) {
+        for line in &mut self.lines {
+            line.set_dirty(false);
+        }
+    }
+
+    /// Marks the lines holding hyperlinks dirty, so that a change of
+    /// highlight is repainted.
+    pub fn set_hyperlinks_dirty(&mut self) {
+        for line in &mut self.lines {
+            if line.has_hyperlink() {
+                line.set_dirty(true);
+            }
+        }
+    }
+}
+
+impl Index<usize> for Lines {
+    type Output = Line;
+
+    fn index(&self, idx: usize) -> &Line {
+        self.lines[idx].get()
+    }
+}
+
+impl IndexMut<usize> for Lines {
+    fn index_mut(&mut self, idx: usize) -> &mut Line {
+        self.lines[idx].get_mut()
+    }
+}
+
+impl From<VecDeque<Line>> for Lines {
+    fn from(lines: VecDeque<Line>) -> Self {
+        Self { lines: lines.into_iter().map(StoredLine::Plain).collect() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn is_packed(lines: &Lines, idx: usize) -> bool {
+        matches!(lines.lines[idx], StoredLine::Packed(..))
+    }
+
+    #[test]
+    fn packed_lines_read_back_unchanged() {
+        let mut red = CellAttributes::default();
+        red.set_foreground(crate::term::color::ColorAttribute::PaletteIndex(1));
+        let original = vec![Line::from_text("plain", &red), Line::from("\u{4e2d} wide")];
+
+        let mut lines: Lines = original.iter().cloned().collect::<VecDeque<_>>().into();
+        lines.pack(0);
+        lines.pack(1);
+        assert!(is_packed(&lines, 0) && is_packed(&lines, 1));
+        assert_eq!(lines.iter().cloned().collect::<Vec<_>>(), original);
+
+        lines.clear_all_dirty();
+        assert!(!lines[0].is_dirty());
+        lines.pack(0);
+        assert!(!lines[0].is_dirty());
+
+        lines[1].set_prompt(true);
+        assert!(!is_packed(&lines, 1));
+        assert!(lines[1].is_prompt());
+        let mut first = original[0].clone();
+        first.clear_dirty();
+        assert_eq!(lines.into_lines().next(), Some(first));
+    }
+}
@@ -13,12 +13,16 @@ pub use crate::core::cell::{self, *};
 
 pub use crate::core::surface::line::*;
 
+pub mod lines;
+pub use lines::*;
+
 pub mod screen;
 pub use screen::*;
 
 pub mod selection;
 use selection::{SelectionCoordinate, SelectionRange};
 
+use crate::core::escape::csi::DeviceAttributeCodes;
 use crate::core::hyperlink::Hyperlink;
 
 pub mod terminal;
@@ -55,4 +59,9 @@ pub struct CursorPosition {
 
 pub mod color;
 
-pub const DEVICE_IDENT: &[u8] = b"\x1b[?6c";
+/// The features advertised in the primary device attributes reply, on top
+/// of VT220 conformance, in line with `TERM=xterm-256color`. Only what is
+/// actually implemented belongs here: applications take sixel graphics (4)
+/// in particular as an invitation to send image data.
+pub const DEVICE_ATTRIBUTES: &[DeviceAttributeCodes] =
+    &[DeviceAttributeCodes::SelectiveErase, DeviceAttributeCodes::AnsiColor];
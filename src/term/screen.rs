@@ -1,12 +1,19 @@
 use super::*;
 use std::collections::VecDeque;
 
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 100_000;
+
 #[derive(Debug, Clone)]
 pub struct Screen {
-    pub lines: VecDeque<Line>,
+    pub lines: Lines,
     pub scrollback_size: usize,
     pub physical_rows: usize,
     pub physical_cols: usize,
+    /// The most cells a `Line`, or a run of soft-wrapped lines being
+    /// reflowed, may hold.
+    pub max_line_length: usize,
+    /// Whether lines are packed as they scroll into the scrollback.
+    pub compress_scrollback: bool,
 }
 
 impl Screen {
@@ -14,12 +21,19 @@ impl Screen {
         let physical_rows = physical_rows.max(1);
         let physical_cols = physical_cols.max(1);
 
-        let mut lines = VecDeque::with_capacity(physical_rows + scrollback_size);
+        let mut lines = Lines::with_capacity(physical_rows + scrollback_size);
         for _ in 0..physical_rows {
             lines.push_back(Line::with_width(physical_cols));
         }
 
-        Screen { lines, scrollback_size, physical_rows, physical_cols }
+        Screen {
+            lines,
+            scrollback_size,
+            physical_rows,
+            physical_cols,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            compress_scrollback: false,
+        }
     }
 
     pub fn resize(&mut self, physical_rows: usize, physical_cols: usize) {
@@ -41,6 +55,124 @@ impl Screen {
         self.physical_cols = physical_cols;
     }
 
+    /// Resize the screen, re-flowing soft-wrapped lines to fit the new width.
+    /// Returns the updated position of `cursor`.
+    pub fn resize_and_reflow(
+        &mut self,
+        physical_rows: usize,
+        physical_cols: usize,
+        cursor: CursorPosition,
+    ) -> CursorPosition {
+        let physical_rows = physical_rows.max(1);
+        let physical_cols = physical_cols.max(1);
+        if physical_cols == self.physical_cols {
+            self.resize(physical_rows, physical_cols);
+            return cursor;
+        }
+
+        let cursor_phys = self.phys_row(cursor.y);
+        let mut lines = VecDeque::with_capacity(physical_rows + self.scrollback_size);
+        let mut new_cursor = CursorPosition::default();
+        let mut logical: Vec<Cell> = Vec::new();
+        let mut logical_cols = 0;
+        let mut cursor_col = None;
+        let mut prompt = false;
+        let num_lines = self.lines.len();
+
+        for (idx, line) in std::mem::take(&mut self.lines).into_lines().enumerate() {
+            if idx == cursor_phys {
+                cursor_col = Some(logical_cols + cursor.x);
+            }
+
+            let wrapped = line.last_cell_was_wrapped();
+            prompt |= line.is_prompt();
+            for (_, cell) in line.visible_cells() {
+                let mut cell = cell.clone();
+                cell.attrs_mut().set_wrapped(false);
+                logical_cols += cell.width().max(1);
+                logical.push(cell);
+            }
+            // A runaway line is broken up rather than joined without limit,
+            // with the break kept as a soft wrap
+            let continues = wrapped && idx + 1 < num_lines;
+            if continues && logical_cols < self.max_line_length {
+                continue;
+            }
+
+            while !continues && logical.last() == Some(&Cell::default()) {
+                logical.pop();
+                logical_cols -= 1;
+            }
+            if let Some(col) = cursor_col {
+                while logical_cols <= col {
+                    logical.push(Cell::default());
+                    logical_cols += 1;
+                }
+            }
+
+            let (mut rows, cursor) =
+                split_logical_line(std::mem::take(&mut logical), physical_cols, cursor_col.take());
+            rows[0].set_prompt(std::mem::take(&mut prompt));
+            if continues {
+                rows.last_mut().unwrap().set_last_cell_was_wrapped(true);
+            }
+            if let Some(cursor) = cursor {
+                new_cursor = CursorPosition { x: cursor.x, y: cursor.y + lines.len() as i64 };
+            }
+            lines.extend(rows);
+            logical_cols = 0;
+        }
+
+        while lines.len() < physical_rows {
+            lines.push_back(Line::with_width(physical_cols));
+        }
+
+        // Drop blank rows below the cursor rather than pushing content into scrollback
+        let blank = Line::with_width(physical_cols);
+        while lines.len() > physical_rows
+            && lines.len() - 1 > new_cursor.y as usize
+            && lines.back().map(|line| line.cells() == blank.cells()).unwrap_or(false)
+        {
+            lines.pop_back();
+        }
+
+        while lines.len() > physical_rows + self.scrollback_size {
+            lines.pop_front();
+            new_cursor.y -= 1;
+        }
+
+        self.lines = lines.into();
+        self.physical_rows = physical_rows;
+        self.physical_cols = physical_cols;
+        self.pack_scrollback(0..0);
+
+        let first_visible = (self.lines.len() - physical_rows) as i64;
+        CursorPosition {
+            x: new_cursor.x.min(physical_cols - 1),
+            y: (new_cursor.y - first_visible).max(0),
+        }
+    }
+
+    /// Discards every line that has scrolled off the top of the screen.
+    pub fn erase_scrollback(&mut self) {
+        let len = self.lines.len();
+        let to_clear = len - self.physical_rows;
+        self.lines.erase_front(to_clear);
+    }
+
+    /// Packs every scrollback line outside of `keep`, and drops what was
+    /// expanded from reading packed ones, when compression is on.
+    pub fn pack_scrollback(&mut self, keep: Range<PhysRowIndex>) {
+        if !self.compress_scrollback {
+            return;
+        }
+        for idx in 0..self.lines.len() - self.physical_rows {
+            if !keep.contains(&idx) {
+                self.lines.pack(idx);
+            }
+        }
+    }
+
     #[inline]
     pub fn line_mut(&mut self, idx: PhysRowIndex) -> &mut Line {
         &mut self.lines[idx]
@@ -54,21 +186,30 @@ impl Screen {
         }
     }
 
-    pub fn insert_cell(&mut self, x: usize, y: VisibleRowIndex) {
+    /// Inserts `blank` at `x`, discarding whatever is pushed past the
+    /// right margin.
+    pub fn insert_cell(&mut self, x: usize, y: VisibleRowIndex, blank: &Cell) {
         let phys_cols = self.physical_cols;
 
         let line_idx = self.phys_row(y);
         let line = self.line_mut(line_idx);
-        line.insert_cell(x, Cell::default());
+        line.insert_cell(x, blank.clone());
         if line.cells().len() > phys_cols {
             line.resize(phys_cols);
         }
     }
 
-    pub fn erase_cell(&mut self, x: usize, y: VisibleRowIndex) {
+    /// Removes the cell at `x`, shifting the rest of the line left and
+    /// filling the vacated cell at the right margin with `blank`.
+    pub fn erase_cell(&mut self, x: usize, y: VisibleRowIndex, blank: &Cell) {
+        let phys_cols = self.physical_cols;
         let line_idx = self.phys_row(y);
         let line = self.line_mut(line_idx);
+        if x >= line.cells().len() {
+            return;
+        }
         line.erase_cell(x);
+        line.set_cell(phys_cols - 1, blank.clone());
     }
 
     pub fn set_cell(&mut self, x: usize, y: VisibleRowIndex, cell: &Cell) -> &Cell {
@@ -78,16 +219,16 @@ impl Screen {
         line.set_cell(x, cell.clone())
     }
 
-    pub fn clear_line(
-        &mut self,
-        y: VisibleRowIndex,
-        cols: impl Iterator<Item = usize>,
-        attr: &CellAttributes,
-    ) {
+    /// Blanks `cols` of row `y`; erasing the whole row also forgets that a
+    /// prompt started there.
+    pub fn clear_line(&mut self, y: VisibleRowIndex, cols: Range<usize>, attr: &CellAttributes) {
         let physical_cols = self.physical_cols;
         let line_idx = self.phys_row(y);
         let line = self.line_mut(line_idx);
         line.resize(physical_cols);
+        if cols.start == 0 && cols.end >= physical_cols {
+            line.set_prompt(false);
+        }
         line.fill_range(cols, &Cell::new(' ', attr.clone()));
     }
 
@@ -124,50 +265,49 @@ impl Screen {
             self.line_mut(y).set_dirty();
         }
 
-        let lines_removed = if scroll_region.start > 0 {
-            num_rows
-        } else {
-            let max_allowed = self.physical_rows + self.scrollback_size;
-            if self.lines.len() + num_rows >= max_allowed {
-                (self.lines.len() + num_rows) - max_allowed
-            } else {
-                0
+        if !self.is_full_screen_region(scroll_region) {
+            for _ in 0..num_rows {
+                let mut line = self.lines.remove(phys_scroll.start).unwrap();
+                line.resize_and_clear(self.physical_cols);
+                self.lines.insert(phys_scroll.end - 1, line);
             }
-        };
+            return;
+        }
 
-        let remove_idx = if scroll_region.start == 0 { 0 } else { phys_scroll.start };
+        let max_allowed = self.physical_rows + self.scrollback_size;
+        let lines_removed = if self.lines.len() + num_rows >= max_allowed {
+            (self.lines.len() + num_rows) - max_allowed
+        } else {
+            0
+        };
 
         let to_move = lines_removed.min(num_rows);
-        let (to_remove, to_add) = {
-            for _ in 0..to_move {
-                let mut line = self.lines.remove(remove_idx).unwrap();
-
-                line.resize_and_clear(self.physical_cols);
-                if scroll_region.end as usize == self.physical_rows {
-                    self.lines.push_back(line);
-                } else {
-                    self.lines.insert(phys_scroll.end - 1, line);
-                }
-            }
+        for _ in 0..to_move {
+            let mut line = self.lines.pop_front().unwrap();
+            line.resize_and_clear(self.physical_cols);
+            self.lines.push_back(line);
+        }
 
-            (lines_removed - to_move, num_rows - to_move)
-        };
+        for _ in 0..lines_removed - to_move {
+            self.lines.pop_front();
+        }
 
-        for _ in 0..to_remove {
-            self.lines.remove(remove_idx);
+        for _ in 0..num_rows - to_move {
+            self.lines.push_back(Line::with_width(self.physical_cols));
         }
 
-        if scroll_region.end as usize == self.physical_rows {
-            for _ in 0..to_add {
-                self.lines.push_back(Line::with_width(self.physical_cols));
-            }
-        } else {
-            for _ in 0..to_add {
-                self.lines.insert(phys_scroll.end, Line::with_width(self.physical_cols));
+        if self.compress_scrollback {
+            let first_visible = self.lines.len() - self.physical_rows;
+            for idx in first_visible.saturating_sub(num_rows)..first_visible {
+                self.lines.pack(idx);
             }
         }
     }
 
+    fn is_full_screen_region(&self, scroll_region: &Range<VisibleRowIndex>) -> bool {
+        scroll_region.start == 0 && scroll_region.end as usize == self.physical_rows
+    }
+
     pub fn scroll_down(&mut self, scroll_region: &Range<VisibleRowIndex>, num_rows: usize) {
         let phys_scroll = self.phys_range(scroll_region);
         let num_rows = num_rows.min(phys_scroll.end - phys_scroll.start);
@@ -187,3 +327,51 @@ impl Screen {
         }
     }
 }
+
+/// Split a logical line into rows of `physical_cols`, marking the end of
+/// every row but the final one as wrapped. When `cursor_col` is given, also
+/// returns the cursor position relative to the first row.
+fn split_logical_line(
+    cells: Vec<Cell>,
+    physical_cols: usize,
+    cursor_col: Option<usize>,
+) -> (Vec<Line>, Option<CursorPosition>) {
+    let mut rows = vec![Line::with_width(physical_cols)];
+    let mut cursor = None;
+    let mut x = 0;
+    let mut last_x = 0;
+    let mut logical_col = 0;
+
+    for cell in cells {
+        let width = cell.width().max(1).min(physical_cols);
+        if x + width > physical_cols {
+            let line = rows.last_mut().unwrap();
+            if x < physical_cols {
+                // A double width cell doesn't fit; pad out the row instead
+                let mut pad = Cell::default();
+                pad.attrs_mut().set_wrapped(true);
+                line.set_cell(physical_cols - 1, pad);
+            } else {
+                let mut last = line.cells()[last_x].clone();
+                last.attrs_mut().set_wrapped(true);
+                line.set_cell(last_x, last);
+            }
+            rows.push(Line::with_width(physical_cols));
+            x = 0;
+        }
+
+        if let Some(col) = cursor_col {
+            if col >= logical_col && col < logical_col + width {
+                let y = rows.len() as i64 - 1;
+                cursor = Some(CursorPosition { x: x + col - logical_col, y });
+            }
+        }
+
+        rows.last_mut().unwrap().set_cell(x, cell);
+        last_x = x;
+        x += width;
+        logical_col += width;
+    }
+
+    (rows, cursor)
+}
@@ -9,6 +9,7 @@ pub trait TerminalHost {
     fn get_clipboard(&mut self) -> anyhow::Result<Arc<dyn Clipboard>>;
     fn set_title(&mut self, title: &str);
     fn click_link(&mut self, link: &Arc<Hyperlink>);
+    fn show_notification(&mut self, _title: Option<&str>, _body: &str) {}
 }
 
 pub struct Terminal {
@@ -16,6 +17,16 @@ pub struct Terminal {
     parser: Parser,
 }
 
+/// The extent of the screen as seen by a renderer.
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderableDimensions {
+    pub cols: usize,
+    pub viewport_rows: usize,
+    /// Total number of lines, including those in the viewport
+    pub scrollback_rows: usize,
+}
+
 impl Deref for Terminal {
     type Target = TerminalState;
 
@@ -43,8 +54,8 @@ impl Terminal {
             state: TerminalState::new(
                 physical_rows,
                 physical_cols,
-                pixel_height,
                 pixel_width,
+                pixel_height,
                 scrollback_size,
                 hyperlink_rules,
             ),
@@ -54,7 +65,37 @@ impl Terminal {
 
     pub fn advance_bytes<B: AsRef<[u8]>>(&mut self, bytes: B, host: &mut dyn TerminalHost) {
         let bytes = bytes.as_ref();
-        let mut performer = Performer::new(&mut self.state, host);
-        self.parser.parse(bytes, |action| performer.perform(action));
+        self.state.undo_predictions();
+        {
+            let mut performer = Performer::new(&mut self.state, host);
+            self.parser.parse(bytes, |action| performer.perform(action));
+        }
+        self.state.reconcile_predictions();
+    }
+}
+
+#[cfg(test)]
+impl Terminal {
+    /// Runs the same resize path as a window resize and reports the
+    /// resulting dimensions.
+    pub fn test_resize(&mut self, rows: usize, cols: usize) -> RenderableDimensions {
+        self.state.resize(rows, cols, 0, 0);
+        self.get_dimensions()
+    }
+
+    pub fn get_dimensions(&self) -> RenderableDimensions {
+        let screen = self.screen();
+        RenderableDimensions {
+            cols: screen.physical_cols,
+            viewport_rows: screen.physical_rows,
+            scrollback_rows: screen.lines.len(),
+        }
+    }
+
+    /// The text of each visible row, without trailing blanks.
+    pub fn visible_lines(&self) -> Vec<String> {
+        let screen = self.screen();
+        let first = screen.lines.len() - screen.physical_rows;
+        screen.lines.iter().skip(first).map(|line| line.as_str().trim_end().to_string()).collect()
     }
 }
@@ -1,17 +1,34 @@
 use super::*;
 use crate::core::escape::csi::{
-    Cursor, DecPrivateMode, DecPrivateModeCode, Device, Edit, EraseInDisplay, EraseInLine, Mode,
-    Sgr, TerminalMode, TerminalModeCode, Window,
+    Cursor, DecPrivateMode, DecPrivateModeCode, Device, DeviceAttribute, DeviceAttributeFlags,
+    DeviceAttributes, Edit, EraseInDisplay, EraseInLine, Mode, Sgr, TerminalMode, TerminalModeCode,
+    Window,
+};
+use crate::core::escape::osc::{
+    ChangeColorPair, ColorOrQuery, ITermProprietary, Selection, SemanticPrompt,
 };
-use crate::core::escape::osc::{ChangeColorPair, ColorOrQuery};
 use crate::core::escape::{
     Action, ControlCode, Esc, EscCode, OneBased, OperatingSystemCommand, CSI,
 };
 use crate::core::hyperlink::Rule as HyperlinkRule;
+use crate::term::clipboard::{Clipboard, ClipboardContents, ClipboardSelection};
 use crate::term::color::ColorPalette;
 use anyhow::bail;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const SYNCHRONIZED_OUTPUT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// xterm caps its title stack at the same depth.
+const MAX_TITLE_STACK: usize = 10;
+
+/// Input larger than this is written to the pty a chunk at a time, so
+/// that a huge paste doesn't block the UI.
+const PASTE_CHUNK_SIZE: usize = 1024;
 
 struct TabStop {
     tabs: Vec<bool>,
@@ -51,6 +68,67 @@ impl TabStop {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResizeRequest {
+    pub rows: usize,
+    pub cols: usize,
+}
+
+/// Which xterm window operations (`CSI Ps t`) applications may use.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum AllowWindowOps {
+    /// Only the size and checksum reports; titles and resizing are left alone
+    ReportsOnly,
+    /// Everything except reporting the titles back, since an application
+    /// that echoes them can be tricked into running injected input
+    NoTitleReports,
+    All,
+}
+
+impl Default for AllowWindowOps {
+    fn default() -> Self {
+        AllowWindowOps::NoTitleReports
+    }
+}
+
+/// A position report (`CSI 13 t`) waiting for the GUI to supply the
+/// coordinates.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PositionReport {
+    Window,
+    TextArea,
+}
+
+/// What pasting does when the clipboard holds an image rather than text.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ImagePasteBehavior {
+    Ignore,
+    Beep,
+    /// Saves the image to a temporary file and pastes its path
+    PastePath,
+}
+
+impl Default for ImagePasteBehavior {
+    fn default() -> Self {
+        ImagePasteBehavior::Ignore
+    }
+}
+
+/// How rows joined by a soft wrap are copied.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CopyWrappedLines {
+    /// As the single logical line the application wrote
+    Joined,
+    /// As they appear on screen, one row per line
+    Separated,
+}
+
+impl Default for CopyWrappedLines {
+    fn default() -> Self {
+        CopyWrappedLines::Joined
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 struct SavedCursor {
     position: CursorPosition,
@@ -58,6 +136,14 @@ struct SavedCursor {
     insert: bool,
 }
 
+/// A printable character echoed locally before the host has echoed it.
+struct Prediction {
+    x: usize,
+    y: VisibleRowIndex,
+    text: char,
+    original: Cell,
+}
+
 struct ScreenOrAlt {
     screen: Screen,
 
@@ -104,9 +190,25 @@ impl ScreenOrAlt {
         }
     }
 
-    pub fn resize(&mut self, physical_rows: usize, physical_cols: usize) {
-        self.screen.resize(physical_rows, physical_cols);
+    /// Resize both screens, re-flowing the primary one. Returns the updated
+    /// position of `cursor`, which belongs to the active screen.
+    pub fn resize(
+        &mut self,
+        physical_rows: usize,
+        physical_cols: usize,
+        cursor: CursorPosition,
+    ) -> CursorPosition {
         self.alt_screen.resize(physical_rows, physical_cols);
+        if self.alt_screen_is_active {
+            let saved = self.saved_cursor.as_ref().map(|saved| saved.position).unwrap_or_default();
+            let saved = self.screen.resize_and_reflow(physical_rows, physical_cols, saved);
+            if let Some(saved_cursor) = self.saved_cursor.as_mut() {
+                saved_cursor.position = saved;
+            }
+            cursor
+        } else {
+            self.screen.resize_and_reflow(physical_rows, physical_cols, cursor)
+        }
     }
 
     pub fn activate_alt_screen(&mut self) {
@@ -121,6 +223,25 @@ impl ScreenOrAlt {
         self.alt_screen_is_active
     }
 
+    pub fn set_max_line_length(&mut self, max_line_length: usize) {
+        self.screen.max_line_length = max_line_length;
+        self.alt_screen.max_line_length = max_line_length;
+    }
+
+    /// Only the primary screen has a scrollback to compress.
+    pub fn set_compress_scrollback(&mut self, compress: bool) {
+        self.screen.compress_scrollback = compress;
+        self.screen.pack_scrollback(0..0);
+    }
+
+    pub fn primary(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn primary_mut(&mut self) -> &mut Screen {
+        &mut self.screen
+    }
+
     pub fn saved_cursor(&mut self) -> &mut Option<SavedCursor> {
         if self.alt_screen_is_active {
             &mut self.alt_saved_cursor
@@ -135,28 +256,78 @@ pub struct TerminalState {
     pen: CellAttributes,
     cursor: CursorPosition,
     wrap_next: bool,
+    /// DECAWM; when off, printing at the right margin overwrites the
+    /// last cell instead of wrapping.
+    dec_auto_wrap: bool,
     insert: bool,
     scroll_region: Range<VisibleRowIndex>,
     application_cursor_keys: bool,
     application_keypad: bool,
     bracketed_paste: bool,
+    synchronized_output: Option<Instant>,
     sgr_mouse: bool,
+    sgr_pixels_mouse: bool,
     button_event_mouse: bool,
     current_mouse_button: MouseButton,
     mouse_position: CursorPosition,
     cursor_visible: bool,
     dec_line_drawing_mode: bool,
+    last_graphic: Option<String>,
+    /// Where the last grapheme was printed, so that a combining mark
+    /// arriving in a later write can be attached to it.
+    last_print: Option<(usize, VisibleRowIndex)>,
     current_highlight: Option<Arc<Hyperlink>>,
     last_mouse_click: Option<LastMouseClick>,
     pub(crate) viewport_offset: VisibleRowIndex,
+    scrollback_in_alt_screen: bool,
     selection_start: Option<SelectionCoordinate>,
     selection_range: Option<SelectionRange>,
     tabs: TabStop,
     hyperlink_rules: Vec<HyperlinkRule>,
     title: String,
+    icon_title: String,
+    title_stack: Vec<String>,
+    icon_title_stack: Vec<String>,
+    /// The window title was fixed by configuration and ignores OSC changes.
+    fixed_title: bool,
+    /// xterm title modes 0 and 1: titles are set and reported hex encoded.
+    set_title_hex: bool,
+    report_title_hex: bool,
     palette: ColorPalette,
+    /// The palette given to `set_palette`, which OSC resets go back to
+    default_palette: ColorPalette,
     pixel_width: usize,
     pixel_height: usize,
+    /// The cell size passed to `resize_pixels`; `resize` leaves it to be
+    /// worked out from the pixel and cell dimensions
+    cell_pixels: Option<(usize, usize)>,
+    allow_resize_escape: bool,
+    allow_window_ops: AllowWindowOps,
+    answerback: String,
+    resize_request: Option<ResizeRequest>,
+    position_reports: Vec<PositionReport>,
+    backspace_sends: BackspaceSends,
+    delete_sends: DeleteSends,
+    ctrl_space_sends: CtrlSpaceSends,
+    read_only: bool,
+    focus_tracking: bool,
+    /// The last focus state the GUI told us about; a new terminal starts
+    /// out unfocused so that the first focus-in is reported.
+    focused: bool,
+    paste_filter: bool,
+    background_color_erase: bool,
+    predictive_echo: bool,
+    predictions: Vec<Prediction>,
+    predictions_blocked: bool,
+    last_command_status: Option<i32>,
+    user_vars: HashMap<String, String>,
+    bell_pending: bool,
+    copy_wrapped_lines_as: CopyWrappedLines,
+    copy_tabs_as_tabs: bool,
+    image_paste_behavior: ImagePasteBehavior,
+    /// Input waiting behind an unfinished paste; keystrokes typed during
+    /// the paste queue up after its end marker.
+    pending_input: VecDeque<u8>,
 }
 
 fn is_double_click_word(s: &str) -> bool {
@@ -189,27 +360,64 @@ impl TerminalState {
             cursor: CursorPosition::default(),
             scroll_region: 0..physical_rows as VisibleRowIndex,
             wrap_next: false,
+            dec_auto_wrap: true,
             insert: false,
             application_cursor_keys: false,
             application_keypad: false,
             bracketed_paste: false,
+            synchronized_output: None,
             sgr_mouse: false,
+            sgr_pixels_mouse: false,
             button_event_mouse: false,
             cursor_visible: true,
             dec_line_drawing_mode: false,
+            last_graphic: None,
+            last_print: None,
             current_mouse_button: MouseButton::None,
             mouse_position: CursorPosition::default(),
             current_highlight: None,
             last_mouse_click: None,
             viewport_offset: 0,
+            scrollback_in_alt_screen: false,
             selection_range: None,
             selection_start: None,
             tabs: TabStop::new(physical_cols, 8),
             hyperlink_rules,
             title: "miro".to_string(),
+            icon_title: "miro".to_string(),
+            title_stack: vec![],
+            icon_title_stack: vec![],
+            fixed_title: false,
+            set_title_hex: false,
+            report_title_hex: false,
             palette: ColorPalette::default(),
+            default_palette: ColorPalette::default(),
             pixel_height,
             pixel_width,
+            cell_pixels: None,
+            allow_resize_escape: false,
+            allow_window_ops: AllowWindowOps::default(),
+            answerback: String::new(),
+            resize_request: None,
+            position_reports: vec![],
+            backspace_sends: BackspaceSends::default(),
+            delete_sends: DeleteSends::default(),
+            ctrl_space_sends: CtrlSpaceSends::default(),
+            read_only: false,
+            focus_tracking: false,
+            focused: false,
+            paste_filter: false,
+            background_color_erase: true,
+            predictive_echo: false,
+            predictions: vec![],
+            predictions_blocked: false,
+            last_command_status: None,
+            user_vars: HashMap::new(),
+            bell_pending: false,
+            pending_input: VecDeque::new(),
+            copy_wrapped_lines_as: CopyWrappedLines::default(),
+            copy_tabs_as_tabs: false,
+            image_paste_behavior: ImagePasteBehavior::default(),
         }
     }
 
@@ -217,10 +425,28 @@ impl TerminalState {
         &self.title
     }
 
+    /// Sets both titles before the application has had a chance to.
+    pub fn set_default_title(&mut self, title: &str) {
+        self.title = title.to_string();
+        self.icon_title = title.to_string();
+    }
+
+    /// Sets both titles and ignores applications that try to change them.
+    pub fn set_fixed_title(&mut self, title: &str) {
+        self.set_default_title(title);
+        self.fixed_title = true;
+    }
+
     pub fn palette(&self) -> &ColorPalette {
         &self.palette
     }
 
+    pub fn set_palette(&mut self, palette: ColorPalette) {
+        self.default_palette = palette.clone();
+        self.palette = palette;
+        self.make_all_lines_dirty();
+    }
+
     pub fn screen(&self) -> &Screen {
         &self.screen
     }
@@ -229,7 +455,284 @@ impl TerminalState {
         &mut self.screen
     }
 
-    pub fn get_selection_text(&self) -> String {
+    /// Serializes the screen back into text and SGR sequences that can be
+    /// replayed into another terminal to reproduce it.
+    pub fn dump_ansi(&self, include_scrollback: bool) -> String {
+        let screen = self.screen();
+        let first = if include_scrollback { 0 } else { screen.lines.len() - screen.physical_rows };
+        let mut out = self.serialize_lines(first, true, "\r\n");
+        write!(
+            out,
+            "{}",
+            CSI::Cursor(Cursor::Position {
+                line: OneBased::from_zero_based(self.cursor.y as u32),
+                col: OneBased::from_zero_based(self.cursor.x as u32),
+            })
+        )
+        .ok();
+        out
+    }
+
+    /// Writes every line from the oldest scrollback row to the bottom of
+    /// the screen, one per logical line, optionally with the SGR sequences
+    /// that reproduce its colors and attributes.
+    pub fn export_scrollback(
+        &self,
+        writer: &mut dyn std::io::Write,
+        include_colors: bool,
+    ) -> anyhow::Result<()> {
+        let mut out = self.serialize_lines(0, include_colors, "\n");
+        out.push('\n');
+        writer.write_all(out.as_bytes())?;
+        Ok(())
+    }
+
+    /// Joins the lines from `first` on, separating logical lines with
+    /// `newline`; soft-wrapped rows run together.
+    fn serialize_lines(&self, first: usize, include_colors: bool, newline: &str) -> String {
+        let screen = self.screen();
+        let mut out = String::new();
+        let mut attrs = CellAttributes::default();
+
+        for (idx, line) in screen.lines.iter().enumerate().skip(first) {
+            if idx > first && !screen.lines[idx - 1].last_cell_was_wrapped() {
+                out.push_str(newline);
+            }
+
+            let blank = Cell::default();
+            let cells = line.cells();
+            let len = if line.last_cell_was_wrapped() {
+                cells.len()
+            } else {
+                cells.iter().rposition(|cell| *cell != blank).map_or(0, |idx| idx + 1)
+            };
+
+            for (_, cell) in line.visible_cells().take_while(|(x, _)| *x < len) {
+                if include_colors {
+                    emit_sgr_changes(&attrs, cell.attrs(), &mut out);
+                    attrs = cell.attrs().clone_sgr_only();
+                }
+                out.push_str(cell.str());
+            }
+        }
+
+        if attrs.clone_sgr_only() != CellAttributes::default() {
+            write!(out, "{}", CSI::Sgr(Sgr::Reset)).ok();
+        }
+        out
+    }
+
+    pub fn set_scrollback_in_alt_screen(&mut self, enable: bool) {
+        self.scrollback_in_alt_screen = enable;
+    }
+
+    pub fn set_allow_resize_escape(&mut self, allow: bool) {
+        self.allow_resize_escape = allow;
+    }
+
+    pub fn set_allow_window_ops(&mut self, allow: AllowWindowOps) {
+        self.allow_window_ops = allow;
+    }
+
+    /// The reply to ENQ; nothing is sent while it is empty.
+    pub fn set_answerback(&mut self, answerback: &str) {
+        self.answerback = answerback.to_string();
+    }
+
+    pub fn set_backspace_sends(&mut self, backspace_sends: BackspaceSends) {
+        self.backspace_sends = backspace_sends;
+    }
+
+    pub fn set_delete_sends(&mut self, delete_sends: DeleteSends) {
+        self.delete_sends = delete_sends;
+    }
+
+    pub fn set_ctrl_space_sends(&mut self, ctrl_space_sends: CtrlSpaceSends) {
+        self.ctrl_space_sends = ctrl_space_sends;
+    }
+
+    pub fn set_max_line_length(&mut self, max_line_length: usize) {
+        self.screen.set_max_line_length(max_line_length.max(1));
+    }
+
+    /// Packs lines as they scroll into the scrollback, so that long
+    /// sessions of mostly plain text take less memory.
+    pub fn set_compress_scrollback(&mut self, compress: bool) {
+        self.screen.set_compress_scrollback(compress);
+    }
+
+    /// Discards keyboard, paste and mouse-report input; output, resizing,
+    /// scrolling and selection keep working.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    #[allow(dead_code)]
+    pub fn auto_wrap(&self) -> bool {
+        self.dec_auto_wrap
+    }
+
+    /// Sets DECAWM, as an application would with `CSI ? 7 h/l`.
+    #[allow(dead_code)]
+    pub fn set_auto_wrap(&mut self, auto_wrap: bool) {
+        self.dec_auto_wrap = auto_wrap;
+        if !auto_wrap {
+            self.wrap_next = false;
+        }
+    }
+
+    /// Whether a full screen application has switched to the alternate screen.
+    pub fn is_alt_screen_active(&self) -> bool {
+        self.screen.is_alt_screen_active()
+    }
+
+    pub fn set_paste_filter(&mut self, enable: bool) {
+        self.paste_filter = enable;
+    }
+
+    pub fn set_copy_wrapped_lines_as(&mut self, copy_wrapped_lines_as: CopyWrappedLines) {
+        self.copy_wrapped_lines_as = copy_wrapped_lines_as;
+    }
+
+    /// Copies the gaps left by horizontal tabs as `\t` rather than spaces.
+    pub fn set_copy_tabs_as_tabs(&mut self, enable: bool) {
+        self.copy_tabs_as_tabs = enable;
+    }
+
+    pub fn set_image_paste_behavior(&mut self, behavior: ImagePasteBehavior) {
+        self.image_paste_behavior = behavior;
+    }
+
+    /// With `bce` erased cells take the current pen's colors, otherwise
+    /// they are reset to the default attributes.
+    pub fn set_background_color_erase(&mut self, enable: bool) {
+        self.background_color_erase = enable;
+    }
+
+    fn erase_pen(&self) -> CellAttributes {
+        if self.background_color_erase {
+            self.pen.clone_sgr_only()
+        } else {
+            CellAttributes::default()
+        }
+    }
+
+    /// Whether the application has asked to receive mouse events.
+    pub fn is_mouse_grabbed(&self) -> bool {
+        self.sgr_mouse || self.sgr_pixels_mouse
+    }
+
+    /// The one-based coordinates to report for `event`: pixels when the
+    /// application enabled SGR-Pixels, cells otherwise.
+    fn mouse_report_position(&self, event: &MouseEvent) -> (usize, i64) {
+        if self.sgr_pixels_mouse {
+            (event.pixel_x + 1, event.pixel_y as i64 + 1)
+        } else {
+            (event.x + 1, event.y + 1)
+        }
+    }
+
+    fn reports_mouse(&self) -> bool {
+        self.is_mouse_grabbed() && !self.read_only
+    }
+
+    pub fn set_predictive_echo(&mut self, enable: bool) {
+        if !enable {
+            self.discard_predictions();
+        }
+        self.predictive_echo = enable;
+    }
+
+    pub fn take_resize_request(&mut self) -> Option<ResizeRequest> {
+        self.resize_request.take()
+    }
+
+    pub fn take_position_reports(&mut self) -> Vec<PositionReport> {
+        std::mem::take(&mut self.position_reports)
+    }
+
+    /// Answers a request returned by `take_position_reports`.
+    pub fn report_position(
+        &mut self,
+        report: PositionReport,
+        x: isize,
+        y: isize,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        match report {
+            PositionReport::Window => self.reply(&format!("\x1b[3;{};{}t", x, y), writer)?,
+            PositionReport::TextArea => self.reply(&format!("\x1b[3;2;{};{}t", x, y), writer)?,
+        }
+        Ok(())
+    }
+
+    /// Records that the window gained or lost focus, reporting it when
+    /// the application enabled focus tracking.
+    pub fn focus_changed(
+        &mut self,
+        focused: bool,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        if focused == self.focused {
+            return Ok(());
+        }
+        self.focused = focused;
+        if self.focus_tracking && !self.read_only {
+            self.write_input(if focused { b"\x1b[I" } else { b"\x1b[O" }, writer)?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if a BEL arrived since the last call
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::replace(&mut self.bell_pending, false)
+    }
+
+    /// True while a BEL is waiting for `take_bell`, which the window only
+    /// calls when it paints.
+    pub fn is_bell_pending(&self) -> bool {
+        self.bell_pending
+    }
+
+    fn request_resize(&mut self, rows: Option<i64>, cols: Option<i64>) {
+        if !self.allow_resize_escape {
+            return;
+        }
+        let (current_rows, current_cols) = self.physical_dimensions();
+        let pick = |requested: Option<i64>, current: usize| match requested {
+            Some(n) if n > 0 => n as usize,
+            _ => current,
+        };
+        self.resize_request =
+            Some(ResizeRequest { rows: pick(rows, current_rows), cols: pick(cols, current_cols) });
+    }
+
+    fn alt_screen_scrollback_enabled(&self) -> bool {
+        self.scrollback_in_alt_screen && self.is_alt_screen_active()
+    }
+
+    fn viewport_screen(&self) -> &Screen {
+        if self.alt_screen_scrollback_enabled() && self.viewport_offset > 0 {
+            self.screen.primary()
+        } else {
+            self.screen()
+        }
+    }
+
+    fn viewport_screen_mut(&mut self) -> &mut Screen {
+        if self.alt_screen_scrollback_enabled() && self.viewport_offset > 0 {
+            self.screen.primary_mut()
+        } else {
+            self.screen_mut()
+        }
+    }
+
+    /// The selected text, without a trailing newline.
+    pub fn get_selection_text(&self, wrapped_lines: CopyWrappedLines) -> String {
         let mut s = String::new();
 
         if let Some(sel) = self.selection_range.as_ref().map(|r| r.normalize()) {
@@ -242,14 +745,24 @@ impl TerminalState {
                 if !s.is_empty() && !last_was_wrapped {
                     s.push('\n');
                 }
-                s.push_str(screen.lines[idx].columns_as_str(cols).trim_end());
+                let text = if self.copy_tabs_as_tabs {
+                    screen.lines[idx].columns_as_str_with_tabs(cols)
+                } else {
+                    screen.lines[idx].columns_as_str(cols)
+                };
+                s.push_str(text.trim_end());
 
                 let last_cell = &screen.lines[idx].cells()[last_col_idx];
 
-                last_was_wrapped = last_cell.attrs().wrapped() && last_cell.str() != " ";
+                last_was_wrapped = wrapped_lines == CopyWrappedLines::Joined
+                    && last_cell.attrs().wrapped()
+                    && last_cell.str() != " ";
             }
         }
 
+        if s.ends_with('\n') {
+            s.pop();
+        }
         s
     }
 
@@ -331,12 +844,7 @@ impl TerminalState {
     }
 
     fn invalidate_hyperlinks(&mut self) {
-        let screen = self.screen_mut();
-        for line in &mut screen.lines {
-            if line.has_hyperlink() {
-                line.set_dirty();
-            }
-        }
+        self.screen_mut().lines.set_hyperlinks_dirty();
     }
 
     fn recompute_highlight(&mut self) {
@@ -358,7 +866,7 @@ impl TerminalState {
             y: event.y as ScrollbackOrVisibleRowIndex
                 - self.viewport_offset as ScrollbackOrVisibleRowIndex,
         });
-        host.get_clipboard()?.set_contents(None)
+        host.get_clipboard()?.set_contents(ClipboardSelection::Clipboard, None)
     }
 
     fn mouse_double_click_left(
@@ -412,8 +920,8 @@ impl TerminalState {
         self.selection_range = Some(selection_range);
 
         self.dirty_selection_lines();
-        let text = self.get_selection_text();
-        host.get_clipboard()?.set_contents(Some(text))
+        let text = self.get_selection_text(self.copy_wrapped_lines_as);
+        host.get_clipboard()?.set_contents(ClipboardSelection::Clipboard, Some(text))
     }
 
     fn mouse_triple_click_left(
@@ -429,8 +937,8 @@ impl TerminalState {
             end: SelectionCoordinate { x: usize::max_value(), y },
         });
         self.dirty_selection_lines();
-        let text = self.get_selection_text();
-        host.get_clipboard()?.set_contents(Some(text))
+        let text = self.get_selection_text(self.copy_wrapped_lines_as);
+        host.get_clipboard()?.set_contents(ClipboardSelection::Clipboard, Some(text))
     }
 
     fn mouse_press_left(
@@ -454,7 +962,7 @@ impl TerminalState {
             _ => {
                 self.selection_range = None;
                 self.selection_start = None;
-                host.get_clipboard()?.set_contents(None)?;
+                host.get_clipboard()?.set_contents(ClipboardSelection::Clipboard, None)?;
             }
         }
 
@@ -468,9 +976,11 @@ impl TerminalState {
     ) -> anyhow::Result<()> {
         self.current_mouse_button = MouseButton::None;
         if let Some(&LastMouseClick { streak: 1, .. }) = self.last_mouse_click.as_ref() {
-            let text = self.get_selection_text();
+            let text = self.get_selection_text(self.copy_wrapped_lines_as);
             if !text.is_empty() {
-                host.get_clipboard()?.set_contents(Some(text))?;
+                let clipboard = host.get_clipboard()?;
+                clipboard.set_contents(ClipboardSelection::Primary, Some(text.clone()))?;
+                clipboard.set_contents(ClipboardSelection::Clipboard, Some(text))?;
             } else if let Some(link) = self.current_highlight() {
                 host.click_link(&link);
             }
@@ -502,20 +1012,23 @@ impl TerminalState {
         event: MouseEvent,
         writer: &mut dyn std::io::Write,
     ) -> anyhow::Result<()> {
-        let (report_button, scroll_delta, key) = match event.button {
-            MouseButton::WheelUp(amount) => (64, -(amount as i64), KeyCode::UpArrow),
-            MouseButton::WheelDown(amount) => (65, amount as i64, KeyCode::DownArrow),
+        let (report_button, amount, key) = match event.button {
+            MouseButton::WheelUp(amount) => (64, amount, KeyCode::UpArrow),
+            MouseButton::WheelDown(amount) => (65, amount, KeyCode::DownArrow),
             _ => bail!("unexpected mouse event {:?}", event),
         };
 
-        if self.sgr_mouse {
-            writer.write_all(
-                format!("\x1b[<{};{};{}M", report_button, event.x + 1, event.y + 1).as_bytes(),
-            )?;
-        } else if self.screen.is_alt_screen_active() {
-            self.key_down(key, KeyModifiers::default(), writer)?;
+        if self.reports_mouse() {
+            let (x, y) = self.mouse_report_position(&event);
+            self.write_input(format!("\x1b[<{};{};{}M", report_button, x, y).as_bytes(), writer)?;
+        } else if self.is_alt_screen_active() && !self.scrollback_in_alt_screen && !self.read_only {
+            for _ in 0..amount {
+                self.key_down(key, KeyModifiers::default(), writer)?;
+            }
+        } else if report_button == 64 {
+            self.scroll_viewport(-(amount as i64))
         } else {
-            self.scroll_viewport(scroll_delta)
+            self.scroll_viewport(amount as i64)
         }
         Ok(())
     }
@@ -532,13 +1045,17 @@ impl TerminalState {
             MouseButton::Right => Some(2),
             _ => None,
         } {
-            if self.sgr_mouse {
-                host.writer().write_all(
-                    format!("\x1b[<{};{};{}M", button, event.x + 1, event.y + 1).as_bytes(),
-                )?;
+            if self.reports_mouse() && !event.modifiers.contains(KeyModifiers::SHIFT) {
+                let (x, y) = self.mouse_report_position(&event);
+                let report = format!("\x1b[<{};{};{}M", button, x, y);
+                self.write_input(report.as_bytes(), host.writer())?;
             } else if event.button == MouseButton::Middle {
-                let clip = host.get_clipboard()?.get_contents()?;
-                self.send_paste(&clip, host.writer())?
+                let clipboard = host.get_clipboard()?;
+                if let Some(clip) =
+                    self.clipboard_paste_text(&*clipboard, ClipboardSelection::Primary)?
+                {
+                    self.send_paste(&clip, host.writer())?
+                }
             }
         }
 
@@ -552,8 +1069,9 @@ impl TerminalState {
     ) -> anyhow::Result<()> {
         if self.current_mouse_button != MouseButton::None {
             self.current_mouse_button = MouseButton::None;
-            if self.sgr_mouse {
-                write!(writer, "\x1b[<3;{};{}m", event.x + 1, event.y + 1)?;
+            if self.reports_mouse() {
+                let (x, y) = self.mouse_report_position(&event);
+                self.write_input(format!("\x1b[<3;{};{}m", x, y).as_bytes(), writer)?;
             }
         }
 
@@ -571,8 +1089,9 @@ impl TerminalState {
             (MouseButton::Right, true) => Some(34),
             (..) => None,
         } {
-            if self.sgr_mouse {
-                write!(writer, "\x1b[<{};{};{}M", button, event.x + 1, event.y + 1)?;
+            if self.reports_mouse() {
+                let (x, y) = self.mouse_report_position(&event);
+                self.write_input(format!("\x1b[<{};{};{}M", button, x, y).as_bytes(), writer)?;
             }
         }
         Ok(())
@@ -583,7 +1102,10 @@ impl TerminalState {
         mut event: MouseEvent,
         host: &mut dyn TerminalHost,
     ) -> anyhow::Result<()> {
-        event.y = event.y.min(self.screen().physical_rows as i64 - 1);
+        // Events landing in the partial row/column left over after dividing
+        // the pixel size given to `resize_pixels` into cells belong to the
+        // last full cell.
+        event.y = event.y.max(0).min(self.screen().physical_rows as i64 - 1);
         event.x = event.x.min(self.screen().physical_cols - 1);
 
         let new_position = CursorPosition { x: event.x, y: event.y as VisibleRowIndex };
@@ -593,7 +1115,7 @@ impl TerminalState {
             self.recompute_highlight();
         }
 
-        let send_event = self.sgr_mouse && !event.modifiers.contains(KeyModifiers::SHIFT);
+        let send_event = self.reports_mouse() && !event.modifiers.contains(KeyModifiers::SHIFT);
 
         if event.kind == MouseEventKind::Press {
             let click = match self.last_mouse_click.take() {
@@ -634,16 +1156,155 @@ impl TerminalState {
         }
     }
 
+    /// The text to paste from `selection`, or None when the clipboard
+    /// holds an image that `image_paste_behavior` says not to paste.
+    pub fn clipboard_paste_text(
+        &mut self,
+        clipboard: &dyn Clipboard,
+        selection: ClipboardSelection,
+    ) -> anyhow::Result<Option<String>> {
+        let (data, extension) = match clipboard.get_typed_contents(selection)? {
+            ClipboardContents::Text(text) => return Ok(Some(text)),
+            ClipboardContents::Image { data, extension } => (data, extension),
+        };
+        match self.image_paste_behavior {
+            ImagePasteBehavior::Ignore => Ok(None),
+            ImagePasteBehavior::Beep => {
+                self.bell_pending = true;
+                Ok(None)
+            }
+            ImagePasteBehavior::PastePath => {
+                use std::os::unix::fs::OpenOptionsExt;
+                static COUNT: AtomicUsize = AtomicUsize::new(0);
+                let path = std::env::temp_dir().join(format!(
+                    "miro-paste-{}-{}.{}",
+                    std::process::id(),
+                    COUNT.fetch_add(1, Ordering::Relaxed),
+                    extension
+                ));
+                // The name is easy to guess, so never open what someone else
+                // put there, such as a symlink to one of the user's files
+                let mut file = std::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(&path)?;
+                std::io::Write::write_all(&mut file, &data)?;
+                Ok(Some(path.display().to_string()))
+            }
+        }
+    }
+
     pub fn send_paste(
         &mut self,
         text: &str,
         writer: &mut dyn std::io::Write,
     ) -> anyhow::Result<()> {
+        if self.paste_filter {
+            return self.send_paste_filtered(text, writer);
+        }
+        self.write_paste(text, writer)
+    }
+
+    /// Like `send_paste`, but first strips control characters other than
+    /// tab and newline and normalizes line endings to LF, so that pasted
+    /// escape sequences can't drive the terminal or the application.
+    pub fn send_paste_filtered(
+        &mut self,
+        text: &str,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let text: String =
+            text.chars().filter(|&c| c == '\t' || c == '\n' || !c.is_control()).collect();
+        self.write_paste(&text, writer)
+    }
+
+    fn write_paste(&mut self, text: &str, writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
         if self.bracketed_paste {
             let buf = format!("\x1b[200~{}\x1b[201~", text);
-            writer.write_all(buf.as_bytes())?;
+            self.write_input(buf.as_bytes(), writer)
         } else {
-            writer.write_all(text.as_bytes())?;
+            self.write_input(text.as_bytes(), writer)
+        }
+    }
+
+    /// Writes `bytes` to the pty, or queues them behind an unfinished
+    /// paste. Large input writes its first chunk and queues the rest.
+    fn write_input(&mut self, bytes: &[u8], writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        if self.pending_input.is_empty() && bytes.len() <= PASTE_CHUNK_SIZE {
+            writer.write_all(bytes)?;
+            return Ok(());
+        }
+        let idle = self.pending_input.is_empty();
+        self.pending_input.extend(bytes);
+        if idle {
+            self.flush_pending_input(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the next chunk of queued input; returns true while more
+    /// remains. The queue is dropped if the write fails, since a pty
+    /// that can't take this chunk won't take the rest either.
+    pub fn flush_pending_input(&mut self, writer: &mut dyn std::io::Write) -> anyhow::Result<bool> {
+        let len = self.pending_input.len().min(PASTE_CHUNK_SIZE);
+        let chunk: Vec<u8> = self.pending_input.drain(..len).collect();
+        if let Err(err) = writer.write_all(&chunk) {
+            self.pending_input.clear();
+            return Err(err.into());
+        }
+        Ok(!self.pending_input.is_empty())
+    }
+
+    /// Answers a query from the host, after any input still queued.
+    fn reply(&mut self, reply: &str, writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        self.write_input(reply.as_bytes(), writer)
+    }
+
+    pub fn bracketed_paste_enabled(&self) -> bool {
+        self.bracketed_paste
+    }
+
+    pub fn has_pending_input(&self) -> bool {
+        !self.pending_input.is_empty()
+    }
+
+    /// Writes text committed by an input method; unlike a paste it is
+    /// never bracketed. Otherwise it is treated like typing the text.
+    pub fn send_ime_commit(
+        &mut self,
+        text: &str,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        self.write_input(text.as_bytes(), writer)?;
+
+        if !text.is_empty() && self.viewport_offset != 0 {
+            self.set_scroll_viewport(0);
+        }
+        if self.predictive_echo {
+            for c in text.chars() {
+                self.predict_key(KeyCode::Char(c), KeyModifiers::NONE);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `bytes` to the pty verbatim; unlike a paste they are never
+    /// bracketed or filtered.
+    pub fn send_bytes(
+        &mut self,
+        bytes: &[u8],
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        if !self.read_only {
+            self.write_input(bytes, writer)?;
         }
         Ok(())
     }
@@ -668,12 +1329,36 @@ impl TerminalState {
         let mut buf = String::new();
 
         let to_send = match (key, ctrl, alt, shift, self.application_cursor_keys) {
+            (Char(c), CTRL, ..) if c == ' ' || c == '@' => match self.ctrl_space_sends {
+                CtrlSpaceSends::Nul => {
+                    if alt == ALT {
+                        buf.push(0x1b as char);
+                    }
+                    buf.push('\0');
+                    buf.as_str()
+                }
+                CtrlSpaceSends::CsiU => {
+                    // `@` is usually typed with shift, which is part of the key
+                    let mut modifier = 5;
+                    if alt == ALT {
+                        modifier += 2;
+                    }
+                    if shift == SHIFT && c == ' ' {
+                        modifier += 1;
+                    }
+                    write!(buf, "\x1b[{};{}u", c as u32, modifier)?;
+                    buf.as_str()
+                }
+            },
             (Char(c), _, ALT, ..) if c.is_ascii_alphanumeric() || c.is_ascii_punctuation() => {
                 buf.push(0x1b as char);
                 buf.push(c);
                 buf.as_str()
             }
-            (Backspace, _, ALT, ..) => "\x1b\x08",
+            (Backspace, _, ALT, ..) => match self.backspace_sends {
+                BackspaceSends::Delete => "\x1b\x7f",
+                BackspaceSends::BackspaceCtrlH => "\x1b\x08",
+            },
             (UpArrow, _, ALT, ..) => "\x1b\x1b[A",
             (DownArrow, _, ALT, ..) => "\x1b\x1b[B",
             (RightArrow, _, ALT, ..) => "\x1b\x1b[C",
@@ -681,11 +1366,16 @@ impl TerminalState {
 
             (Tab, ..) => "\t",
             (Enter, ..) => "\r",
-            (Backspace, ..) => "\x08",
+            (Backspace, ..) => match self.backspace_sends {
+                BackspaceSends::Delete => "\x7f",
+                BackspaceSends::BackspaceCtrlH => "\x08",
+            },
             (Escape, ..) => "\x1b",
 
-            (Char('\x7f'), _, _, _, false) | (Delete, _, _, _, false) => "\x7f",
-            (Char('\x7f'), _, _, _, true) | (Delete, _, _, _, true) => "\x7f",
+            (Char('\x7f'), ..) | (Delete, ..) => match self.delete_sends {
+                DeleteSends::Delete => "\x7f",
+                DeleteSends::EscapeSequence => "\x1b[3~",
+            },
 
             (Char(c), CTRL, _, SHIFT, _) if c <= 0xff as char && c > 0x40 as char => {
                 buf.push((c as u8 - 0x40) as char);
@@ -773,6 +1463,7 @@ impl TerminalState {
                 }
             }
 
+            // DECKPAM only changes the keypad; the arrows follow DECCKM above
             (Numpad0, ..)
             | (Numpad1, ..)
             | (Numpad2, ..)
@@ -788,7 +1479,33 @@ impl TerminalState {
             | (Separator, ..)
             | (Subtract, ..)
             | (Decimal, ..)
-            | (Divide, ..) => "",
+            | (Divide, ..) => {
+                let (normal, application) = match key {
+                    Numpad0 => ('0', 'p'),
+                    Numpad1 => ('1', 'q'),
+                    Numpad2 => ('2', 'r'),
+                    Numpad3 => ('3', 's'),
+                    Numpad4 => ('4', 't'),
+                    Numpad5 => ('5', 'u'),
+                    Numpad6 => ('6', 'v'),
+                    Numpad7 => ('7', 'w'),
+                    Numpad8 => ('8', 'x'),
+                    Numpad9 => ('9', 'y'),
+                    Multiply => ('*', 'j'),
+                    Add => ('+', 'k'),
+                    Separator => (',', 'l'),
+                    Subtract => ('-', 'm'),
+                    Decimal => ('.', 'n'),
+                    _ => ('/', 'o'),
+                };
+                if self.application_keypad {
+                    buf.push_str("\x1bO");
+                    buf.push(application);
+                } else {
+                    buf.push(normal);
+                }
+                buf.as_str()
+            }
 
             (Control, ..)
             | (LeftControl, ..)
@@ -839,36 +1556,194 @@ impl TerminalState {
             | (InternalPasteEnd, ..) => "",
         };
 
-        writer.write_all(to_send.as_bytes())?;
+        if self.read_only {
+            return Ok(());
+        }
+
+        self.write_input(to_send.as_bytes(), writer)?;
 
         if !to_send.is_empty() && self.viewport_offset != 0 {
             self.set_scroll_viewport(0);
         }
+        if !to_send.is_empty() && self.predictive_echo {
+            self.predict_key(key, mods);
+        }
 
         Ok(())
     }
 
-    pub fn resize(
-        &mut self,
-        physical_rows: usize,
-        physical_cols: usize,
-        pixel_width: usize,
-        pixel_height: usize,
-    ) {
-        self.screen.resize(physical_rows, physical_cols);
-        self.scroll_region = 0..physical_rows as i64;
-        self.pixel_height = pixel_height;
-        self.pixel_width = pixel_width;
-        self.tabs.resize(physical_cols);
-        self.set_scroll_viewport(0);
-
-        self.set_cursor_pos(&Position::Relative(0), &Position::Relative(0));
+    fn cell_at(&self, x: usize, y: VisibleRowIndex) -> Option<&Cell> {
+        let screen = self.screen();
+        screen.lines[screen.phys_row(y)].cells().get(x)
+    }
+
+    fn predict_key(&mut self, key: KeyCode, mods: KeyModifiers) {
+        if self.predictions_blocked || self.screen.alt_screen_is_active {
+            return;
+        }
+        match key {
+            KeyCode::Char(c)
+                if !c.is_control() && !mods.intersects(KeyModifiers::CTRL | KeyModifiers::ALT) =>
+            {
+                let (x, y) = (self.cursor.x, self.cursor.y);
+                let mut attrs = self.pen.clone_sgr_only();
+                attrs.set_predicted(true);
+                let cell = Cell::new(c, attrs);
+                // Leave wrapping and wide characters to the host
+                if self.wrap_next || x + 1 >= self.screen().physical_cols || cell.width() != 1 {
+                    self.predictions_blocked = true;
+                    return;
+                }
+                let original = match self.cell_at(x, y) {
+                    Some(original) => original.clone(),
+                    None => return,
+                };
+                self.screen_mut().set_cell(x, y, &cell);
+                self.predictions.push(Prediction { x, y, text: c, original });
+                self.cursor.x += 1;
+            }
+            KeyCode::Backspace if mods.is_empty() => match self.predictions.last() {
+                Some(last) if last.x + 1 == self.cursor.x && last.y == self.cursor.y => {
+                    let last = self.predictions.pop().unwrap();
+                    self.screen_mut().set_cell(last.x, last.y, &last.original);
+                    self.cursor.x = last.x;
+                }
+                _ => self.predictions_blocked = true,
+            },
+            _ => self.predictions_blocked = true,
+        }
+    }
+
+    /// Puts back the cells and cursor as the host last left them so that
+    /// its output is applied to the authoritative screen.
+    pub(crate) fn undo_predictions(&mut self) {
+        if let Some(first) = self.predictions.first() {
+            self.cursor = CursorPosition { x: first.x, y: first.y };
+        }
+        let predictions = std::mem::replace(&mut self.predictions, vec![]);
+        for prediction in predictions.iter().rev() {
+            self.screen_mut().set_cell(prediction.x, prediction.y, &prediction.original);
+        }
+        self.predictions = predictions;
+    }
+
+    fn discard_predictions(&mut self) {
+        self.undo_predictions();
+        self.predictions.clear();
+        self.predictions_blocked = false;
+    }
+
+    /// Drops the predictions the host has echoed and re-applies the ones it
+    /// hasn't got to yet; anything else means the guess was wrong and the
+    /// remaining predictions stay rolled back.
+    pub(crate) fn reconcile_predictions(&mut self) {
+        let predictions = std::mem::replace(&mut self.predictions, vec![]);
+        let mut pending = predictions.into_iter().peekable();
+
+        while let Some(prediction) = pending.peek() {
+            let at_cursor = self.cursor.x == prediction.x && self.cursor.y == prediction.y;
+            let echoed = self
+                .cell_at(prediction.x, prediction.y)
+                .map_or(false, |cell| cell.str().chars().eq(std::iter::once(prediction.text)));
+            if echoed && !at_cursor {
+                pending.next();
+            } else {
+                break;
+            }
+        }
+
+        match pending.peek() {
+            Some(next) if self.cursor.x == next.x && self.cursor.y == next.y => {
+                for prediction in pending {
+                    let (x, y) = (self.cursor.x, self.cursor.y);
+                    if x + 1 >= self.screen().physical_cols {
+                        break;
+                    }
+                    let original = match self.cell_at(x, y) {
+                        Some(original) => original.clone(),
+                        None => break,
+                    };
+                    let mut attrs = self.pen.clone_sgr_only();
+                    attrs.set_predicted(true);
+                    self.screen_mut().set_cell(x, y, &Cell::new(prediction.text, attrs));
+                    self.predictions.push(Prediction { original, ..prediction });
+                    self.cursor.x += 1;
+                }
+            }
+            _ => {}
+        }
+
+        if self.predictions.is_empty() {
+            self.predictions_blocked = false;
+        }
+    }
+
+    pub fn resize(
+        &mut self,
+        physical_rows: usize,
+        physical_cols: usize,
+        pixel_width: usize,
+        pixel_height: usize,
+    ) {
+        self.discard_predictions();
+        self.last_print = None;
+        self.cursor = self.screen.resize(physical_rows, physical_cols, self.cursor);
+        self.scroll_region = 0..physical_rows as i64;
+        self.pixel_height = pixel_height;
+        self.pixel_width = pixel_width;
+        self.cell_pixels = None;
+        self.tabs.resize(physical_cols);
+        self.set_scroll_viewport(0);
+
+        self.set_cursor_pos(&Position::Relative(0), &Position::Relative(0));
+    }
+
+    pub fn resize_pixels(
+        &mut self,
+        pixel_width: usize,
+        pixel_height: usize,
+        cell_width: usize,
+        cell_height: usize,
+    ) {
+        let cell_width = cell_width.max(1);
+        let cell_height = cell_height.max(1);
+        let physical_rows = (pixel_height / cell_height).max(1);
+        let physical_cols = (pixel_width / cell_width).max(1);
+        self.resize(physical_rows, physical_cols, pixel_width, pixel_height);
+        self.cell_pixels = Some((cell_width, cell_height));
+    }
+
+    fn is_output_synchronized(&self) -> bool {
+        match self.synchronized_output {
+            Some(start) => start.elapsed() < SYNCHRONIZED_OUTPUT_TIMEOUT,
+            None => false,
+        }
+    }
+
+    /// Yields `(row, col, cell)` for every cell in the viewport, without the
+    /// spacer cells that follow wide glyphs.
+    #[allow(dead_code)]
+    pub fn visible_cells(&self) -> impl Iterator<Item = (usize, usize, &Cell)> {
+        let screen = self.viewport_screen();
+        let height = screen.physical_rows;
+        let len = screen.lines.len() - self.viewport_offset as usize;
+
+        screen
+            .lines
+            .iter()
+            .skip(len - height)
+            .take(height)
+            .enumerate()
+            .flat_map(|(row, line)| line.visible_cells().map(move |(col, cell)| (row, col, cell)))
     }
 
     pub fn get_dirty_lines(&self) -> Vec<(usize, &Line, Range<usize>)> {
         let mut res = Vec::new();
+        if self.is_output_synchronized() {
+            return res;
+        }
 
-        let screen = self.screen();
+        let screen = self.viewport_screen();
         let height = screen.physical_rows;
         let len = screen.lines.len() - self.viewport_offset as usize;
 
@@ -894,18 +1769,26 @@ impl TerminalState {
         res
     }
 
+    pub fn has_dirty_lines(&self) -> bool {
+        if self.is_output_synchronized() {
+            return false;
+        }
+
+        let screen = self.viewport_screen();
+        let height = screen.physical_rows;
+        let len = screen.lines.len() - self.viewport_offset as usize;
+        screen.lines.iter().skip(len - height).take(height).any(|line| line.is_dirty())
+    }
+
     pub fn clean_dirty_lines(&mut self) {
-        let screen = self.screen_mut();
-        for line in &mut screen.lines {
-            line.clear_dirty();
+        if self.is_output_synchronized() {
+            return;
         }
+        self.viewport_screen_mut().lines.clear_all_dirty();
     }
 
     pub fn make_all_lines_dirty(&mut self) {
-        let screen = self.screen_mut();
-        for line in &mut screen.lines {
-            line.set_dirty();
-        }
+        self.screen_mut().lines.set_all_dirty();
     }
 
     pub fn physical_dimensions(&self) -> (usize, usize) {
@@ -913,6 +1796,22 @@ impl TerminalState {
         (screen.physical_rows, screen.physical_cols)
     }
 
+    /// The (width, height) of a cell in pixels, as last passed to
+    /// `resize_pixels` or else derived from the pixel and cell dimensions
+    /// passed to `resize`; (0, 0) if the pixel size of the window is not
+    /// known.
+    #[allow(dead_code)]
+    pub fn cell_pixel_size(&self) -> (usize, usize) {
+        self.known_cell_pixel_size().unwrap_or((0, 0))
+    }
+
+    /// Erases the visible screen using the current pen and discards the
+    /// scrollback, as `CSI 2J` followed by `CSI 3J` would.
+    pub fn clear_screen_and_scrollback(&mut self) {
+        self.erase_in_display(EraseInDisplay::EraseDisplay);
+        self.erase_in_display(EraseInDisplay::EraseScrollback);
+    }
+
     pub fn cursor_pos(&self) -> CursorPosition {
         CursorPosition { x: self.cursor.x, y: self.cursor.y + self.viewport_offset }
     }
@@ -932,9 +1831,9 @@ impl TerminalState {
         };
 
         let rows = self.screen().physical_rows;
-        let cols = self.screen().physical_cols;
         let old_y = self.cursor.y;
         let new_y = y.min(rows as i64 - 1);
+        let cols = self.line_columns(new_y);
 
         self.cursor.x = x.min(cols as i64 - 1) as usize;
         self.cursor.y = new_y;
@@ -945,26 +1844,97 @@ impl TerminalState {
         screen.dirty_line(new_y);
     }
 
+    /// The number of columns available on row `y`; a double width or
+    /// double height line holds half as many as the screen.
+    fn line_columns(&self, y: VisibleRowIndex) -> usize {
+        let screen = self.screen();
+        match screen.lines[screen.phys_row(y)].line_size() {
+            LineSize::Single => screen.physical_cols,
+            _ => (screen.physical_cols / 2).max(1),
+        }
+    }
+
+    fn set_line_size(&mut self, size: LineSize) {
+        let y = self.cursor.y;
+        let idx = self.screen().phys_row(y);
+        self.screen_mut().line_mut(idx).set_line_size(size);
+        self.cursor.x = self.cursor.x.min(self.line_columns(y) - 1);
+    }
+
     fn set_scroll_viewport(&mut self, position: VisibleRowIndex) {
         self.clear_selection();
         let position = position.max(0);
 
         let rows = self.screen().physical_rows;
-        let avail_scrollback = self.screen().lines.len() - rows;
+        let scrollback_len = if self.alt_screen_scrollback_enabled() {
+            self.screen.primary().lines.len()
+        } else {
+            self.screen().lines.len()
+        };
+        let avail_scrollback = scrollback_len - rows;
 
         let position = position.min(avail_scrollback as i64);
 
         self.viewport_offset = position;
-        let top = self.screen().lines.len() - (rows + position as usize);
         {
-            let screen = self.screen_mut();
+            let screen = self.viewport_screen_mut();
+            let top = screen.lines.len() - (rows + position as usize);
             for y in top..top + rows {
                 screen.line_mut(y).set_dirty();
             }
+            screen.pack_scrollback(top..top + rows);
         }
         self.recompute_highlight();
     }
 
+    /// Scrolls back so that the nearest prompt above the viewport, as marked
+    /// by `OSC 133 ; A`, is the top row.
+    pub fn scroll_to_prev_prompt(&mut self) {
+        let (top, scrollback) = self.viewport_top_and_scrollback();
+        let lines = &self.viewport_screen().lines;
+        if let Some(row) = (0..top).rev().find(|&idx| lines[idx].is_prompt()) {
+            self.set_scroll_viewport((scrollback - row) as VisibleRowIndex);
+        }
+    }
+
+    /// The counterpart of `scroll_to_prev_prompt`; scrolls to the bottom
+    /// when there are no later prompts.
+    pub fn scroll_to_next_prompt(&mut self) {
+        let (top, scrollback) = self.viewport_top_and_scrollback();
+        let lines = &self.viewport_screen().lines;
+        let row = (top + 1..lines.len()).find(|&idx| lines[idx].is_prompt());
+        self.set_scroll_viewport(
+            row.map(|row| scrollback as VisibleRowIndex - row as VisibleRowIndex).unwrap_or(0),
+        );
+    }
+
+    /// The index of the top visible row among all of the lines, and how
+    /// many lines there are, counting scrollback.
+    pub fn scroll_position(&self) -> (usize, usize) {
+        let (top, _) = self.viewport_top_and_scrollback();
+        (top, self.viewport_screen().lines.len())
+    }
+
+    /// The physical index of the top visible row and the number of lines
+    /// in scrollback.
+    fn viewport_top_and_scrollback(&self) -> (usize, usize) {
+        let screen = self.viewport_screen();
+        let scrollback = screen.lines.len() - screen.physical_rows;
+        (scrollback - self.viewport_offset as usize, scrollback)
+    }
+
+    /// A variable set by the application with iTerm2's `SetUserVar`.
+    #[allow(dead_code)]
+    pub fn get_user_var(&self, name: &str) -> Option<&str> {
+        self.user_vars.get(name).map(String::as_str)
+    }
+
+    /// The exit status reported by the most recent `OSC 133 ; D`.
+    #[allow(dead_code)]
+    pub fn last_command_status(&self) -> Option<i32> {
+        self.last_command_status
+    }
+
     pub fn scroll_viewport(&mut self, delta: VisibleRowIndex) {
         let position = self.viewport_offset - delta;
         self.set_scroll_viewport(position);
@@ -982,6 +1952,117 @@ impl TerminalState {
         self.screen_mut().scroll_down(&scroll_region, num_rows)
     }
 
+    fn print_graphemes(&mut self, text: &str) {
+        let mut x_offset = 0;
+
+        for g in unicode_segmentation::UnicodeSegmentation::graphemes(text, true) {
+            let g = if self.dec_line_drawing_mode {
+                match g {
+                    "j" => "┘",
+                    "k" => "┐",
+                    "l" => "┌",
+                    "m" => "└",
+                    "n" => "┼",
+                    "q" => "─",
+                    "t" => "├",
+                    "u" => "┤",
+                    "v" => "┴",
+                    "w" => "┬",
+                    "x" => "│",
+                    _ => g,
+                }
+            } else {
+                g
+            };
+
+            if unicode_column_width(g) == 0 && self.combine_with_last_print(g) {
+                continue;
+            }
+
+            if !self.insert && self.wrap_next {
+                self.new_line(true);
+            }
+
+            let print_width = unicode_column_width(g).max(1);
+
+            // Insert mode runs on past the margin; rather than growing the
+            // line without limit, carry on from the next one as a soft wrap
+            if self.cursor.x + x_offset + print_width > self.screen().max_line_length {
+                let y = self.cursor.y;
+                let screen = self.screen_mut();
+                let idx = screen.phys_row(y);
+                screen.line_mut(idx).set_last_cell_was_wrapped(true);
+                self.new_line(true);
+                x_offset = 0;
+            }
+
+            let x = self.cursor.x;
+            let y = self.cursor.y;
+            let width = self.line_columns(y);
+
+            let mut pen = self.pen.clone();
+
+            if !self.insert && self.dec_auto_wrap && x + print_width >= width {
+                pen.set_wrapped(true);
+            }
+
+            let cell = Cell::new_grapheme(g, pen);
+            self.last_graphic = Some(g.to_string());
+
+            if self.insert {
+                let screen = self.screen_mut();
+                for _ in x..x + print_width as usize {
+                    screen.insert_cell(x + x_offset, y, &Cell::default());
+                }
+            }
+
+            self.screen_mut().set_cell(x + x_offset, y, &cell);
+            self.last_print = Some((x + x_offset, y));
+
+            self.clear_selection_if_intersects(
+                x..x + print_width,
+                y as ScrollbackOrVisibleRowIndex,
+            );
+
+            if self.insert {
+                x_offset += print_width;
+            } else if x + print_width < width {
+                self.cursor.x += print_width;
+                self.wrap_next = false;
+            } else {
+                self.wrap_next = self.dec_auto_wrap;
+            }
+        }
+    }
+
+    /// Appends a zero-width grapheme that has no base of its own to the
+    /// last printed cell; returns false if there is nothing to attach to.
+    fn combine_with_last_print(&mut self, g: &str) -> bool {
+        let (x, y) = match self.last_print {
+            Some(pos) => pos,
+            None => return false,
+        };
+        let screen = self.screen_mut();
+        let line_idx = screen.phys_row(y);
+        let cell = match screen.line_mut(line_idx).cells().get(x) {
+            Some(cell) => cell,
+            None => return false,
+        };
+        let cell = Cell::new_grapheme(&format!("{}{}", cell.str(), g), cell.attrs().clone());
+        screen.set_cell(x, y, &cell);
+        true
+    }
+
+    /// REP: prints the last graphic character `n` more times, bounded by
+    /// what could possibly fit on the screen.
+    fn repeat_last_graphic(&mut self, n: u32) {
+        if let Some(g) = self.last_graphic.clone() {
+            let (rows, cols) = self.physical_dimensions();
+            let n = (n as usize).min(rows * cols);
+            self.print_graphemes(&g.repeat(n));
+        }
+    }
+
     fn new_line(&mut self, move_to_first_column: bool) {
         let x = if move_to_first_column { 0 } else { self.cursor.x };
         let y = self.cursor.y;
@@ -1018,6 +2099,12 @@ impl TerminalState {
             Some(x) => x,
             None => self.screen().physical_cols - 1,
         };
+        let (from, y) = (self.cursor.x, self.cursor.y);
+        {
+            let screen = self.screen_mut();
+            let idx = screen.phys_row(y);
+            screen.line_mut(idx).mark_tab_gap(from..x);
+        }
         self.set_cursor_pos(&Position::Absolute(x as i64), &Position::Relative(0));
     }
 
@@ -1032,6 +2119,34 @@ impl TerminalState {
         self.set_cursor_pos(&Position::Relative(0), &Position::Absolute(y as i64));
     }
 
+    fn dec_forward_index(&mut self) {
+        if self.cursor.x + 1 < self.screen().physical_cols {
+            self.set_cursor_pos(&Position::Relative(1), &Position::Relative(0));
+            return;
+        }
+        let region = self.scroll_region.clone();
+        for y in region.clone() {
+            self.screen_mut().erase_cell(0, y, &Cell::default());
+        }
+        self.clear_selection_if_intersects_rows(
+            region.start as ScrollbackOrVisibleRowIndex..region.end as ScrollbackOrVisibleRowIndex,
+        );
+    }
+
+    fn dec_back_index(&mut self) {
+        if self.cursor.x > 0 {
+            self.set_cursor_pos(&Position::Relative(-1), &Position::Relative(0));
+            return;
+        }
+        let region = self.scroll_region.clone();
+        for y in region.clone() {
+            self.screen_mut().insert_cell(0, y, &Cell::default());
+        }
+        self.clear_selection_if_intersects_rows(
+            region.start as ScrollbackOrVisibleRowIndex..region.end as ScrollbackOrVisibleRowIndex,
+        );
+    }
+
     fn set_hyperlink(&mut self, link: Option<Hyperlink>) {
         self.pen.hyperlink = match link {
             Some(hyperlink) => Some(Arc::new(hyperlink)),
@@ -1039,24 +2154,56 @@ impl TerminalState {
         }
     }
 
+    fn answer_enquiry(&mut self, host: &mut dyn TerminalHost) {
+        if !self.answerback.is_empty() {
+            let answerback = self.answerback.clone();
+            self.reply(&answerback, host.writer()).ok();
+        }
+    }
+
     fn perform_device(&mut self, dev: Device, host: &mut dyn TerminalHost) {
         match dev {
             Device::DeviceAttributes(_) => {}
-            Device::SoftReset => {
-                self.pen = CellAttributes::default();
-            }
+            Device::SoftReset => self.soft_reset(),
             Device::RequestPrimaryDeviceAttributes => {
-                host.writer().write(DEVICE_IDENT).ok();
+                let flags = DeviceAttributeFlags {
+                    attributes: DEVICE_ATTRIBUTES
+                        .iter()
+                        .cloned()
+                        .map(DeviceAttribute::Code)
+                        .collect(),
+                };
+                let reply = Device::DeviceAttributes(DeviceAttributes::Vt220(flags));
+                self.reply(&CSI::Device(Box::new(reply)).to_string(), host.writer()).ok();
             }
             Device::RequestSecondaryDeviceAttributes => {
-                host.writer().write(b"\x1b[>0;0;0c").ok();
+                self.reply("\x1b[>0;0;0c", host.writer()).ok();
+            }
+            Device::RequestTerminalNameAndVersion => {
+                let reply = format!("\x1bP>|miro({})\x1b\\", env!("CARGO_PKG_VERSION"));
+                self.reply(&reply, host.writer()).ok();
             }
             Device::StatusReport => {
-                host.writer().write(b"\x1b[0n").ok();
+                self.reply("\x1b[0n", host.writer()).ok();
             }
         }
     }
 
+    /// DECSTR, as described for the VT510. Mouse reporting, bracketed
+    /// paste and the screen contents are deliberately left alone.
+    fn soft_reset(&mut self) {
+        self.cursor_visible = true;
+        self.insert = false;
+        self.dec_auto_wrap = true;
+        self.wrap_next = false;
+        self.application_cursor_keys = false;
+        self.application_keypad = false;
+        self.dec_line_drawing_mode = false;
+        self.scroll_region = 0..self.screen().physical_rows as VisibleRowIndex;
+        self.pen = CellAttributes::default();
+        *self.screen.saved_cursor() = None;
+    }
+
     fn perform_csi_mode(&mut self, mode: Mode) {
         match mode {
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
@@ -1080,6 +2227,17 @@ impl TerminalState {
                 self.bracketed_paste = false;
             }
 
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::SynchronizedOutput,
+            )) => {
+                self.synchronized_output = Some(Instant::now());
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::SynchronizedOutput,
+            )) => {
+                self.synchronized_output = None;
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::EnableAlternateScreen,
             )) => {
@@ -1097,6 +2255,24 @@ impl TerminalState {
                 }
             }
 
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::EnableAlternateScreenClearOnExit,
+            )) => {
+                if !self.screen.is_alt_screen_active() {
+                    self.screen.activate_alt_screen();
+                    self.set_scroll_viewport(0);
+                }
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::EnableAlternateScreenClearOnExit,
+            )) => {
+                if self.screen.is_alt_screen_active() {
+                    self.erase_in_display(EraseInDisplay::EraseDisplay);
+                    self.screen.activate_primary_screen();
+                    self.set_scroll_viewport(0);
+                }
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::ApplicationCursorKeys,
             )) => {
@@ -1108,6 +2284,13 @@ impl TerminalState {
                 self.application_cursor_keys = false;
             }
 
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AutoWrap)) => {
+                self.set_auto_wrap(true);
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AutoWrap)) => {
+                self.set_auto_wrap(false);
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ShowCursor)) => {
                 self.cursor_visible = true;
             }
@@ -1139,6 +2322,13 @@ impl TerminalState {
             | Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AnyEventMouse)) => {
             }
 
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::FocusTracking)) => {
+                self.focus_tracking = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::FocusTracking)) => {
+                self.focus_tracking = false;
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRMouse)) => {
                 self.sgr_mouse = true;
             }
@@ -1146,6 +2336,13 @@ impl TerminalState {
                 self.sgr_mouse = false;
             }
 
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRPixelsMouse)) => {
+                self.sgr_pixels_mouse = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRPixelsMouse)) => {
+                self.sgr_pixels_mouse = false;
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::ClearAndEnableAlternateScreen,
             )) => {
@@ -1199,45 +2396,193 @@ impl TerminalState {
         checksum
     }
 
-    fn perform_csi_window(&mut self, window: Window, host: &mut dyn TerminalHost) {
-        match window {
-            Window::ReportTextAreaSizeCells => {
-                let screen = self.screen();
-                let height = Some(screen.physical_rows as i64);
-                let width = Some(screen.physical_cols as i64);
+    fn push_window_title(&mut self) {
+        if self.title_stack.len() == MAX_TITLE_STACK {
+            self.title_stack.remove(0);
+        }
+        self.title_stack.push(self.title.clone());
+    }
 
-                let response = Window::ResizeWindowCells { width, height };
-                write!(host.writer(), "{}", CSI::Window(response)).ok();
-            }
-            Window::ChecksumRectangularArea { request_id, top, left, bottom, right, .. } => {
-                let checksum = self.checksum_rectangle(
-                    left.as_zero_based(),
-                    top.as_zero_based(),
-                    right.as_zero_based(),
-                    bottom.as_zero_based(),
-                );
-                write!(host.writer(), "\x1bP{}!~{:04x}\x1b\\", request_id, checksum).ok();
-            }
-            Window::Iconify | Window::DeIconify => {}
-            Window::PopIconAndWindowTitle
-            | Window::PopWindowTitle
-            | Window::PopIconTitle
-            | Window::PushIconAndWindowTitle
-            | Window::PushIconTitle
-            | Window::PushWindowTitle => {}
-            _ => {}
+    fn push_icon_title(&mut self) {
+        if self.icon_title_stack.len() == MAX_TITLE_STACK {
+            self.icon_title_stack.remove(0);
         }
+        self.icon_title_stack.push(self.icon_title.clone());
     }
 
-    fn erase_in_display(&mut self, erase: EraseInDisplay) {
-        let cy = self.cursor.y;
-        let pen = self.pen.clone_sgr_only();
-        let rows = self.screen().physical_rows as VisibleRowIndex;
-        let col_range = 0..usize::max_value();
-        let row_range = match erase {
-            EraseInDisplay::EraseToEndOfDisplay => {
-                self.perform_csi_edit(Edit::EraseInLine(EraseInLine::EraseToEndOfLine));
-                cy + 1..rows
+    fn pop_window_title(&mut self, host: &mut dyn TerminalHost) {
+        if let Some(title) = self.title_stack.pop() {
+            host.set_title(&title);
+            self.title = title;
+        }
+    }
+
+    fn pop_icon_title(&mut self) {
+        if let Some(title) = self.icon_title_stack.pop() {
+            self.icon_title = title;
+        }
+    }
+
+    fn known_cell_pixel_size(&self) -> Option<(usize, usize)> {
+        if self.pixel_width == 0 || self.pixel_height == 0 {
+            return None;
+        }
+        if let Some(cell_pixels) = self.cell_pixels {
+            return Some(cell_pixels);
+        }
+        let (rows, cols) = self.physical_dimensions();
+        Some(((self.pixel_width / cols).max(1), (self.pixel_height / rows).max(1)))
+    }
+
+    fn set_title_modes(&mut self, modes: &[i64], enable: bool) {
+        if modes.is_empty() {
+            self.set_title_hex = false;
+            self.report_title_hex = false;
+        }
+        for mode in modes {
+            match mode {
+                0 => self.set_title_hex = enable,
+                1 => self.report_title_hex = enable,
+                // Titles are always UTF-8
+                _ => {}
+            }
+        }
+    }
+
+    /// Undoes the hex encoding of title mode 0, leaving titles that aren't
+    /// valid hex as they are.
+    fn decode_title(&self, title: String) -> String {
+        if !self.set_title_hex || title.len() % 2 != 0 {
+            return title;
+        }
+        let bytes: Option<Vec<u8>> = (0..title.len())
+            .step_by(2)
+            .map(|idx| title.get(idx..idx + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+            .collect();
+        match bytes {
+            Some(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            None => title,
+        }
+    }
+
+    fn encode_title(&self, title: &str) -> String {
+        if self.report_title_hex {
+            title.bytes().map(|b| format!("{:02X}", b)).collect()
+        } else {
+            title.to_string()
+        }
+    }
+
+    fn perform_csi_window(&mut self, window: Window, host: &mut dyn TerminalHost) {
+        match window {
+            Window::SetTitleModes(modes) => return self.set_title_modes(&modes, true),
+            Window::ResetTitleModes(modes) => return self.set_title_modes(&modes, false),
+            _ => {}
+        }
+        let is_size_report = match window {
+            Window::ReportTextAreaSizeCells
+            | Window::ReportTextAreaSizePixels
+            | Window::ReportCellSizePixels
+            | Window::ChecksumRectangularArea { .. } => true,
+            _ => false,
+        };
+        if self.allow_window_ops == AllowWindowOps::ReportsOnly && !is_size_report {
+            return;
+        }
+
+        match window {
+            Window::ReportTextAreaSizeCells => {
+                let screen = self.screen();
+                let height = Some(screen.physical_rows as i64);
+                let width = Some(screen.physical_cols as i64);
+
+                let response = Window::ResizeWindowCells { width, height };
+                self.reply(&CSI::Window(response).to_string(), host.writer()).ok();
+            }
+            Window::ChecksumRectangularArea { request_id, top, left, bottom, right, .. } => {
+                let checksum = self.checksum_rectangle(
+                    left.as_zero_based(),
+                    top.as_zero_based(),
+                    right.as_zero_based(),
+                    bottom.as_zero_based(),
+                );
+                let reply = format!("\x1bP{}!~{:04x}\x1b\\", request_id, checksum);
+                self.reply(&reply, host.writer()).ok();
+            }
+            Window::ResizeWindowCells { width, height } => self.request_resize(height, width),
+            Window::ReportTextAreaSizePixels => {
+                if let Some((cell_width, cell_height)) = self.known_cell_pixel_size() {
+                    let (rows, cols) = self.physical_dimensions();
+                    let response = Window::ResizeWindowPixels {
+                        width: Some((cols * cell_width) as i64),
+                        height: Some((rows * cell_height) as i64),
+                    };
+                    self.reply(&CSI::Window(response).to_string(), host.writer()).ok();
+                }
+            }
+            Window::ReportCellSizePixels => {
+                if let Some((cell_width, cell_height)) = self.known_cell_pixel_size() {
+                    let reply = format!("\x1b[6;{};{}t", cell_height, cell_width);
+                    self.reply(&reply, host.writer()).ok();
+                }
+            }
+            Window::ResizeWindowPixels { width, height } => {
+                if let Some((cell_width, cell_height)) = self.known_cell_pixel_size() {
+                    self.request_resize(
+                        height.map(|h| h / cell_height as i64),
+                        width.map(|w| w / cell_width as i64),
+                    );
+                }
+            }
+            Window::ReportWindowPosition => self.position_reports.push(PositionReport::Window),
+            Window::ReportTextAreaPosition => self.position_reports.push(PositionReport::TextArea),
+            Window::Iconify | Window::DeIconify => {}
+            Window::PushIconAndWindowTitle => {
+                self.push_window_title();
+                self.push_icon_title();
+            }
+            Window::PushWindowTitle => self.push_window_title(),
+            Window::PushIconTitle => self.push_icon_title(),
+            Window::PopIconAndWindowTitle => {
+                self.pop_window_title(host);
+                self.pop_icon_title();
+            }
+            Window::PopWindowTitle => self.pop_window_title(host),
+            Window::PopIconTitle => self.pop_icon_title(),
+            Window::ReportIconLabel => {
+                if self.allow_window_ops == AllowWindowOps::All {
+                    let label = self.encode_title(&self.icon_title);
+                    self.reply(&format!("\x1b]L{}\x1b\\", label), host.writer()).ok();
+                }
+            }
+            Window::ReportWindowTitle => {
+                if self.allow_window_ops == AllowWindowOps::All {
+                    let title = self.encode_title(&self.title);
+                    self.reply(&format!("\x1b]l{}\x1b\\", title), host.writer()).ok();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Stops row `y` from continuing onto the row below it.
+    fn break_wrap(&mut self, y: VisibleRowIndex) {
+        if y >= 0 && y < self.screen().physical_rows as VisibleRowIndex {
+            let screen = self.screen_mut();
+            let idx = screen.phys_row(y);
+            screen.line_mut(idx).set_last_cell_was_wrapped(false);
+        }
+    }
+
+    fn erase_in_display(&mut self, erase: EraseInDisplay) {
+        let cy = self.cursor.y;
+        let pen = self.erase_pen();
+        let rows = self.screen().physical_rows as VisibleRowIndex;
+        let col_range = 0..usize::max_value();
+        let row_range = match erase {
+            EraseInDisplay::EraseToEndOfDisplay => {
+                self.perform_csi_edit(Edit::EraseInLine(EraseInLine::EraseToEndOfLine));
+                cy + 1..rows
             }
             EraseInDisplay::EraseToStartOfDisplay => {
                 self.perform_csi_edit(Edit::EraseInLine(EraseInLine::EraseToStartOfLine));
@@ -1245,6 +2590,8 @@ impl TerminalState {
             }
             EraseInDisplay::EraseDisplay => 0..rows,
             EraseInDisplay::EraseScrollback => {
+                self.screen_mut().erase_scrollback();
+                self.set_scroll_viewport(0);
                 return;
             }
         };
@@ -1265,24 +2612,76 @@ impl TerminalState {
         }
     }
 
+    /// The columns and rows of a DEC rectangular area, clipped to the
+    /// screen, or `None` when nothing of it is left.
+    fn rectangle(
+        &self,
+        top: OneBased,
+        left: OneBased,
+        bottom: OneBased,
+        right: OneBased,
+    ) -> Option<(Range<usize>, Range<VisibleRowIndex>)> {
+        let screen = self.screen();
+        let top = top.as_zero_based() as usize;
+        let left = left.as_zero_based() as usize;
+        let bottom = (bottom.as_zero_based() as usize).min(screen.physical_rows - 1);
+        let right = (right.as_zero_based() as usize).min(screen.physical_cols - 1);
+        if top > bottom || left > right {
+            return None;
+        }
+        Some((left..right + 1, top as VisibleRowIndex..bottom as VisibleRowIndex + 1))
+    }
+
+    fn fill_rectangle(&mut self, cols: Range<usize>, rows: Range<VisibleRowIndex>, cell: &Cell) {
+        {
+            let screen = self.screen_mut();
+            for y in rows.clone() {
+                for x in cols.clone() {
+                    screen.set_cell(x, y, cell);
+                }
+            }
+        }
+        self.clear_selection_if_intersects_rows(
+            rows.start as ScrollbackOrVisibleRowIndex..rows.end as ScrollbackOrVisibleRowIndex,
+        );
+    }
+
     fn perform_csi_edit(&mut self, edit: Edit) {
         match edit {
+            Edit::EraseRectangularArea { top, left, bottom, right } => {
+                if let Some((cols, rows)) = self.rectangle(top, left, bottom, right) {
+                    let blank = Cell::new(' ', self.erase_pen());
+                    self.fill_rectangle(cols, rows, &blank);
+                }
+            }
+            Edit::FillRectangularArea { ch, top, left, bottom, right } => {
+                if let Some((cols, rows)) = self.rectangle(top, left, bottom, right) {
+                    let cell = Cell::new(ch, self.pen.clone_sgr_only());
+                    self.fill_rectangle(cols, rows, &cell);
+                }
+            }
             Edit::DeleteCharacter(n) => {
                 let y = self.cursor.y;
                 let x = self.cursor.x;
-                let limit = (x + n as usize).min(self.screen().physical_cols);
+                let cols = self.screen().physical_cols;
+                let limit = (x + n as usize).min(cols);
                 {
+                    let blank = Cell::new(' ', self.erase_pen());
                     let screen = self.screen_mut();
                     for _ in x..limit as usize {
-                        screen.erase_cell(x, y);
+                        screen.erase_cell(x, y, &blank);
                     }
                 }
-                self.clear_selection_if_intersects(x..limit, y as ScrollbackOrVisibleRowIndex);
+                self.clear_selection_if_intersects(x..cols, y as ScrollbackOrVisibleRowIndex);
             }
             Edit::DeleteLine(n) => {
                 if self.scroll_region.contains(&self.cursor.y) {
                     let scroll_region = self.cursor.y..self.scroll_region.end;
                     self.screen_mut().scroll_up(&scroll_region, n as usize);
+                    // Lines now next to different neighbours must not join them
+                    let moved = (scroll_region.end - scroll_region.start - n as i64).max(0);
+                    self.break_wrap(scroll_region.start - 1);
+                    self.break_wrap(scroll_region.start + moved - 1);
 
                     let scrollback_region = self.cursor.y as ScrollbackOrVisibleRowIndex
                         ..self.scroll_region.end as ScrollbackOrVisibleRowIndex;
@@ -1294,7 +2693,7 @@ impl TerminalState {
                 let x = self.cursor.x;
                 let limit = (x + n as usize).min(self.screen().physical_cols);
                 {
-                    let blank = Cell::new(' ', self.pen.clone_sgr_only());
+                    let blank = Cell::new(' ', self.erase_pen());
                     let screen = self.screen_mut();
                     for x in x..limit as usize {
                         screen.set_cell(x, y, &blank);
@@ -1306,7 +2705,7 @@ impl TerminalState {
             Edit::EraseInLine(erase) => {
                 let cx = self.cursor.x;
                 let cy = self.cursor.y;
-                let pen = self.pen.clone_sgr_only();
+                let pen = self.erase_pen();
                 let cols = self.screen().physical_cols;
                 let range = match erase {
                     EraseInLine::EraseToEndOfLine => cx..cols,
@@ -1321,19 +2720,23 @@ impl TerminalState {
                 let y = self.cursor.y;
                 let x = self.cursor.x;
 
-                let limit = (x + n as usize).min(self.screen().physical_cols);
+                let cols = self.screen().physical_cols;
+                let limit = (x + n as usize).min(cols);
                 {
+                    let blank = Cell::new(' ', self.erase_pen());
                     let screen = self.screen_mut();
                     for x in x..limit as usize {
-                        screen.insert_cell(x, y);
+                        screen.insert_cell(x, y, &blank);
                     }
                 }
-                self.clear_selection_if_intersects(x..limit, y as ScrollbackOrVisibleRowIndex);
+                self.clear_selection_if_intersects(x..cols, y as ScrollbackOrVisibleRowIndex);
             }
             Edit::InsertLine(n) => {
                 if self.scroll_region.contains(&self.cursor.y) {
                     let scroll_region = self.cursor.y..self.scroll_region.end;
                     self.screen_mut().scroll_down(&scroll_region, n as usize);
+                    self.break_wrap(scroll_region.start - 1);
+                    self.break_wrap(scroll_region.end - 1);
 
                     let scrollback_region = self.cursor.y as ScrollbackOrVisibleRowIndex
                         ..self.scroll_region.end as ScrollbackOrVisibleRowIndex;
@@ -1343,19 +2746,60 @@ impl TerminalState {
             Edit::ScrollDown(n) => self.scroll_down(n as usize),
             Edit::ScrollUp(n) => self.scroll_up(n as usize),
             Edit::EraseInDisplay(erase) => self.erase_in_display(erase),
-            Edit::Repeat(n) => {
-                let y = self.cursor.y;
-                let x = self.cursor.x;
-                let to_copy = x.saturating_sub(1);
-                let screen = self.screen_mut();
-                let line_idx = screen.phys_row(y);
-                let line = screen.line_mut(line_idx);
-                if let Some(cell) = line.cells().get(to_copy).cloned() {
-                    line.fill_range(x..=x + n as usize, &cell);
-                    self.set_cursor_pos(&Position::Relative(i64::from(n)), &Position::Relative(0))
+            Edit::Repeat(n) => self.repeat_last_graphic(n),
+            Edit::SelectiveEraseInLine(erase) => {
+                let cx = self.cursor.x;
+                let cols = self.screen().physical_cols;
+                let range = match erase {
+                    EraseInLine::EraseToEndOfLine => cx..cols,
+                    EraseInLine::EraseToStartOfLine => 0..cx,
+                    EraseInLine::EraseLine => 0..cols,
+                };
+                self.selective_erase(self.cursor.y, range);
+            }
+            Edit::SelectiveEraseInDisplay(erase) => {
+                let cx = self.cursor.x;
+                let cy = self.cursor.y;
+                let rows = self.screen().physical_rows as VisibleRowIndex;
+                let cols = self.screen().physical_cols;
+                let row_range = match erase {
+                    EraseInDisplay::EraseToEndOfDisplay => {
+                        self.selective_erase(cy, cx..cols);
+                        cy + 1..rows
+                    }
+                    EraseInDisplay::EraseToStartOfDisplay => {
+                        self.selective_erase(cy, 0..cx);
+                        0..cy
+                    }
+                    EraseInDisplay::EraseDisplay => 0..rows,
+                    EraseInDisplay::EraseScrollback => return,
+                };
+                for y in row_range {
+                    self.selective_erase(y, 0..cols);
                 }
             }
+            Edit::CharacterProtection(protected) => {
+                self.pen.set_protected(protected);
+            }
+        }
+    }
+
+    /// Blanks the cells of row `y` in `cols` that are not protected.
+    fn selective_erase(&mut self, y: VisibleRowIndex, cols: Range<usize>) {
+        let blank = Cell::new(' ', self.erase_pen());
+        let idx = self.screen().phys_row(y);
+        let erase: Vec<usize> = self.screen().lines[idx]
+            .cells()
+            .iter()
+            .enumerate()
+            .filter(|(x, cell)| cols.contains(x) && !cell.attrs().protected())
+            .map(|(x, _)| x)
+            .collect();
+        let screen = self.screen_mut();
+        for x in erase {
+            screen.set_cell(x, y, &blank);
         }
+        self.clear_selection_if_intersects(cols, y as ScrollbackOrVisibleRowIndex);
     }
 
     fn perform_csi_cursor(&mut self, cursor: Cursor, host: &mut dyn TerminalHost) {
@@ -1368,6 +2812,8 @@ impl TerminalState {
                     std::mem::swap(&mut top, &mut bottom);
                 }
                 self.scroll_region = top..bottom + 1;
+                // As in xterm, DECSTBM homes the cursor
+                self.set_cursor_pos(&Position::Absolute(0), &Position::Absolute(0));
             }
             Cursor::ForwardTabulation(n) => {
                 for _ in 0..n {
@@ -1430,7 +2876,7 @@ impl TerminalState {
                 let line = OneBased::from_zero_based(self.cursor.y as u32);
                 let col = OneBased::from_zero_based(self.cursor.x as u32);
                 let report = CSI::Cursor(Cursor::ActivePositionReport { line, col });
-                write!(host.writer(), "{}", report).ok();
+                self.reply(&report.to_string(), host.writer()).ok();
             }
             Cursor::SaveCursor => self.save_cursor(),
             Cursor::RestoreCursor => self.restore_cursor(),
@@ -1460,8 +2906,10 @@ impl TerminalState {
         match sgr {
             Sgr::Reset => {
                 let link = self.pen.hyperlink.take();
+                let protected = self.pen.protected();
                 self.pen = CellAttributes::default();
                 self.pen.hyperlink = link;
+                self.pen.set_protected(protected);
             }
             Sgr::Intensity(intensity) => {
                 self.pen.set_intensity(intensity);
@@ -1527,88 +2975,30 @@ impl<'a> Performer<'a> {
     }
 
     fn flush_print(&mut self) {
-        let p = match self.print.take() {
-            Some(s) => s,
-            None => return,
-        };
-
-        let mut x_offset = 0;
-
-        for g in unicode_segmentation::UnicodeSegmentation::graphemes(p.as_str(), true) {
-            let g = if self.dec_line_drawing_mode {
-                match g {
-                    "j" => "┘",
-                    "k" => "┐",
-                    "l" => "┌",
-                    "m" => "└",
-                    "n" => "┼",
-                    "q" => "─",
-                    "t" => "├",
-                    "u" => "┤",
-                    "v" => "┴",
-                    "w" => "┬",
-                    "x" => "│",
-                    _ => g,
-                }
-            } else {
-                g
-            };
-
-            if !self.insert && self.wrap_next {
-                self.new_line(true);
-            }
-
-            let x = self.cursor.x;
-            let y = self.cursor.y;
-            let width = self.screen().physical_cols;
-
-            let mut pen = self.pen.clone();
-
-            let print_width = unicode_column_width(g).max(1);
-
-            if !self.insert && x + print_width >= width {
-                pen.set_wrapped(true);
-            }
-
-            let cell = Cell::new_grapheme(g, pen);
-
-            if self.insert {
-                let screen = self.screen_mut();
-                for _ in x..x + print_width as usize {
-                    screen.insert_cell(x + x_offset, y);
-                }
-            }
-
-            self.screen_mut().set_cell(x + x_offset, y, &cell);
-
-            self.clear_selection_if_intersects(
-                x..x + print_width,
-                y as ScrollbackOrVisibleRowIndex,
-            );
-
-            if self.insert {
-                x_offset += print_width;
-            } else if x + print_width < width {
-                self.cursor.x += print_width;
-                self.wrap_next = false;
-            } else {
-                self.wrap_next = true;
-            }
+        if let Some(p) = self.print.take() {
+            self.state.print_graphemes(&p);
         }
     }
 
     pub fn perform(&mut self, action: Action) {
         match action {
-            Action::Print(c) => self.print(c),
+            Action::Print(c) => return self.print(c),
             Action::Control(code) => self.control(code),
             Action::DeviceControl(_) => {}
             Action::OperatingSystemCommand(osc) => self.osc_dispatch(*osc),
             Action::Esc(esc) => self.esc_dispatch(esc),
             Action::CSI(csi) => self.csi_dispatch(csi),
         }
+        // Anything but text separates a combining mark from what was
+        // printed before it
+        self.last_print = None;
     }
 
     fn print(&mut self, c: char) {
+        // DEL and NUL are fillers in the data stream, as in xterm
+        if c == '\u{7f}' || c == '\0' {
+            return;
+        }
         self.print.get_or_insert_with(String::new).push(c);
     }
 
@@ -1625,7 +3015,12 @@ impl<'a> Performer<'a> {
                 self.set_cursor_pos(&Position::Relative(-1), &Position::Relative(0));
             }
             ControlCode::HorizontalTab => self.c0_horizontal_tab(),
-            ControlCode::Bell => {}
+            ControlCode::Bell => self.bell_pending = true,
+            ControlCode::Enquiry => self.state.answer_enquiry(self.host),
+            ControlCode::IND => self.c1_index(),
+            ControlCode::NEL => self.c1_nel(),
+            ControlCode::HTS => self.c1_hts(),
+            ControlCode::RI => self.c1_reverse_index(),
             _ => {}
         }
     }
@@ -1657,7 +3052,17 @@ impl<'a> Performer<'a> {
             Esc::Code(EscCode::ReverseIndex) => self.c1_reverse_index(),
             Esc::Code(EscCode::Index) => self.c1_index(),
             Esc::Code(EscCode::NextLine) => self.c1_nel(),
+            Esc::Code(EscCode::DecForwardIndex) => self.dec_forward_index(),
+            Esc::Code(EscCode::DecBackIndex) => self.dec_back_index(),
             Esc::Code(EscCode::HorizontalTabSet) => self.c1_hts(),
+            Esc::Code(EscCode::DecDoubleHeightTopHalfLine) => {
+                self.set_line_size(LineSize::DoubleHeightTop)
+            }
+            Esc::Code(EscCode::DecDoubleHeightBottomHalfLine) => {
+                self.set_line_size(LineSize::DoubleHeightBottom)
+            }
+            Esc::Code(EscCode::DecSingleWidthLine) => self.set_line_size(LineSize::Single),
+            Esc::Code(EscCode::DecDoubleWidthLine) => self.set_line_size(LineSize::DoubleWidth),
             Esc::Code(EscCode::DecLineDrawing) => {
                 self.dec_line_drawing_mode = true;
             }
@@ -1673,12 +3078,23 @@ impl<'a> Performer<'a> {
     fn osc_dispatch(&mut self, osc: OperatingSystemCommand) {
         self.flush_print();
         match osc {
-            OperatingSystemCommand::SetIconNameAndWindowTitle(title)
-            | OperatingSystemCommand::SetWindowTitle(title) => {
+            OperatingSystemCommand::SetIconNameAndWindowTitle(_)
+            | OperatingSystemCommand::SetWindowTitle(_)
+                if self.fixed_title => {}
+            OperatingSystemCommand::SetIconNameAndWindowTitle(title) => {
+                let title = self.decode_title(title);
+                self.icon_title = title.clone();
+                self.title = title.clone();
+                self.host.set_title(&title);
+            }
+            OperatingSystemCommand::SetWindowTitle(title) => {
+                let title = self.decode_title(title);
                 self.title = title.clone();
                 self.host.set_title(&title);
             }
-            OperatingSystemCommand::SetIconName(_) => {}
+            OperatingSystemCommand::SetIconName(title) => {
+                self.icon_title = self.decode_title(title);
+            }
             OperatingSystemCommand::SetHyperlink(link) => {
                 self.set_hyperlink(link);
             }
@@ -1690,21 +3106,43 @@ impl<'a> Performer<'a> {
                 }
             }
 
-            OperatingSystemCommand::ClearSelection(_) => {
+            OperatingSystemCommand::ClearSelection(selection) => {
                 if let Ok(clip) = self.host.get_clipboard() {
-                    clip.set_contents(None).ok();
+                    clip.set_contents(clipboard_selection(selection), None).ok();
                 }
             }
             OperatingSystemCommand::QuerySelection(_) => {}
-            OperatingSystemCommand::SetSelection(_, selection_data) => {
+            OperatingSystemCommand::SetSelection(selection, selection_data) => {
                 if let Ok(clip) = self.host.get_clipboard() {
-                    match clip.set_contents(Some(selection_data)) {
+                    match clip.set_contents(clipboard_selection(selection), Some(selection_data)) {
                         Ok(_) => (),
                         Err(_) => {}
                     }
                 }
             }
-            OperatingSystemCommand::SystemNotification(_) => {}
+            OperatingSystemCommand::SystemNotification { title, body } => {
+                self.host.show_notification(title.as_ref().map(String::as_str), &body);
+            }
+            OperatingSystemCommand::SemanticPrompt(SemanticPrompt::PromptStart) => {
+                if self.cursor.x != 0 {
+                    self.new_line(true);
+                }
+                let y = self.cursor.y;
+                let screen = self.screen_mut();
+                let idx = screen.phys_row(y);
+                screen.line_mut(idx).set_prompt(true);
+            }
+            OperatingSystemCommand::SemanticPrompt(SemanticPrompt::CommandFinished(status)) => {
+                self.last_command_status = status;
+            }
+            OperatingSystemCommand::SemanticPrompt(_) => {}
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::SetUserVar {
+                name,
+                value,
+            }) => {
+                self.user_vars.insert(name, value);
+            }
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::Unsupported(_)) => {}
             OperatingSystemCommand::ChangeColorNumber(specs) => {
                 for pair in specs {
                     match pair.color {
@@ -1716,7 +3154,7 @@ impl<'a> Performer<'a> {
                                         self.palette.colors.0[pair.palette_index as usize],
                                     ),
                                 }]);
-                            write!(self.host.writer(), "{}", response).ok();
+                            self.state.reply(&response.to_string(), self.host.writer()).ok();
                         }
                         ColorOrQuery::Color(c) => {
                             self.palette.colors.0[pair.palette_index as usize] = c;
@@ -1739,7 +3177,8 @@ impl<'a> Performer<'a> {
                                             which_color,
                                             vec![ColorOrQuery::Color(self.palette.$name)],
                                         );
-                                        write!(self.host.writer(), "{}", response).ok();
+                                        let response = response.to_string();
+                                        self.state.reply(&response, self.host.writer()).ok();
                                     }
                                     ColorOrQuery::Color(c) => self.palette.$name = c,
                                 }
@@ -1766,6 +3205,1517 @@ impl<'a> Performer<'a> {
                 }
                 self.make_all_lines_dirty();
             }
+            OperatingSystemCommand::ResetDynamicColor(which_color) => {
+                use crate::core::escape::osc::DynamicColorNumber;
+                macro_rules! reset {
+                    ($name:ident) => {
+                        self.palette.$name = self.default_palette.$name
+                    };
+                }
+                match which_color {
+                    DynamicColorNumber::TextForegroundColor => reset!(foreground),
+                    DynamicColorNumber::TextBackgroundColor => reset!(background),
+                    DynamicColorNumber::TextCursorColor => reset!(cursor_bg),
+                    DynamicColorNumber::HighlightForegroundColor => reset!(selection_fg),
+                    DynamicColorNumber::HighlightBackgroundColor => reset!(selection_bg),
+                    DynamicColorNumber::MouseForegroundColor
+                    | DynamicColorNumber::MouseBackgroundColor
+                    | DynamicColorNumber::TektronixForegroundColor
+                    | DynamicColorNumber::TektronixBackgroundColor
+                    | DynamicColorNumber::TektronixCursorColor => {}
+                }
+                self.make_all_lines_dirty();
+            }
         }
     }
 }
+
+fn clipboard_selection(selection: Selection) -> ClipboardSelection {
+    if selection.contains(Selection::CLIPBOARD) {
+        ClipboardSelection::Clipboard
+    } else if selection.intersects(Selection::PRIMARY | Selection::SELECT) {
+        ClipboardSelection::Primary
+    } else {
+        ClipboardSelection::Clipboard
+    }
+}
+
+fn emit_sgr_changes(prev: &CellAttributes, next: &CellAttributes, out: &mut String) {
+    let mut emit = |sgr: Sgr| {
+        write!(out, "{}", CSI::Sgr(sgr)).ok();
+    };
+    if prev.intensity() != next.intensity() {
+        emit(Sgr::Intensity(next.intensity()));
+    }
+    if prev.underline() != next.underline() {
+        emit(Sgr::Underline(next.underline()));
+    }
+    if prev.blink() != next.blink() {
+        emit(Sgr::Blink(next.blink()));
+    }
+    if prev.italic() != next.italic() {
+        emit(Sgr::Italic(next.italic()));
+    }
+    if prev.reverse() != next.reverse() {
+        emit(Sgr::Inverse(next.reverse()));
+    }
+    if prev.invisible() != next.invisible() {
+        emit(Sgr::Invisible(next.invisible()));
+    }
+    if prev.strikethrough() != next.strikethrough() {
+        emit(Sgr::StrikeThrough(next.strikethrough()));
+    }
+    if prev.foreground != next.foreground {
+        emit(Sgr::Foreground(next.foreground.into()));
+    }
+    if prev.background != next.background {
+        emit(Sgr::Background(next.background.into()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::term::color::{ColorAttribute, RgbColor};
+    use crate::testing::TestHost;
+
+    fn new_terminal(rows: usize, cols: usize) -> Terminal {
+        Terminal::new(rows, cols, 0, 0, 100, vec![])
+    }
+
+    fn visible_text(term: &Terminal) -> Vec<String> {
+        let screen = term.screen();
+        let first = screen.lines.len() - screen.physical_rows;
+        screen.lines.iter().skip(first).map(|line| line.as_str().trim_end().to_string()).collect()
+    }
+
+    #[test]
+    fn printing_past_the_margin_overwrites_without_auto_wrap() {
+        let mut term = new_terminal(3, 5);
+        let mut host = TestHost::new();
+        assert!(term.auto_wrap());
+
+        term.set_auto_wrap(false);
+        term.advance_bytes("abcdefg", &mut host);
+        assert_eq!(visible_text(&term), vec!["abcdg", "", ""]);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 4, y: 0 });
+
+        term.advance_bytes("\x1b[?7h\rhello!", &mut host);
+        assert!(term.auto_wrap());
+        assert_eq!(visible_text(&term), vec!["hello", "!", ""]);
+
+        term.advance_bytes("\x1b[?7l", &mut host);
+        assert!(!term.auto_wrap());
+    }
+
+    #[test]
+    fn reverse_index_scrolls_only_region() {
+        let mut term = new_terminal(5, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("a\r\nb\r\nc\r\nd\r\ne", &mut host);
+        term.advance_bytes("\x1b[2;4r\x1b[2;1H\x1bM", &mut host);
+
+        assert_eq!(visible_text(&term), vec!["a", "", "b", "c", "e"]);
+        assert_eq!(term.screen().lines.len(), 5);
+    }
+
+    #[test]
+    fn primary_scrollback_visible_from_alt_screen() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.set_scrollback_in_alt_screen(true);
+        term.advance_bytes("1\r\n2\r\n3\r\n4\r\n5", &mut host);
+        term.advance_bytes("\x1b[?1049halt", &mut host);
+
+        term.scroll_viewport(-2);
+        let lines: Vec<String> = term
+            .get_dirty_lines()
+            .into_iter()
+            .map(|(_, line, _)| line.as_str().trim_end().to_string())
+            .collect();
+        assert_eq!(lines, vec!["1", "2", "3"]);
+
+        term.scroll_viewport(2);
+        assert_eq!(visible_text(&term), vec!["alt", "", ""]);
+    }
+
+    #[test]
+    fn repeat_last_printed_graphic() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("A\x1b[3b", &mut host);
+        assert_eq!(visible_text(&term), vec!["AAAA", "", ""]);
+
+        term.advance_bytes("\r\nxy\x1b[12b", &mut host);
+        assert_eq!(visible_text(&term), vec!["AAAA", "xyyyyyyyyy", "yyyy"]);
+
+        term.advance_bytes("\x1b[H\x1b[2Jz\x1b[4294967295b", &mut host);
+        assert_eq!(visible_text(&term), vec!["zzzzzzzzzz", "zzzzzzzzzz", "z"]);
+    }
+
+    #[test]
+    fn resize_escape_is_opt_in() {
+        let mut term = new_terminal(24, 80);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b[8;30;100t", &mut host);
+        assert_eq!(term.take_resize_request(), None);
+
+        term.set_allow_resize_escape(true);
+        term.advance_bytes("\x1b[8;30;100t", &mut host);
+        assert_eq!(term.take_resize_request(), Some(ResizeRequest { rows: 30, cols: 100 }));
+
+        term.advance_bytes("\x1b[8;;120t", &mut host);
+        assert_eq!(term.take_resize_request(), Some(ResizeRequest { rows: 24, cols: 120 }));
+        assert_eq!(term.take_resize_request(), None);
+    }
+
+    #[test]
+    fn synchronized_output_withholds_dirty_lines() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.clean_dirty_lines();
+
+        term.advance_bytes("\x1b[?2026hhello", &mut host);
+        assert!(term.get_dirty_lines().is_empty());
+        term.clean_dirty_lines();
+
+        term.advance_bytes("\x1b[?2026l", &mut host);
+        let dirty: Vec<(usize, String)> = term
+            .get_dirty_lines()
+            .into_iter()
+            .map(|(idx, line, _)| (idx, line.as_str().trim_end().to_string()))
+            .collect();
+        assert_eq!(dirty, vec![(0, "hello".to_string())]);
+    }
+
+    #[test]
+    fn osc52_routes_selection_specifier() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b]52;p;aGVsbG8=\x07", &mut host);
+        term.advance_bytes("\x1b]52;c;d29ybGQ=\x07", &mut host);
+
+        assert_eq!(
+            *host.clipboard.contents.lock().unwrap(),
+            vec![
+                (ClipboardSelection::Primary, Some("hello".to_string())),
+                (ClipboardSelection::Clipboard, Some("world".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn resize_pixels_derives_cells_and_keeps_remainder() {
+        let mut term = new_terminal(3, 10);
+        term.resize_pixels(805, 413, 8, 16);
+
+        assert_eq!(term.physical_dimensions(), (25, 100));
+        assert_eq!((term.pixel_width, term.pixel_height), (805, 413));
+        assert_eq!(term.cell_pixel_size(), (8, 16));
+    }
+
+    #[test]
+    fn reset_cursor_color() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b]12;#ff0000\x07", &mut host);
+        assert_eq!(term.palette().cursor_bg, RgbColor::new(0xff, 0, 0));
+
+        term.advance_bytes("\x1b]112\x07", &mut host);
+        assert_eq!(term.palette().cursor_bg, ColorPalette::default().cursor_bg);
+    }
+
+    #[test]
+    fn reset_dynamic_colors_to_the_configured_palette() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        let mut configured = ColorPalette::default();
+        configured.foreground = RgbColor::new(0x12, 0x34, 0x56);
+        configured.cursor_bg = RgbColor::new(0, 0xff, 0);
+        term.set_palette(configured.clone());
+
+        term.advance_bytes("\x1b]10;#ff0000\x07\x1b]12;#0000ff\x07", &mut host);
+        assert_eq!(term.palette().foreground, RgbColor::new(0xff, 0, 0));
+
+        term.advance_bytes("\x1b]110\x07\x1b]112\x07", &mut host);
+        assert_eq!(term.palette().foreground, configured.foreground);
+        assert_eq!(term.palette().cursor_bg, configured.cursor_bg);
+    }
+
+    #[test]
+    fn query_dynamic_colors() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b]10;#102030\x07\x1b]11;#405060\x07\x1b]12;#7080ff\x07", &mut host);
+        assert!(host.writer.is_empty());
+
+        term.advance_bytes("\x1b]10;?\x07\x1b]11;?\x07\x1b]12;?\x07", &mut host);
+        assert_eq!(
+            String::from_utf8(host.writer).unwrap(),
+            "\x1b]10;rgb:1010/2020/3030\x07\
+             \x1b]11;rgb:4040/5050/6060\x07\
+             \x1b]12;rgb:7070/8080/ffff\x07"
+        );
+    }
+
+    #[test]
+    fn resize_reflows_wrapped_lines() {
+        let mut term = new_terminal(4, 80);
+        let mut host = TestHost::new();
+        let text: String = (0..100).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+        term.advance_bytes(&text, &mut host);
+        term.advance_bytes("\r\nshort", &mut host);
+        assert_eq!(visible_text(&term), vec![&text[..80], &text[80..], "short", ""]);
+
+        term.resize(4, 40, 0, 0);
+        assert_eq!(visible_text(&term), vec![&text[..40], &text[40..80], &text[80..], "short"]);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 5, y: 3 });
+
+        term.resize(4, 120, 0, 0);
+        assert_eq!(visible_text(&term), vec![text.as_str(), "short", "", ""]);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 5, y: 1 });
+    }
+
+    #[test]
+    fn idle_terminal_has_no_dirty_lines() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        assert!(term.has_dirty_lines());
+
+        term.clean_dirty_lines();
+        assert!(!term.has_dirty_lines());
+        assert!(term.get_dirty_lines().is_empty());
+
+        term.advance_bytes("x", &mut host);
+        assert!(term.has_dirty_lines());
+    }
+
+    #[test]
+    fn xtversion_reports_name_and_version() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b[>q", &mut host);
+        assert_eq!(
+            String::from_utf8(host.writer).unwrap(),
+            format!("\x1bP>|miro({})\x1b\\", env!("CARGO_PKG_VERSION"))
+        );
+    }
+
+    #[test]
+    fn alternate_screen_modes_differ_in_saving_and_clearing() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+
+        // 47 neither clears nor saves the cursor
+        term.advance_bytes("main\x1b[?47halt", &mut host);
+        assert_eq!(visible_text(&term), vec!["    alt", "", ""]);
+        term.advance_bytes("\x1b[?47l", &mut host);
+        assert_eq!(visible_text(&term), vec!["main", "", ""]);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 7, y: 0 });
+        term.advance_bytes("\x1b[?47h", &mut host);
+        assert_eq!(visible_text(&term), vec!["    alt", "", ""]);
+
+        // 1047 clears the alternate screen on the way out
+        term.advance_bytes("\x1b[?1047l", &mut host);
+        assert_eq!(visible_text(&term), vec!["main", "", ""]);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 7, y: 0 });
+        term.advance_bytes("\x1b[?1047hok", &mut host);
+        assert_eq!(visible_text(&term), vec!["       ok", "", ""]);
+        term.advance_bytes("\x1b[?47l\x1b[?47h", &mut host);
+        assert_eq!(visible_text(&term), vec!["       ok", "", ""]);
+        term.advance_bytes("\x1b[?1047l\x1b[?47h", &mut host);
+        assert_eq!(visible_text(&term), vec!["", "", ""]);
+
+        // 1049 saves the cursor and clears on the way in
+        term.advance_bytes("\x1b[Hjunk\x1b[?47l\x1b[2;3H\x1b[?1049h", &mut host);
+        assert_eq!(visible_text(&term), vec!["", "", ""]);
+        term.advance_bytes("x\x1b[?1049l", &mut host);
+        assert_eq!(visible_text(&term), vec!["main", "", ""]);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 2, y: 1 });
+    }
+
+    #[test]
+    fn focus_changes_are_reported_once_tracking_is_enabled() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.focus_changed(true, &mut host.writer).unwrap();
+        term.focus_changed(false, &mut host.writer).unwrap();
+        assert!(host.writer.is_empty());
+
+        term.advance_bytes("\x1b[?1004h", &mut host);
+        term.focus_changed(true, &mut host.writer).unwrap();
+        term.focus_changed(true, &mut host.writer).unwrap();
+        term.focus_changed(false, &mut host.writer).unwrap();
+        assert_eq!(std::mem::replace(&mut host.writer, vec![]), b"\x1b[I\x1b[O");
+
+        term.advance_bytes("\x1b[?1004l", &mut host);
+        term.focus_changed(true, &mut host.writer).unwrap();
+        assert!(host.writer.is_empty());
+    }
+
+    #[test]
+    fn delete_character_shifts_left_and_fills_with_the_pen() {
+        let mut term = new_terminal(3, 8);
+        let mut host = TestHost::new();
+        term.advance_bytes("ABCDE\x1b[1;2H\x1b[44m\x1b[2P", &mut host);
+        assert_eq!(visible_text(&term), vec!["ADE", "", ""]);
+
+        let line = &term.screen().lines[0];
+        assert_eq!(line.cells().len(), 8);
+        let background = |x: usize| line.cells()[x].attrs().background;
+        assert_eq!(background(5), ColorAttribute::Default);
+        assert_eq!(background(6), ColorAttribute::PaletteIndex(4));
+        assert_eq!(background(7), ColorAttribute::PaletteIndex(4));
+    }
+
+    #[test]
+    fn insert_character_shifts_right_and_drops_the_overflow() {
+        let mut term = new_terminal(3, 5);
+        let mut host = TestHost::new();
+        term.advance_bytes("ABCDE\x1b[H\x1b[44m\x1b[2@", &mut host);
+        assert_eq!(visible_text(&term), vec!["  ABC", "", ""]);
+
+        let line = &term.screen().lines[0];
+        assert_eq!(line.cells().len(), 5);
+        assert_eq!(line.cells()[0].attrs().background, ColorAttribute::PaletteIndex(4));
+        assert_eq!(line.cells()[2].attrs().background, ColorAttribute::Default);
+    }
+
+    #[test]
+    fn decfi_at_right_margin_shifts_left() {
+        let mut term = new_terminal(3, 5);
+        let mut host = TestHost::new();
+        term.advance_bytes("abcd\r\nvwxyz\x1b[1;5H\x1b9", &mut host);
+        assert_eq!(visible_text(&term), vec!["bcd", "wxyz", ""]);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 4, y: 0 });
+
+        term.advance_bytes("\x1b[1;1H\x1b6", &mut host);
+        assert_eq!(visible_text(&term), vec![" bcd", " wxyz", ""]);
+
+        term.advance_bytes("\x1b9\x1b9", &mut host);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 2, y: 0 });
+        assert_eq!(visible_text(&term), vec![" bcd", " wxyz", ""]);
+    }
+
+    #[test]
+    fn dump_ansi_round_trips() {
+        let mut term = new_terminal(4, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes(
+            "\x1b[1;31mred\x1b[0m plain\r\n\x1b[44;4mblue\x1b[38;2;1;2;3mtc\x1b[0mwrapped text",
+            &mut host,
+        );
+
+        let dump = term.dump_ansi(false);
+        let mut copy = new_terminal(4, 10);
+        copy.advance_bytes(&dump, &mut host);
+
+        let cells = |term: &Terminal| -> Vec<Vec<Cell>> {
+            term.screen().lines.iter().map(|line| line.cells().to_vec()).collect()
+        };
+        assert_eq!(cells(&copy), cells(&term));
+        assert_eq!(copy.cursor_pos(), term.cursor_pos());
+        assert_eq!(dump.matches("\x1b[44m").count(), 1);
+    }
+
+    #[test]
+    fn ime_commit_is_written_verbatim() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b[?2004h", &mut host);
+
+        term.send_ime_commit("é漢字", &mut host.writer).unwrap();
+        assert_eq!(String::from_utf8(host.writer).unwrap(), "é漢字");
+    }
+
+    #[test]
+    fn ime_commit_is_treated_like_typing() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.set_predictive_echo(true);
+        term.advance_bytes("1\r\n2\r\n3\r\n4\r\n$ ", &mut host);
+        term.scroll_viewport(-1);
+        assert_ne!(term.viewport_offset, 0);
+
+        term.send_ime_commit("ls", &mut host.writer).unwrap();
+        assert_eq!(term.viewport_offset, 0);
+        assert_eq!(visible_text(&term), vec!["3", "4", "$ ls"]);
+        assert_eq!(predicted_cells(&term), 2);
+        assert_eq!(host.writer, b"ls");
+    }
+
+    #[test]
+    fn pop_title_restores_pushed_title() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b]2;original\x07\x1b[22;2t\x1b]2;changed\x07", &mut host);
+        assert_eq!(term.get_title(), "changed");
+
+        term.advance_bytes("\x1b[23;2t", &mut host);
+        assert_eq!(term.get_title(), "original");
+
+        term.advance_bytes("\x1b[23;2t", &mut host);
+        assert_eq!(term.get_title(), "original");
+    }
+
+    #[test]
+    fn default_title_holds_until_the_application_sets_one() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.set_default_title("scratch");
+        assert_eq!(term.get_title(), "scratch");
+
+        term.advance_bytes("\x1b[22;0t\x1b]0;vim\x07", &mut host);
+        assert_eq!(term.get_title(), "vim");
+        term.advance_bytes("\x1b[23;0t", &mut host);
+        assert_eq!(term.get_title(), "scratch");
+    }
+
+    #[test]
+    fn fixed_title_ignores_osc_title_changes() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.set_fixed_title("pinned");
+        assert_eq!(term.get_title(), "pinned");
+
+        term.advance_bytes("\x1b]0;vim\x07\x1b]2;less\x07", &mut host);
+        assert_eq!(term.get_title(), "pinned");
+    }
+
+    #[test]
+    fn pop_icon_title_leaves_window_title() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b]0;both\x07\x1b[22;1t\x1b]2;window\x07", &mut host);
+        term.advance_bytes("\x1b[23;1t", &mut host);
+        assert_eq!(term.get_title(), "window");
+    }
+
+    #[test]
+    fn title_reports_are_gated_by_allow_window_ops() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b]2;secret\x07\x1b[21t\x1b[20t", &mut host);
+        assert!(host.writer.is_empty());
+
+        term.set_allow_window_ops(AllowWindowOps::All);
+        term.advance_bytes("\x1b[21t\x1b[20t", &mut host);
+        assert_eq!(host.writer, b"\x1b]lsecret\x1b\\\x1b]Lmiro\x1b\\");
+
+        host.writer.clear();
+        term.set_allow_window_ops(AllowWindowOps::ReportsOnly);
+        term.advance_bytes("\x1b[22;2t\x1b]2;changed\x07\x1b[23;2t\x1b[21t\x1b[18t", &mut host);
+        assert_eq!(term.get_title(), "changed");
+        assert_eq!(host.writer, b"\x1b[8;3;10t");
+    }
+
+    #[test]
+    fn title_modes_set_and_report_titles_in_hex() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.set_allow_window_ops(AllowWindowOps::All);
+        term.advance_bytes("\x1b[>0;1t\x1b]2;68C3A9\x07\x1b[21t", &mut host);
+        assert_eq!(term.get_title(), "h\u{e9}");
+        assert_eq!(host.writer, b"\x1b]l68C3A9\x1b\\");
+
+        host.writer.clear();
+        term.advance_bytes("\x1b[>T\x1b]2;6869\x07\x1b[21t", &mut host);
+        assert_eq!(term.get_title(), "6869");
+        assert_eq!(host.writer, b"\x1b]l6869\x1b\\");
+    }
+
+    #[test]
+    fn rectangular_areas_are_filled_and_erased() {
+        let mut term = new_terminal(4, 6);
+        let mut host = TestHost::new();
+        term.advance_bytes("abcdef\r\nghijkl\r\nmnopqr\r\nstuvwx", &mut host);
+
+        term.advance_bytes("\x1b[1m\x1b[42;2;2;3;4$x", &mut host);
+        assert_eq!(visible_text(&term), vec!["abcdef", "g***kl", "m***qr", "stuvwx"]);
+        let bold: Vec<(usize, usize)> = term
+            .visible_cells()
+            .filter(|(_, _, cell)| cell.attrs().intensity() == Intensity::Bold)
+            .map(|(row, col, _)| (row, col))
+            .collect();
+        assert_eq!(bold, vec![(1, 1), (1, 2), (1, 3), (2, 1), (2, 2), (2, 3)]);
+
+        term.advance_bytes("\x1b[3;5$z", &mut host);
+        assert_eq!(visible_text(&term), vec!["abcdef", "g***kl", "m***", "stuv"]);
+
+        // Outside the screen, with the corners the wrong way around, or
+        // filling with a control character
+        term.advance_bytes("\x1b[9;1;9;6$z\x1b[2;4;1;1$z\x1b[7;1;1;1;1$x", &mut host);
+        assert_eq!(visible_text(&term), vec!["abcdef", "g***kl", "m***", "stuv"]);
+    }
+
+    #[test]
+    fn primary_device_attributes_only_advertise_implemented_features() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b[c", &mut host);
+        assert_eq!(host.writer, b"\x1b[?62;6;22c");
+        assert!(!DEVICE_ATTRIBUTES.contains(&DeviceAttributeCodes::SixelGraphics));
+    }
+
+    #[test]
+    fn visible_cells_skip_wide_glyph_spacers() {
+        let mut term = new_terminal(2, 4);
+        let mut host = TestHost::new();
+        term.advance_bytes("a\u{4e16}b\r\n\x1b[1mc", &mut host);
+
+        let cells: Vec<(usize, usize, String, bool)> = term
+            .visible_cells()
+            .filter(|(_, _, cell)| cell.str() != " ")
+            .map(|(row, col, cell)| {
+                (row, col, cell.str().to_string(), cell.attrs().intensity() == Intensity::Bold)
+            })
+            .collect();
+        assert_eq!(
+            cells,
+            vec![
+                (0, 0, "a".to_string(), false),
+                (0, 1, "\u{4e16}".to_string(), false),
+                (0, 3, "b".to_string(), false),
+                (1, 0, "c".to_string(), true),
+            ]
+        );
+        assert_eq!(term.visible_cells().count(), 7);
+    }
+
+    #[test]
+    fn enquiry_sends_answerback() {
+        let mut term = new_terminal(2, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x05", &mut host);
+        assert!(host.writer.is_empty());
+
+        term.set_answerback("miro-term");
+        term.advance_bytes("a\x05b", &mut host);
+        assert_eq!(host.writer, b"miro-term");
+        assert_eq!(visible_text(&term)[0], "ab");
+    }
+
+    struct ImageClipboard;
+
+    impl Clipboard for ImageClipboard {
+        fn get_contents(&self, _selection: ClipboardSelection) -> anyhow::Result<String> {
+            bail!("not text")
+        }
+
+        fn get_typed_contents(
+            &self,
+            _selection: ClipboardSelection,
+        ) -> anyhow::Result<ClipboardContents> {
+            Ok(ClipboardContents::Image { data: b"\x89PNG".to_vec(), extension: "png".into() })
+        }
+
+        fn set_contents(
+            &self,
+            _selection: ClipboardSelection,
+            _data: Option<String>,
+        ) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn pasting_an_image_follows_image_paste_behavior() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut term = new_terminal(3, 10);
+        let clipboard = ImageClipboard;
+        let paste = |term: &mut Terminal| {
+            term.clipboard_paste_text(&clipboard, ClipboardSelection::Clipboard).unwrap()
+        };
+
+        assert_eq!(paste(&mut term), None);
+        assert!(!term.take_bell());
+
+        term.set_image_paste_behavior(ImagePasteBehavior::Beep);
+        assert_eq!(paste(&mut term), None);
+        assert!(term.take_bell());
+
+        term.set_image_paste_behavior(ImagePasteBehavior::PastePath);
+        let path = paste(&mut term).unwrap();
+        assert!(path.ends_with(".png"));
+        assert_eq!(std::fs::read(&path).unwrap(), b"\x89PNG");
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        std::fs::remove_file(&path).unwrap();
+        assert!(!term.take_bell());
+    }
+
+    #[test]
+    fn a_lone_bell_stays_pending_until_taken() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.clean_dirty_lines();
+        term.advance_bytes("\x07", &mut host);
+        assert!(!term.has_dirty_lines());
+        assert!(term.is_bell_pending());
+
+        assert!(term.take_bell());
+        assert!(!term.is_bell_pending());
+    }
+
+    #[test]
+    fn scroll_position_follows_the_viewport() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        for _ in 0..10 {
+            term.advance_bytes("line\r\n", &mut host);
+        }
+        assert_eq!(term.scroll_position(), (8, 11));
+
+        term.scroll_viewport(-5);
+        assert_eq!(term.scroll_position(), (3, 11));
+
+        term.scroll_viewport(-100);
+        assert_eq!(term.scroll_position(), (0, 11));
+    }
+
+    #[test]
+    fn soft_font_download_leaves_no_glyphs() {
+        let mut term = new_terminal(2, 20);
+        let mut host = TestHost::new();
+        term.advance_bytes(
+            "a\x1bP1;1;1;0;0;2;0;0{ @???~~~~/????~~~~;~~~~????/~~~~???\x1b\\b",
+            &mut host,
+        );
+        assert_eq!(visible_text(&term), vec!["ab", ""]);
+        assert!(host.writer.is_empty());
+    }
+
+    #[test]
+    fn setting_margins_homes_the_cursor() {
+        let mut term = new_terminal(5, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b[3;4H\x1b[2;4r", &mut host);
+        assert_eq!(term.scroll_region, 1..4);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 0, y: 0 });
+
+        term.advance_bytes("\x1b[5;6Habc\x1b[r", &mut host);
+        assert_eq!(term.scroll_region, 0..5);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn line_feed_below_scroll_region_does_not_scroll() {
+        let mut term = new_terminal(5, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("a\r\nb\r\nc\r\nd\r\ne\x1b[2;3r", &mut host);
+
+        term.advance_bytes("\x1b[4;1H\n\n\nX\x1bDY", &mut host);
+        assert_eq!(visible_text(&term), vec!["a", "b", "c", "d", "XY"]);
+        assert_eq!(term.cursor_pos().y, 4);
+
+        // At the bottom margin itself only the region scrolls
+        term.advance_bytes("\x1b[3;1H\n", &mut host);
+        assert_eq!(visible_text(&term), vec!["a", "c", "", "d", "XY"]);
+    }
+
+    #[test]
+    fn position_reports_wait_for_the_gui() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b[13t\x1b[13;2t", &mut host);
+        assert!(host.writer.is_empty());
+
+        let reports = term.take_position_reports();
+        assert_eq!(reports, vec![PositionReport::Window, PositionReport::TextArea]);
+        assert!(term.take_position_reports().is_empty());
+
+        term.report_position(reports[0], 120, 45, &mut host.writer).unwrap();
+        term.report_position(reports[1], 120, 61, &mut host.writer).unwrap();
+        assert_eq!(host.writer, b"\x1b[3;120;45t\x1b[3;2;120;61t");
+
+        term.set_allow_window_ops(AllowWindowOps::ReportsOnly);
+        term.advance_bytes("\x1b[13t", &mut host);
+        assert!(term.take_position_reports().is_empty());
+    }
+
+    #[test]
+    fn copy_wrapped_lines_joined_or_separated() {
+        let mut term = new_terminal(5, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("aaaaaaaaaabbbbbbbbbbcc\r\ndd", &mut host);
+        // The blank last row must not leave a trailing newline behind
+        term.selection_range = Some(SelectionRange {
+            start: SelectionCoordinate { x: 0, y: 0 },
+            end: SelectionCoordinate { x: usize::max_value(), y: 4 },
+        });
+
+        assert_eq!(term.get_selection_text(CopyWrappedLines::Joined), "aaaaaaaaaabbbbbbbbbbcc\ndd");
+        assert_eq!(
+            term.get_selection_text(CopyWrappedLines::Separated),
+            "aaaaaaaaaa\nbbbbbbbbbb\ncc\ndd"
+        );
+    }
+
+    #[test]
+    fn tab_gaps_copy_as_tabs() {
+        let mut term = new_terminal(3, 30);
+        let mut host = TestHost::new();
+        term.advance_bytes("name\tsize\r\nfoo\t\t1 2\r\nbar\x1b[3Cbaz", &mut host);
+        term.selection_range = Some(SelectionRange {
+            start: SelectionCoordinate { x: 0, y: 0 },
+            end: SelectionCoordinate { x: usize::max_value(), y: 2 },
+        });
+
+        let text = term.get_selection_text(CopyWrappedLines::Joined);
+        assert_eq!(text, "name    size\nfoo             1 2\nbar   baz");
+
+        term.set_copy_tabs_as_tabs(true);
+        let text = term.get_selection_text(CopyWrappedLines::Joined);
+        assert_eq!(text, "name\tsize\nfoo\t\t1 2\nbar   baz");
+    }
+
+    #[test]
+    fn delete_and_insert_line_break_soft_wraps() {
+        let select_rows = |term: &mut Terminal, last: ScrollbackOrVisibleRowIndex| {
+            term.selection_range = Some(SelectionRange {
+                start: SelectionCoordinate { x: 0, y: 0 },
+                end: SelectionCoordinate { x: usize::max_value(), y: last },
+            });
+            term.get_selection_text(CopyWrappedLines::Joined)
+        };
+
+        let mut term = new_terminal(4, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("aaaaaaaaaabbb\r\ncc", &mut host);
+        assert_eq!(select_rows(&mut term, 2), "aaaaaaaaaabbb\ncc");
+
+        term.advance_bytes("\x1b[2;1H\x1b[M", &mut host);
+        assert_eq!(select_rows(&mut term, 1), "aaaaaaaaaa\ncc");
+
+        let mut term = new_terminal(4, 10);
+        term.advance_bytes("aaaaaaaaaabbb", &mut host);
+        term.advance_bytes("\x1b[2;1H\x1b[L", &mut host);
+        assert_eq!(select_rows(&mut term, 2), "aaaaaaaaaa\n\nbbb");
+    }
+
+    #[test]
+    fn semantic_prompts_mark_rows_to_scroll_to() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        for cmd in &["one", "two", "three"] {
+            term.advance_bytes(format!("\x1b]133;A\x07$ \x1b]133;B\x07{}\r\n", cmd), &mut host);
+            term.advance_bytes("\x1b]133;C\x07out\r\nout\r\n\x1b]133;D;1\x07", &mut host);
+        }
+        term.advance_bytes("\x1b]133;A\x07$ ", &mut host);
+        assert_eq!(term.last_command_status(), Some(1));
+
+        let screen = term.screen();
+        let prompts: Vec<usize> =
+            (0..screen.lines.len()).filter(|&idx| screen.lines[idx].is_prompt()).collect();
+        assert_eq!(prompts, vec![0, 3, 6, 9]);
+        assert_eq!(screen.lines.len(), 10);
+
+        term.scroll_to_prev_prompt();
+        assert_eq!(term.viewport_offset, 1);
+        term.scroll_to_prev_prompt();
+        assert_eq!(term.viewport_offset, 4);
+        term.scroll_to_prev_prompt();
+        assert_eq!(term.viewport_offset, 7);
+        term.scroll_to_prev_prompt();
+        assert_eq!(term.viewport_offset, 7);
+
+        term.scroll_to_next_prompt();
+        assert_eq!(term.viewport_offset, 4);
+        term.scroll_to_next_prompt();
+        assert_eq!(term.viewport_offset, 1);
+        term.scroll_to_next_prompt();
+        assert_eq!(term.viewport_offset, 0);
+
+        // A marker mid-line starts the prompt on a fresh line
+        term.advance_bytes("x\x1b]133;A\x07", &mut host);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 0, y: 2 });
+    }
+
+    #[test]
+    fn clearing_the_screen_forgets_its_prompts() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        for cmd in &["one", "two"] {
+            term.advance_bytes(format!("\x1b]133;A\x07$ {}\r\nout\r\n", cmd), &mut host);
+        }
+        term.advance_bytes("\x1b]133;A\x07$ ", &mut host);
+
+        // What `clear` sends, followed by output that scrolls the erased
+        // rows back into the scrollback
+        term.advance_bytes("\x1b[H\x1b[2J\x1b[3Ja\r\nb\r\nc\r\nd", &mut host);
+        let screen = term.screen();
+        assert!(screen.lines.iter().all(|line| !line.is_prompt()));
+
+        term.scroll_to_prev_prompt();
+        assert_eq!(term.viewport_offset, 0);
+
+        // Erasing part of a line keeps its prompt
+        term.advance_bytes("\r\n\x1b]133;A\x07$ ls\x1b[3G\x1b[K", &mut host);
+        let last = term.screen().lines.len() - 1;
+        assert!(term.screen().lines[last].is_prompt());
+        term.advance_bytes("\x1b[2K", &mut host);
+        assert!(!term.screen().lines[last].is_prompt());
+    }
+
+    #[test]
+    fn set_user_var() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b]1337;SetUserVar=cwd=L2hvbWUvbWlybw==\x07", &mut host);
+        term.advance_bytes("\x1b]1337;File=inline=1:AAAA\x07", &mut host);
+        assert_eq!(term.get_user_var("cwd"), Some("/home/miro"));
+        assert_eq!(term.get_user_var("missing"), None);
+        assert_eq!(visible_text(&term), vec!["", "", ""]);
+    }
+
+    fn predicted_cells(term: &Terminal) -> usize {
+        let screen = term.screen();
+        screen.lines.iter().flat_map(|line| line.cells()).filter(|c| c.attrs().predicted()).count()
+    }
+
+    #[test]
+    fn predictive_echo_confirmed_by_host() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.set_predictive_echo(true);
+        term.advance_bytes("$ ", &mut host);
+
+        term.key_down(KeyCode::Char('l'), KeyModifiers::NONE, &mut host.writer).unwrap();
+        term.key_down(KeyCode::Char('s'), KeyModifiers::NONE, &mut host.writer).unwrap();
+        assert_eq!(visible_text(&term), vec!["$ ls", "", ""]);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 4, y: 0 });
+        assert_eq!(predicted_cells(&term), 2);
+
+        term.advance_bytes("l", &mut host);
+        assert_eq!(visible_text(&term), vec!["$ ls", "", ""]);
+        assert_eq!(predicted_cells(&term), 1);
+
+        term.advance_bytes("s", &mut host);
+        assert_eq!(visible_text(&term), vec!["$ ls", "", ""]);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 4, y: 0 });
+        assert_eq!(predicted_cells(&term), 0);
+        assert_eq!(host.writer, b"ls");
+    }
+
+    #[test]
+    fn predictive_echo_rolled_back_on_mismatch() {
+        let mut term = new_terminal(3, 20);
+        let mut host = TestHost::new();
+        term.set_predictive_echo(true);
+        term.advance_bytes("$ ", &mut host);
+
+        term.key_down(KeyCode::Char('a'), KeyModifiers::NONE, &mut host.writer).unwrap();
+        term.key_down(KeyCode::Char('b'), KeyModifiers::NONE, &mut host.writer).unwrap();
+        term.key_down(KeyCode::Backspace, KeyModifiers::NONE, &mut host.writer).unwrap();
+        assert_eq!(visible_text(&term), vec!["$ a", "", ""]);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 3, y: 0 });
+
+        // e.g. a password prompt that doesn't echo
+        term.advance_bytes("\r\nPassword: ", &mut host);
+        assert_eq!(visible_text(&term), vec!["$", "Password:", ""]);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 10, y: 1 });
+        assert_eq!(predicted_cells(&term), 0);
+    }
+
+    #[test]
+    fn eight_bit_c1_controls_match_esc_forms() {
+        let mut host = TestHost::new();
+        let cases: &[(&[u8], &[u8])] = &[
+            (b"ab\x85cd", b"ab\x1bEcd"),
+            (b"ab\x84cd", b"ab\x1bDcd"),
+            (b"\n\nab\x8dcd", b"\n\nab\x1bMcd"),
+            (b"\x9b2;3Hx", b"\x1b[2;3Hx"),
+        ];
+        for (eight_bit, seven_bit) in cases {
+            let mut c1 = new_terminal(4, 10);
+            c1.advance_bytes(eight_bit, &mut host);
+            let mut esc = new_terminal(4, 10);
+            esc.advance_bytes(seven_bit, &mut host);
+            assert_eq!(visible_text(&c1), visible_text(&esc));
+            assert_eq!(c1.cursor_pos(), esc.cursor_pos());
+        }
+
+        let mut term = new_terminal(4, 10);
+        term.advance_bytes(b"ab\x85cd", &mut host);
+        assert_eq!(visible_text(&term), vec!["ab", "cd", "", ""]);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 2, y: 1 });
+    }
+
+    fn key_bytes(term: &mut Terminal, key: KeyCode, mods: KeyModifiers) -> Vec<u8> {
+        let mut writer = vec![];
+        term.key_down(key, mods, &mut writer).unwrap();
+        writer
+    }
+
+    #[test]
+    fn keypad_and_cursor_key_modes_are_independent() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        let keys = |term: &mut Terminal| {
+            (
+                key_bytes(term, KeyCode::UpArrow, KeyModifiers::NONE),
+                key_bytes(term, KeyCode::Numpad5, KeyModifiers::NONE),
+            )
+        };
+        assert_eq!(keys(&mut term), (b"\x1b[A".to_vec(), b"5".to_vec()));
+
+        term.advance_bytes("\x1b=", &mut host);
+        assert_eq!(keys(&mut term), (b"\x1b[A".to_vec(), b"\x1bOu".to_vec()));
+
+        term.advance_bytes("\x1b>\x1b[?1h", &mut host);
+        assert_eq!(keys(&mut term), (b"\x1bOA".to_vec(), b"5".to_vec()));
+    }
+
+    #[test]
+    fn backspace_sends_configured_byte() {
+        let mut term = new_terminal(3, 10);
+        assert_eq!(key_bytes(&mut term, KeyCode::Backspace, KeyModifiers::NONE), b"\x08");
+        assert_eq!(key_bytes(&mut term, KeyCode::Backspace, KeyModifiers::ALT), b"\x1b\x08");
+
+        term.set_backspace_sends(BackspaceSends::Delete);
+        assert_eq!(key_bytes(&mut term, KeyCode::Backspace, KeyModifiers::NONE), b"\x7f");
+        assert_eq!(key_bytes(&mut term, KeyCode::Backspace, KeyModifiers::ALT), b"\x1b\x7f");
+    }
+
+    #[test]
+    fn delete_sends_configured_sequence() {
+        let mut term = new_terminal(3, 10);
+        assert_eq!(key_bytes(&mut term, KeyCode::Delete, KeyModifiers::NONE), b"\x7f");
+
+        term.set_delete_sends(DeleteSends::EscapeSequence);
+        assert_eq!(key_bytes(&mut term, KeyCode::Delete, KeyModifiers::NONE), b"\x1b[3~");
+        assert_eq!(key_bytes(&mut term, KeyCode::Char('\x7f'), KeyModifiers::NONE), b"\x1b[3~");
+    }
+
+    #[test]
+    fn ctrl_space_and_ctrl_at_send_nul() {
+        let mut term = new_terminal(3, 10);
+        let ctrl_shift = KeyModifiers::CTRL | KeyModifiers::SHIFT;
+        assert_eq!(key_bytes(&mut term, KeyCode::Char(' '), KeyModifiers::CTRL), b"\0");
+        assert_eq!(key_bytes(&mut term, KeyCode::Char('@'), KeyModifiers::CTRL), b"\0");
+        assert_eq!(key_bytes(&mut term, KeyCode::Char('@'), ctrl_shift), b"\0");
+        let ctrl_alt = KeyModifiers::CTRL | KeyModifiers::ALT;
+        assert_eq!(key_bytes(&mut term, KeyCode::Char(' '), ctrl_alt), b"\x1b\0");
+        assert_eq!(key_bytes(&mut term, KeyCode::Char(' '), KeyModifiers::NONE), b" ");
+
+        term.set_ctrl_space_sends(CtrlSpaceSends::CsiU);
+        assert_eq!(key_bytes(&mut term, KeyCode::Char(' '), KeyModifiers::CTRL), b"\x1b[32;5u");
+        assert_eq!(key_bytes(&mut term, KeyCode::Char(' '), ctrl_shift), b"\x1b[32;6u");
+        assert_eq!(key_bytes(&mut term, KeyCode::Char('@'), ctrl_shift), b"\x1b[64;5u");
+        assert_eq!(key_bytes(&mut term, KeyCode::Char('@'), ctrl_alt), b"\x1b[64;7u");
+    }
+
+    #[test]
+    fn read_only_discards_input() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.set_read_only(true);
+
+        assert!(key_bytes(&mut term, KeyCode::Char('a'), KeyModifiers::NONE).is_empty());
+        assert!(key_bytes(&mut term, KeyCode::Enter, KeyModifiers::NONE).is_empty());
+        term.send_paste("pasted", &mut host.writer).unwrap();
+
+        term.advance_bytes("\x1b[?1006hshown", &mut host);
+        let event = MouseEvent {
+            kind: MouseEventKind::Press,
+            x: 1,
+            y: 0,
+            pixel_x: 12,
+            pixel_y: 5,
+            button: MouseButton::Right,
+            modifiers: KeyModifiers::NONE,
+        };
+        term.mouse_event(event, &mut host).unwrap();
+        assert!(host.writer.is_empty());
+        assert_eq!(visible_text(&term), vec!["shown", "", ""]);
+
+        term.set_read_only(false);
+        assert_eq!(key_bytes(&mut term, KeyCode::Char('a'), KeyModifiers::NONE), b"a");
+    }
+
+    #[test]
+    fn send_bytes_writes_verbatim() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b[?2004h", &mut host);
+
+        term.send_bytes(b"\x1b[Aecho hi\r\x00", &mut host.writer).unwrap();
+        assert_eq!(host.writer, b"\x1b[Aecho hi\r\x00");
+
+        host.writer.clear();
+        term.set_read_only(true);
+        term.send_bytes(b"ignored", &mut host.writer).unwrap();
+        assert!(host.writer.is_empty());
+    }
+
+    #[test]
+    fn report_cell_and_text_area_pixels() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.resize_pixels(805, 413, 8, 16);
+        term.advance_bytes("\x1b[16t\x1b[14t\x1b[18t", &mut host);
+        assert_eq!(
+            String::from_utf8(host.writer).unwrap(),
+            "\x1b[6;16;8t\x1b[4;400;800t\x1b[8;25;100t"
+        );
+    }
+
+    #[test]
+    fn paste_filter_strips_controls() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b[?2004h", &mut host);
+        let paste = "ls\x1b[201~; rm -rf ~\r\n\tok\u{9b}31m\x07\rdone";
+
+        term.send_paste(paste, &mut host.writer).unwrap();
+        assert_eq!(
+            String::from_utf8(std::mem::replace(&mut host.writer, vec![])).unwrap(),
+            format!("\x1b[200~{}\x1b[201~", paste)
+        );
+
+        term.set_paste_filter(true);
+        term.send_paste(paste, &mut host.writer).unwrap();
+        assert_eq!(
+            String::from_utf8(host.writer).unwrap(),
+            "\x1b[200~ls[201~; rm -rf ~\n\tok31m\ndone\x1b[201~"
+        );
+    }
+
+    #[test]
+    fn large_paste_is_written_in_order_before_later_keys() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b[?2004h", &mut host);
+        let paste: String =
+            (0..PASTE_CHUNK_SIZE * 3).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+
+        term.send_paste(&paste, &mut host.writer).unwrap();
+        assert_eq!(host.writer.len(), PASTE_CHUNK_SIZE);
+        assert!(term.has_pending_input());
+
+        term.key_down(KeyCode::Char('x'), KeyModifiers::NONE, &mut host.writer).unwrap();
+        term.send_paste("yz", &mut host.writer).unwrap();
+        while term.flush_pending_input(&mut host.writer).unwrap() {}
+        assert!(!term.has_pending_input());
+
+        assert_eq!(
+            String::from_utf8(std::mem::replace(&mut host.writer, vec![])).unwrap(),
+            format!("\x1b[200~{}\x1b[201~x\x1b[200~yz\x1b[201~", paste)
+        );
+
+        // Once drained, keys go straight out again
+        term.key_down(KeyCode::Char('q'), KeyModifiers::NONE, &mut host.writer).unwrap();
+        assert_eq!(host.writer, b"q");
+    }
+
+    #[test]
+    fn reports_wait_behind_a_trickled_paste() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b[?1000h\x1b[?1006h\x1b[?1004h", &mut host);
+        let paste = "a".repeat(PASTE_CHUNK_SIZE * 2);
+        term.send_paste(&paste, &mut host.writer).unwrap();
+        assert_eq!(host.writer.len(), PASTE_CHUNK_SIZE);
+
+        let event = MouseEvent {
+            kind: MouseEventKind::Press,
+            x: 2,
+            y: 1,
+            pixel_x: 17,
+            pixel_y: 20,
+            button: MouseButton::Left,
+            modifiers: KeyModifiers::NONE,
+        };
+        term.mouse_event(event, &mut host).unwrap();
+        term.focus_changed(true, &mut host.writer).unwrap();
+        term.advance_bytes("\x1b[5n", &mut host);
+        assert_eq!(host.writer.len(), PASTE_CHUNK_SIZE);
+
+        while term.flush_pending_input(&mut host.writer).unwrap() {}
+        assert_eq!(
+            String::from_utf8(host.writer).unwrap(),
+            format!("{}\x1b[<0;3;2M\x1b[I\x1b[0n", paste)
+        );
+    }
+
+    #[test]
+    fn failed_paste_write_drops_the_queue() {
+        struct BrokenPty;
+        impl std::io::Write for BrokenPty {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        let paste = "a".repeat(PASTE_CHUNK_SIZE * 3);
+        term.send_paste(&paste, &mut host.writer).unwrap();
+        assert!(term.has_pending_input());
+
+        assert!(term.flush_pending_input(&mut BrokenPty).is_err());
+        assert!(!term.has_pending_input());
+    }
+
+    #[test]
+    fn cell_pixel_size_matches_pixel_ratios() {
+        let mut term = new_terminal(3, 10);
+        assert_eq!(term.cell_pixel_size(), (0, 0));
+
+        term.resize(24, 80, 640, 384);
+        assert_eq!(term.cell_pixel_size(), (640 / 80, 384 / 24));
+
+        term.resize_pixels(805, 413, 8, 16);
+        let (rows, cols) = term.physical_dimensions();
+        let (cell_width, cell_height) = term.cell_pixel_size();
+        assert_eq!((cell_width, cell_height), (8, 16));
+        assert!(cols * cell_width <= 805 && 805 - cols * cell_width < cell_width);
+        assert!(rows * cell_height <= 413 && 413 - rows * cell_height < cell_height);
+    }
+
+    #[test]
+    fn runaway_lines_stay_bounded() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.set_max_line_length(25);
+        let longest = |term: &Terminal| {
+            term.screen().lines.iter().map(|line| line.cells().len()).max().unwrap()
+        };
+
+        // Text printed in insert mode is pushed along the line rather than
+        // wrapping at the margin
+        term.advance_bytes(format!("\x1b[4h{}\x1b[4l", "y".repeat(1000)), &mut host);
+        assert!(longest(&term) <= 25);
+        let rows = term.screen().lines.len();
+        assert!(term.screen().lines.iter().take(rows - 1).all(|line| line.last_cell_was_wrapped()));
+
+        term.advance_bytes(format!("\r\n{}", "x".repeat(1000)), &mut host);
+        term.resize(3, 40, 0, 0);
+        assert!(longest(&term) <= 40);
+        // The logical line is broken up at the cap, but still reads as one
+        let x_rows: Vec<&Line> =
+            term.screen().lines.iter().filter(|line| line.as_str().contains('x')).collect();
+        assert!(x_rows.len() > 1);
+        assert!(x_rows[..x_rows.len() - 1].iter().all(|line| line.last_cell_was_wrapped()));
+        assert!(!x_rows[x_rows.len() - 1].last_cell_was_wrapped());
+    }
+
+    #[test]
+    fn clear_screen_and_scrollback_empties_history() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("1\r\n2\r\n3\r\n4\r\n5\r\n6", &mut host);
+        assert_eq!(term.screen().lines.len(), 6);
+
+        term.clear_screen_and_scrollback();
+        assert_eq!(term.screen().lines.len(), term.screen().physical_rows);
+        assert_eq!(visible_text(&term), vec!["", "", ""]);
+
+        term.advance_bytes("7\r\n8\r\n9\r\n\x1b[3J", &mut host);
+        assert_eq!(term.screen().lines.len(), term.screen().physical_rows);
+    }
+
+    #[test]
+    fn cursor_color_follows_osc_12() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        let bg = RgbColor::new(0x10, 0x20, 0x30);
+        term.advance_bytes("\x1b]12;#ff0000\x07", &mut host);
+        assert_eq!(term.palette().resolve_cursor(bg), (bg, RgbColor::new(0xff, 0, 0)));
+
+        term.palette.cursor_fg = Some(RgbColor::new(0, 0, 0xff));
+        assert_eq!(
+            term.palette().resolve_cursor(bg),
+            (RgbColor::new(0, 0, 0xff), RgbColor::new(0xff, 0, 0))
+        );
+    }
+
+    #[test]
+    fn middle_click_pastes_primary_unless_mouse_grabbed() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        host.clipboard.set_contents(ClipboardSelection::Clipboard, Some("clip".into())).unwrap();
+        host.clipboard.set_contents(ClipboardSelection::Primary, Some("primary".into())).unwrap();
+        let event = MouseEvent {
+            kind: MouseEventKind::Press,
+            x: 1,
+            y: 0,
+            pixel_x: 12,
+            pixel_y: 5,
+            button: MouseButton::Middle,
+            modifiers: KeyModifiers::NONE,
+        };
+
+        term.advance_bytes("\x1b[?2004h", &mut host);
+        term.mouse_event(event, &mut host).unwrap();
+        assert_eq!(host.writer, b"\x1b[200~primary\x1b[201~");
+
+        host.writer.clear();
+        term.advance_bytes("\x1b[?1006h", &mut host);
+        term.mouse_event(event, &mut host).unwrap();
+        assert_eq!(host.writer, b"\x1b[<1;2;1M");
+
+        // Shift overrides the application's grab
+        host.writer.clear();
+        term.mouse_event(MouseEvent { modifiers: KeyModifiers::SHIFT, ..event }, &mut host)
+            .unwrap();
+        assert_eq!(host.writer, b"\x1b[200~primary\x1b[201~");
+    }
+
+    #[test]
+    fn sgr_pixels_mouse_reports_pixel_coordinates() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        let event = MouseEvent {
+            kind: MouseEventKind::Press,
+            x: 2,
+            y: 1,
+            pixel_x: 17,
+            pixel_y: 20,
+            button: MouseButton::Left,
+            modifiers: KeyModifiers::NONE,
+        };
+
+        term.advance_bytes("\x1b[?1006h", &mut host);
+        term.mouse_event(event, &mut host).unwrap();
+        term.mouse_event(MouseEvent { kind: MouseEventKind::Release, ..event }, &mut host).unwrap();
+        assert_eq!(host.writer, b"\x1b[<0;3;2M\x1b[<3;3;2m");
+
+        host.writer.clear();
+        term.advance_bytes("\x1b[?1016h", &mut host);
+        term.mouse_event(event, &mut host).unwrap();
+        term.mouse_event(MouseEvent { kind: MouseEventKind::Release, ..event }, &mut host).unwrap();
+        assert_eq!(host.writer, b"\x1b[<0;18;21M\x1b[<3;18;21m");
+
+        host.writer.clear();
+        term.advance_bytes("\x1b[?1016l", &mut host);
+        term.mouse_event(event, &mut host).unwrap();
+        assert_eq!(host.writer, b"\x1b[<0;3;2M");
+    }
+
+    #[test]
+    fn double_width_line_holds_half_the_columns() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b#6abcdefgh", &mut host);
+
+        assert_eq!(term.screen().lines[0].line_size(), LineSize::DoubleWidth);
+        assert_eq!(term.screen().lines[1].line_size(), LineSize::Single);
+        assert_eq!(visible_text(&term), vec!["abcde", "fgh", ""]);
+
+        term.advance_bytes("\x1b[1;9H", &mut host);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 4, y: 0 });
+
+        term.advance_bytes("\x1b#5\x1b[1;9H", &mut host);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 8, y: 0 });
+    }
+
+    #[test]
+    fn test_resize_round_trips_dimensions() {
+        let mut term = new_terminal(4, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("hello world\r\nbye", &mut host);
+        let original = term.get_dimensions();
+        let before = term.visible_lines();
+
+        let smaller = term.test_resize(2, 5);
+        assert_eq!((smaller.viewport_rows, smaller.cols), (2, 5));
+        assert!(smaller.scrollback_rows >= smaller.viewport_rows);
+        assert!(term.cursor_pos().x < 5);
+
+        assert_eq!(term.test_resize(4, 10), original);
+        assert_eq!(term.visible_lines(), before);
+    }
+
+    #[test]
+    fn export_scrollback_joins_wrapped_lines() {
+        let mut term = new_terminal(2, 5);
+        let mut host = TestHost::new();
+        term.advance_bytes("one\r\n\x1b[31mtwo\x1b[0m\r\nthree four\r\nfive", &mut host);
+
+        let mut plain = vec![];
+        term.export_scrollback(&mut plain, false).unwrap();
+        assert_eq!(String::from_utf8(plain).unwrap(), "one\ntwo\nthree four\nfive\n");
+
+        let mut colored = vec![];
+        term.export_scrollback(&mut colored, true).unwrap();
+        assert_eq!(
+            String::from_utf8(colored).unwrap(),
+            "one\n\x1b[31mtwo\n\x1b[39mthree four\nfive\n"
+        );
+    }
+
+    #[test]
+    fn compressed_scrollback_reads_like_plain_scrollback() {
+        let output = "one\r\n\x1b[31mtwo\x1b[0m\r\nthree four\r\n\u{4e2d}\u{6587}\r\nfive";
+        let mut host = TestHost::new();
+        let mut plain = new_terminal(2, 5);
+        plain.advance_bytes(output, &mut host);
+        let mut packed = new_terminal(2, 5);
+        packed.set_compress_scrollback(true);
+        packed.advance_bytes(output, &mut host);
+
+        let lines =
+            |term: &Terminal| -> Vec<Line> { term.screen().lines.iter().cloned().collect() };
+        let export = |term: &Terminal| {
+            let mut out = vec![];
+            term.export_scrollback(&mut out, true).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(lines(&packed), lines(&plain));
+        assert_eq!(export(&packed), export(&plain));
+
+        // Scrolling back unpacks the rows in view and packs the rest again
+        plain.scroll_viewport(-3);
+        plain.clean_dirty_lines();
+        packed.scroll_viewport(-3);
+        packed.clean_dirty_lines();
+        assert_eq!(lines(&packed), lines(&plain));
+        assert_eq!(packed.get_dirty_lines().len(), 0);
+    }
+
+    #[test]
+    fn selective_erase_spares_protected_cells() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("ab\x1b[1\"qKEEP\x1b[0mme\x1b[0\"qcd\r\nxyz", &mut host);
+        term.advance_bytes("\x1b[1;1H\x1b[?2K", &mut host);
+        assert_eq!(visible_text(&term), vec!["  KEEPme", "xyz", ""]);
+
+        term.advance_bytes("\x1b[?J", &mut host);
+        assert_eq!(visible_text(&term), vec!["  KEEPme", "", ""]);
+
+        term.advance_bytes("\x1b[2K", &mut host);
+        assert_eq!(visible_text(&term), vec!["", "", ""]);
+    }
+
+    #[test]
+    fn background_color_erase_toggles_erase_attributes() {
+        let mut host = TestHost::new();
+        let red = ColorAttribute::PaletteIndex(1);
+
+        let mut term = new_terminal(2, 4);
+        term.advance_bytes("ab\x1b[41m\x1b[K\r\n\x1b[2X", &mut host);
+        let cells = term.screen().lines[0].cells().to_vec();
+        assert_eq!(cells[1].attrs().background, ColorAttribute::Default);
+        assert_eq!(cells[2].attrs().background, red);
+        assert_eq!(term.screen().lines[1].cells()[0].attrs().background, red);
+
+        let mut term = new_terminal(2, 4);
+        term.set_background_color_erase(false);
+        term.advance_bytes("ab\x1b[41m\x1b[K\r\n\x1b[2X", &mut host);
+        let cells = term.screen().lines[0].cells().to_vec();
+        assert_eq!(cells[2].attrs().background, ColorAttribute::Default);
+        assert_eq!(term.screen().lines[1].cells()[0].attrs().background, ColorAttribute::Default);
+
+        term.advance_bytes("\x1b[2J", &mut host);
+        assert_eq!(term.screen().lines[0].cells()[0].attrs().background, ColorAttribute::Default);
+    }
+
+    #[test]
+    fn reports_alt_screen_state() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        assert!(!term.is_alt_screen_active());
+
+        term.advance_bytes("\x1b[?1049h", &mut host);
+        assert!(term.is_alt_screen_active());
+
+        term.advance_bytes("\x1b[?1049l", &mut host);
+        assert!(!term.is_alt_screen_active());
+
+        term.advance_bytes("\x1b[?47h", &mut host);
+        assert!(term.is_alt_screen_active());
+    }
+
+    #[test]
+    fn del_and_nul_do_not_occupy_cells() {
+        let mut term = new_terminal(2, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("A\x7fB\0C", &mut host);
+
+        let cells = term.screen().lines[0].cells().to_vec();
+        assert_eq!(cells[0].str(), "A");
+        assert_eq!(cells[1].str(), "B");
+        assert_eq!(cells[2].str(), "C");
+        assert_eq!(term.cursor_pos().x, 3);
+    }
+
+    #[test]
+    fn wheel_in_alt_screen_sends_one_arrow_per_line() {
+        let mut term = new_terminal(3, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("\x1b[?1049h", &mut host);
+        let event = MouseEvent {
+            kind: MouseEventKind::Press,
+            x: 0,
+            y: 0,
+            pixel_x: 0,
+            pixel_y: 0,
+            button: MouseButton::WheelUp(3),
+            modifiers: KeyModifiers::NONE,
+        };
+
+        term.mouse_event(event, &mut host).unwrap();
+        assert_eq!(host.writer, b"\x1b[A\x1b[A\x1b[A");
+    }
+
+    #[test]
+    fn soft_reset_restores_decstr_defaults() {
+        let mut term = new_terminal(5, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes(
+            "\x1b[2;3r\x1b[4h\x1b[?7l\x1b[?1h\x1b=\x1b[?25l\x1b(0\x1b[1;31m",
+            &mut host,
+        );
+        term.advance_bytes("\x1b[?2004h\x1b[?1002h\x1b[?1006h\x1b[3;4H\x1b7", &mut host);
+
+        term.advance_bytes("\x1b[!p", &mut host);
+        assert_eq!(term.scroll_region, 0..5);
+        assert!(!term.insert);
+        assert!(term.dec_auto_wrap);
+        assert!(!term.application_cursor_keys);
+        assert!(!term.application_keypad);
+        assert!(term.cursor_visible);
+        assert!(!term.dec_line_drawing_mode);
+        assert_eq!(term.pen, CellAttributes::default());
+
+        // Not covered by DECSTR
+        assert!(term.bracketed_paste);
+        assert!(term.sgr_mouse);
+        assert!(term.button_event_mouse);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 3, y: 2 });
+
+        // The saved cursor goes back to the home position
+        term.advance_bytes("\x1b8", &mut host);
+        assert_eq!(term.cursor_pos(), CursorPosition { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn scroll_up_in_partial_region_skips_scrollback() {
+        let mut term = new_terminal(5, 10);
+        let mut host = TestHost::new();
+        term.advance_bytes("a\r\nb\r\nc\r\nd\r\ne", &mut host);
+        term.advance_bytes("\x1b[1;3r\x1b[3;1H\n", &mut host);
+
+        assert_eq!(visible_text(&term), vec!["b", "c", "", "d", "e"]);
+        assert_eq!(term.screen().lines.len(), 5);
+    }
+
+    #[test]
+    fn combining_mark_in_a_later_write_joins_the_previous_cell() {
+        let mut term = new_terminal(2, 5);
+        let mut host = TestHost::new();
+        term.advance_bytes("e", &mut host);
+        term.advance_bytes("\u{301}x", &mut host);
+
+        assert_eq!(visible_text(&term), vec!["e\u{301}x", ""]);
+        assert_eq!(term.cursor_pos().x, 2);
+
+        term.advance_bytes("\r\n\u{301}", &mut host);
+        assert_eq!(term.cursor_pos().x, 1);
+    }
+}
@@ -0,0 +1,108 @@
+//! Stand-ins for the window, clipboard and pty, so that terminals and
+//! tabs can be driven from tests.
+
+use crate::core::hyperlink::Hyperlink;
+use crate::pty::{Child, ExitStatus, MasterPty, PtySize};
+use crate::term::clipboard::{Clipboard, ClipboardSelection};
+use crate::term::TerminalHost;
+use anyhow::bail;
+use std::io::Result as IoResult;
+use std::os::unix::process::ExitStatusExt;
+use std::sync::{Arc, Mutex};
+
+/// Remembers every selection that was set, latest last.
+#[derive(Default)]
+pub struct TestClipboard {
+    pub contents: Mutex<Vec<(ClipboardSelection, Option<String>)>>,
+}
+
+impl Clipboard for TestClipboard {
+    fn get_contents(&self, selection: ClipboardSelection) -> anyhow::Result<String> {
+        match self.contents.lock().unwrap().iter().rev().find(|(sel, _)| *sel == selection) {
+            Some((_, Some(data))) => Ok(data.clone()),
+            _ => bail!("no clipboard contents in tests"),
+        }
+    }
+
+    fn set_contents(
+        &self,
+        selection: ClipboardSelection,
+        data: Option<String>,
+    ) -> anyhow::Result<()> {
+        self.contents.lock().unwrap().push((selection, data));
+        Ok(())
+    }
+}
+
+/// Collects what the terminal writes back to the application.
+pub struct TestHost {
+    pub writer: Vec<u8>,
+    pub clipboard: Arc<TestClipboard>,
+}
+
+impl TestHost {
+    pub fn new() -> Self {
+        Self { writer: Vec::new(), clipboard: Arc::new(TestClipboard::default()) }
+    }
+}
+
+impl TerminalHost for TestHost {
+    fn writer(&mut self) -> &mut dyn std::io::Write {
+        &mut self.writer
+    }
+
+    fn get_clipboard(&mut self) -> anyhow::Result<Arc<dyn Clipboard>> {
+        Ok(Arc::clone(&self.clipboard) as Arc<dyn Clipboard>)
+    }
+
+    fn set_title(&mut self, _title: &str) {}
+
+    fn click_link(&mut self, _link: &Arc<Hyperlink>) {}
+}
+
+/// A child that has exited with `status`, or is still running if None.
+#[derive(Debug)]
+pub struct MockChild {
+    pub status: Option<ExitStatus>,
+}
+
+impl Child for MockChild {
+    fn try_wait(&mut self) -> IoResult<Option<ExitStatus>> {
+        Ok(self.status)
+    }
+
+    fn kill(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+
+    fn wait(&mut self) -> IoResult<ExitStatus> {
+        Ok(self.status.unwrap_or_else(|| std::process::ExitStatus::from_raw(0).into()))
+    }
+}
+
+/// A pty that discards input and never produces output.
+pub struct MockPty;
+
+impl std::io::Write for MockPty {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+impl MasterPty for MockPty {
+    fn resize(&self, _size: PtySize) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn get_size(&self) -> anyhow::Result<PtySize> {
+        Ok(PtySize::default())
+    }
+
+    fn try_clone_reader(&self) -> anyhow::Result<Box<dyn std::io::Read + Send>> {
+        Ok(Box::new(std::io::empty()))
+    }
+}
@@ -10,6 +10,14 @@ pub struct OutOfTextureSpace {
     pub size: usize,
 }
 
+/// How far an `Atlas` had allocated at some point, to `rewind` it to.
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasMark {
+    bottom: usize,
+    tallest: usize,
+    left: usize,
+}
+
 pub struct Atlas<T>
 where
     T: Texture2d,
@@ -84,6 +92,23 @@ where
     pub fn size(&self) -> usize {
         self.side
     }
+
+    /// The number of rows of the texture reached by allocations so far.
+    pub fn used_height(&self) -> usize {
+        self.bottom + self.tallest
+    }
+
+    pub fn mark(&self) -> AtlasMark {
+        AtlasMark { bottom: self.bottom, tallest: self.tallest, left: self.left }
+    }
+
+    /// Frees the space of everything allocated since `mark` was taken; the
+    /// sprites handed out for it must not be drawn afterwards.
+    pub fn rewind(&mut self, mark: AtlasMark) {
+        self.bottom = mark.bottom;
+        self.tallest = mark.tallest;
+        self.left = mark.left;
+    }
 }
 
 pub struct Sprite<T>
@@ -1,8 +1,9 @@
 use crate::window::color::Color;
-use crate::window::{Operator, Point, Rect};
+use crate::window::{Operator, Point, Rect, Size};
 use glium::texture::SrgbTexture2d;
 use palette::LinSrgba;
 use rgb::FromSlice;
+use std::cell::RefCell;
 
 pub mod atlas;
 
@@ -80,6 +81,35 @@ impl Texture2d for SrgbTexture2d {
     }
 }
 
+/// A texture kept in system memory, for rendering without a GPU.
+pub struct ImageTexture {
+    pub image: RefCell<Image>,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { image: RefCell::new(Image::new(width, height)) }
+    }
+}
+
+impl Texture2d for ImageTexture {
+    fn write(&self, rect: Rect, im: &dyn BitmapImage) {
+        self.image.borrow_mut().draw_image(rect.origin, None, im, Operator::Source);
+    }
+
+    fn read(&self, rect: Rect, im: &mut dyn BitmapImage) {
+        im.draw_image(Point::new(0, 0), Some(rect), &*self.image.borrow(), Operator::Source);
+    }
+
+    fn width(&self) -> usize {
+        self.image.borrow().image_dimensions().0
+    }
+
+    fn height(&self) -> usize {
+        self.image.borrow().image_dimensions().1
+    }
+}
+
 pub trait BitmapImage {
     unsafe fn pixel_data(&self) -> *const u8;
     unsafe fn pixel_data_mut(&mut self) -> *mut u8;
@@ -186,6 +216,48 @@ pub trait BitmapImage {
         }
     }
 
+    /// Composites `src_rect` of `im`, or all of it, with its top left corner
+    /// at `dest_top_left`; pixels falling outside either image are skipped.
+    fn draw_image(
+        &mut self,
+        dest_top_left: Point,
+        src_rect: Option<Rect>,
+        im: &dyn BitmapImage,
+        operator: Operator,
+    ) {
+        let (dest_width, dest_height) = self.image_dimensions();
+        let (im_width, im_height) = im.image_dimensions();
+        let src_rect = src_rect.unwrap_or_else(|| {
+            Rect::new(Point::new(0, 0), Size::new(im_width as isize, im_height as isize))
+        });
+
+        for y in 0..src_rect.size.height {
+            let src_y = src_rect.origin.y + y;
+            let dest_y = dest_top_left.y + y;
+            if src_y < 0
+                || src_y >= im_height as isize
+                || dest_y < 0
+                || dest_y >= dest_height as isize
+            {
+                continue;
+            }
+            for x in 0..src_rect.size.width {
+                let src_x = src_rect.origin.x + x;
+                let dest_x = dest_top_left.x + x;
+                if src_x < 0
+                    || src_x >= im_width as isize
+                    || dest_x < 0
+                    || dest_x >= dest_width as isize
+                {
+                    continue;
+                }
+                let src = Color(*im.pixel(src_x as usize, src_y as usize));
+                let pix = self.pixel_mut(dest_x as usize, dest_y as usize);
+                *pix = src.composite(Color(*pix), operator).0;
+            }
+        }
+    }
+
     fn draw_rect(&mut self, rect: Rect, color: Color, operator: Operator) {
         let bottom_right = rect.origin.add_size(&rect.size);
 
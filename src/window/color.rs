@@ -77,7 +77,7 @@ impl From<LinSrgba> for Color {
         let r = linear_f32_to_srgb8_using_table(s.red);
         let g = linear_f32_to_srgb8_using_table(s.green);
         let b = linear_f32_to_srgb8_using_table(s.blue);
-        let a = linear_f32_to_srgb8_using_table(s.alpha);
+        let a = (s.alpha.max(0.).min(1.) * 255.).round() as u8;
         Color::rgba(r, g, b, a)
     }
 }
@@ -116,7 +116,7 @@ impl From<Color> for LinSrgba {
             srgb8_to_linear_f32(c.0),
             srgb8_to_linear_f32(c.1),
             srgb8_to_linear_f32(c.2),
-            srgb8_to_linear_f32(c.3),
+            c.3 as f32 / 255.,
         )
     }
 }
@@ -176,15 +176,17 @@ impl Color {
             Operator::Multiply => {
                 let src: LinSrgba = self.into();
                 let dest: LinSrgba = dest.into();
-                let result: Color = src.multiply(dest).into();
-                result
+                multiply(src, dest).into()
             }
             Operator::MultiplyThenOver(ref tint) => {
                 let src: LinSrgba = self.into();
                 let tint: LinSrgba = (*tint).into();
-                let mut tinted = src.multiply(tint);
-
-                tinted.alpha = src.alpha;
+                let tinted = LinSrgba::new(
+                    src.red * tint.red,
+                    src.green * tint.green,
+                    src.blue * tint.blue,
+                    src.alpha * tint.alpha,
+                );
 
                 let dest: LinSrgba = dest.into();
                 tinted.over(dest).into()
@@ -192,3 +194,89 @@ impl Color {
         }
     }
 }
+
+/// The separable multiply blend mode composited with source-over, computed
+/// on premultiplied components: `co = cs·cb + cs·(1 - αb) + cb·(1 - αs)`.
+fn multiply(src: LinSrgba, dest: LinSrgba) -> LinSrgba {
+    let (sa, da) = (src.alpha, dest.alpha);
+    let alpha = sa + da - sa * da;
+    if alpha <= 0. {
+        return LinSrgba::new(0., 0., 0., 0.);
+    }
+
+    let channel = |s: f32, d: f32| {
+        let (s, d) = (s * sa, d * da);
+        (s * d + s * (1. - da) + d * (1. - sa)) / alpha
+    };
+    LinSrgba::new(
+        channel(src.red, dest.red),
+        channel(src.green, dest.green),
+        channel(src.blue, dest.blue),
+        alpha,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_rgba(color: Color, expected: (u8, u8, u8, u8)) {
+        let actual = color.as_rgba();
+        let close = |a: u8, b: u8| (a as i16 - b as i16).abs() <= 1;
+        assert!(
+            close(actual.0, expected.0)
+                && close(actual.1, expected.1)
+                && close(actual.2, expected.2)
+                && close(actual.3, expected.3),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn source() {
+        let src = Color::rgba(0x12, 0x34, 0x56, 0x78);
+        assert_rgba(src.composite(Color::rgb(0, 0, 0), Operator::Source), (0x12, 0x34, 0x56, 0x78));
+    }
+
+    #[test]
+    fn over() {
+        let dest = Color::rgb(0, 0, 0xff);
+        assert_rgba(Color::rgb(0xff, 0, 0).composite(dest, Operator::Over), (0xff, 0, 0, 0xff));
+        assert_rgba(Color::rgba(0xff, 0, 0, 0).composite(dest, Operator::Over), (0, 0, 0xff, 0xff));
+        // 20% white over black is 0.2 linear, which is 124 in sRGB
+        let src = Color::rgba(0xff, 0xff, 0xff, 51);
+        assert_rgba(src.composite(Color::rgb(0, 0, 0), Operator::Over), (124, 124, 124, 0xff));
+    }
+
+    #[test]
+    fn multiply() {
+        let src = Color::rgb(0xff, 0, 0xff);
+        assert_rgba(
+            src.composite(Color::rgb(0xff, 0xff, 0), Operator::Multiply),
+            (0xff, 0, 0, 0xff),
+        );
+        // Multiplying onto a transparent destination leaves the source as is
+        assert_rgba(
+            src.composite(Color::rgba(0, 0, 0, 0), Operator::Multiply),
+            (0xff, 0, 0xff, 0xff),
+        );
+        // 20% black multiplied onto white is 0.8 linear, which is 231 in sRGB
+        let src = Color::rgba(0, 0, 0, 51);
+        assert_rgba(
+            src.composite(Color::rgb(0xff, 0xff, 0xff), Operator::Multiply),
+            (231, 231, 231, 0xff),
+        );
+    }
+
+    #[test]
+    fn multiply_then_over() {
+        let tint = Operator::MultiplyThenOver(Color::rgb(0xff, 0, 0));
+        let dest = Color::rgb(0, 0, 0xff);
+        assert_rgba(Color::rgb(0xff, 0xff, 0xff).composite(dest, tint), (0xff, 0, 0, 0xff));
+        assert_rgba(Color::rgba(0xff, 0xff, 0xff, 0).composite(dest, tint), (0, 0, 0xff, 0xff));
+        // A 20% coverage glyph tints 20% of the way from blue towards red
+        assert_rgba(Color::rgba(0xff, 0xff, 0xff, 51).composite(dest, tint), (124, 0, 231, 0xff));
+    }
+}
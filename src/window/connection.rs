@@ -2,8 +2,31 @@ use crate::window::os::Connection;
 use crate::window::spawn;
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
 
-pub const FPS: u32 = 60;
+pub const DEFAULT_FPS: u32 = 60;
+
+static MAX_FPS: AtomicU32 = AtomicU32::new(DEFAULT_FPS);
+static SOFTWARE_RENDERING: AtomicBool = AtomicBool::new(false);
+
+pub fn set_max_fps(fps: u32) {
+    MAX_FPS.store(fps.max(1), Ordering::Relaxed);
+}
+
+pub fn paint_interval() -> Duration {
+    Duration::from_micros(1_000_000 / MAX_FPS.load(Ordering::Relaxed) as u64)
+}
+
+/// When enabled, windows created afterwards skip OpenGL and are painted
+/// through `WindowCallbacks::paint_software`.
+pub fn set_software_rendering(enabled: bool) {
+    SOFTWARE_RENDERING.store(enabled, Ordering::Relaxed);
+}
+
+pub fn software_rendering() -> bool {
+    SOFTWARE_RENDERING.load(Ordering::Relaxed)
+}
 
 thread_local! {
     static CONN: RefCell<Option<Rc<Connection>>> = RefCell::new(None);
@@ -78,12 +78,17 @@ pub enum MousePress {
     Middle,
 }
 
+/// The wheel delta of one notch; high resolution devices report fractions
+/// of it.
+pub const WHEEL_DELTA: i16 = 120;
+
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MouseEventKind {
     Move,
     Press(MousePress),
     Release(MousePress),
+    /// In units of `WHEEL_DELTA` per notch; positive is up
     VertWheel(i16),
     HorzWheel(i16),
 }
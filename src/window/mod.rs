@@ -51,13 +51,24 @@ pub trait WindowCallbacks: Any {
     fn focus_change(&mut self, focused: bool);
     fn destroy(&mut self) {}
     fn resize(&mut self, dimensions: Dimensions) {}
+    /// Called when the window moves to a display with a different density.
+    fn dpi_changed(&mut self, dpi: usize) {}
+    fn needs_paint(&self) -> bool {
+        true
+    }
     fn paint(&mut self, frame: &mut glium::Frame) {}
+    /// Used instead of `paint` when software rendering is enabled; `image`
+    /// keeps its contents between calls until the window is resized.
+    fn paint_software(&mut self, image: &mut bitmaps::Image) {}
     fn key_event(&mut self, key: &KeyEvent, context: &dyn WindowOps) -> bool {
         false
     }
     fn mouse_event(&mut self, event: &MouseEvent, context: &dyn WindowOps) {
         context.set_cursor(Some(MouseCursor::Arrow));
     }
+    /// Called with text committed by an input method or dead-key sequence;
+    /// the key events that produced it are not delivered separately.
+    fn ime_commit(&mut self, text: &str) {}
     fn created(
         &mut self,
         _window: &Window,
@@ -65,6 +76,9 @@ pub trait WindowCallbacks: Any {
     ) -> anyhow::Result<()> {
         Ok(())
     }
+    fn created_software(&mut self, _window: &Window) -> anyhow::Result<()> {
+        Ok(())
+    }
     fn as_any(&mut self) -> &mut dyn Any;
 }
 
@@ -76,6 +90,18 @@ pub trait WindowOps {
     fn set_title(&self, title: &str);
     fn set_inner_size(&self, width: usize, height: usize);
     fn set_text_cursor_position(&self, _cursor: Rect) {}
+    fn beep(&self) {}
+    fn request_attention(&self) {}
+    /// The top left corner of the window's contents in screen coordinates;
+    /// only valid on the GUI thread.
+    fn get_position(&self) -> Option<Point> {
+        None
+    }
+    /// The width and height in pixels that a window's contents can grow to
+    /// on its screen; only valid on the GUI thread.
+    fn get_work_area_size(&self) -> Option<(usize, usize)> {
+        None
+    }
     fn apply<F: Send + 'static + Fn(&mut dyn Any, &dyn WindowOps)>(&self, func: F)
     where
         Self: Sized;
@@ -89,4 +115,6 @@ pub trait WindowOpsMut {
     fn set_title(&mut self, title: &str);
     fn set_inner_size(&self, width: usize, height: usize);
     fn set_text_cursor_position(&mut self, _cursor: Rect) {}
+    fn beep(&mut self) {}
+    fn request_attention(&mut self) {}
 }
@@ -1,18 +1,21 @@
 #![allow(clippy::let_unit_value)]
 use super::{nsstring, nsstring_to_str};
-use crate::window::connection::{ConnectionOps, FPS};
+use crate::window::connection::{paint_interval, ConnectionOps};
 use crate::window::{
     Connection, Dimensions, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseCursor, MouseEvent,
     MouseEventKind, MousePress, Point, Rect, Size, WindowCallbacks, WindowOps, WindowOpsMut,
+    WHEEL_DELTA,
 };
 use anyhow::ensure;
 use cocoa::appkit::{
-    NSApplicationActivateIgnoringOtherApps, NSBackingStoreBuffered, NSEvent, NSEventModifierFlags,
-    NSRunningApplication, NSView, NSViewHeightSizable, NSViewWidthSizable, NSWindow,
-    NSWindowStyleMask,
+    NSApp, NSApplicationActivateIgnoringOtherApps, NSBackingStoreBuffered, NSEvent,
+    NSEventModifierFlags, NSRunningApplication, NSView, NSViewHeightSizable, NSViewWidthSizable,
+    NSWindow, NSWindowStyleMask,
 };
 use cocoa::base::*;
-use cocoa::foundation::{NSArray, NSNotFound, NSPoint, NSRect, NSSize, NSUInteger};
+use cocoa::foundation::{
+    NSArray, NSInteger, NSNotFound, NSPoint, NSProcessInfo, NSRect, NSSize, NSUInteger,
+};
 use objc::declare::ClassDecl;
 use objc::rc::{StrongPtr, WeakPtr};
 use objc::runtime::{Class, Object, Protocol, Sel};
@@ -236,7 +239,7 @@ pub struct Window(usize);
 
 impl Window {
     pub fn new_window(
-        _class_name: &str,
+        class_name: &str,
         name: &str,
         width: usize,
         height: usize,
@@ -258,6 +261,7 @@ impl Window {
                 window_id,
                 gl_context_pair: None,
                 text_cursor_position: Rect::new(Point::new(0, 0), Size::new(0, 0)),
+                marked_text: String::new(),
             }));
             let window =
                 StrongPtr::new(NSWindow::alloc(nil).initWithContentRect_styleMask_backing_defer_(
@@ -268,6 +272,9 @@ impl Window {
                 ));
             window.setReleasedWhenClosed_(NO);
 
+            let process_info = NSProcessInfo::processInfo(nil);
+            let () = msg_send![process_info, setProcessName: *nsstring(class_name)];
+
             window.center();
             window.setTitle_(*nsstring(&name));
             window.setAcceptsMouseMovedEvents_(YES);
@@ -295,31 +302,30 @@ impl Window {
                 dpi: (96.0 * (backing_frame.size.width / frame.size.width)) as usize,
             });
 
-            conn.schedule_timer(
-                std::time::Duration::from_micros(1_000_000 / FPS as u64),
-                move || {
-                    Connection::with_window_inner(window_id, move |inner| {
-                        let frame = NSView::frame(*inner.view as *mut _);
-                        let backing_frame =
-                            NSView::convertRectToBacking(*inner.view as *mut _, frame);
-                        if let Some(window_view) = WindowView::get_this(&**inner.view) {
-                            let mut inner = window_view.inner.borrow_mut();
-                            let width = backing_frame.size.width;
-                            let height = backing_frame.size.height;
-                            if let Some(gl_context_pair) = inner.gl_context_pair.as_ref() {
-                                let mut frame = glium::Frame::new(
-                                    Rc::clone(&gl_context_pair.context),
-                                    (width as u32, height as u32),
-                                );
-                                inner.callbacks.paint(&mut frame);
-                                frame
-                                    .finish()
-                                    .expect("frame.finish failed and we don't know how to recover");
-                            }
+            conn.schedule_timer(paint_interval(), move || {
+                Connection::with_window_inner(window_id, move |inner| {
+                    let frame = NSView::frame(*inner.view as *mut _);
+                    let backing_frame = NSView::convertRectToBacking(*inner.view as *mut _, frame);
+                    if let Some(window_view) = WindowView::get_this(&**inner.view) {
+                        let mut inner = window_view.inner.borrow_mut();
+                        if !inner.callbacks.needs_paint() {
+                            return;
                         }
-                    });
-                },
-            );
+                        let width = backing_frame.size.width;
+                        let height = backing_frame.size.height;
+                        if let Some(gl_context_pair) = inner.gl_context_pair.as_ref() {
+                            let mut frame = glium::Frame::new(
+                                Rc::clone(&gl_context_pair.context),
+                                (width as u32, height as u32),
+                            );
+                            inner.callbacks.paint(&mut frame);
+                            frame
+                                .finish()
+                                .expect("frame.finish failed and we don't know how to recover");
+                        }
+                    }
+                });
+            });
 
             Ok(window)
         }
@@ -355,6 +361,48 @@ impl WindowOps for Window {
         Connection::with_window_inner(self.0, move |inner| inner.set_text_cursor_position(cursor));
     }
 
+    fn beep(&self) {
+        Connection::with_window_inner(self.0, |inner| inner.beep());
+    }
+
+    fn request_attention(&self) {
+        Connection::with_window_inner(self.0, |inner| inner.request_attention());
+    }
+
+    fn get_position(&self) -> Option<Point> {
+        let inner = Connection::get()?.window_by_id(self.0)?;
+        let inner = inner.borrow();
+        unsafe {
+            // Cocoa measures from the bottom left of the screen
+            let screen: id = msg_send![*inner.window, screen];
+            if screen == nil {
+                return None;
+            }
+            let screen_frame: NSRect = msg_send![screen, frame];
+            let frame: NSRect = msg_send![*inner.window, frame];
+            let content: NSRect = msg_send![*inner.window, contentRectForFrameRect: frame];
+            Some(Point::new(
+                content.origin.x as isize,
+                (screen_frame.size.height - (content.origin.y + content.size.height)) as isize,
+            ))
+        }
+    }
+
+    fn get_work_area_size(&self) -> Option<(usize, usize)> {
+        let inner = Connection::get()?.window_by_id(self.0)?;
+        let inner = inner.borrow();
+        unsafe {
+            let screen: id = msg_send![*inner.window, screen];
+            if screen == nil {
+                return None;
+            }
+            // Leaves out the menu bar and dock, and the window's title bar
+            let visible: NSRect = msg_send![screen, visibleFrame];
+            let content: NSRect = msg_send![*inner.window, contentRectForFrameRect: visible];
+            Some((content.size.width as usize, content.size.height as usize))
+        }
+    }
+
     fn apply<F: Send + 'static + Fn(&mut dyn Any, &dyn WindowOps)>(&self, func: F)
     where
         Self: Sized,
@@ -428,6 +476,24 @@ impl WindowOpsMut for WindowInner {
             let () = msg_send![input_context, invalidateCharacterCoordinates];
         }
     }
+
+    fn beep(&mut self) {
+        unsafe { NSBeep() }
+    }
+
+    fn request_attention(&mut self) {
+        unsafe {
+            // The dock icon bounces once; AppKit stops it when the app
+            // becomes active.
+            let _: NSInteger = msg_send![NSApp(), requestUserAttention: NS_INFORMATIONAL_REQUEST];
+        }
+    }
+}
+
+const NS_INFORMATIONAL_REQUEST: NSInteger = 10;
+
+extern "C" {
+    fn NSBeep();
 }
 
 struct Inner {
@@ -436,6 +502,7 @@ struct Inner {
     window_id: usize,
     gl_context_pair: Option<opengl::GlContextPair>,
     text_cursor_position: Rect,
+    marked_text: String,
 }
 
 impl Inner {
@@ -563,12 +630,29 @@ impl WindowView {
         }
     }
 
-    extern "C" fn has_marked_text(_this: &mut Object, _sel: Sel) -> BOOL {
-        NO
+    fn has_marked_text_inner(this: &mut Object) -> bool {
+        match Self::get_this(this) {
+            Some(myself) => !myself.inner.borrow().marked_text.is_empty(),
+            None => false,
+        }
     }
 
-    extern "C" fn marked_range(_this: &mut Object, _sel: Sel) -> NSRange {
-        NSRange::new(NSNotFound as _, 0)
+    extern "C" fn has_marked_text(this: &mut Object, _sel: Sel) -> BOOL {
+        if Self::has_marked_text_inner(this) {
+            YES
+        } else {
+            NO
+        }
+    }
+
+    extern "C" fn marked_range(this: &mut Object, _sel: Sel) -> NSRange {
+        match Self::get_this(this) {
+            Some(myself) if !myself.inner.borrow().marked_text.is_empty() => {
+                let len = myself.inner.borrow().marked_text.encode_utf16().count();
+                NSRange::new(0, len as _)
+            }
+            _ => NSRange::new(NSNotFound as _, 0),
+        }
     }
 
     extern "C" fn selected_range(_this: &mut Object, _sel: Sel) -> NSRange {
@@ -583,37 +667,30 @@ impl WindowView {
     ) {
         let s = unsafe { nsstring_to_str(astring) };
 
-        let event = KeyEvent {
-            key: KeyCode::Composed(s.to_string()),
-            raw_key: None,
-            modifiers: Modifiers::default(),
-            repeat_count: 1,
-            key_is_down: true,
-        };
-
         if let Some(myself) = Self::get_this(this) {
             let mut inner = myself.inner.borrow_mut();
-            let window = Window(inner.window_id);
-            inner.callbacks.key_event(&event, &window);
+            inner.marked_text.clear();
+            inner.callbacks.ime_commit(s);
         }
     }
 
     extern "C" fn set_marked_text_selected_range_replacement_range(
-        _this: &mut Object,
+        this: &mut Object,
         _sel: Sel,
-        _astring: id,
-        selected_range: NSRange,
-        replacement_range: NSRange,
+        astring: id,
+        _selected_range: NSRange,
+        _replacement_range: NSRange,
     ) {
-        let s = unsafe { nsstring_to_str(_astring) };
-        eprintln!(
-            "set_marked_text_selected_range_replacement_range {} {:?} {:?}",
-            s, selected_range, replacement_range
-        );
+        let s = unsafe { nsstring_to_str(astring) };
+        if let Some(myself) = Self::get_this(this) {
+            myself.inner.borrow_mut().marked_text = s.to_string();
+        }
     }
 
-    extern "C" fn unmark_text(_this: &mut Object, _sel: Sel) {
-        eprintln!("unmarkText");
+    extern "C" fn unmark_text(this: &mut Object, _sel: Sel) {
+        if let Some(myself) = Self::get_this(this) {
+            myself.inner.borrow_mut().marked_text.clear();
+        }
     }
 
     extern "C" fn valid_attributes_for_marked_text(_this: &mut Object, _sel: Sel) -> id {
@@ -753,10 +830,15 @@ impl WindowView {
     extern "C" fn scroll_wheel(this: &mut Object, _sel: Sel, nsevent: id) {
         let vert_delta = unsafe { nsevent.scrollingDeltaY() };
         let horz_delta = unsafe { nsevent.scrollingDeltaX() };
+        let to_wheel_delta = |delta: f64| {
+            (delta * f64::from(WHEEL_DELTA))
+                .max(f64::from(i16::min_value()))
+                .min(f64::from(i16::max_value())) as i16
+        };
         let kind = if vert_delta.abs() > horz_delta.abs() {
-            MouseEventKind::VertWheel(vert_delta as i16)
+            MouseEventKind::VertWheel(to_wheel_delta(vert_delta))
         } else {
-            MouseEventKind::HorzWheel(horz_delta as i16)
+            MouseEventKind::HorzWheel(to_wheel_delta(horz_delta))
         };
         Self::mouse_common(this, nsevent, kind);
     }
@@ -783,7 +865,7 @@ impl WindowView {
                 unmod
             };
 
-        if modifiers.is_empty() && !is_a_repeat {
+        if (modifiers.is_empty() && !is_a_repeat) || Self::has_marked_text_inner(this) {
             unsafe {
                 let input_context: id = msg_send![this, inputContext];
                 let res: BOOL = msg_send![input_context, handleEvent: nsevent];
@@ -906,6 +988,17 @@ impl WindowView {
         }
     }
 
+    extern "C" fn did_change_backing_properties(this: &mut Object, _sel: Sel, _notification: id) {
+        let frame = unsafe { NSView::frame(this as *mut _) };
+        let backing_frame = unsafe { NSView::convertRectToBacking(this as *mut _, frame) };
+        if let Some(this) = Self::get_this(this) {
+            this.inner
+                .borrow_mut()
+                .callbacks
+                .dpi_changed((96.0 * (backing_frame.size.width / frame.size.width)) as usize);
+        }
+    }
+
     fn get_this(this: &Object) -> Option<&mut Self> {
         unsafe {
             let myself: *mut c_void = *this.get_ivar(CLS_NAME);
@@ -975,6 +1068,10 @@ impl WindowView {
                 sel!(windowDidResize:),
                 Self::did_resize as extern "C" fn(&mut Object, Sel, id),
             );
+            cls.add_method(
+                sel!(windowDidChangeBackingProperties:),
+                Self::did_change_backing_properties as extern "C" fn(&mut Object, Sel, id),
+            );
 
             cls.add_method(
                 sel!(mouseMoved:),
@@ -1,6 +1,6 @@
 use super::keyboard::Keyboard;
 use crate::core::promise;
-use crate::window::connection::{ConnectionOps, FPS};
+use crate::window::connection::{paint_interval, ConnectionOps};
 use crate::window::os::x11::WindowInner;
 use crate::window::spawn::SPAWN_QUEUE;
 use anyhow::{anyhow, bail};
@@ -193,7 +193,7 @@ impl ConnectionOps for Connection {
         poll.register(self, tok_xcb, Ready::readable(), PollOpt::level())?;
         poll.register(&*SPAWN_QUEUE, tok_spawn, Ready::readable(), PollOpt::level())?;
 
-        let paint_interval = Duration::from_micros(1_000_000 / FPS as u64);
+        let paint_interval = paint_interval();
         let mut last_interval = Instant::now();
 
         while !*self.should_terminate.borrow() {
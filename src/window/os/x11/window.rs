@@ -1,8 +1,9 @@
 use super::*;
-use crate::window::connection::ConnectionOps;
+use crate::window::bitmaps::{BitmapImage, Image};
+use crate::window::connection::{software_rendering, ConnectionOps};
 use crate::window::{
-    Dimensions, KeyEvent, MouseButtons, MouseCursor, MouseEvent, MouseEventKind, MousePress,
-    WindowCallbacks, WindowOps, WindowOpsMut,
+    Dimensions, KeyEvent, MouseButtons, MouseCursor, MouseEvent, MouseEventKind, MousePress, Point,
+    WindowCallbacks, WindowOps, WindowOpsMut, WHEEL_DELTA,
 };
 use anyhow::anyhow;
 use std::any::Any;
@@ -18,15 +19,70 @@ pub(crate) struct WindowInner {
     height: u16,
     cursor: Option<MouseCursor>,
     gl_state: Option<Rc<glium::backend::Context>>,
+    software: Option<SoftwareSurface>,
+    damaged: bool,
+}
+
+/// The CPU side framebuffer of a window painted without OpenGL.
+struct SoftwareSurface {
+    gc: xcb::xproto::Gcontext,
+    image: Image,
+}
+
+impl SoftwareSurface {
+    fn present(&self, conn: &Connection, window_id: xcb::xproto::Window) {
+        let (width, height) = self.image.image_dimensions();
+        if width == 0 || height == 0 {
+            return;
+        }
+        let stride = width * 4;
+        let data = unsafe { std::slice::from_raw_parts(self.image.pixel_data(), stride * height) };
+
+        // Stay under the maximum request size, leaving room for the header
+        let max_bytes = (conn.conn().get_maximum_request_length() as usize * 4).saturating_sub(64);
+        let rows_per_request = (max_bytes / stride).max(1);
+
+        for top in (0..height).step_by(rows_per_request) {
+            let rows = rows_per_request.min(height - top);
+            xcb::put_image(
+                conn.conn(),
+                xcb::IMAGE_FORMAT_Z_PIXMAP as u8,
+                window_id,
+                self.gc,
+                width as u16,
+                rows as u16,
+                0,
+                top as i16,
+                0,
+                24,
+                &data[top * stride..(top + rows) * stride],
+            );
+        }
+        conn.conn().flush();
+    }
 }
 
 impl Drop for WindowInner {
     fn drop(&mut self) {
+        if let Some(surface) = self.software.take() {
+            xcb::free_gc(self.conn.conn(), surface.gc);
+        }
         xcb::destroy_window(self.conn.conn(), self.window_id);
     }
 }
 
 impl WindowInner {
+    fn enable_software(&mut self) -> anyhow::Result<()> {
+        let gc = self.conn.conn().generate_id();
+        xcb::create_gc_checked(self.conn.conn(), gc, self.window_id, &[]).request_check()?;
+        self.software.replace(SoftwareSurface {
+            gc,
+            image: Image::new(self.width as usize, self.height as usize),
+        });
+        let window_handle = Window::from_id(self.window_id);
+        self.callbacks.created_software(&window_handle)
+    }
+
     fn enable_opengl(&mut self) -> anyhow::Result<()> {
         let gl_state = crate::window::egl::GlState::create(
             Some(self.conn.display as *const _),
@@ -51,6 +107,11 @@ impl WindowInner {
     }
 
     pub fn paint(&mut self) -> anyhow::Result<()> {
+        if !self.damaged && !self.callbacks.needs_paint() {
+            return Ok(());
+        }
+        self.damaged = false;
+
         if let Some(gl_context) = self.gl_state.as_ref() {
             let mut frame = glium::Frame::new(
                 Rc::clone(&gl_context),
@@ -62,6 +123,15 @@ impl WindowInner {
             return Ok(());
         }
 
+        if let Some(surface) = self.software.as_mut() {
+            let (width, height) = (self.width as usize, self.height as usize);
+            if surface.image.image_dimensions() != (width, height) {
+                surface.image = Image::new(width, height);
+            }
+            self.callbacks.paint_software(&mut surface.image);
+            surface.present(&self.conn, self.window_id);
+        }
+
         Ok(())
     }
 
@@ -108,6 +178,26 @@ impl WindowInner {
         Ok(())
     }
 
+    /// Sets or clears the ICCCM urgency flag in `WM_HINTS`.  We don't
+    /// set any other hints, so the property is rewritten wholesale.
+    fn set_urgency_hint(&mut self, urgent: bool) {
+        const INPUT_HINT: u32 = 1;
+        const URGENCY_HINT: u32 = 1 << 8;
+        let flags = if urgent { INPUT_HINT | URGENCY_HINT } else { INPUT_HINT };
+        // flags, input, initial_state, icon_pixmap, icon_window, icon_x,
+        // icon_y, icon_mask, window_group
+        let hints: [u32; 9] = [flags, 1, 0, 0, 0, 0, 0, 0, 0];
+        xcb::change_property(
+            self.conn.conn(),
+            xcb::PROP_MODE_REPLACE as u8,
+            self.window_id,
+            xcb::ATOM_WM_HINTS,
+            xcb::ATOM_WM_HINTS,
+            32,
+            &hints,
+        );
+    }
+
     pub fn dispatch_event(&mut self, event: &xcb::GenericEvent) -> anyhow::Result<()> {
         let r = event.response_type() & 0x7f;
         match r {
@@ -115,12 +205,16 @@ impl WindowInner {
                 let cfg: &xcb::ConfigureNotifyEvent = unsafe { xcb::cast_event(event) };
                 self.width = cfg.width();
                 self.height = cfg.height();
+                self.damaged = true;
                 self.callbacks.resize(Dimensions {
                     pixel_width: self.width as usize,
                     pixel_height: self.height as usize,
                     dpi: 96,
                 })
             }
+            xcb::EXPOSE => {
+                self.damaged = true;
+            }
             xcb::KEY_PRESS | xcb::KEY_RELEASE => {
                 let key_press: &xcb::KeyPressEvent = unsafe { xcb::cast_event(event) };
                 if let Some((code, mods)) = self.conn.keyboard.process_key_event(key_press) {
@@ -167,7 +261,7 @@ impl WindowInner {
                         if r == xcb::BUTTON_RELEASE {
                             return Ok(());
                         }
-                        MouseEventKind::VertWheel(if b == 4 { 1 } else { -1 })
+                        MouseEventKind::VertWheel(if b == 4 { WHEEL_DELTA } else { -WHEEL_DELTA })
                     }
                     _ => {
                         eprintln!("button {} is not implemented", button_press.detail());
@@ -195,6 +289,7 @@ impl WindowInner {
                 self.conn.windows.borrow_mut().remove(&self.window_id);
             }
             xcb::FOCUS_IN => {
+                self.set_urgency_hint(false);
                 self.callbacks.focus_change(true);
             }
             xcb::FOCUS_OUT => {
@@ -218,7 +313,7 @@ impl Window {
     }
 
     pub fn new_window(
-        _class_name: &str,
+        class_name: &str,
         name: &str,
         width: usize,
         height: usize,
@@ -276,6 +371,8 @@ impl Window {
                 height: height.try_into()?,
                 cursor: None,
                 gl_state: None,
+                software: None,
+                damaged: true,
             }))
         };
 
@@ -289,8 +386,24 @@ impl Window {
             &[conn.atom_delete],
         );
 
+        // WM_CLASS holds the instance and class names, each NUL terminated
+        let wm_class = format!("{}\0{}\0", class_name, class_name);
+        xcb::change_property(
+            &*conn,
+            xcb::PROP_MODE_REPLACE as u8,
+            window_id,
+            xcb::ATOM_WM_CLASS,
+            xcb::ATOM_STRING,
+            8,
+            wm_class.as_bytes(),
+        );
+
         let window_handle = Window::from_id(window_id);
-        window.lock().unwrap().enable_opengl()?;
+        if software_rendering() {
+            window.lock().unwrap().enable_software()?;
+        } else {
+            window.lock().unwrap().enable_opengl()?;
+        }
         conn.windows.borrow_mut().insert(window_id, window.clone());
         window_handle.set_title(name);
         window_handle.show();
@@ -323,6 +436,14 @@ impl WindowOpsMut for WindowInner {
     fn set_title(&mut self, title: &str) {
         xcb_util::icccm::set_wm_name(self.conn.conn(), self.window_id, title);
     }
+
+    fn beep(&mut self) {
+        xcb::bell(self.conn.conn(), 0);
+    }
+
+    fn request_attention(&mut self) {
+        self.set_urgency_hint(true);
+    }
 }
 
 impl WindowOps for Window {
@@ -349,6 +470,47 @@ impl WindowOps for Window {
         Connection::with_window_inner(self.0, move |inner| inner.set_inner_size(width, height));
     }
 
+    fn beep(&self) {
+        Connection::with_window_inner(self.0, |inner| inner.beep());
+    }
+
+    fn request_attention(&self) {
+        Connection::with_window_inner(self.0, |inner| inner.request_attention());
+    }
+
+    fn get_position(&self) -> Option<Point> {
+        let conn = Connection::get()?;
+        let root = conn.conn().get_setup().roots().nth(conn.screen_num() as usize)?.root();
+        let reply = xcb::translate_coordinates(conn.conn(), self.0, root, 0, 0).get_reply().ok()?;
+        Some(Point::new(reply.dst_x() as isize, reply.dst_y() as isize))
+    }
+
+    fn get_work_area_size(&self) -> Option<(usize, usize)> {
+        let conn = Connection::get()?;
+        let screen = conn.conn().get_setup().roots().nth(conn.screen_num() as usize)?;
+        let full = (screen.width_in_pixels() as usize, screen.height_in_pixels() as usize);
+
+        // The window manager publishes the area its panels leave free as
+        // x, y, width, height for each desktop
+        let work_area = xcb::intern_atom(conn.conn(), true, "_NET_WORKAREA")
+            .get_reply()
+            .ok()
+            .map(|reply| reply.atom())
+            .filter(|&atom| atom != xcb::ATOM_NONE)
+            .and_then(|atom| {
+                xcb::get_property(conn.conn(), false, screen.root(), atom, xcb::ATOM_CARDINAL, 0, 4)
+                    .get_reply()
+                    .ok()
+            });
+        match work_area {
+            Some(reply) if reply.value::<u32>().len() == 4 => {
+                let area = reply.value::<u32>();
+                Some((area[2] as usize, area[3] as usize))
+            }
+            _ => Some(full),
+        }
+    }
+
     fn apply<F: Send + 'static + Fn(&mut dyn Any, &dyn WindowOps)>(&self, func: F)
     where
         Self: Sized,